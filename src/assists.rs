@@ -0,0 +1,121 @@
+//! Driver assists and difficulty
+//!
+//! Traction control eases off the throttle when the driven wheels spin, and
+//! the ABS releases the brakes when the wheels lock up, so newcomers can drive
+//! the scanned roads without spinning out while experts drive with the tires
+//! at their limit. Each difficulty comes with its own assists: both on Easy,
+//! only ABS on Normal and none on Hard.
+//!
+//! Press F2 to cycle the difficulty, F5 to toggle traction control and F6 to
+//! toggle the ABS.
+
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, DriverAssists};
+
+/// Plugin for the driver assists
+pub struct AssistsPlugin;
+
+impl Plugin for AssistsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AssistSettings>().add_systems(
+            Update,
+            (toggle_assists, apply_assists)
+                .chain()
+                .after(CarSet::Input)
+                .before(CarSet::Physics),
+        );
+    }
+}
+
+/// How forgiving the car is to drive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// The next difficulty, wrapping around
+    fn next(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    /// Assists that come with the difficulty
+    pub fn assists(self) -> DriverAssists {
+        match self {
+            Difficulty::Easy => DriverAssists {
+                traction_control: true,
+                abs: true,
+            },
+            Difficulty::Normal => DriverAssists {
+                traction_control: false,
+                abs: true,
+            },
+            Difficulty::Hard => DriverAssists::default(),
+        }
+    }
+}
+
+/// Resource with the difficulty and the assists in use
+#[derive(Resource)]
+pub struct AssistSettings {
+    pub difficulty: Difficulty,
+    /// Assists in use; the difficulty's, unless toggled since choosing it
+    pub assists: DriverAssists,
+}
+
+impl Default for AssistSettings {
+    fn default() -> Self {
+        let difficulty = Difficulty::default();
+        Self {
+            difficulty,
+            assists: difficulty.assists(),
+        }
+    }
+}
+
+/// Cycle the difficulty with F2 and toggle traction control and ABS with F5 and F6
+fn toggle_assists(keyboard: Res<ButtonInput<KeyCode>>, mut settings: ResMut<AssistSettings>) {
+    if keyboard.just_pressed(KeyCode::F2) {
+        settings.difficulty = settings.difficulty.next();
+        settings.assists = settings.difficulty.assists();
+        info!(
+            "Difficulty: {:?} (traction control {}, ABS {})",
+            settings.difficulty,
+            on_off(settings.assists.traction_control),
+            on_off(settings.assists.abs)
+        );
+    }
+    if keyboard.just_pressed(KeyCode::F5) {
+        settings.assists.traction_control = !settings.assists.traction_control;
+        info!("Traction control {}", on_off(settings.assists.traction_control));
+    }
+    if keyboard.just_pressed(KeyCode::F6) {
+        settings.assists.abs = !settings.assists.abs;
+        info!("ABS {}", on_off(settings.assists.abs));
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Hand the assists to the cars, which lose them whenever the vehicle changes
+fn apply_assists(settings: Res<AssistSettings>, mut car_query: Query<&mut Car>) {
+    for mut car in car_query.iter_mut() {
+        if car.assists != settings.assists {
+            car.assists = settings.assists;
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Righting the car after a rollover
+//!
+//! With rigid-body physics the car can end up on its roof or its side with no
+//! way to drive off. Once it has lain there, nearly still, for a couple of
+//! seconds it is turned back onto its wheels where it is, keeping its
+//! heading. A prompt counts down while the car waits to be righted.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar, RIDE_HEIGHT};
+use crate::terrain::GroundSurface;
+
+/// The car counts as rolled over while its up axis is this far from the ground's
+const MAX_UPRIGHT_DOT: f32 = 0.3;
+
+/// The car counts as stuck below this speed, in m/s
+const STUCK_SPEED: f32 = 1.0;
+
+/// Height above the ground the car is dropped back onto its wheels from
+const RIGHTING_DROP: f32 = 0.5;
+
+/// Plugin for righting a rolled-over car
+pub struct AutoFlipPlugin;
+
+impl Plugin for AutoFlipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutoFlip>()
+            .add_systems(Startup, spawn_flip_prompt)
+            .add_systems(
+                Update,
+                (right_rolled_over_car, update_flip_prompt)
+                    .chain()
+                    .after(CarSet::Input)
+                    .before(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource configuring and tracking the righting of the car
+#[derive(Resource)]
+pub struct AutoFlip {
+    /// Seconds the car has to lie rolled over before it is righted
+    pub delay: f32,
+    /// Seconds the car has been rolled over and stuck
+    stuck_time: f32,
+}
+
+impl Default for AutoFlip {
+    fn default() -> Self {
+        Self {
+            delay: 2.0,
+            stuck_time: 0.0,
+        }
+    }
+}
+
+/// Marker for the righting prompt
+#[derive(Component)]
+struct FlipPrompt;
+
+/// Spawn the hidden prompt shown while the car waits to be righted
+fn spawn_flip_prompt(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(30.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            FlipPrompt,
+        ))
+        .with_child((
+            Text::new(""),
+            TextFont {
+                font_size: 28.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextShadow::default(),
+        ));
+}
+
+/// Turn the car back onto its wheels once it has been stuck upside down or on
+/// its side for long enough
+fn right_rolled_over_car(
+    time: Res<Time>,
+    mut auto_flip: ResMut<AutoFlip>,
+    ground: GroundSurface,
+    mut car_query: Query<(&mut Car, &mut Transform, &LinearVelocity), With<PrimaryCar>>,
+) {
+    let Ok((mut car, mut transform, velocity)) = car_query.single_mut() else {
+        return;
+    };
+
+    let normal = ground.normal_at(transform.translation);
+    let rolled_over = transform.up().dot(normal) < MAX_UPRIGHT_DOT;
+    if !rolled_over || velocity.length() > STUCK_SPEED {
+        auto_flip.stuck_time = 0.0;
+        return;
+    }
+
+    auto_flip.stuck_time += time.delta_secs();
+    if auto_flip.stuck_time < auto_flip.delay {
+        return;
+    }
+    auto_flip.stuck_time = 0.0;
+
+    // Keep the heading, level with the ground, just above the ground
+    let forward = transform
+        .forward()
+        .reject_from_normalized(normal)
+        .try_normalize()
+        .unwrap_or_else(|| transform.up().reject_from_normalized(normal).normalize_or(Vec3::Z));
+    let height = ground.height_at(transform.translation);
+    transform.translation += normal * (RIDE_HEIGHT + RIGHTING_DROP - height);
+    transform.look_to(forward, normal);
+    car.stop();
+    info!("Car righted");
+}
+
+/// Show a countdown while the car waits to be righted
+fn update_flip_prompt(
+    auto_flip: Res<AutoFlip>,
+    mut prompt_query: Query<(&mut Visibility, &Children), With<FlipPrompt>>,
+    mut text_query: Query<&mut Text>,
+) {
+    let Ok((mut visibility, children)) = prompt_query.single_mut() else {
+        return;
+    };
+    if auto_flip.stuck_time <= 0.0 {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+    let remaining = (auto_flip.delay - auto_flip.stuck_time).max(0.0);
+    for &child in children {
+        if let Ok(mut text) = text_query.get_mut(child) {
+            text.0 = format!("Rolled over - righting the car in {:.1} s", remaining);
+        }
+    }
+}
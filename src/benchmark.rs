@@ -0,0 +1,110 @@
+//! Benchmark mode
+//!
+//! `--benchmark[=<seconds>]` on the command line measures how smoothly a scene
+//! runs, for comparing machines, settings and changes to the renderer. Once the
+//! scene has loaded and the frame rate has had `WARMUP_SECONDS` to settle, every
+//! frame time is recorded for the given duration. The results go to the log
+//! and the game quits, with an error if the scene failed to load.
+
+use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostic, DiagnosticsStore};
+use bevy::prelude::*;
+
+use crate::cli;
+use crate::diagnostics_overlay::SPLAT_COUNT;
+use crate::splat_loader::SplatLoadState;
+
+/// Seconds between the scene loading and the measurement starting
+const WARMUP_SECONDS: f32 = 3.0;
+
+/// Fraction of the slowest frames the "low" frame rate is taken from
+const LOW_FRACTION: f32 = 0.01;
+
+/// Plugin for the benchmark mode
+pub struct BenchmarkPlugin;
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(duration) = cli::args().benchmark else {
+            return;
+        };
+        app.insert_resource(Benchmark {
+            duration: duration.max(1.0),
+            warmup: WARMUP_SECONDS,
+            frame_times: Vec::new(),
+        })
+        .add_systems(Update, run_benchmark);
+    }
+}
+
+/// Resource with the running benchmark
+#[derive(Resource)]
+struct Benchmark {
+    /// Seconds to measure for
+    duration: f32,
+    /// Seconds left before measuring
+    warmup: f32,
+    /// Real frame times measured so far, in seconds
+    frame_times: Vec<f32>,
+}
+
+/// Wait for the scene, measure the frame times and report them
+fn run_benchmark(
+    time: Res<Time<Real>>,
+    load_state: Res<State<SplatLoadState>>,
+    store: Res<DiagnosticsStore>,
+    mut benchmark: ResMut<Benchmark>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    match load_state.get() {
+        SplatLoadState::Failed => {
+            error!("Benchmark aborted, the scene failed to load");
+            exit.write(AppExit::error());
+            return;
+        }
+        SplatLoadState::Loaded => {}
+        // Without a scene to open the empty world is measured
+        _ if !cli::args().opens_anything() => {}
+        // Anything still loading starts the benchmark over
+        _ => {
+            benchmark.warmup = WARMUP_SECONDS;
+            benchmark.frame_times.clear();
+            return;
+        }
+    }
+
+    if benchmark.warmup > 0.0 {
+        benchmark.warmup -= time.delta_secs();
+        if benchmark.warmup <= 0.0 {
+            info!("Benchmark measuring for {} s", benchmark.duration);
+        }
+        return;
+    }
+    benchmark.frame_times.push(time.delta_secs());
+    let elapsed: f32 = benchmark.frame_times.iter().sum();
+    if elapsed < benchmark.duration {
+        return;
+    }
+
+    let mut frame_times = std::mem::take(&mut benchmark.frame_times);
+    frame_times.sort_by(|a, b| b.total_cmp(a));
+    let frames = frame_times.len();
+    let slowest = ((frames as f32 * LOW_FRACTION).ceil() as usize).clamp(1, frames);
+    let low_frame_time = frame_times[..slowest].iter().sum::<f32>() / slowest as f32;
+    let splats = store
+        .get(&SPLAT_COUNT)
+        .and_then(Diagnostic::value)
+        .unwrap_or(0.0);
+    info!(
+        "Benchmark: {} frames in {:.1} s, {:.1} fps average ({:.2} ms), {:.1} fps 1% low, \
+         slowest frame {:.2} ms, {:.0} splats",
+        frames,
+        elapsed,
+        frames as f32 / elapsed,
+        elapsed / frames as f32 * 1000.0,
+        1.0 / low_frame_time,
+        frame_times[0] * 1000.0,
+        splats,
+    );
+    exit.write(AppExit::Success);
+}
@@ -0,0 +1,166 @@
+//! Blob shadows under the cars
+//!
+//! Without a shadow on the ground right under it, a car seems to hover over
+//! the scan, however well it follows the road: the sun's shadow falls off to
+//! one side and disappears at night or under an overcast sky. Every car, the
+//! other players' included, gets a soft dark quad on the ground beneath it,
+//! laid along the surface and turned with the car. It fades and spreads as
+//! the car leaves the ground, so jumps still read as jumps.
+
+use bevy::asset::embedded_asset;
+use bevy::light::{NotShadowCaster, NotShadowReceiver};
+use bevy::prelude::*;
+use bevy::render::render_resource::AsBindGroup;
+use bevy::shader::ShaderRef;
+
+use crate::car::{Car, CarSet, CAR_HALF_EXTENTS};
+use crate::network::RemoteCar;
+use crate::terrain::GroundSurface;
+
+/// Opacity of the middle of a blob shadow with the car on the ground
+const BLOB_DARKNESS: f32 = 0.6;
+
+/// Size of a blob shadow relative to the car's footprint
+const BLOB_MARGIN: Vec2 = Vec2::new(1.4, 1.2);
+
+/// Height above the ground at which a blob shadow has faded out, in meters
+const FADE_HEIGHT: f32 = 4.0;
+
+/// How much wider a blob shadow is per meter the car is above the ground
+const SPREAD_PER_METER: f32 = 0.15;
+
+/// How far a blob shadow is lifted off the ground, in meters
+const GROUND_OFFSET: f32 = 0.03;
+
+/// Added to a blob shadow's distance from the camera when sorting transparent
+/// meshes, so it is drawn after the splats
+const BLOB_SORT_BIAS: f32 = 1.0e5;
+
+/// Plugin for the blob shadows under the cars
+pub struct BlobShadowPlugin;
+
+impl Plugin for BlobShadowPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "blob_shadow.wgsl");
+        app.add_plugins(MaterialPlugin::<BlobShadowMaterial>::default())
+            .add_systems(
+                Update,
+                (spawn_blob_shadows, place_blob_shadows)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Material of a soft shadow fading out from the middle of a quad
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct BlobShadowMaterial {
+    /// Color of the shadow, with its opacity in the middle
+    #[uniform(0)]
+    pub shadow_color: LinearRgba,
+}
+
+impl Material for BlobShadowMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "embedded://gaussrace/blob_shadow.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn depth_bias(&self) -> f32 {
+        BLOB_SORT_BIAS
+    }
+}
+
+/// Component of the blob shadow under a car
+#[derive(Component)]
+struct BlobShadow {
+    car: Entity,
+}
+
+/// Spawn a blob shadow under every new car and remove those of cars that are
+/// gone
+fn spawn_blob_shadows(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<BlobShadowMaterial>>,
+    car_query: Query<Entity, Or<(With<Car>, With<RemoteCar>)>>,
+    shadow_query: Query<(Entity, &BlobShadow)>,
+    mut mesh: Local<Option<Handle<Mesh>>>,
+) {
+    for (entity, shadow) in shadow_query.iter() {
+        if !car_query.contains(shadow.car) {
+            commands.entity(entity).despawn();
+        }
+    }
+    for car in car_query.iter() {
+        if shadow_query.iter().any(|(_, shadow)| shadow.car == car) {
+            continue;
+        }
+        let mesh = mesh.get_or_insert_with(|| {
+            let half_size = Vec2::new(CAR_HALF_EXTENTS.x, CAR_HALF_EXTENTS.z) * BLOB_MARGIN;
+            meshes.add(Plane3d::new(Vec3::Y, half_size))
+        });
+        commands.spawn((
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(materials.add(BlobShadowMaterial {
+                shadow_color: LinearRgba::NONE,
+            })),
+            Transform::default(),
+            Visibility::Hidden,
+            NotShadowCaster,
+            NotShadowReceiver,
+            BlobShadow { car },
+        ));
+    }
+}
+
+/// Lay each blob shadow on the ground under its car, turned with the car, and
+/// fade it with the car's height
+fn place_blob_shadows(
+    ground: GroundSurface,
+    mut materials: ResMut<Assets<BlobShadowMaterial>>,
+    car_query: Query<(&Transform, &InheritedVisibility), Without<BlobShadow>>,
+    mut shadow_query: Query<(
+        &BlobShadow,
+        &MeshMaterial3d<BlobShadowMaterial>,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    for (shadow, material, mut transform, mut visibility) in shadow_query.iter_mut() {
+        let Ok((car, car_visibility)) = car_query.get(shadow.car) else {
+            continue;
+        };
+        let height = ground.height_at(car.translation).max(0.0);
+        let fade = 1.0 - height / FADE_HEIGHT;
+        if !car_visibility.get() || fade <= 0.0 {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        }
+        visibility.set_if_neq(Visibility::Inherited);
+
+        let normal = ground.normal_at(car.translation);
+        let forward = car
+            .forward()
+            .reject_from_normalized(normal)
+            .try_normalize()
+            .unwrap_or_else(|| normal.any_orthonormal_vector());
+        *transform = Transform {
+            translation: ground.project_point(car.translation) + normal * GROUND_OFFSET,
+            rotation: Quat::from_mat3(&Mat3::from_cols(normal.cross(-forward), normal, -forward)),
+            scale: Vec3::splat(1.0 + height * SPREAD_PER_METER),
+        };
+        let color = LinearRgba::BLACK.with_alpha(BLOB_DARKNESS * fade);
+        if materials
+            .get(&material.0)
+            .is_some_and(|material| material.shadow_color != color)
+        {
+            if let Some(material) = materials.get_mut(&material.0) {
+                material.shadow_color = color;
+            }
+        }
+    }
+}
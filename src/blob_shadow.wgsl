@@ -0,0 +1,13 @@
+// Soft blob shadow under a car: darkest in the middle of the quad and fading
+// out towards its edges
+
+#import bevy_pbr::forward_io::VertexOutput
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<uniform> shadow_color: vec4<f32>;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = length(in.uv * 2.0 - vec2<f32>(1.0));
+    let falloff = 1.0 - smoothstep(0.35, 1.0, distance);
+    return vec4<f32>(shadow_color.rgb, shadow_color.a * falloff);
+}
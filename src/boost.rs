@@ -0,0 +1,130 @@
+//! Nitro boost
+//!
+//! The boost meter slowly fills over time and quickly while drifting. Holding
+//! the boost key (Space by default, or the gamepad's south button) burns it to
+//! push the car harder and past its usual top speed, widening the camera's
+//! field of view and leaving a flame trail behind the car.
+
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar, CAR_HALF_EXTENTS};
+use crate::input_map::{Action, InputMap};
+use crate::particles::{jitter, SpawnParticle};
+
+/// Meter needed to start boosting, so tapping an empty meter does nothing
+const MIN_BOOST_TO_START: f32 = 0.1;
+
+/// Flame particles per second while boosting
+const FLAME_RATE: f32 = 60.0;
+
+/// Plugin for the boost meter and its effects
+pub struct BoostPlugin;
+
+impl Plugin for BoostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Boost>().add_systems(
+            Update,
+            (
+                update_boost.after(CarSet::Input).before(CarSet::Physics),
+                boost_flames.after(CarSet::Camera),
+            ),
+        );
+    }
+}
+
+/// Resource with the boost meter
+#[derive(Resource)]
+pub struct Boost {
+    /// Stored boost, from 0 (empty) to 1 (full)
+    pub meter: f32,
+    /// Whether the boost is burning
+    pub active: bool,
+    /// Meter gained per second while driving
+    pub charge_rate: f32,
+    /// Extra meter gained per second while the rear tires slide
+    pub drift_charge_rate: f32,
+    /// Meter burnt per second while boosting
+    pub drain_rate: f32,
+    /// Smoothed boost effect, from 0 to 1, for the camera
+    pub intensity: f32,
+}
+
+impl Default for Boost {
+    fn default() -> Self {
+        Self {
+            meter: 0.5,
+            active: false,
+            charge_rate: 0.03,
+            drift_charge_rate: 0.25,
+            drain_rate: 0.3,
+            intensity: 0.0,
+        }
+    }
+}
+
+/// Charge the meter and burn it while the boost key is held
+fn update_boost(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    gamepads: Query<&Gamepad>,
+    time: Res<Time>,
+    mut boost: ResMut<Boost>,
+    mut car_query: Query<&mut Car, With<PrimaryCar>>,
+) {
+    let Ok(mut car) = car_query.single_mut() else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    let pressed = input_map.pressed(Action::Boost, &keyboard)
+        || gamepads.iter().any(|gamepad| gamepad.pressed(GamepadButton::South));
+    let can_boost = car.gear > 0 && boost.meter > 0.0;
+    boost.active = pressed && can_boost && (boost.active || boost.meter >= MIN_BOOST_TO_START);
+
+    if boost.active {
+        boost.meter = (boost.meter - boost.drain_rate * dt).max(0.0);
+    } else {
+        // Sliding rear tires count as drifting once the car is moving
+        let rear_slip = (car.wheel_slip[2] + car.wheel_slip[3]) * 0.5;
+        let drifting = car.velocity.abs() > 5.0 && rear_slip > 1.0;
+        let rate = boost.charge_rate + if drifting { boost.drift_charge_rate } else { 0.0 };
+        boost.meter = (boost.meter + rate * dt).min(1.0);
+    }
+
+    car.boost = if boost.active { 1.0 } else { 0.0 };
+    let target = car.boost;
+    boost.intensity += (target - boost.intensity) * (6.0 * dt).min(1.0);
+}
+
+/// Leave a trail of flames from the exhaust while boosting
+fn boost_flames(
+    time: Res<Time>,
+    boost: Res<Boost>,
+    car_query: Query<&Transform, With<PrimaryCar>>,
+    mut particles: MessageWriter<SpawnParticle>,
+    mut pending: Local<f32>,
+) {
+    if !boost.active {
+        *pending = 0.0;
+        return;
+    }
+    let Ok(transform) = car_query.single() else {
+        return;
+    };
+
+    *pending += FLAME_RATE * time.delta_secs();
+    let exhaust = transform.translation
+        + transform.back() * CAR_HALF_EXTENTS.z
+        + transform.up() * 0.3;
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+        particles.write(SpawnParticle {
+            position: exhaust,
+            velocity: transform.back() * 6.0 + jitter() * 0.5,
+            color: Color::srgba(0.3, 0.6, 1.0, 0.9),
+            size: 0.12,
+            lifetime: 0.3,
+            emissive: true,
+        });
+    }
+}
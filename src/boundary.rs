@@ -0,0 +1,200 @@
+//! Drivable-area boundary and its editor
+//!
+//! Press 'B' to draw a polygon on the ground around the drivable region:
+//! click to add corners, Ctrl+Z to remove the last one and 'B' again to close
+//! it. Delete removes the boundary. Outside the polygon the car is slowed down
+//! and pushed back by a soft wall, so it can't drive through scanned
+//! buildings. The boundary is saved with the splat sidecar and scene manifest.
+
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::car::{Car, CarSet};
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::point_cloud::SplatPointCloud;
+
+/// How strongly the wall pushes the car back, per second per meter outside
+const WALL_STIFFNESS: f32 = 8.0;
+
+/// How quickly the car's speed bleeds off outside the boundary, per second
+const WALL_DRAG: f32 = 3.0;
+
+/// Distance past the boundary at which the wall becomes solid
+const MAX_PENETRATION: f32 = 1.5;
+
+/// Plugin for the drivable area and its editor
+pub struct BoundaryPlugin;
+
+impl Plugin for BoundaryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DrivableArea>()
+            .init_resource::<BoundaryEditor>()
+            .add_systems(Update, (edit_boundary, draw_boundary).chain())
+            .add_systems(FixedUpdate, apply_boundary_walls.in_set(CarSet::Constraints));
+    }
+}
+
+/// Resource with the polygon bounding the drivable region
+///
+/// Containment is tested in the horizontal (XZ) plane. With fewer than three
+/// points the whole world is drivable.
+#[derive(Resource, Default, Clone)]
+pub struct DrivableArea {
+    pub points: Vec<Vec3>,
+}
+
+impl DrivableArea {
+    /// Whether the polygon encloses an area
+    pub fn is_closed(&self) -> bool {
+        self.points.len() >= 3
+    }
+
+    /// Whether a point lies inside the polygon
+    pub fn contains(&self, point: Vec3) -> bool {
+        !self.is_closed() || polygon_contains(&self.points, point)
+    }
+
+    /// Closest point on the polygon outline, in the XZ plane
+    pub fn closest_edge_point(&self, point: Vec3) -> Option<Vec2> {
+        let p = point.xz();
+        self.edges()
+            .map(|(a, b)| {
+                let edge = b - a;
+                let t = ((p - a).dot(edge) / edge.length_squared().max(1e-6)).clamp(0.0, 1.0);
+                a + edge * t
+            })
+            .min_by(|a, b| a.distance_squared(p).total_cmp(&b.distance_squared(p)))
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let count = self.points.len();
+        (0..count).map(move |i| (self.points[i].xz(), self.points[(i + 1) % count].xz()))
+    }
+}
+
+/// Whether a point lies inside a polygon in the XZ plane, using the even-odd rule
+pub fn polygon_contains(points: &[Vec3], point: Vec3) -> bool {
+    let p = point.xz();
+    let mut inside = false;
+    for (i, a) in points.iter().enumerate() {
+        let (a, b) = (a.xz(), points[(i + 1) % points.len()].xz());
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Resource tracking the boundary editor
+#[derive(Resource, Default)]
+pub struct BoundaryEditor {
+    /// Whether the editor is active
+    pub active: bool,
+    /// Corners of the polygon being drawn
+    points: Vec<Vec3>,
+}
+
+/// Toggle the editor with 'B', add corners by clicking, undo with Ctrl+Z, delete with Delete
+fn edit_boundary(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut editor: ResMut<BoundaryEditor>,
+    mut area: ResMut<DrivableArea>,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+) {
+    if keyboard.just_pressed(KeyCode::Delete) && area.is_closed() {
+        area.points.clear();
+        info!("Drivable area boundary removed");
+    }
+
+    if keyboard.just_pressed(KeyCode::KeyB) {
+        if !editor.active {
+            editor.active = true;
+            editor.points.clear();
+            info!("Boundary editor ACTIVE - click the corners of the drivable area, B to finish");
+        } else {
+            editor.active = false;
+            if editor.points.len() >= 3 {
+                area.points = std::mem::take(&mut editor.points);
+                info!("Drivable area set with {} corners", area.points.len());
+                info!("Press Ctrl+S to save it with this splat");
+            } else {
+                editor.points.clear();
+                info!("Boundary editor INACTIVE - at least 3 corners are needed");
+            }
+        }
+    }
+
+    if !editor.active {
+        return;
+    }
+
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    if ctrl && keyboard.just_pressed(KeyCode::KeyZ) {
+        editor.points.pop();
+    }
+
+    if mouse_button.just_pressed(MouseButton::Left) {
+        if let Some(point) = cursor_ray(&camera_query, &windows)
+            .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes))
+        {
+            editor.points.push(point);
+        }
+    }
+}
+
+/// Draw the drivable area and the polygon being edited
+fn draw_boundary(mut gizmos: Gizmos, editor: Res<BoundaryEditor>, area: Res<DrivableArea>) {
+    let wall_color = Color::srgba(1.0, 0.5, 0.0, 0.6);
+    if area.is_closed() {
+        let outline = area.points.iter().chain(area.points.first()).copied();
+        gizmos.linestrip(outline, wall_color);
+        for &point in &area.points {
+            gizmos.line(point, point + Vec3::Y * 1.5, wall_color);
+        }
+    }
+
+    if editor.active && !editor.points.is_empty() {
+        gizmos.linestrip(editor.points.iter().copied(), Color::srgb(1.0, 1.0, 0.0));
+        for &point in &editor.points {
+            gizmos.sphere(Isometry3d::from_translation(point), 0.2, Color::srgb(1.0, 1.0, 0.0));
+        }
+    }
+}
+
+/// Slow the cars down and push them back inside when they cross the boundary
+fn apply_boundary_walls(
+    time: Res<Time>,
+    area: Res<DrivableArea>,
+    mut car_query: Query<(&mut Car, &mut Transform)>,
+) {
+    if !area.is_closed() {
+        return;
+    }
+    let dt = time.delta_secs();
+    for (mut car, mut transform) in car_query.iter_mut() {
+        if area.contains(transform.translation) {
+            continue;
+        }
+        let Some(edge_point) = area.closest_edge_point(transform.translation) else {
+            continue;
+        };
+
+        let outside = edge_point - transform.translation.xz();
+        let penetration = outside.length();
+
+        car.velocity *= (1.0 - WALL_DRAG * dt).max(0.0);
+
+        // Spring back toward the boundary, never letting the car get further than
+        // the maximum penetration
+        let push = if penetration > MAX_PENETRATION {
+            outside * (1.0 - MAX_PENETRATION / penetration)
+        } else {
+            outside * (WALL_STIFFNESS * dt).min(1.0)
+        };
+        transform.translation += Vec3::new(push.x, 0.0, push.y);
+    }
+}
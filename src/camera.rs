@@ -0,0 +1,428 @@
+//! Camera views of the car
+//!
+//! Press F4 (rebindable) to cycle through the views:
+//!
+//! | View     | Camera                                                  |
+//! |----------|---------------------------------------------------------|
+//! | Chase    | behind and above the car, following it smoothly         |
+//! | Hood     | on the bonnet, fixed to the car                         |
+//! | Cockpit  | at the driver's head, fixed to the car                  |
+//! | Overhead | high above the car, looking straight down               |
+//! | Orbit    | circling the car; drag with RMB to orbit, wheel to zoom |
+//!
+//! The gamepad's north button cycles the views as well. The chase view is set
+//! up by the `CarCamera` component on the camera; while driving in it, '[' and
+//! ']' move it closer and further, with Shift lower and higher, and '-' and '='
+//! make it follow more loosely or tightly.
+//!
+//! While driving in the chase, hood or cockpit view, hold K (rebindable) or
+//! click the gamepad's right stick to look behind the car, and drag with RMB or
+//! tilt the right stick to look around it. The view swings back once released.
+//!
+//! The chase, overhead and orbit views keep a clear line of sight to the car:
+//! when the splat is dense enough between the car and the camera, as with a
+//! scanned wall or tree, the camera is pulled in in front of it, and it never
+//! dips below the ground.
+
+use std::f32::consts::PI;
+
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll};
+use bevy::prelude::*;
+
+use crate::car::{apply_deadzone, CarSet, GamepadDeadzones, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+use crate::point_cloud::SplatPointCloud;
+use crate::terrain::GroundSurface;
+
+/// Camera position relative to the car in the hood view
+const HOOD_OFFSET: Vec3 = Vec3::new(0.0, 1.05, -1.2);
+
+/// Camera position relative to the car in the cockpit view, at the driver's head
+const COCKPIT_OFFSET: Vec3 = Vec3::new(-0.35, 1.15, 0.3);
+
+/// Height of the overhead camera above the car
+const OVERHEAD_HEIGHT: f32 = 35.0;
+
+/// Radians of orbit per pixel of mouse movement
+const ORBIT_SENSITIVITY: f32 = 0.005;
+
+/// How fast the chase camera adjustment keys move it, in meters per second
+const CHASE_ADJUST_SPEED: f32 = 6.0;
+
+/// Radians of look-around per pixel of mouse movement
+const LOOK_SENSITIVITY: f32 = 0.004;
+
+/// Look-around speed at full right stick deflection, in radians per second
+const LOOK_STICK_SPEED: f32 = 2.5;
+
+/// How fast the view swings back once the look-around input is released
+const LOOK_RECENTER_RATE: f32 = 4.0;
+
+/// Height above the car of the point the camera keeps a line of sight to
+const SIGHT_PIVOT_HEIGHT: f32 = 1.0;
+
+/// Distance kept between the camera and splat geometry or the ground
+const CAMERA_CLEARANCE: f32 = 0.3;
+
+/// The camera is never pulled closer to the car than this
+const MIN_CAMERA_DISTANCE: f32 = 1.5;
+
+/// Plugin for the camera views
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraMode>()
+            .init_resource::<OrbitCamera>()
+            .init_resource::<LookAround>()
+            .add_systems(
+                Update,
+                (
+                    cycle_camera_mode,
+                    adjust_chase_camera,
+                    orbit_camera_controls,
+                    look_around_controls,
+                    update_camera_follow,
+                    avoid_camera_occlusion,
+                )
+                    .chain()
+                    .in_set(CarSet::Camera),
+            );
+    }
+}
+
+/// Component for the camera that follows the car, configuring the chase view
+#[derive(Component)]
+pub struct CarCamera {
+    /// Offset from the car in local space: height above it and distance behind it
+    pub offset: Vec3,
+    /// How smoothly the camera follows (lower = smoother)
+    pub smoothness: f32,
+    /// How far ahead of the car the camera looks
+    pub look_ahead: f32,
+}
+
+impl Default for CarCamera {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::new(0.0, 5.0, 12.0),
+            smoothness: 5.0,
+            look_ahead: 5.0,
+        }
+    }
+}
+
+/// Resource with the active camera view
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Behind and above the car, following it smoothly
+    #[default]
+    Chase,
+    /// On the bonnet, looking down the road
+    Hood,
+    /// At the driver's head
+    Cockpit,
+    /// High above the car, looking straight down
+    Overhead,
+    /// Circling the car under mouse control
+    Orbit,
+    /// Flying freely through the splat, away from the car
+    FreeFly,
+    /// Framing a shot in photo mode, flying like the spectator camera
+    Photo,
+}
+
+impl CameraMode {
+    /// Whether the camera flies freely instead of following the car
+    pub fn is_free(self) -> bool {
+        matches!(self, CameraMode::FreeFly | CameraMode::Photo)
+    }
+
+    /// The next view, wrapping around
+    fn next(self) -> Self {
+        match self {
+            CameraMode::Chase => CameraMode::Hood,
+            CameraMode::Hood => CameraMode::Cockpit,
+            CameraMode::Cockpit => CameraMode::Overhead,
+            CameraMode::Overhead => CameraMode::Orbit,
+            CameraMode::Orbit | CameraMode::FreeFly | CameraMode::Photo => CameraMode::Chase,
+        }
+    }
+}
+
+/// Resource with the orbit view's angles around the car
+#[derive(Resource)]
+pub struct OrbitCamera {
+    /// Angle around the vertical axis, in radians
+    pub yaw: f32,
+    /// Angle above the horizon, in radians
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.4,
+            distance: 12.0,
+        }
+    }
+}
+
+/// Resource with where the driver is looking, relative to straight ahead
+#[derive(Resource, Default)]
+pub struct LookAround {
+    /// Angle to the left, in radians
+    pub yaw: f32,
+    /// Angle upwards, in radians
+    pub pitch: f32,
+    /// Whether the look-back key is held
+    pub looking_back: bool,
+    /// Set when looking back starts or stops, to cut to the new view
+    cut: bool,
+}
+
+impl LookAround {
+    /// Rotation of the view around the car
+    fn rotation(&self) -> Quat {
+        let yaw = if self.looking_back { PI } else { self.yaw };
+        let pitch = if self.looking_back { 0.0 } else { self.pitch };
+        Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0)
+    }
+}
+
+/// Switch to the next view
+fn cycle_camera_mode(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    gamepads: Query<&Gamepad>,
+    mut mode: ResMut<CameraMode>,
+) {
+    let pressed = input_map.just_pressed(Action::CycleCamera, &keyboard)
+        || gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::North));
+    if pressed && !mode.is_free() {
+        *mode = mode.next();
+        info!("Camera: {:?}", *mode);
+    }
+}
+
+/// Move the chase camera with '[' and ']' (Shift: height) and change its
+/// smoothing with '-' and '='
+fn adjust_chase_camera(
+    mode: Res<CameraMode>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut camera_query: Query<&mut CarCamera>,
+) {
+    if *mode != CameraMode::Chase {
+        return;
+    }
+    let Ok(mut camera) = camera_query.single_mut() else {
+        return;
+    };
+
+    let step = CHASE_ADJUST_SPEED * time.delta_secs();
+    let mut change = 0.0;
+    if keyboard.pressed(KeyCode::BracketLeft) {
+        change -= step;
+    }
+    if keyboard.pressed(KeyCode::BracketRight) {
+        change += step;
+    }
+    if change != 0.0 {
+        let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+        if shift {
+            camera.offset.y = (camera.offset.y + change).clamp(0.5, 30.0);
+        } else {
+            camera.offset.z = (camera.offset.z + change).clamp(2.0, 50.0);
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Minus) {
+        camera.smoothness = (camera.smoothness * 0.8).max(0.5);
+        info!("Camera smoothing {:.1}", camera.smoothness);
+    }
+    if keyboard.just_pressed(KeyCode::Equal) {
+        camera.smoothness = (camera.smoothness * 1.25).min(30.0);
+        info!("Camera smoothing {:.1}", camera.smoothness);
+    }
+}
+
+/// Orbit around the car while dragging with the right mouse button, zoom with the wheel
+fn orbit_camera_controls(
+    mode: Res<CameraMode>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut orbit: ResMut<OrbitCamera>,
+) {
+    if *mode != CameraMode::Orbit {
+        return;
+    }
+    if mouse_button.pressed(MouseButton::Right) {
+        orbit.yaw -= mouse_motion.delta.x * ORBIT_SENSITIVITY;
+        orbit.pitch = (orbit.pitch + mouse_motion.delta.y * ORBIT_SENSITIVITY).clamp(-0.1, 1.5);
+    }
+    if mouse_scroll.delta.y != 0.0 {
+        orbit.distance = (orbit.distance * (1.0 - mouse_scroll.delta.y * 0.1)).clamp(3.0, 60.0);
+    }
+}
+
+/// Look behind the car or around it while the keys, mouse or stick are held,
+/// swinging back to straight ahead when they are released
+fn look_around_controls(
+    mode: Res<CameraMode>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    gamepads: Query<&Gamepad>,
+    deadzones: Res<GamepadDeadzones>,
+    time: Res<Time<Real>>,
+    mut look: ResMut<LookAround>,
+) {
+    if !matches!(*mode, CameraMode::Chase | CameraMode::Hood | CameraMode::Cockpit) {
+        *look = LookAround::default();
+        return;
+    }
+    let dt = time.delta_secs();
+
+    let looking_back = input_map.pressed(Action::LookBack, &keyboard)
+        || gamepads.iter().any(|gamepad| gamepad.pressed(GamepadButton::RightThumb));
+    look.cut = looking_back != look.looking_back;
+    look.looking_back = looking_back;
+
+    let mut turning = false;
+    if mouse_button.pressed(MouseButton::Right) {
+        look.yaw -= mouse_motion.delta.x * LOOK_SENSITIVITY;
+        look.pitch -= mouse_motion.delta.y * LOOK_SENSITIVITY;
+        turning = true;
+    }
+    for gamepad in gamepads.iter() {
+        let stick = gamepad.right_stick();
+        let x = apply_deadzone(stick.x, deadzones.steering);
+        let y = apply_deadzone(stick.y, deadzones.steering);
+        if x != 0.0 || y != 0.0 {
+            look.yaw -= x * LOOK_STICK_SPEED * dt;
+            look.pitch += y * LOOK_STICK_SPEED * dt;
+            turning = true;
+        }
+    }
+    look.yaw = look.yaw.clamp(-PI, PI);
+    look.pitch = look.pitch.clamp(-0.6, 0.6);
+
+    if !turning {
+        let recenter = (LOOK_RECENTER_RATE * dt).min(1.0);
+        look.yaw -= look.yaw * recenter;
+        look.pitch -= look.pitch * recenter;
+    }
+}
+
+/// Move the camera to the car according to the active view
+fn update_camera_follow(
+    mode: Res<CameraMode>,
+    orbit: Res<OrbitCamera>,
+    look: Res<LookAround>,
+    car_query: Query<&Transform, (With<PrimaryCar>, Without<CarCamera>)>,
+    mut camera_query: Query<(&mut Transform, &CarCamera)>,
+    time: Res<Time>,
+) {
+    let Ok(car_transform) = car_query.single() else {
+        return;
+    };
+    let Ok((mut camera_transform, camera)) = camera_query.single_mut() else {
+        return;
+    };
+
+    let dt = time.delta_secs();
+    let smoothness = camera.smoothness;
+
+    match *mode {
+        CameraMode::Chase => {
+            // Calculate target camera position (behind and above the car),
+            // swung around it by the look-around angles
+            let look_rotation = car_transform.rotation * look.rotation();
+            let target_position = car_transform.translation + look_rotation * camera.offset;
+
+            // Smoothly interpolate camera position, cutting straight to the
+            // view behind when looking back
+            camera_transform.translation = if look.cut {
+                target_position
+            } else {
+                camera_transform.translation.lerp(target_position, (smoothness * dt).min(1.0))
+            };
+
+            // Look at a point slightly ahead of the car
+            let look_target =
+                car_transform.translation + look_rotation * Vec3::NEG_Z * camera.look_ahead;
+            camera_transform.look_at(look_target, Vec3::Y);
+        }
+        CameraMode::Hood | CameraMode::Cockpit => {
+            // Fixed to the car, so it pitches and rolls with the body
+            let offset = if *mode == CameraMode::Hood {
+                HOOD_OFFSET
+            } else {
+                COCKPIT_OFFSET
+            };
+            *camera_transform = car_transform.mul_transform(Transform {
+                translation: offset,
+                rotation: look.rotation(),
+                ..default()
+            });
+        }
+        CameraMode::Overhead => {
+            let target_position = car_transform.translation + Vec3::Y * OVERHEAD_HEIGHT;
+            camera_transform.translation =
+                camera_transform.translation.lerp(target_position, (smoothness * dt).min(1.0));
+            // The car points up the screen
+            let heading = car_transform.forward().with_y(0.0).normalize_or(Vec3::NEG_Z);
+            camera_transform.look_at(car_transform.translation, heading);
+        }
+        CameraMode::Orbit => {
+            let direction = Quat::from_euler(EulerRot::YXZ, orbit.yaw, -orbit.pitch, 0.0) * Vec3::Z;
+            camera_transform.translation = car_transform.translation + direction * orbit.distance;
+            camera_transform.look_at(car_transform.translation, Vec3::Y);
+        }
+        // The spectator and photo cameras fly on their own
+        CameraMode::FreeFly | CameraMode::Photo => {}
+    }
+}
+
+/// Pull the camera in front of splat geometry hiding the car and lift it
+/// above the ground
+fn avoid_camera_occlusion(
+    mode: Res<CameraMode>,
+    point_cloud: Res<SplatPointCloud>,
+    ground: GroundSurface,
+    car_query: Query<&Transform, (With<PrimaryCar>, Without<CarCamera>)>,
+    mut camera_query: Query<&mut Transform, With<CarCamera>>,
+) {
+    if !matches!(*mode, CameraMode::Chase | CameraMode::Overhead | CameraMode::Orbit) {
+        return;
+    }
+    let Ok(car_transform) = car_query.single() else {
+        return;
+    };
+    let Ok(mut camera_transform) = camera_query.single_mut() else {
+        return;
+    };
+
+    let pivot = car_transform.translation + car_transform.up() * SIGHT_PIVOT_HEIGHT;
+    let to_camera = camera_transform.translation - pivot;
+    let distance = to_camera.length();
+    if !point_cloud.is_empty() && distance > MIN_CAMERA_DISTANCE {
+        if let Ok(direction) = Dir3::new(to_camera) {
+            if let Some(hit) = point_cloud.raycast(Ray3d::new(pivot, direction), distance) {
+                let clear = (hit.distance(pivot) - CAMERA_CLEARANCE).max(MIN_CAMERA_DISTANCE);
+                camera_transform.translation = pivot + direction * clear;
+            }
+        }
+    }
+
+    let height = ground.height_at(camera_transform.translation);
+    if height < CAMERA_CLEARANCE {
+        let normal = ground.normal_at(camera_transform.translation);
+        camera_transform.translation += normal * (CAMERA_CLEARANCE - height);
+    }
+}
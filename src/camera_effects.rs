@@ -0,0 +1,137 @@
+//! Sense of speed
+//!
+//! Splat backdrops are static, so speed is hard to feel from them alone. The
+//! camera's field of view widens smoothly with speed, and more while boosting,
+//! and the camera shakes subtly close to top speed and hard on crashes. The
+//! `CameraEffects` resource sets how strong each effect is; zero turns it off.
+
+use bevy::prelude::*;
+
+use crate::boost::Boost;
+use crate::camera::{CameraMode, CarCamera};
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::damage::CarImpact;
+
+/// Extra vertical field of view at full boost, in radians
+const BOOST_FOV_KICK: f32 = 0.2;
+
+/// Fraction of the top speed above which the camera starts to shake
+const SPEED_SHAKE_START: f32 = 0.7;
+
+/// Trauma added per m/s of impact speed; full trauma shakes the hardest
+const TRAUMA_PER_IMPACT_SPEED: f32 = 0.05;
+
+/// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+
+/// Shake at full trauma, in radians
+const MAX_IMPACT_SHAKE: f32 = 0.06;
+
+/// Plugin for the speed-dependent camera effects
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraEffects>().add_systems(
+            Update,
+            (speed_fov, camera_shake).chain().after(CarSet::Camera),
+        );
+    }
+}
+
+/// Resource configuring the field of view and shake effects
+#[derive(Resource)]
+pub struct CameraEffects {
+    /// Extra vertical field of view at top speed, in radians
+    pub speed_fov: f32,
+    /// Shake at top speed, in radians
+    pub speed_shake: f32,
+    /// Multiplier for the shake on crashes
+    pub impact_shake: f32,
+    /// Smoothed fraction of the top speed the field of view follows
+    speed_fraction: f32,
+    /// Shake left from crashes, from 0 to 1
+    trauma: f32,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            speed_fov: 0.25,
+            speed_shake: 0.004,
+            impact_shake: 1.0,
+            speed_fraction: 0.0,
+            trauma: 0.0,
+        }
+    }
+}
+
+/// Widen the field of view with speed and while boosting
+fn speed_fov(
+    time: Res<Time>,
+    mode: Res<CameraMode>,
+    boost: Res<Boost>,
+    mut effects: ResMut<CameraEffects>,
+    car_query: Query<&Car, With<PrimaryCar>>,
+    mut projection_query: Query<&mut Projection, With<CarCamera>>,
+) {
+    // Photo mode sets its own field of view
+    if *mode == CameraMode::Photo {
+        return;
+    }
+    let target = car_query
+        .single()
+        .map_or(0.0, |car| (car.velocity.abs() / car.max_speed).min(1.0));
+    let blend = (3.0 * time.delta_secs()).min(1.0);
+    effects.speed_fraction += (target - effects.speed_fraction) * blend;
+
+    // Mostly at the top of the speed range, so cruising looks normal
+    let fov = PerspectiveProjection::default().fov
+        + effects.speed_fov * effects.speed_fraction * effects.speed_fraction
+        + BOOST_FOV_KICK * boost.intensity;
+    for mut projection in projection_query.iter_mut() {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = fov;
+        }
+    }
+}
+
+/// Shake the camera near top speed and after crashes
+///
+/// The shake turns the camera rather than moving it, so the views, which aim
+/// it afresh every frame, don't drift from it.
+fn camera_shake(
+    time: Res<Time>,
+    mode: Res<CameraMode>,
+    mut effects: ResMut<CameraEffects>,
+    mut impacts: MessageReader<CarImpact>,
+    mut camera_query: Query<&mut Transform, With<CarCamera>>,
+) {
+    for impact in impacts.read() {
+        let trauma = impact.speed * TRAUMA_PER_IMPACT_SPEED * effects.impact_shake;
+        effects.trauma = (effects.trauma + trauma).min(1.0);
+    }
+    effects.trauma = (effects.trauma - TRAUMA_DECAY * time.delta_secs()).max(0.0);
+
+    if mode.is_free() {
+        return;
+    }
+    let speed_shake = ((effects.speed_fraction - SPEED_SHAKE_START) / (1.0 - SPEED_SHAKE_START))
+        .clamp(0.0, 1.0)
+        * effects.speed_shake;
+    let amplitude = speed_shake + effects.trauma * effects.trauma * MAX_IMPACT_SHAKE;
+    if amplitude <= 0.0 {
+        return;
+    }
+    let Ok(mut transform) = camera_query.single_mut() else {
+        return;
+    };
+
+    // Sums of unrelated frequencies look random without jumping between frames
+    let t = time.elapsed_secs();
+    let wave = |a: f32, b: f32| ((t * a).sin() + 0.5 * (t * b).sin()) / 1.5;
+    let yaw = amplitude * wave(31.0, 17.3);
+    let pitch = amplitude * wave(37.0, 23.1);
+    let roll = 0.5 * amplitude * wave(29.0, 13.7);
+    transform.rotate_local(Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll));
+}
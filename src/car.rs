@@ -1,46 +1,312 @@
 //! Car/vehicle physics and controls
 //!
-//! This module provides a simple car that can drive around on the selected ground plane.
+//! This module provides a simple car that can drive around on the selected ground
+//! plane, following the extracted terrain heightfield where there is one.
+//!
+//! The car is a dynamic rigid body: gravity, jumps and crashes into colliders
+//! come from the physics engine. Flying off a ramp or crest is ballistic, and
+//! landing compresses the suspension and scrubs off speed. Each wheel hangs on
+//! a spring/damper that pushes the body off the splat ground, so the car rolls
+//! in corners, squats when accelerating and dives when braking. The controls
+//! set how fast the wheels turn; a simplified Pacejka tire model turns the
+//! slip between the wheels and the ground into grip, so pushing too hard makes
+//! the car understeer, oversteer or spin its wheels. Gravity along a slope
+//! acts on the wheels as well as the body, so the car slows climbing a sloped
+//! ground plane and speeds up going down it. The front, rear or all four
+//! wheels are driven by an engine with a torque curve through a gearbox that
+//! shifts automatically, or by hand after switching to manual. Weight shifts
+//! between the wheels as the body pitches and rolls, and heavily loaded tires
+//! grip less per newton, so how the car is driven changes its balance.
+//! Keyboard controls go through the rebindable `InputMap`.
+//!
+//! The simulation runs on a fixed timestep, so the car handles the same at 30
+//! and 240 frames per second; its transform is interpolated between physics
+//! steps for rendering.
+//!
+//! Besides the keyboard the car can be driven with a gamepad: the left stick
+//! steers proportionally, the right and left triggers are throttle and brake,
+//! and the shoulder buttons shift in manual mode. In split-screen the gamepad
+//! drives the second player's car instead.
+
+use std::collections::HashMap;
 
+use avian3d::prelude::*;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::input_map::{Action, InputMap};
+use crate::particles::{jitter, SpawnParticle};
+use crate::race_start::driving_allowed;
+use crate::spawn_point::SpawnPoint;
+use crate::terrain::GroundSurface;
+
+/// Height of the car origin above the ground contacts
+pub const RIDE_HEIGHT: f32 = 0.5;
+
+/// Half size of the car body's bounding box, for collision
+pub const CAR_HALF_EXTENTS: Vec3 = Vec3::new(1.0, 0.7, 2.0);
+
+/// How much stiffer than the spring the bump stop at the end of the travel is
+const BUMP_STOP_RATIO: f32 = 10.0;
+
+/// Pacejka stiffness factor for the slip angle, per radian
+const LATERAL_STIFFNESS: f32 = 8.0;
+
+/// Pacejka stiffness factor for the slip ratio
+const LONGITUDINAL_STIFFNESS: f32 = 12.0;
+
+/// Pacejka shape factors; above 1 the grip falls off past the peak slip
+const LATERAL_SHAPE: f32 = 1.3;
+const LONGITUDINAL_SHAPE: f32 = 1.65;
+
+/// Below this speed slip angles are meaningless and the car steers kinematically
+const MIN_SLIP_SPEED: f32 = 3.0;
+
+/// How quickly the wheels spin up or down to match the ground speed, per second
+const WHEEL_SPIN_RELAX: f32 = 5.0;
 
-use crate::ground_plane::GroundPlane;
+/// Hops shorter than this many seconds don't count as landings
+const MIN_AIRTIME: f32 = 0.2;
+
+/// Fraction of the speed lost on landing per m/s of vertical impact speed, and at most
+const LANDING_SPEED_LOSS: f32 = 0.03;
+const MAX_LANDING_SPEED_LOSS: f32 = 0.4;
+
+/// Engine braking torque when off the throttle, as a fraction of the peak torque
+const ENGINE_BRAKING: f32 = 0.15;
+
+/// Extra acceleration from the nitro at full boost, in m/s²
+const NITRO_ACCELERATION: f32 = 6.0;
+
+/// Fraction the nitro raises the top speed by at full boost
+const NITRO_SPEED_BONUS: f32 = 0.25;
+
+/// Fractions of the top speed and steering lock lost when fully wrecked
+const DAMAGE_SPEED_LOSS: f32 = 0.4;
+const DAMAGE_STEERING_LOSS: f32 = 0.5;
+
+/// Particles per second from a wheel sliding at twice its peak slip, or
+/// driving off-road at the top speed
+const TIRE_PARTICLE_RATE: f32 = 40.0;
+
+/// How far past the peak slip the assists fully cut the throttle or release the brakes
+const ASSIST_SLIP_RANGE: f32 = 0.5;
+
+/// Automatic gearboxes shift up above this and down below this fraction of the redline
+const UPSHIFT_RPM_FRACTION: f32 = 0.9;
+const DOWNSHIFT_RPM_FRACTION: f32 = 0.45;
+
+/// Steps per second of the fixed-timestep car simulation
+const PHYSICS_RATE: f64 = 120.0;
+
+/// Radians per second to revolutions per minute
+const RAD_PER_SEC_TO_RPM: f32 = 60.0 / std::f32::consts::TAU;
 
 /// Plugin for car physics and controls
 pub struct CarPlugin;
 
 impl Plugin for CarPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_car)
+        app.init_resource::<GamepadDeadzones>()
+            .add_message::<CarLanded>()
+            .configure_sets(Update, (
+                CarSet::Input,
+                CarSet::Physics,
+                CarSet::Constraints,
+                CarSet::Camera,
+            ).chain())
+            .configure_sets(FixedUpdate, (CarSet::Physics, CarSet::Constraints).chain())
+            .insert_resource(Time::<Fixed>::from_hz(PHYSICS_RATE))
+            .add_systems(Startup, spawn_car)
+            .add_systems(
+                FixedUpdate,
+                (update_drivetrain, update_car_physics).chain().in_set(CarSet::Physics),
+            )
             .add_systems(Update, (
-                handle_car_input,
-                update_car_physics,
-                update_camera_follow,
-            ).chain());
+                handle_car_input.in_set(CarSet::Input).run_if(driving_allowed),
+                (position_wheels, emit_tire_particles).chain().in_set(CarSet::Physics),
+            ));
     }
 }
 
-/// Component marking the player's car
+/// Resource with the gamepad deadzones, as fractions of the full axis range
+///
+/// Inputs inside the deadzone are ignored and the rest of the range is
+/// rescaled, so small stick drift doesn't steer the car.
+#[derive(Resource)]
+pub struct GamepadDeadzones {
+    pub steering: f32,
+    pub triggers: f32,
+}
+
+impl Default for GamepadDeadzones {
+    fn default() -> Self {
+        Self {
+            steering: 0.12,
+            triggers: 0.05,
+        }
+    }
+}
+
+/// Remove the deadzone from an axis value and rescale the rest to the full range
+pub fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    let magnitude = ((value.abs() - deadzone) / (1.0 - deadzone).max(1e-3)).clamp(0.0, 1.0);
+    magnitude.copysign(value)
+}
+
+/// Ordering of the car update, for systems in other modules to hook into
+///
+/// Input is read every frame in `Update`. The simulation, `Physics` and
+/// `Constraints`, runs in `FixedUpdate` so it steps the same way at any frame
+/// rate; in `Update` the same sets order the visual effects that follow it.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CarSet {
+    /// Reading player input into the `Car` controls
+    Input,
+    /// Driving the car's rigid body from the controls
+    Physics,
+    /// Correcting the car's position after it moved, e.g. keeping it inside walls
+    Constraints,
+    /// Moving the camera to follow the car
+    Camera,
+}
+
+/// Message sent when the car touches down after flying off a ramp or crest
+#[derive(Message, Debug, Clone, Copy)]
+pub struct CarLanded {
+    /// Speed toward the ground at touchdown, in m/s
+    pub impact_speed: f32,
+}
+
+/// Marker for the first player's car
+///
+/// The camera, the HUD and the single-player features follow this car; in
+/// split-screen a second car drives alongside it.
 #[derive(Component)]
+pub struct PrimaryCar;
+
+/// Component marking the player's car
+///
+/// The tunables are loaded from vehicle definition files; the driving state
+/// is skipped so a definition only describes the vehicle.
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Car {
-    /// Current velocity (speed along forward direction)
+    /// Current speed of the wheels' rims; the car's speed follows it through the tires
+    #[serde(skip)]
     pub velocity: f32,
     /// Current steering angle in radians
+    #[serde(skip)]
     pub steering: f32,
+    /// Throttle pedal position, from 0 to 1
+    #[serde(skip)]
+    pub throttle: f32,
+    /// Brake pedal position, from 0 to 1
+    #[serde(skip)]
+    pub brake: f32,
+    /// Clutch pedal position, from 0 (engaged) to 1 (disengaged)
+    #[serde(skip)]
+    pub clutch: f32,
+    /// Nitro thrust, from 0 (off) to 1
+    #[serde(skip)]
+    pub boost: f32,
+    /// Crash damage, from 0 (intact) to 1 (wrecked)
+    #[serde(skip)]
+    pub damage: f32,
     /// Maximum speed
     pub max_speed: f32,
-    /// Acceleration rate
-    pub acceleration: f32,
     /// Braking/deceleration rate
     pub brake_power: f32,
-    /// Friction coefficient
-    pub friction: f32,
+    /// Selected gear: -1 for reverse, 0 for neutral, 1 and up for the forward gears
+    #[serde(skip)]
+    pub gear: i32,
+    /// Automatic or manual shifting
+    pub shift_mode: ShiftMode,
+    /// Ratios of the forward gears, first gear first
+    pub gear_ratios: Vec<f32>,
+    /// Ratio of the reverse gear
+    pub reverse_ratio: f32,
+    /// Ratio of the differential, applied on top of every gear
+    pub final_drive: f32,
+    /// Seconds without drive while changing gear
+    pub shift_time: f32,
+    /// Engine speed in revolutions per minute
+    #[serde(skip)]
+    pub rpm: f32,
+    pub idle_rpm: f32,
+    pub redline_rpm: f32,
+    /// Engine torque at the peak of the torque curve, in newton meters
+    pub peak_torque: f32,
+    /// Rolling radius of the wheels in meters
+    pub wheel_radius: f32,
+    /// Rolling resistance on asphalt, as a fraction of the load on each wheel
+    pub rolling_resistance: f32,
     /// Maximum steering angle in radians
     pub max_steering: f32,
     /// Steering speed (how fast steering changes)
     pub steering_speed: f32,
-    /// Car length (wheelbase) for turning calculations
+    /// Distance between the front and rear axles
     pub wheelbase: f32,
+    /// Distance between the left and right wheels
+    pub track_width: f32,
+    /// Mass of the car in kilograms
+    pub mass: f32,
+    /// Type of ground the car is driving on
+    #[serde(skip)]
+    pub surface: Surface,
+    /// Multiplier of the tire grip for the weather, 1 in the dry
+    #[serde(skip)]
+    pub weather_grip: f32,
+    /// Tire friction coefficients on each surface type
+    pub grip: HashMap<Surface, TireGrip>,
+    /// Suspension spring rate of each wheel, in newtons per meter of compression
+    pub spring_rate: f32,
+    /// Suspension damping of each wheel, in newtons per meter per second
+    pub damping: f32,
+    /// Distance each wheel can move between full droop and the bump stop
+    pub suspension_travel: f32,
+    /// Which wheels the engine drives
+    pub drivetrain: Drivetrain,
+    /// Height of the center of mass above the car's origin; the higher it is,
+    /// the more weight shifts between the wheels under acceleration, braking
+    /// and cornering
+    pub center_of_mass_height: f32,
+    /// How much a tire's friction coefficient drops as its load rises above
+    /// its share of the car's weight, so shifting weight onto an axle costs
+    /// the other more grip than it gains
+    pub load_sensitivity: f32,
+    /// Current compression of each wheel's suspension, in `wheel_positions` order
+    #[serde(skip)]
+    pub wheel_compression: [f32; 4],
+    /// Slip of each tire relative to the slip giving the most grip, above 1
+    /// while it slides; zero in the air
+    #[serde(skip)]
+    pub wheel_slip: [f32; 4],
+    /// Longitudinal slip of each tire relative to the slip giving the most
+    /// grip: above 1 while it spins, below -1 while it locks up
+    #[serde(skip)]
+    pub wheel_spin: [f32; 4],
+    /// Load on each tire in newtons; zero in the air
+    #[serde(skip)]
+    pub wheel_load: [f32; 4],
+    /// Driver aids acting on the pedals
+    #[serde(skip)]
+    pub assists: DriverAssists,
+    /// Whether traction control is cutting the throttle
+    #[serde(skip)]
+    pub traction_control_active: bool,
+    /// Whether the ABS is releasing the brakes
+    #[serde(skip)]
+    pub abs_active: bool,
+    /// Seconds since all wheels left the ground, zero while any of them touches it
+    #[serde(skip)]
+    pub airtime: f32,
+    /// Seconds left in the current gear change
+    #[serde(skip)]
+    shift_timer: f32,
+    /// Set by `stop` so the rigid body is brought to rest as well
+    #[serde(skip)]
+    halted: bool,
 }
 
 impl Default for Car {
@@ -48,149 +314,487 @@ impl Default for Car {
         Self {
             velocity: 0.0,
             steering: 0.0,
+            throttle: 0.0,
+            brake: 0.0,
+            clutch: 0.0,
+            boost: 0.0,
+            damage: 0.0,
             max_speed: 30.0,
-            acceleration: 15.0,
             brake_power: 25.0,
-            friction: 5.0,
+            gear: 1,
+            shift_mode: ShiftMode::Automatic,
+            // Short ratios so top gear tops out at the maximum speed
+            gear_ratios: vec![3.2, 2.4, 1.8, 1.4, 1.13],
+            reverse_ratio: 3.0,
+            final_drive: 8.0,
+            shift_time: 0.2,
+            rpm: 900.0,
+            idle_rpm: 900.0,
+            redline_rpm: 6500.0,
+            peak_torque: 180.0,
+            wheel_radius: 0.4,
+            rolling_resistance: 0.3,
             max_steering: 0.6,
             steering_speed: 3.0,
-            wheelbase: 2.0,
+            wheelbase: 2.4,
+            track_width: 2.0,
+            mass: 1200.0,
+            surface: Surface::default(),
+            weather_grip: 1.0,
+            grip: HashMap::from([
+                (Surface::Asphalt, TireGrip { longitudinal: 1.1, lateral: 1.0 }),
+                (Surface::Dirt, TireGrip { longitudinal: 0.7, lateral: 0.6 }),
+                (Surface::Grass, TireGrip { longitudinal: 0.5, lateral: 0.45 }),
+                (Surface::Ice, TireGrip { longitudinal: 0.15, lateral: 0.1 }),
+            ]),
+            // Sags by half the travel under the car's weight, leaving the
+            // body at the ride height
+            spring_rate: 20000.0,
+            damping: 3500.0,
+            suspension_travel: 0.3,
+            drivetrain: Drivetrain::Fwd,
+            center_of_mass_height: CAR_HALF_EXTENTS.y,
+            load_sensitivity: 0.2,
+            wheel_compression: [0.0; 4],
+            wheel_slip: [0.0; 4],
+            wheel_spin: [0.0; 4],
+            wheel_load: [0.0; 4],
+            assists: DriverAssists::default(),
+            traction_control_active: false,
+            abs_active: false,
+            airtime: 0.0,
+            shift_timer: 0.0,
+            halted: false,
         }
     }
 }
 
-/// Component for the camera that follows the car
-#[derive(Component)]
-pub struct CarCamera {
-    /// Offset from the car in local space
-    pub offset: Vec3,
-    /// How smoothly the camera follows (lower = smoother)
-    pub smoothness: f32,
+impl Car {
+    /// Wheel positions relative to the car: front left, front right, rear left, rear right
+    pub fn wheel_positions(&self) -> [Vec3; 4] {
+        let x = self.track_width * 0.5;
+        let z = self.wheelbase * 0.5;
+        [
+            Vec3::new(-x, 0.0, -z),
+            Vec3::new(x, 0.0, -z),
+            Vec3::new(-x, 0.0, z),
+            Vec3::new(x, 0.0, z),
+        ]
+    }
+
+    /// Principal moments of inertia of the car body, treated as a solid box
+    fn inertia(&self) -> Vec3 {
+        let size = CAR_HALF_EXTENTS * CAR_HALF_EXTENTS;
+        self.mass / 3.0 * Vec3::new(size.y + size.z, size.x + size.z, size.x + size.y)
+    }
+
+    /// Steering lock, reduced by damage
+    pub fn steering_limit(&self) -> f32 {
+        self.max_steering * (1.0 - DAMAGE_STEERING_LOSS * self.damage)
+    }
+
+    /// Tire grip on the surface the car is currently driving on, in the weather
+    pub fn current_grip(&self) -> TireGrip {
+        let grip = self.grip.get(&self.surface).copied().unwrap_or_default();
+        TireGrip {
+            longitudinal: grip.longitudinal * self.weather_grip,
+            lateral: grip.lateral * self.weather_grip,
+        }
+    }
+
+    /// Bring the car to a standstill with the wheels straight
+    pub fn stop(&mut self) {
+        self.velocity = 0.0;
+        self.steering = 0.0;
+        self.rpm = self.idle_rpm;
+        self.halted = true;
+    }
+
+    /// The tunable of the given name, as in a vehicle file, e.g. `max_speed`
+    pub fn tunable_mut(&mut self, name: &str) -> Option<&mut f32> {
+        Some(match name {
+            "max_speed" => &mut self.max_speed,
+            "brake_power" => &mut self.brake_power,
+            "reverse_ratio" => &mut self.reverse_ratio,
+            "final_drive" => &mut self.final_drive,
+            "shift_time" => &mut self.shift_time,
+            "idle_rpm" => &mut self.idle_rpm,
+            "redline_rpm" => &mut self.redline_rpm,
+            "peak_torque" => &mut self.peak_torque,
+            "wheel_radius" => &mut self.wheel_radius,
+            "rolling_resistance" => &mut self.rolling_resistance,
+            "max_steering" => &mut self.max_steering,
+            "steering_speed" => &mut self.steering_speed,
+            "wheelbase" => &mut self.wheelbase,
+            "track_width" => &mut self.track_width,
+            "mass" => &mut self.mass,
+            "spring_rate" => &mut self.spring_rate,
+            "damping" => &mut self.damping,
+            "suspension_travel" => &mut self.suspension_travel,
+            "center_of_mass_height" => &mut self.center_of_mass_height,
+            "load_sensitivity" => &mut self.load_sensitivity,
+            _ => return None,
+        })
+    }
+
+    /// Overall ratio between engine and wheel speed in the selected gear, zero in neutral
+    pub fn drive_ratio(&self) -> f32 {
+        let gear_ratio = match self.gear {
+            gear if gear < 0 => -self.reverse_ratio,
+            0 => 0.0,
+            gear => self.gear_ratios.get(gear as usize - 1).copied().unwrap_or(0.0),
+        };
+        gear_ratio * self.final_drive
+    }
+
+    /// Set the pedals from the forward and backward controls, from 0 to 1
+    ///
+    /// With the automatic gearbox, backward selects reverse once the car has
+    /// (nearly) stopped and forward selects first gear again; in reverse the
+    /// pedals swap so backward is the throttle.
+    pub fn apply_pedals(&mut self, forward: f32, backward: f32) {
+        if self.shift_mode == ShiftMode::Manual {
+            self.throttle = forward;
+            self.brake = backward;
+            return;
+        }
+        let stopped = self.velocity.abs() < 0.5;
+        if backward > 0.1 && forward == 0.0 && stopped && self.gear >= 0 {
+            self.shift_to(-1);
+        } else if forward > 0.1 && backward == 0.0 && stopped && self.gear <= 0 {
+            self.shift_to(1);
+        }
+        (self.throttle, self.brake) = if self.gear < 0 {
+            (backward, forward)
+        } else {
+            (forward, backward)
+        };
+    }
+
+    /// Change gear, cutting the drive for the duration of the shift
+    pub fn shift_to(&mut self, gear: i32) {
+        let gear = gear.clamp(-1, self.gear_ratios.len() as i32);
+        if gear != self.gear {
+            self.gear = gear;
+            self.shift_timer = self.shift_time;
+        }
+    }
+}
+
+/// Driver aids that keep the tires near their peak grip
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DriverAssists {
+    /// Ease off the throttle while the driven wheels spin
+    pub traction_control: bool,
+    /// Release the brakes while the wheels lock up
+    pub abs: bool,
+}
+
+/// Which axle the engine drives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Drivetrain {
+    /// Front-wheel drive: pulls out of corners, pushing wide when it spins the wheels
+    #[default]
+    #[serde(rename = "FWD")]
+    Fwd,
+    /// Rear-wheel drive: too much throttle in a corner steps the tail out
+    #[serde(rename = "RWD")]
+    Rwd,
+    /// All-wheel drive: the torque is split evenly between the axles
+    #[serde(rename = "AWD")]
+    Awd,
+}
+
+impl Drivetrain {
+    /// Whether the wheel with this index in `Car::wheel_positions` is driven
+    pub fn drives(self, wheel: usize) -> bool {
+        match self {
+            Drivetrain::Fwd => wheel < 2,
+            Drivetrain::Rwd => wheel >= 2,
+            Drivetrain::Awd => true,
+        }
+    }
+}
+
+/// How the gearbox changes gear
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShiftMode {
+    #[default]
+    Automatic,
+    Manual,
+}
+
+/// Engine torque relative to the peak, for an engine speed relative to the redline
+fn torque_curve(rpm_fraction: f32) -> f32 {
+    (1.0 - 1.5 * (rpm_fraction - 0.65).powi(2)).clamp(0.3, 1.0)
 }
 
-impl Default for CarCamera {
+/// Kind of ground under the car, selecting the tire grip
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Surface {
+    #[default]
+    Asphalt,
+    /// Dirt or gravel
+    #[serde(alias = "Gravel")]
+    Dirt,
+    Grass,
+    Ice,
+}
+
+impl Surface {
+    /// Color of the smoke or dust thrown up by the tires
+    pub fn dust_color(self) -> Color {
+        match self {
+            Surface::Asphalt => Color::srgba(0.85, 0.85, 0.85, 0.5),
+            Surface::Dirt => Color::srgba(0.6, 0.45, 0.3, 0.6),
+            Surface::Grass => Color::srgba(0.35, 0.45, 0.2, 0.5),
+            Surface::Ice => Color::srgba(0.9, 0.95, 1.0, 0.5),
+        }
+    }
+
+    /// Every surface, in the order the surface zone editor cycles through them
+    pub const ALL: [Surface; 4] = [Surface::Asphalt, Surface::Dirt, Surface::Grass, Surface::Ice];
+
+    /// How much harder the wheels roll on this surface than on asphalt
+    pub fn rolling_resistance_factor(self) -> f32 {
+        match self {
+            Surface::Asphalt => 1.0,
+            Surface::Dirt => 1.5,
+            Surface::Grass => 2.0,
+            Surface::Ice => 0.7,
+        }
+    }
+}
+
+/// Peak friction coefficients of the tires on a surface
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TireGrip {
+    /// Along the wheel, for accelerating and braking
+    pub longitudinal: f32,
+    /// Across the wheel, for cornering
+    pub lateral: f32,
+}
+
+impl Default for TireGrip {
     fn default() -> Self {
         Self {
-            offset: Vec3::new(0.0, 5.0, 12.0),
-            smoothness: 5.0,
+            longitudinal: 1.0,
+            lateral: 1.0,
         }
     }
 }
 
-/// Spawn the player's car
-fn spawn_car(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Car body (main box)
-    let car_body = meshes.add(Cuboid::new(2.0, 0.8, 4.0));
-    let car_top = meshes.add(Cuboid::new(1.6, 0.6, 2.0));
-    let wheel = meshes.add(Cylinder::new(0.4, 0.3));
-    
-    let body_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.8, 0.2, 0.2),
-        metallic: 0.8,
-        perceptual_roughness: 0.3,
-        ..default()
-    });
-    
-    let top_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.6, 0.15, 0.15),
-        metallic: 0.6,
-        perceptual_roughness: 0.4,
-        ..default()
-    });
-    
-    let wheel_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.1, 0.1, 0.1),
-        metallic: 0.2,
-        perceptual_roughness: 0.8,
-        ..default()
-    });
-
-    // Spawn the car entity with children for body parts
-    commands.spawn((
-        Car::default(),
-        Transform::from_xyz(0.0, 0.5, 0.0),
-        Visibility::default(),
-    )).with_children(|parent| {
-        // Car body
-        parent.spawn((
-            Mesh3d(car_body.clone()),
-            MeshMaterial3d(body_material.clone()),
-            Transform::from_xyz(0.0, 0.4, 0.0),
-        ));
-        
-        // Car top (cabin)
-        parent.spawn((
-            Mesh3d(car_top),
-            MeshMaterial3d(top_material),
-            Transform::from_xyz(0.0, 1.0, 0.2),
-        ));
-        
-        // Wheels
-        let wheel_positions = [
-            Vec3::new(-1.0, 0.0, 1.2),  // Front left
-            Vec3::new(1.0, 0.0, 1.2),   // Front right
-            Vec3::new(-1.0, 0.0, -1.2), // Rear left
-            Vec3::new(1.0, 0.0, -1.2),  // Rear right
-        ];
-        
-        for pos in wheel_positions {
-            parent.spawn((
-                Mesh3d(wheel.clone()),
-                MeshMaterial3d(wheel_material.clone()),
-                Transform::from_translation(pos)
-                    .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2)),
-            ));
+/// Simplified Pacejka "magic formula": normalized tire force for a slip value
+fn magic_formula(slip: f32, stiffness: f32, shape: f32) -> f32 {
+    (shape * (stiffness * slip).atan()).sin()
+}
+
+/// Slip at which the magic formula peaks
+fn peak_slip(stiffness: f32, shape: f32) -> f32 {
+    (std::f32::consts::FRAC_PI_2 / shape).tan() / stiffness
+}
+
+/// Component marking a wheel mesh
+#[derive(Component)]
+pub struct CarWheel {
+    /// Index in `Car::wheel_positions`
+    pub index: usize,
+    /// Transform of the wheel with the suspension at half travel, steered
+    /// straight ahead and not spun
+    pub rest: Transform,
+    /// Current angle of the wheel around its axle in radians
+    spin: f32,
+}
+
+impl CarWheel {
+    pub fn new(index: usize, rest: Transform) -> Self {
+        Self {
+            index,
+            rest,
+            spin: 0.0,
         }
-    });
+    }
+}
+
+/// Physics body of a car with the given tunables, standing at a transform
+pub fn car_body(car: Car, transform: Transform) -> impl Bundle {
+    let mass = Mass(car.mass);
+    let center_of_mass = CenterOfMass(Vec3::Y * car.center_of_mass_height);
+    (
+        car,
+        transform,
+        Visibility::default(),
+        RigidBody::Dynamic,
+        Collider::compound(vec![(
+            Vec3::Y * CAR_HALF_EXTENTS.y,
+            Quat::IDENTITY,
+            Collider::cuboid(
+                CAR_HALF_EXTENTS.x * 2.0,
+                CAR_HALF_EXTENTS.y * 2.0,
+                CAR_HALF_EXTENTS.z * 2.0,
+            ),
+        )]),
+        mass,
+        center_of_mass,
+        LinearDamping(0.1),
+        AngularDamping(1.0),
+        // Rendered between the last two physics steps, so motion is smooth
+        // at any frame rate
+        TransformInterpolation,
+    )
+}
+
+/// Spawn the player's car
+///
+/// Only the physics body is spawned here; the vehicle module gives it the
+/// tunables and meshes of the selected vehicle.
+fn spawn_car(mut commands: Commands, spawn: Res<SpawnPoint>) {
+    commands.spawn((car_body(Car::default(), spawn.0), PrimaryCar));
 
-    // Mark the main camera as the car camera
     info!("Car spawned! Use WASD or arrow keys to drive.");
     info!("Press 'P' to enter plane selection mode.");
-    info!("Press 'L' to load a Gaussian splat file.");
+    info!("Press 'O' to place the car's spawn point.");
+    info!("Press 'F' to respawn the car.");
+    info!("Press 'M' to switch to manual gears, shifting with Q and E.");
+    info!("Press F2 to change the difficulty; F5 and F6 toggle traction control and ABS.");
+    info!("Hold Space to boost.");
+    info!("Press 'H' to switch the headlights on and off.");
+    info!("Press F4 to change the camera view, F7 to fly around freely, F9 for photo mode.");
+    info!("Hold 'K' to look back, drag with RMB to look around.");
+    info!("Press F8 to show or hide the rear-view mirror.");
+    info!("Press 'I' to show the racing line through the checkpoint gates.");
+    info!("Press F11 to add a second player on a gamepad in split-screen.");
+    info!("Press 'V' to choose another vehicle.");
+    info!("Press F1 to rebind the driving controls.");
+    info!("Press 'L' to show the leaderboard.");
+}
+
+/// Driving controls read from a player's keyboard or gamepads
+#[derive(Clone, Copy, Default)]
+pub struct DriverInput {
+    pub forward: f32,
+    backward: f32,
+    /// Digital steering, positive to the left
+    steering: f32,
+    /// Stick position, positive to the left, while the stick is off center
+    analog_steering: Option<f32>,
+    toggle_gearbox: bool,
+    shift_up: bool,
+    shift_down: bool,
+}
+
+impl DriverInput {
+    /// Read the driving actions from the keyboard through the input map
+    pub fn from_keyboard(input_map: &InputMap, keyboard: &ButtonInput<KeyCode>) -> Self {
+        Self {
+            forward: input_map.value(Action::Accelerate, keyboard),
+            backward: input_map.value(Action::Brake, keyboard),
+            steering: input_map.value(Action::SteerLeft, keyboard)
+                - input_map.value(Action::SteerRight, keyboard),
+            analog_steering: None,
+            toggle_gearbox: input_map.just_pressed(Action::ToggleGearbox, keyboard),
+            shift_up: input_map.just_pressed(Action::ShiftUp, keyboard),
+            shift_down: input_map.just_pressed(Action::ShiftDown, keyboard),
+        }
+    }
+
+    /// Read the triggers, left stick and shift buttons of the gamepads
+    pub fn from_gamepads<'a>(
+        gamepads: impl Iterator<Item = &'a Gamepad>,
+        deadzones: &GamepadDeadzones,
+    ) -> Self {
+        let mut input = Self::default();
+        for gamepad in gamepads {
+            let trigger =
+                |button| apply_deadzone(gamepad.get(button).unwrap_or(0.0), deadzones.triggers);
+            let stick = apply_deadzone(gamepad.left_stick().x, deadzones.steering);
+            input = input.combine(Self {
+                forward: trigger(GamepadButton::RightTrigger2),
+                backward: trigger(GamepadButton::LeftTrigger2),
+                steering: 0.0,
+                analog_steering: (stick != 0.0).then_some(-stick),
+                toggle_gearbox: gamepad.just_pressed(GamepadButton::Select),
+                shift_up: gamepad.just_pressed(GamepadButton::RightTrigger),
+                shift_down: gamepad.just_pressed(GamepadButton::LeftTrigger),
+            });
+        }
+        input
+    }
+
+    /// Both inputs at once, the other's stick taking over while it is used
+    fn combine(self, other: Self) -> Self {
+        Self {
+            forward: self.forward.max(other.forward),
+            backward: self.backward.max(other.backward),
+            steering: (self.steering + other.steering).clamp(-1.0, 1.0),
+            analog_steering: other.analog_steering.or(self.analog_steering),
+            toggle_gearbox: self.toggle_gearbox || other.toggle_gearbox,
+            shift_up: self.shift_up || other.shift_up,
+            shift_down: self.shift_down || other.shift_down,
+        }
+    }
 }
 
-/// Handle keyboard input for car controls
+/// Handle keyboard and gamepad input for car controls
+///
+/// Alone, the first player drives with the keyboard and any gamepad; with a
+/// second car in split-screen the gamepads drive that one instead.
 fn handle_car_input(
     keyboard: Res<ButtonInput<KeyCode>>,
-    mut car_query: Query<&mut Car>,
+    input_map: Res<InputMap>,
+    gamepads: Query<&Gamepad>,
+    deadzones: Res<GamepadDeadzones>,
+    mut car_query: Query<(&mut Car, Has<PrimaryCar>)>,
     time: Res<Time>,
 ) {
-    let Ok(mut car) = car_query.single_mut() else {
-        return;
-    };
+    let keyboard_input = DriverInput::from_keyboard(&input_map, &keyboard);
+    let gamepad_input = DriverInput::from_gamepads(gamepads.iter(), &deadzones);
+    let alone = car_query.iter().count() == 1;
+    for (mut car, primary) in car_query.iter_mut() {
+        let input = match (primary, alone) {
+            (true, true) => keyboard_input.combine(gamepad_input),
+            (true, false) => keyboard_input,
+            (false, _) => gamepad_input,
+        };
+        apply_driver_input(&mut car, input, time.delta_secs());
+    }
+}
 
-    let dt = time.delta_secs();
-    
-    // Acceleration (W or Up)
-    if keyboard.pressed(KeyCode::KeyW) || keyboard.pressed(KeyCode::ArrowUp) {
-        car.velocity += car.acceleration * dt;
+/// Work the pedals, gearbox and steering of a car
+fn apply_driver_input(car: &mut Car, input: DriverInput, dt: f32) {
+    if input.toggle_gearbox {
+        car.shift_mode = match car.shift_mode {
+            ShiftMode::Automatic => ShiftMode::Manual,
+            ShiftMode::Manual => ShiftMode::Automatic,
+        };
+        info!("Gearbox: {:?}", car.shift_mode);
     }
-    
-    // Braking/Reverse (S or Down)
-    if keyboard.pressed(KeyCode::KeyS) || keyboard.pressed(KeyCode::ArrowDown) {
-        if car.velocity > 0.0 {
-            car.velocity -= car.brake_power * dt;
-        } else {
-            car.velocity -= car.acceleration * 0.5 * dt; // Slower reverse
+
+    if car.shift_mode == ShiftMode::Manual {
+        if input.shift_up {
+            let gear = car.gear + 1;
+            car.shift_to(gear);
+        }
+        if input.shift_down {
+            let gear = car.gear - 1;
+            car.shift_to(gear);
         }
     }
+    car.apply_pedals(input.forward, input.backward);
+
+    if let Some(stick) = input.analog_steering {
+        // The stick sets the steering angle directly, limited to a fast steering rate
+        let target = stick * car.steering_limit();
+        let max_change = car.steering_speed * 2.0 * dt;
+        car.steering += (target - car.steering).clamp(-max_change, max_change);
+        return;
+    }
     
     // Steering (A/D or Left/Right)
-    let steering_input = if keyboard.pressed(KeyCode::KeyA) || keyboard.pressed(KeyCode::ArrowLeft) {
-        1.0
-    } else if keyboard.pressed(KeyCode::KeyD) || keyboard.pressed(KeyCode::ArrowRight) {
-        -1.0
-    } else {
-        0.0
-    };
-    
-    if steering_input != 0.0 {
-        car.steering += steering_input * car.steering_speed * dt;
-        car.steering = car.steering.clamp(-car.max_steering, car.max_steering);
+    if input.steering != 0.0 {
+        car.steering += input.steering * car.steering_speed * dt;
+        let limit = car.steering_limit();
+        car.steering = car.steering.clamp(-limit, limit);
     } else {
         // Return steering to center
         let return_speed = car.steering_speed * 2.0 * dt;
@@ -200,102 +804,361 @@ fn handle_car_input(
             car.steering -= car.steering.signum() * return_speed;
         }
     }
-    
-    // Apply friction
-    if !keyboard.pressed(KeyCode::KeyW) && !keyboard.pressed(KeyCode::ArrowUp) &&
-       !keyboard.pressed(KeyCode::KeyS) && !keyboard.pressed(KeyCode::ArrowDown) {
-        let friction_decel = car.friction * dt;
-        if car.velocity.abs() < friction_decel {
-            car.velocity = 0.0;
+}
+
+/// Run the engine and gearbox: engine speed from the wheels, shifting, and
+/// spinning the wheels up with the engine torque or down with the brakes
+fn update_drivetrain(mut car_query: Query<&mut Car>, time: Res<Time>) {
+    let dt = time.delta_secs();
+    for mut car in car_query.iter_mut() {
+        car.shift_timer = (car.shift_timer - dt).max(0.0);
+
+        // Traction control cuts the throttle as the driven wheels spin past their
+        // peak grip, and the ABS eases the brakes as the wheels lock up
+        let drivetrain = car.drivetrain;
+        let spin = (0..4)
+            .filter(|&wheel| drivetrain.drives(wheel))
+            .map(|wheel| car.wheel_spin[wheel].abs())
+            .fold(0.0, f32::max);
+        let lock = car.wheel_spin.iter().map(|spin| spin.abs()).fold(0.0, f32::max);
+        let traction_cut = ((spin - 1.0) / ASSIST_SLIP_RANGE).clamp(0.0, 1.0);
+        let brake_release = ((lock - 1.0) / ASSIST_SLIP_RANGE).clamp(0.0, 1.0);
+        car.traction_control_active =
+            car.assists.traction_control && car.throttle > 0.0 && traction_cut > 0.0;
+        car.abs_active = car.assists.abs && car.brake > 0.0 && brake_release > 0.0;
+        let throttle = if car.traction_control_active {
+            car.throttle * (1.0 - traction_cut)
+        } else {
+            car.throttle
+        };
+        let brake = if car.abs_active {
+            car.brake * (1.0 - brake_release)
+        } else {
+            car.brake
+        };
+
+        // Engine speed follows the wheels in gear, and the throttle in neutral or
+        // with the clutch down
+        let ratio = car.drive_ratio();
+        let engaged = ratio != 0.0 && car.shift_timer <= 0.0 && car.clutch < 0.5;
+        let free_revs = car.idle_rpm + car.throttle * (car.redline_rpm - car.idle_rpm);
+        let target_rpm = if !engaged {
+            free_revs
         } else {
-            car.velocity -= car.velocity.signum() * friction_decel;
+            (car.velocity / car.wheel_radius * ratio * RAD_PER_SEC_TO_RPM).max(car.idle_rpm)
+        };
+        car.rpm += (target_rpm - car.rpm) * (10.0 * dt).min(1.0);
+
+        if car.shift_mode == ShiftMode::Automatic && car.gear > 0 && car.shift_timer <= 0.0 {
+            let top_gear = car.gear_ratios.len() as i32;
+            if car.rpm > car.redline_rpm * UPSHIFT_RPM_FRACTION && car.gear < top_gear {
+                let gear = car.gear + 1;
+                car.shift_to(gear);
+            } else if car.rpm < car.redline_rpm * DOWNSHIFT_RPM_FRACTION && car.gear > 1 {
+                let gear = car.gear - 1;
+                car.shift_to(gear);
+            }
+        }
+
+        // Torque at the wheels, cut at the rev limiter, while shifting and with
+        // the clutch down
+        if engaged {
+            let torque = if throttle > 0.0 && car.rpm < car.redline_rpm {
+                throttle * car.peak_torque * torque_curve(car.rpm / car.redline_rpm)
+            } else if car.velocity.abs() > 0.1 {
+                -ENGINE_BRAKING * car.peak_torque * car.velocity.signum() * ratio.signum()
+            } else {
+                0.0
+            };
+            let wheel_force = torque * ratio / car.wheel_radius * (1.0 - car.clutch);
+            car.velocity += wheel_force / car.mass * dt;
+        }
+
+        // The nitro pushes forward gears past the engine's limits
+        if car.gear > 0 {
+            car.velocity += car.boost * NITRO_ACCELERATION * dt;
+        }
+
+        // Brakes slow the wheels toward a standstill
+        let braking = brake * car.brake_power * dt;
+        car.velocity -= car.velocity.signum() * braking.min(car.velocity.abs());
+
+        // Clamp velocity, easing back down past the top speed when the nitro runs out
+        let top_speed = car.max_speed
+            * (1.0 + NITRO_SPEED_BONUS * car.boost)
+            * (1.0 - DAMAGE_SPEED_LOSS * car.damage);
+        if car.velocity > top_speed {
+            car.velocity = (car.velocity - car.brake_power * dt).max(top_speed);
         }
+        car.velocity = car.velocity.max(-car.max_speed * 0.3);
     }
-    
-    // Clamp velocity
-    car.velocity = car.velocity.clamp(-car.max_speed * 0.3, car.max_speed);
 }
 
-/// Update car physics and position
+/// Drive the car's rigid body through its suspension and tires
 fn update_car_physics(
-    mut car_query: Query<(&mut Car, &mut Transform)>,
-    ground_plane: Res<GroundPlane>,
+    mut car_query: Query<(
+        &mut Car,
+        &Transform,
+        &mut LinearVelocity,
+        &mut AngularVelocity,
+        Has<PrimaryCar>,
+    )>,
+    ground: GroundSurface,
+    gravity: Res<Gravity>,
     time: Res<Time>,
+    mut landings: MessageWriter<CarLanded>,
 ) {
-    let Ok((car, mut transform)) = car_query.single_mut() else {
-        return;
-    };
-
     let dt = time.delta_secs();
-    
-    if car.velocity.abs() < 0.001 {
+    if dt <= 0.0 {
         return;
     }
-    
-    // Calculate the forward direction on the ground plane
-    let forward = transform.forward();
-    
-    // Ackermann-like steering: turning radius depends on wheelbase and steering angle
-    if car.steering.abs() > 0.001 {
-        let turning_radius = car.wheelbase / car.steering.tan();
-        let angular_velocity = car.velocity / turning_radius;
-        
-        // Rotate the car
-        let rotation = Quat::from_axis_angle(ground_plane.up, angular_velocity * dt);
-        transform.rotation = rotation * transform.rotation;
-    }
-    
-    // Move the car forward
-    let displacement = forward * car.velocity * dt;
-    transform.translation += displacement;
-    
-    // Project the car onto the ground plane
-    transform.translation = ground_plane.project_point(transform.translation);
-    
-    // Align the car's up vector with the ground plane normal
-    let target_up = ground_plane.normal;
-    let current_up = transform.up();
-    
-    if current_up.dot(target_up) < 0.999 {
-        // Smoothly align to ground plane
-        let align_rotation = Quat::from_rotation_arc(*current_up, target_up);
-        let smoothed_rotation = Quat::IDENTITY.slerp(align_rotation, 10.0 * dt);
-        transform.rotation = smoothed_rotation * transform.rotation;
+
+    for (mut car, transform, mut linear_velocity, mut angular_velocity, primary) in
+        car_query.iter_mut()
+    {
+        if car.halted {
+            car.halted = false;
+            linear_velocity.0 = Vec3::ZERO;
+            angular_velocity.0 = Vec3::ZERO;
+        }
+
+        car.wheel_slip = [0.0; 4];
+        car.wheel_spin = [0.0; 4];
+        car.wheel_load = [0.0; 4];
+        let mass = car.mass;
+        let inertia = car.inertia();
+        let up = *transform.up();
+        let center_of_mass = transform.translation + up * car.center_of_mass_height;
+        let apply_impulse = |linear_velocity: &mut LinearVelocity,
+                             angular_velocity: &mut AngularVelocity,
+                             impulse: Vec3,
+                             point: Vec3| {
+            let torque = transform.rotation.inverse() * (point - center_of_mass).cross(impulse);
+            linear_velocity.0 += impulse / mass;
+            angular_velocity.0 += transform.rotation * (torque / inertia);
+        };
+
+        let impact_speed = -linear_velocity.dot(up);
+
+        // Spring and damper of each wheel, measured from its mount on the body
+        // down to the ground
+        let full_length = RIDE_HEIGHT + car.suspension_travel * 0.5;
+        let wheel_positions = car.wheel_positions();
+        let mut contacts = Vec::with_capacity(wheel_positions.len());
+        for (index, wheel) in wheel_positions.into_iter().enumerate() {
+            let mount = transform.translation + transform.rotation * wheel;
+            let length = ground.height_at(mount);
+            let compression = full_length - length;
+            car.wheel_compression[index] = compression.clamp(0.0, car.suspension_travel);
+            if compression <= 0.0 {
+                continue;
+            }
+
+            let bump_stop = (compression - car.suspension_travel).max(0.0) * BUMP_STOP_RATIO;
+            let point_velocity = linear_velocity.0 + angular_velocity.cross(mount - center_of_mass);
+            let force = car.spring_rate * (car.wheel_compression[index] + bump_stop)
+                - car.damping * point_velocity.dot(up);
+            let load = force.max(0.0);
+            apply_impulse(&mut linear_velocity, &mut angular_velocity, up * load * dt, mount);
+            car.wheel_load[index] = load;
+            contacts.push((index, mount - up * length, load));
+        }
+
+        // In the air the body flies on under gravity alone
+        if contacts.is_empty() {
+            car.airtime += dt;
+            continue;
+        }
+
+        // Touching down after a jump scrubs off speed, more the harder the car lands
+        if car.airtime > MIN_AIRTIME {
+            let loss = (impact_speed.max(0.0) * LANDING_SPEED_LOSS).min(MAX_LANDING_SPEED_LOSS);
+            car.velocity *= 1.0 - loss;
+            let horizontal = linear_velocity.reject_from_normalized(up);
+            linear_velocity.0 -= horizontal * loss;
+            if primary {
+                landings.write(CarLanded { impact_speed });
+            }
+        }
+        car.airtime = 0.0;
+
+        // Tire forces at each contact patch, from how much the wheel slips over
+        // the ground along and across its heading. The forces act at the ground,
+        // below the center of mass, so they pitch and roll the body on its
+        // springs and shift load between the wheels: onto the rear when
+        // accelerating, the front when braking and the outside when cornering.
+        let normal = ground.normal_at(transform.translation);
+        let forward = transform
+            .forward()
+            .reject_from_normalized(normal)
+            .normalize_or(*transform.forward());
+        let grip = car.current_grip();
+        let rolling_resistance = car.rolling_resistance * car.surface.rolling_resistance_factor();
+        let wheel_mass = car.mass / wheel_positions.len() as f32;
+        let static_load = wheel_mass * gravity.0.length();
+        let mut ground_speed = 0.0;
+        let mut driven_speed = 0.0;
+        let mut driven_contacts = 0;
+        for &(index, contact, load) in &contacts {
+            // The front wheels are steered
+            let heading = if index < 2 {
+                Quat::from_axis_angle(normal, car.steering) * forward
+            } else {
+                forward
+            };
+            let side = heading.cross(normal);
+            let contact_velocity =
+                linear_velocity.0 + angular_velocity.cross(contact - center_of_mass);
+            let rolling_speed = contact_velocity.dot(heading);
+            let sliding_speed = contact_velocity.dot(side);
+            ground_speed += rolling_speed / contacts.len() as f32;
+
+            // Driven wheels turn with the drivetrain and every wheel is braked;
+            // otherwise a wheel rolls freely with the ground
+            let wheel_speed = if car.drivetrain.drives(index) || car.brake > 0.0 {
+                driven_speed += rolling_speed;
+                driven_contacts += 1;
+                car.velocity
+            } else {
+                rolling_speed
+            };
+
+            // Tires grip less per newton the more heavily they are loaded
+            let load_factor =
+                (1.0 - car.load_sensitivity * (load / static_load.max(1e-3) - 1.0)).clamp(0.5, 1.5);
+            let peak_longitudinal = grip.longitudinal * load_factor * load;
+            let peak_lateral = grip.lateral * load_factor * load;
+
+            let reference_speed = rolling_speed.abs().max(MIN_SLIP_SPEED);
+            let slip_angle = (sliding_speed / reference_speed).atan();
+            let slip_ratio = (wheel_speed - rolling_speed) / reference_speed;
+            car.wheel_spin[index] =
+                slip_ratio / peak_slip(LONGITUDINAL_STIFFNESS, LONGITUDINAL_SHAPE);
+            car.wheel_slip[index] = Vec2::new(
+                car.wheel_spin[index],
+                slip_angle / peak_slip(LATERAL_STIFFNESS, LATERAL_SHAPE),
+            )
+            .length();
+            let mut longitudinal = peak_longitudinal
+                * magic_formula(slip_ratio, LONGITUDINAL_STIFFNESS, LONGITUDINAL_SHAPE)
+                - rolling_resistance * load * rolling_speed / rolling_speed.abs().max(0.5);
+            let mut lateral =
+                -peak_lateral * magic_formula(slip_angle, LATERAL_STIFFNESS, LATERAL_SHAPE);
+
+            // Friction ellipse: braking or accelerating hard leaves less grip for cornering
+            let usage = Vec2::new(
+                longitudinal / peak_longitudinal.max(1e-3),
+                lateral / peak_lateral.max(1e-3),
+            )
+            .length();
+            if usage > 1.0 {
+                longitudinal /= usage;
+                lateral /= usage;
+            }
+
+            // Never push harder than needed to stop the slip within this update
+            let longitudinal_limit =
+                wheel_mass * (wheel_speed - rolling_speed).abs().max(rolling_speed.abs()) / dt;
+            let lateral_limit = wheel_mass * sliding_speed.abs() / dt;
+            let force = heading * longitudinal.clamp(-longitudinal_limit, longitudinal_limit)
+                + side * lateral.clamp(-lateral_limit, lateral_limit);
+            apply_impulse(&mut linear_velocity, &mut angular_velocity, force * dt, contact);
+        }
+
+        // The body already feels gravity; its component along the slope also acts
+        // on the wheels, so the engine has to work against it uphill and the car
+        // rolls away downhill
+        car.velocity += gravity.0.dot(forward) * dt;
+
+        // The driven wheels spin up or down toward the speed of the ground under
+        // them; with none of them touching it they spin freely
+        if driven_contacts > 0 {
+            let driven_speed = driven_speed / driven_contacts as f32;
+            car.velocity += (driven_speed - car.velocity) * (WHEEL_SPIN_RELAX * dt).min(1.0);
+        }
+
+        // At walking pace, yaw with Ackermann-like steering: turning radius depends
+        // on wheelbase and steering angle
+        if ground_speed.abs() < MIN_SLIP_SPEED && car.steering.abs() > 0.001 {
+            let yaw_rate = ground_speed * car.steering.tan() / car.wheelbase;
+            let current_yaw_rate = angular_velocity.dot(up);
+            angular_velocity.0 += up * (yaw_rate - current_yaw_rate);
+        }
     }
-    
-    // Keep car slightly above the ground
-    transform.translation += ground_plane.normal * 0.5;
 }
 
-/// Update camera to follow the car
-fn update_camera_follow(
-    car_query: Query<&Transform, (With<Car>, Without<Camera3d>)>,
-    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+/// Move the wheel meshes up and down with their suspension, spin them with
+/// the wheel speed and turn the front ones with the steering
+fn position_wheels(
+    car_query: Query<&Car>,
+    parent_query: Query<&ChildOf>,
+    mut wheel_query: Query<(Entity, &mut CarWheel, &mut Transform)>,
     time: Res<Time>,
 ) {
-    let Ok(car_transform) = car_query.single() else {
-        return;
-    };
-    let Ok(mut camera_transform) = camera_query.single_mut() else {
-        return;
-    };
+    for (entity, mut wheel, mut transform) in wheel_query.iter_mut() {
+        // Wheels hang under their car, directly or inside its model
+        let Some(car) = parent_query
+            .iter_ancestors(entity)
+            .find_map(|ancestor| car_query.get(ancestor).ok())
+        else {
+            continue;
+        };
+        // Rolling toward -Z turns the top of the wheel forward, a negative
+        // rotation around the axle
+        let spin_delta = -car.velocity / car.wheel_radius * time.delta_secs();
+        wheel.spin = (wheel.spin + spin_delta) % std::f32::consts::TAU;
+        let steering = if wheel.index < 2 { car.steering } else { 0.0 };
+        transform.translation.y = wheel.rest.translation.y
+            + car.wheel_compression[wheel.index]
+            - car.suspension_travel * 0.5;
+        transform.rotation = Quat::from_rotation_y(steering)
+            * Quat::from_rotation_x(wheel.spin)
+            * wheel.rest.rotation;
+    }
+}
 
+/// Throw up tire smoke from the rear wheels when they spin or slide, and dust
+/// when driving off the tarmac
+fn emit_tire_particles(
+    car_query: Query<(Entity, &Car, &Transform, &LinearVelocity)>,
+    time: Res<Time>,
+    mut particles: MessageWriter<SpawnParticle>,
+    mut pending_by_car: Local<HashMap<Entity, [f32; 2]>>,
+) {
     let dt = time.delta_secs();
-    let smoothness = 5.0;
-    
-    // Calculate target camera position (behind and above the car)
-    let offset = Vec3::new(0.0, 5.0, 12.0);
-    let target_position = car_transform.translation 
-        + car_transform.back() * offset.z 
-        + car_transform.up() * offset.y;
-    
-    // Smoothly interpolate camera position
-    camera_transform.translation = camera_transform.translation.lerp(
-        target_position,
-        smoothness * dt,
-    );
-    
-    // Look at a point slightly ahead of the car
-    let look_target = car_transform.translation + car_transform.forward() * 5.0;
-    camera_transform.look_at(look_target, Vec3::Y);
+    for (entity, car, transform, velocity) in car_query.iter() {
+        let pending = pending_by_car.entry(entity).or_default();
+        let speed = velocity.length();
+        let off_road = if car.surface == Surface::Asphalt {
+            0.0
+        } else {
+            (speed / car.max_speed).min(1.0)
+        };
+
+        let wheel_positions = car.wheel_positions();
+        for (rear, pending) in pending.iter_mut().enumerate() {
+            let index = 2 + rear;
+            if car.wheel_compression[index] <= 0.0 {
+                *pending = 0.0;
+                continue;
+            }
+            let sliding = (car.wheel_slip[index] - 1.0).clamp(0.0, 1.0);
+            *pending += TIRE_PARTICLE_RATE * sliding.max(off_road) * dt;
+
+            let contact = transform.translation + transform.rotation * wheel_positions[index]
+                - transform.up() * RIDE_HEIGHT;
+            while *pending >= 1.0 {
+                *pending -= 1.0;
+                particles.write(SpawnParticle {
+                    position: contact + jitter() * 0.2,
+                    // Left behind the car, drifting up and out
+                    velocity: velocity.0 * 0.3 + Vec3::Y * 0.8 + jitter(),
+                    color: car.surface.dust_color(),
+                    size: 0.25,
+                    lifetime: 1.2,
+                    emissive: false,
+                });
+            }
+        }
+    }
 }
@@ -0,0 +1,161 @@
+//! Headlights and brake lights
+//!
+//! Every car gets a pair of headlamps at the front of its body that cast
+//! spotlights down the road, so dark splat scans can be driven at night, and a
+//! pair of tail lamps that glow red while braking. Press 'H' (rebindable) to
+//! switch the headlights on and off. The lamps are part of the car's
+//! hierarchy, rebuilt with the rest of the body whenever the vehicle changes.
+
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+
+/// Luminous power of each headlight, in lumens
+const HEADLIGHT_INTENSITY: f32 = 400_000.0;
+
+/// How far the headlights reach, in meters
+const HEADLIGHT_RANGE: f32 = 60.0;
+
+/// How far the headlights are aimed down from horizontal, in radians
+const HEADLIGHT_DIP: f32 = 0.06;
+
+/// Size of a lamp lens
+const LAMP_SIZE: Vec3 = Vec3::new(0.3, 0.12, 0.05);
+
+/// Tail lamp glow with the lights on but not braking, and while braking
+const TAIL_LIGHT_GLOW: f32 = 2.0;
+const BRAKE_LIGHT_GLOW: f32 = 20.0;
+
+/// Plugin for the car's headlights and brake lights
+pub struct CarLightsPlugin;
+
+impl Plugin for CarLightsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CarLights>().add_systems(
+            Update,
+            (toggle_headlights.in_set(CarSet::Input), update_car_lights.after(CarSet::Input)),
+        );
+    }
+}
+
+/// Resource with the state of the car's lights
+#[derive(Resource, Default)]
+pub struct CarLights {
+    /// Whether the headlights are on
+    pub headlights: bool,
+}
+
+/// Component on a headlamp lens, which also casts the headlight beam
+#[derive(Component)]
+struct Headlight;
+
+/// Component on a tail lamp lens
+#[derive(Component)]
+struct BrakeLight;
+
+/// Add the lamps to a car's body of the given size
+pub fn spawn_car_lights(
+    entity_commands: &mut EntityCommands,
+    body_size: Vec3,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let lens_mesh = meshes.add(Cuboid::from_size(LAMP_SIZE));
+    let head_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.9, 0.9, 0.85),
+        ..default()
+    });
+    let tail_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.5, 0.0, 0.0),
+        ..default()
+    });
+
+    // Lamps sit in the upper half of the body's front and back, near the corners
+    let x = (body_size.x * 0.5 - LAMP_SIZE.x).max(0.0);
+    let y = body_size.y * 0.7;
+    let z = body_size.z * 0.5 + LAMP_SIZE.z * 0.5;
+    entity_commands.with_children(|parent| {
+        for side in [-1.0, 1.0] {
+            parent.spawn((
+                Headlight,
+                Mesh3d(lens_mesh.clone()),
+                MeshMaterial3d(head_material.clone()),
+                SpotLight {
+                    color: Color::srgb(1.0, 0.95, 0.85),
+                    intensity: 0.0,
+                    range: HEADLIGHT_RANGE,
+                    outer_angle: 0.5,
+                    inner_angle: 0.3,
+                    ..default()
+                },
+                Transform::from_xyz(side * x, y, -z)
+                    .with_rotation(Quat::from_rotation_x(-HEADLIGHT_DIP)),
+            ));
+            parent.spawn((
+                BrakeLight,
+                Mesh3d(lens_mesh.clone()),
+                MeshMaterial3d(tail_material.clone()),
+                Transform::from_xyz(side * x, y, z),
+            ));
+        }
+    });
+}
+
+/// Switch the headlights on and off
+fn toggle_headlights(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut lights: ResMut<CarLights>,
+) {
+    if input_map.just_pressed(Action::Headlights, &keyboard) {
+        lights.headlights = !lights.headlights;
+        info!("Headlights {}", if lights.headlights { "ON" } else { "OFF" });
+    }
+}
+
+/// Light the lamps: the headlights as switched, and the tail lamps brighter while braking
+fn update_car_lights(
+    lights: Res<CarLights>,
+    car_query: Query<&Car, With<PrimaryCar>>,
+    mut headlight_query: Query<(&mut SpotLight, &MeshMaterial3d<StandardMaterial>), With<Headlight>>,
+    brake_light_query: Query<&MeshMaterial3d<StandardMaterial>, With<BrakeLight>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+
+    let head_glow = if lights.headlights { 10.0 } else { 0.0 };
+    for (mut spot, material) in headlight_query.iter_mut() {
+        let intensity = if lights.headlights { HEADLIGHT_INTENSITY } else { 0.0 };
+        if spot.intensity != intensity {
+            spot.intensity = intensity;
+        }
+        set_emissive(&mut materials, &material.0, LinearRgba::rgb(1.0, 0.95, 0.85) * head_glow);
+    }
+
+    let tail_glow = if car.brake > 0.0 {
+        BRAKE_LIGHT_GLOW * car.brake.max(0.3)
+    } else if lights.headlights {
+        TAIL_LIGHT_GLOW
+    } else {
+        0.0
+    };
+    for material in brake_light_query.iter() {
+        set_emissive(&mut materials, &material.0, LinearRgba::rgb(1.0, 0.02, 0.0) * tail_glow);
+    }
+}
+
+/// Change a lamp's glow, leaving the material untouched if it is already right
+fn set_emissive(
+    materials: &mut Assets<StandardMaterial>,
+    handle: &Handle<StandardMaterial>,
+    emissive: LinearRgba,
+) {
+    if materials.get(handle).is_some_and(|material| material.emissive != emissive) {
+        if let Some(material) = materials.get_mut(handle) {
+            material.emissive = emissive;
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Command line
+//!
+//! `gaussrace --help` lists the arguments. Paths given without a flag are
+//! opened by their extension, like dropped files: splats, scene manifests and
+//! track files. The arguments are parsed once, on first use, and any module
+//! can read them with `args()`.
+//!
+//! ```text
+//! gaussrace --splat scan.ply --plane 0,0,0,0,1,0 --vehicle Rally
+//! gaussrace --scene garden.scene.json --benchmark=60 --fullscreen
+//! gaussrace --headless --host=7777 --config server.toml
+//! ```
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use crate::ground_plane::GroundPlane;
+
+/// Seconds the benchmark runs for when `--benchmark` gives no duration
+const DEFAULT_BENCHMARK_SECONDS: &str = "30";
+
+/// The command line arguments
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct CliArgs {
+    /// Splat, scene manifest or track files to open, told apart by their
+    /// extension
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<String>,
+
+    /// Splat file to open; further ones are composed into the first
+    #[arg(long = "splat", value_name = "PATH")]
+    pub splats: Vec<String>,
+
+    /// Scene manifest to open, with its own splats
+    #[arg(long, value_name = "PATH")]
+    pub scene: Option<String>,
+
+    /// Ground plane through a point with a normal; repeat for more planes.
+    /// Replaces the planes saved with the splat
+    #[arg(
+        long = "plane",
+        value_name = "X,Y,Z,NX,NY,NZ",
+        value_parser = parse_plane,
+        allow_hyphen_values = true
+    )]
+    pub planes: Vec<GroundPlane>,
+
+    /// Vehicle to drive, by name, instead of picking one in the vehicle menu
+    #[arg(long, value_name = "NAME")]
+    pub vehicle: Option<String>,
+
+    /// Measure the frame rate for this many seconds once the scene has
+    /// loaded, log the results and quit
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_BENCHMARK_SECONDS
+    )]
+    pub benchmark: Option<f32>,
+
+    /// Run without a window, e.g. to host a race on a server
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Configuration file to read instead of gaussrace.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Rotate Z-up scans upright
+    #[arg(long)]
+    pub z_up: bool,
+
+    /// Detect the up axis of the scans
+    #[arg(long, conflicts_with = "z_up")]
+    pub auto_up: bool,
+
+    /// Rescale the scan so its largest horizontal extent is this size
+    #[arg(long, value_name = "METERS")]
+    pub fit: Option<f32>,
+
+    /// Keep the scan where it is instead of centering it on the origin
+    #[arg(long)]
+    pub no_recenter: bool,
+
+    /// Host a race on this UDP port
+    #[arg(long, value_name = "PORT")]
+    pub host: Option<String>,
+
+    /// Join the race hosted at this address
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "host")]
+    pub join: Option<String>,
+
+    /// Share lap times on the leaderboard server at this URL
+    #[arg(long, value_name = "URL")]
+    pub leaderboard: Option<String>,
+
+    /// Name to submit lap times under
+    #[arg(long, value_name = "NAME")]
+    pub name: Option<String>,
+}
+
+impl CliArgs {
+    /// Whether the command line names anything to open
+    pub fn opens_anything(&self) -> bool {
+        !self.paths.is_empty() || !self.splats.is_empty() || self.scene.is_some()
+    }
+}
+
+/// The command line arguments, parsed on first use
+///
+/// Prints the help or the error and quits on `--help` and on arguments that
+/// can't be parsed.
+pub fn args() -> &'static CliArgs {
+    static ARGS: OnceLock<CliArgs> = OnceLock::new();
+    ARGS.get_or_init(CliArgs::parse)
+}
+
+/// Parse a ground plane given as a point and a normal
+fn parse_plane(value: &str) -> Result<GroundPlane, String> {
+    let numbers = value
+        .split(',')
+        .map(|number| {
+            number
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("{} is not a number", number))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [x, y, z, nx, ny, nz] = numbers[..] else {
+        return Err("expected six numbers, the point and the normal".to_string());
+    };
+    let normal = Vec3::new(nx, ny, nz)
+        .try_normalize()
+        .ok_or_else(|| "the normal can't be zero".to_string())?;
+    Ok(GroundPlane {
+        origin: Vec3::new(x, y, z),
+        normal,
+        up: normal,
+        extent: None,
+    })
+}
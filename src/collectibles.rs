@@ -0,0 +1,472 @@
+//! Collectibles and their editor
+//!
+//! Coins placed on the ground of the scan make any scene playable without
+//! laying out a track. In the Collectibles game mode the first player gathers
+//! them against the clock by driving through them: the clock starts when the
+//! car first moves and stops at the last coin, and the best time of the
+//! session is kept. Restarting the race puts every coin back.
+//!
+//! Press Insert to edit the coins:
+//!
+//! | Input             | Action                                            |
+//! |-------------------|---------------------------------------------------|
+//! | click             | place a coin on the ground                        |
+//! | .                 | scatter `SCATTER_COUNT` coins around the car      |
+//! | Backspace         | remove the coin under the cursor                  |
+//! | Ctrl+Z            | remove the last coin placed                       |
+//! | Shift+Backspace   | remove every coin                                 |
+//!
+//! Scattered coins stay inside the drivable area and the splat bounds. Ctrl+S
+//! saves the coins into the open scene manifest and Ctrl+Shift+S into the
+//! track file.
+
+use bevy::prelude::*;
+
+use crate::boundary::DrivableArea;
+use crate::camera::CarCamera;
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::particles::jitter;
+use crate::point_cloud::SplatPointCloud;
+use crate::race_start::{RacePhase, RaceStart, RestartRace};
+use crate::splat_loader::SplatBounds;
+use crate::terrain::GroundSurface;
+use crate::track::format_lap_time;
+
+/// Height of a coin's center above the ground, in meters
+const COIN_HEIGHT: f32 = 1.0;
+
+/// Radius and thickness of a coin
+const COIN_RADIUS: f32 = 0.6;
+const COIN_THICKNESS: f32 = 0.12;
+
+/// Turns per second of a spinning coin
+const SPIN_RATE: f32 = 0.5;
+
+/// Distance from the car at which a coin is picked up
+const PICKUP_RADIUS: f32 = 2.5;
+
+/// Speed at which the car has moved off and the clock starts, in m/s
+const START_SPEED: f32 = 1.0;
+
+/// Coins placed at a time by scattering, how far from the car and how close
+/// to each other at most
+const SCATTER_COUNT: usize = 10;
+const SCATTER_RADIUS: f32 = 50.0;
+const MIN_SPACING: f32 = 4.0;
+
+/// Distance from the cursor within which Backspace removes a coin
+const REMOVE_RADIUS: f32 = 2.0;
+
+/// Plugin for the collectibles, their editor and the Collectibles mode
+pub struct CollectiblesPlugin;
+
+impl Plugin for CollectiblesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SceneCollectibles>()
+            .init_resource::<CollectibleEditor>()
+            .init_resource::<CollectRun>()
+            .add_systems(Startup, spawn_collect_panel)
+            .add_systems(
+                Update,
+                (
+                    edit_collectibles,
+                    draw_collectible_editor,
+                    rebuild_coins,
+                    reset_collect_run,
+                    collect_coins
+                        .run_if(resource_equals(GameMode::Collect))
+                        .after(CarSet::Camera),
+                    animate_coins,
+                    update_collect_panel,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Resource with the collectibles of the current scene, as points on the ground
+#[derive(Resource, Default)]
+pub struct SceneCollectibles(pub Vec<Vec3>);
+
+/// Resource tracking the collectibles editor
+#[derive(Resource, Default)]
+pub struct CollectibleEditor {
+    /// Whether the editor is active
+    pub active: bool,
+}
+
+/// Resource with the first player's run in the Collectibles mode
+#[derive(Resource, Default)]
+pub struct CollectRun {
+    /// Whether each coin has been picked up, in the order of `SceneCollectibles`
+    collected: Vec<bool>,
+    /// Seconds since the car moved off, `None` before it has
+    time: Option<f32>,
+    /// Time of the run once every coin is picked up
+    finished: Option<f32>,
+    /// Fastest run of the session
+    pub best: Option<f32>,
+    /// Whether the last run beat the previous best
+    new_best: bool,
+}
+
+impl CollectRun {
+    fn count(&self) -> usize {
+        self.collected
+            .iter()
+            .filter(|&&collected| collected)
+            .count()
+    }
+
+    /// Put every coin back for a new run, keeping the best time
+    fn restart(&mut self, coins: usize) {
+        *self = CollectRun {
+            collected: vec![false; coins],
+            best: self.best,
+            ..default()
+        };
+    }
+}
+
+/// Component for a coin, with its index in `SceneCollectibles`
+#[derive(Component)]
+struct Coin(usize);
+
+/// Marker for the collectibles panel
+#[derive(Component)]
+struct CollectPanel;
+
+/// Marker for the text of the collectibles panel
+#[derive(Component)]
+struct CollectText;
+
+/// Center of a coin standing on the ground at a point
+fn coin_center(ground: &GroundSurface, coin: Vec3) -> Vec3 {
+    coin + ground.normal_at(coin) * COIN_HEIGHT
+}
+
+/// Index of the coin closest to a point, if within `radius` of it
+fn coin_near(coins: &[Vec3], point: Vec3, radius: f32) -> Option<usize> {
+    coins
+        .iter()
+        .enumerate()
+        .map(|(index, coin)| (index, coin.distance(point)))
+        .filter(|&(_, distance)| distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+}
+
+/// Toggle the editor with Insert, place coins by clicking, scatter them around
+/// the car and remove them
+fn edit_collectibles(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut editor: ResMut<CollectibleEditor>,
+    mut coins: ResMut<SceneCollectibles>,
+    area: Res<DrivableArea>,
+    bounds: Option<Res<SplatBounds>>,
+    ground: GroundSurface,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    car_query: Query<&Transform, With<PrimaryCar>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+) {
+    if keyboard.just_pressed(KeyCode::Insert) {
+        editor.active = !editor.active;
+        if editor.active {
+            info!("Collectibles editor ACTIVE - click to place a coin, '.' to scatter some");
+            info!("Backspace: remove the coin under the cursor, Ctrl+Z: remove the last one");
+        } else {
+            info!("Collectibles editor INACTIVE - {} coin(s)", coins.0.len());
+            info!("Press Ctrl+S to save them into the scene");
+        }
+    }
+    if !editor.active {
+        return;
+    }
+
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if ctrl && keyboard.just_pressed(KeyCode::KeyZ) {
+        coins.0.pop();
+    }
+
+    let cursor_point = cursor_ray(&camera_query, &windows)
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes));
+
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        if shift {
+            coins.0.clear();
+            info!("Removed every coin");
+        } else if let Some(index) =
+            cursor_point.and_then(|point| coin_near(&coins.0, point, REMOVE_RADIUS))
+        {
+            coins.0.remove(index);
+        }
+    }
+
+    if mouse_button.just_pressed(MouseButton::Left) {
+        if let Some(point) = cursor_point {
+            coins.0.push(point);
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Period) {
+        let Ok(car) = car_query.single() else {
+            return;
+        };
+        let in_bounds = |point: Vec3| {
+            bounds.as_ref().is_none_or(|bounds| {
+                point.xz().cmpge(bounds.min.xz()).all() && point.xz().cmple(bounds.max.xz()).all()
+            })
+        };
+        let mut placed = 0;
+        // Random points in a disc around the car, dropped onto the ground
+        for _ in 0..SCATTER_COUNT * 20 {
+            if placed == SCATTER_COUNT {
+                break;
+            }
+            let offset = jitter().xz() * SCATTER_RADIUS;
+            if offset.length() > SCATTER_RADIUS {
+                continue;
+            }
+            let point = ground.project_point(car.translation + Vec3::new(offset.x, 0.0, offset.y));
+            let spaced = coin_near(&coins.0, point, MIN_SPACING).is_none();
+            if spaced && area.contains(point) && in_bounds(point) {
+                coins.0.push(point);
+                placed += 1;
+            }
+        }
+        info!("Scattered {} coin(s) - {} in total", placed, coins.0.len());
+    }
+}
+
+/// Ring the coins while editing them
+fn draw_collectible_editor(
+    mut gizmos: Gizmos,
+    editor: Res<CollectibleEditor>,
+    coins: Res<SceneCollectibles>,
+) {
+    if !editor.active {
+        return;
+    }
+    let color = Color::srgb(1.0, 0.85, 0.1);
+    for &coin in &coins.0 {
+        let isometry = Isometry3d::new(coin, Quat::from_rotation_x(std::f32::consts::FRAC_PI_2));
+        gizmos.circle(isometry, REMOVE_RADIUS, color);
+        gizmos.line(coin, coin + Vec3::Y * COIN_HEIGHT, color);
+    }
+}
+
+/// Spawn a coin for each collectible whenever they change
+fn rebuild_coins(
+    mut commands: Commands,
+    coins: Res<SceneCollectibles>,
+    ground: GroundSurface,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    coin_query: Query<Entity, With<Coin>>,
+) {
+    if !coins.is_changed() {
+        return;
+    }
+    for entity in coin_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let mesh = meshes.add(Cylinder::new(COIN_RADIUS, COIN_THICKNESS));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgb(1.0, 0.8, 0.2),
+        emissive: LinearRgba::rgb(0.6, 0.4, 0.05),
+        metallic: 0.9,
+        perceptual_roughness: 0.3,
+        ..default()
+    });
+    for (index, &coin) in coins.0.iter().enumerate() {
+        let center = coin_center(&ground, coin);
+        commands.spawn((
+            Coin(index),
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            // Standing on its edge, to spin around the vertical
+            Transform::from_translation(center)
+                .with_rotation(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)),
+            Visibility::Hidden,
+        ));
+    }
+}
+
+/// Put the coins back when they change, the race restarts or the mode changes
+fn reset_collect_run(
+    coins: Res<SceneCollectibles>,
+    mode: Res<GameMode>,
+    race: Res<RaceStart>,
+    mut restarts: MessageReader<RestartRace>,
+    mut run: ResMut<CollectRun>,
+) {
+    let restarted = restarts.read().count() > 0;
+    let countdown = race.is_changed() && matches!(race.phase, RacePhase::Countdown { .. });
+    // Best times only compare runs over the same coins
+    if coins.is_changed() {
+        run.best = None;
+    }
+    if coins.is_changed() || restarted || countdown || mode.is_changed() {
+        run.restart(coins.0.len());
+    }
+}
+
+/// Pick up the coins the first player drives through and time the run
+fn collect_coins(
+    time: Res<Time>,
+    coins: Res<SceneCollectibles>,
+    editor: Res<CollectibleEditor>,
+    race: Res<RaceStart>,
+    ground: GroundSurface,
+    mut run: ResMut<CollectRun>,
+    car_query: Query<(&Car, &Transform), With<PrimaryCar>>,
+) {
+    if editor.active || run.finished.is_some() || coins.0.is_empty() {
+        return;
+    }
+    let Ok((car, transform)) = car_query.single() else {
+        return;
+    };
+    let held = matches!(race.phase, RacePhase::Countdown { .. });
+    match run.time.as_mut() {
+        Some(seconds) => *seconds += time.delta_secs(),
+        None if !held && car.velocity.abs() > START_SPEED => {
+            run.time = Some(0.0);
+            info!("Clock started - collect all {} coins", coins.0.len());
+        }
+        None => return,
+    }
+
+    let car_position = transform.translation;
+    let mut picked = false;
+    for (index, &coin) in coins.0.iter().enumerate() {
+        let center = coin_center(&ground, coin);
+        let collected = run.collected.get(index).copied().unwrap_or(true);
+        if !collected && center.distance(car_position) <= PICKUP_RADIUS {
+            run.collected[index] = true;
+            picked = true;
+        }
+    }
+    if !picked || run.count() < coins.0.len() {
+        return;
+    }
+
+    let seconds = run.time.unwrap_or(0.0);
+    run.new_best = run.best.is_none_or(|best| seconds < best);
+    if run.new_best {
+        run.best = Some(seconds);
+    }
+    run.finished = Some(seconds);
+    info!(
+        "All {} coins collected - {}{}",
+        coins.0.len(),
+        format_lap_time(seconds),
+        if run.new_best { " (best)" } else { "" }
+    );
+}
+
+/// Spin the coins, showing those still to be collected in the Collectibles
+/// mode and all of them while editing
+fn animate_coins(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    editor: Res<CollectibleEditor>,
+    run: Res<CollectRun>,
+    mut coin_query: Query<(&Coin, &mut Transform, &mut Visibility)>,
+) {
+    let spin = Quat::from_rotation_y(time.delta_secs() * SPIN_RATE * std::f32::consts::TAU);
+    for (coin, mut transform, mut visibility) in coin_query.iter_mut() {
+        let collected = run.collected.get(coin.0).copied().unwrap_or(false);
+        let shown = editor.active || (*mode == GameMode::Collect && !collected);
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+        if shown {
+            transform.rotation = spin * transform.rotation;
+        }
+    }
+}
+
+/// Spawn the hidden collectibles panel near the top of the screen
+fn spawn_collect_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(20.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            CollectPanel,
+        ))
+        .with_child((
+            Text::new(""),
+            TextFont {
+                font_size: 26.0,
+                ..default()
+            },
+            TextColor(Color::srgb(1.0, 0.85, 0.2)),
+            TextShadow::default(),
+            TextLayout::new_with_justify(Justify::Center),
+            CollectText,
+        ));
+}
+
+/// Show the coins collected, the clock and the best run in the Collectibles mode
+fn update_collect_panel(
+    mode: Res<GameMode>,
+    coins: Res<SceneCollectibles>,
+    run: Res<CollectRun>,
+    mut panel_query: Query<&mut Visibility, With<CollectPanel>>,
+    mut text_query: Query<&mut Text, With<CollectText>>,
+) {
+    let Ok(mut visibility) = panel_query.single_mut() else {
+        return;
+    };
+    let shown = *mode == GameMode::Collect;
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    let Ok(mut text) = text_query.single_mut() else {
+        return;
+    };
+    if !shown || (!run.is_changed() && !mode.is_changed() && !coins.is_changed()) {
+        return;
+    }
+
+    let best = run.best.map_or("-".to_string(), format_lap_time);
+    text.0 = if coins.0.is_empty() {
+        "No coins in this scene - press Insert to place some".to_string()
+    } else if let Some(seconds) = run.finished {
+        format!(
+            "ALL COINS COLLECTED\n{}{}\nRestart the race to go again",
+            format_lap_time(seconds),
+            if run.new_best { "  New best!" } else { "" }
+        )
+    } else {
+        let clock = match run.time {
+            Some(seconds) => format_lap_time(seconds),
+            None => "Drive off to start the clock".to_string(),
+        };
+        format!(
+            "Coins {}/{}   {}\nBest {}",
+            run.count(),
+            coins.0.len(),
+            clock,
+            best
+        )
+    };
+}
@@ -0,0 +1,270 @@
+//! Hand-placed collision proxies
+//!
+//! Automatic density collision is noisy around foliage and glass, so level
+//! authors can place invisible box and capsule colliders instead. Press 'C' to
+//! enter the editor:
+//!
+//! | Input            | Action                                   |
+//! |------------------|------------------------------------------|
+//! | 1 / 2            | add a box / capsule under the cursor     |
+//! | LMB click        | select the proxy under the cursor        |
+//! | LMB drag         | move horizontally (Shift: vertically)    |
+//! | RMB drag         | rotate around the vertical axis          |
+//! | wheel            | scale (hold X, Y or Z for a single axis) |
+//! | Tab              | select the next proxy                    |
+//! | Backspace        | delete the selected proxy                |
+//!
+//! Proxies are static colliders in the physics world, saved with the splat
+//! sidecar or scene manifest.
+
+use avian3d::prelude::*;
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CarCamera;
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::point_cloud::SplatPointCloud;
+
+/// Bounciness of the proxies when the car drives into them
+const RESTITUTION: f32 = 0.3;
+
+/// Plugin for collision proxies and their editor
+pub struct CollisionProxyPlugin;
+
+impl Plugin for CollisionProxyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionProxies>()
+            .init_resource::<ProxyEditor>()
+            .add_systems(
+                Update,
+                (edit_collision_proxies, sync_proxy_colliders, draw_collision_proxies).chain(),
+            );
+    }
+}
+
+/// Shape of a collision proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProxyShape {
+    /// Box filling the unit cube scaled by the transform
+    Box,
+    /// Upright capsule with the diameter `scale.x` and total height `scale.y`
+    Capsule,
+}
+
+/// An invisible collider placed in the splat
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CollisionProxy {
+    pub shape: ProxyShape,
+    pub transform: Transform,
+}
+
+impl CollisionProxy {
+    /// Physics collider with the proxy's size, to be placed at its translation and rotation
+    fn collider(&self) -> Collider {
+        let size = self.transform.scale;
+        match self.shape {
+            ProxyShape::Box => Collider::cuboid(size.x, size.y, size.z),
+            ProxyShape::Capsule => {
+                let capsule = self.capsule();
+                Collider::capsule(capsule.radius, capsule.half_length * 2.0)
+            }
+        }
+    }
+
+    fn capsule(&self) -> Capsule3d {
+        let radius = self.transform.scale.x * 0.5;
+        Capsule3d::new(radius, (self.transform.scale.y - 2.0 * radius).max(0.0))
+    }
+}
+
+/// Resource with all collision proxies of the level
+#[derive(Resource, Default)]
+pub struct CollisionProxies(pub Vec<CollisionProxy>);
+
+/// Resource tracking the proxy editor
+#[derive(Resource, Default)]
+pub struct ProxyEditor {
+    /// Whether the editor is active
+    pub active: bool,
+    /// Index of the selected proxy
+    pub selected: Option<usize>,
+}
+
+/// Place, select, move, rotate, scale and delete proxies
+fn edit_collision_proxies(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut editor: ResMut<ProxyEditor>,
+    mut proxies: ResMut<CollisionProxies>,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyC) {
+        editor.active = !editor.active;
+        if editor.active {
+            info!("Collision proxy editor ACTIVE - 1: add box, 2: add capsule, click: select");
+            info!("LMB drag: move (Shift: vertical), RMB drag: rotate, wheel: scale (X/Y/Z: one axis)");
+            info!("Tab: next proxy, Backspace: delete");
+        } else {
+            info!("Collision proxy editor INACTIVE");
+        }
+    }
+    if !editor.active {
+        return;
+    }
+
+    let ray = cursor_ray(&camera_query, &windows);
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+
+    // Add a new proxy standing on the ground under the cursor
+    for (key, shape, scale) in [
+        (KeyCode::Digit1, ProxyShape::Box, Vec3::splat(2.0)),
+        (KeyCode::Digit2, ProxyShape::Capsule, Vec3::new(1.0, 2.0, 1.0)),
+    ] {
+        if !keyboard.just_pressed(key) {
+            continue;
+        }
+        let Some(point) = ray.and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes))
+        else {
+            continue;
+        };
+        proxies.0.push(CollisionProxy {
+            shape,
+            transform: Transform::from_translation(point + Vec3::Y * scale.y * 0.5)
+                .with_scale(scale),
+        });
+        editor.selected = Some(proxies.0.len() - 1);
+        info!("Added {:?} proxy {}", shape, proxies.0.len());
+    }
+
+    if keyboard.just_pressed(KeyCode::Tab) && !proxies.0.is_empty() {
+        let next = editor.selected.map_or(0, |selected| (selected + 1) % proxies.0.len());
+        editor.selected = Some(next);
+    }
+
+    // Select the proxy whose bounding sphere is hit first by the cursor ray
+    if mouse_button.just_pressed(MouseButton::Left) {
+        if let Some(ray) = ray {
+            let hit = proxies
+                .0
+                .iter()
+                .enumerate()
+                .filter_map(|(index, proxy)| {
+                    let to_center = proxy.transform.translation - ray.origin;
+                    let t = to_center.dot(*ray.direction);
+                    let radius = proxy.transform.scale.max_element() * 0.5;
+                    let miss = (to_center - *ray.direction * t).length();
+                    (t > 0.0 && miss <= radius).then_some((index, t))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((index, _)) = hit {
+                editor.selected = Some(index);
+            }
+        }
+    }
+
+    let Some(index) = editor.selected.filter(|&index| index < proxies.0.len()) else {
+        editor.selected = None;
+        return;
+    };
+
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        proxies.0.remove(index);
+        editor.selected = None;
+        info!("Deleted proxy {}", index + 1);
+        return;
+    }
+    let proxy = &mut proxies.0[index];
+
+    let Ok((_, camera_transform)) = camera_query.single() else {
+        return;
+    };
+    let delta = mouse_motion.delta;
+    // Scale drag speed with distance so both near and far edits feel the same
+    let distance = camera_transform.translation().distance(proxy.transform.translation).max(1.0);
+    let move_speed = distance * 0.002;
+
+    if mouse_button.pressed(MouseButton::Left) && delta != Vec2::ZERO {
+        if shift {
+            proxy.transform.translation.y -= delta.y * move_speed;
+        } else {
+            let right = camera_transform.right().with_y(0.0).normalize_or_zero();
+            let forward = camera_transform.forward().with_y(0.0).normalize_or_zero();
+            proxy.transform.translation += (right * delta.x - forward * delta.y) * move_speed;
+        }
+    }
+
+    if mouse_button.pressed(MouseButton::Right) && delta.x != 0.0 {
+        proxy.transform.rotate_y(delta.x * 0.005);
+    }
+
+    if mouse_scroll.delta.y != 0.0 {
+        let factor = (1.0 + mouse_scroll.delta.y * 0.1).max(0.1);
+        // Holding X, Y or Z restricts scaling to those axes
+        let axes = [(KeyCode::KeyX, Vec3::X), (KeyCode::KeyY, Vec3::Y), (KeyCode::KeyZ, Vec3::Z)]
+            .into_iter()
+            .filter(|(key, _)| keyboard.pressed(*key))
+            .fold(Vec3::ZERO, |axes, (_, axis)| axes + axis);
+        let axes = if axes == Vec3::ZERO { Vec3::ONE } else { axes };
+        let scale = proxy.transform.scale * (Vec3::ONE + axes * (factor - 1.0));
+        proxy.transform.scale = scale.clamp(Vec3::splat(0.1), Vec3::splat(200.0));
+    }
+}
+
+/// Draw the proxies while the editor is active, highlighting the selected one
+fn draw_collision_proxies(
+    mut gizmos: Gizmos,
+    editor: Res<ProxyEditor>,
+    proxies: Res<CollisionProxies>,
+) {
+    if !editor.active {
+        return;
+    }
+    for (index, proxy) in proxies.0.iter().enumerate() {
+        let color = if editor.selected == Some(index) {
+            Color::srgb(1.0, 0.8, 0.0)
+        } else {
+            Color::srgb(0.0, 0.8, 1.0)
+        };
+        match proxy.shape {
+            ProxyShape::Box => gizmos.cuboid(proxy.transform, color),
+            ProxyShape::Capsule => {
+                let isometry = Isometry3d::new(proxy.transform.translation, proxy.transform.rotation);
+                gizmos.primitive_3d(&proxy.capsule(), isometry, color);
+            }
+        }
+    }
+}
+
+/// Marker for the static physics colliders built from the proxies
+#[derive(Component)]
+struct ProxyCollider;
+
+/// Rebuild the static colliders whenever the proxies change
+fn sync_proxy_colliders(
+    mut commands: Commands,
+    proxies: Res<CollisionProxies>,
+    collider_query: Query<Entity, With<ProxyCollider>>,
+) {
+    if !proxies.is_changed() {
+        return;
+    }
+    for entity in collider_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for proxy in &proxies.0 {
+        commands.spawn((
+            ProxyCollider,
+            RigidBody::Static,
+            proxy.collider(),
+            Restitution::new(RESTITUTION),
+            Transform::from_translation(proxy.transform.translation)
+                .with_rotation(proxy.transform.rotation),
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+//! Configuration file
+//!
+//! `gaussrace.toml` in the working directory, or the file given with
+//! `--config` on the command line, holds the tunables that would
+//! otherwise mean recompiling: the window, the chase camera, the car, the key
+//! bindings, the graphics and the ground. Every section and value is
+//! optional; whatever is left out keeps its built-in value. The file is
+//! checked for changes every second while the game runs and applied right
+//! away, so it can be edited next to the game window.
+//!
+//! ```toml
+//! [window]
+//! title = "GaussRace"
+//! resolution = [1920, 1080]
+//! fullscreen = false
+//! vsync = true
+//!
+//! [camera]
+//! offset = [0.0, 4.0, 10.0]
+//! smoothness = 6.0
+//! look_ahead = 5.0
+//!
+//! # Tunables the vehicle files leave out, as in their [car] table
+//! [car]
+//! max_speed = 45.0
+//!
+//! # Replaces the bindings of the listed actions
+//! [keys]
+//! Accelerate = ["KeyW", "ArrowUp"]
+//!
+//! # Any of the values in settings.json
+//! [graphics]
+//! splat_quality = "Medium"
+//! bloom = false
+//!
+//! [ground]
+//! step_tolerance = 0.5
+//! ```
+//!
+//! The file's values win over the ones changed in the game: the settings
+//! screen and the rebinding screen can still change them, until the section
+//! they are in changes in the file.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CarCamera;
+use crate::cli;
+use crate::ground_plane::GroundPlanes;
+use crate::input_map::{Action, InputMap};
+use crate::settings::Settings;
+use crate::vehicle::{SelectedVehicle, Vehicles, VEHICLES_DIR};
+
+/// File the configuration is read from, unless the command line names another
+const CONFIG_PATH: &str = "gaussrace.toml";
+
+/// How often the file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Title of the window unless the configuration names another
+pub const DEFAULT_TITLE: &str = "GaussRace - Gaussian Splat Racing";
+
+/// Window size in logical pixels unless the configuration gives another
+pub const DEFAULT_RESOLUTION: UVec2 = UVec2::new(1280, 720);
+
+/// Plugin reading the configuration file and applying it as it changes
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameConfig>()
+            .insert_resource(ConfigWatcher {
+                timer: Timer::new(WATCH_INTERVAL, TimerMode::Repeating),
+                modified: modified_time(),
+            })
+            .add_systems(
+                Update,
+                (watch_config, apply_config, configure_new_cameras).chain(),
+            );
+    }
+}
+
+/// Resource with the configuration as last read from the file
+#[derive(Resource, Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub window: WindowConfig,
+    pub camera: CameraConfig,
+    /// `Car` tunables for the ones a vehicle file leaves out
+    pub car: toml::Table,
+    pub keys: HashMap<Action, Vec<KeyCode>>,
+    /// Values of `Settings`, by field name
+    pub graphics: toml::Table,
+    pub ground: GroundConfig,
+}
+
+/// The `[window]` section; the values left out follow the settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Size in logical pixels, when not fullscreen
+    pub resolution: Option<UVec2>,
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<bool>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_TITLE.to_string(),
+            resolution: None,
+            fullscreen: None,
+            vsync: None,
+        }
+    }
+}
+
+/// The `[camera]` section, configuring the chase view (see `CarCamera`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub offset: Vec3,
+    pub smoothness: f32,
+    pub look_ahead: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        let camera = CarCamera::default();
+        Self {
+            offset: camera.offset,
+            smoothness: camera.smoothness,
+            look_ahead: camera.look_ahead,
+        }
+    }
+}
+
+impl CameraConfig {
+    fn apply(&self, camera: &mut CarCamera) {
+        camera.offset = self.offset;
+        camera.smoothness = self.smoothness;
+        camera.look_ahead = self.look_ahead;
+    }
+}
+
+/// The `[ground]` section
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GroundConfig {
+    /// How far above a point a ground plane may be and still count as the
+    /// ground under it
+    pub step_tolerance: f32,
+}
+
+impl Default for GroundConfig {
+    fn default() -> Self {
+        Self {
+            step_tolerance: GroundPlanes::default().step_tolerance,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Read the configuration file, using the defaults if it doesn't exist
+    pub fn load() -> Self {
+        if !config_path().exists() {
+            return Self::default();
+        }
+        match Self::read() {
+            Ok(config) => {
+                info!("Loaded configuration from {}", config_path().display());
+                config
+            }
+            Err(err) => {
+                let path = config_path().display();
+                warn!("Ignoring unreadable configuration {}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    fn read() -> Result<Self, String> {
+        let contents = std::fs::read_to_string(config_path()).map_err(|err| err.to_string())?;
+        toml::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    /// The settings with this configuration's window and graphics values
+    fn apply_to_settings(&self, settings: &Settings) -> Result<Settings, String> {
+        let mut settings = overlay(settings, &self.graphics)?;
+        if let Some(resolution) = self.window.resolution {
+            settings.resolution = resolution;
+        }
+        if let Some(fullscreen) = self.window.fullscreen {
+            settings.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = self.window.vsync {
+            settings.vsync = vsync;
+        }
+        Ok(settings)
+    }
+}
+
+/// The configuration file's path
+fn config_path() -> &'static Path {
+    cli::args()
+        .config
+        .as_deref()
+        .unwrap_or(Path::new(CONFIG_PATH))
+}
+
+/// A value with some of its fields replaced by the ones in a table
+fn overlay<T: Serialize + DeserializeOwned>(value: &T, table: &toml::Table) -> Result<T, String> {
+    let toml::Value::Table(mut merged) =
+        toml::Value::try_from(value).map_err(|err| err.to_string())?
+    else {
+        return Err("not a table".to_string());
+    };
+    merged.extend(table.clone());
+    toml::Value::Table(merged)
+        .try_into()
+        .map_err(|err: toml::de::Error| err.to_string())
+}
+
+/// Resource noticing changes to the configuration file
+#[derive(Resource)]
+struct ConfigWatcher {
+    timer: Timer,
+    /// Modification time of the file when it was last read
+    modified: Option<SystemTime>,
+}
+
+/// Modification time of the configuration file, if there is one
+fn modified_time() -> Option<SystemTime> {
+    std::fs::metadata(config_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Read the configuration again when the file changes, keeping the old one
+/// if the new one can't be read
+fn watch_config(
+    time: Res<Time<Real>>,
+    mut watcher: ResMut<ConfigWatcher>,
+    mut config: ResMut<GameConfig>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = modified_time();
+    if modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+    if modified.is_none() {
+        return;
+    }
+    match GameConfig::read() {
+        Ok(new) => {
+            info!("Reloaded configuration from {}", config_path().display());
+            config.set_if_neq(new);
+        }
+        Err(err) => warn!(
+            "Ignoring unreadable configuration {}: {}",
+            config_path().display(),
+            err
+        ),
+    }
+}
+
+/// Apply the sections of the configuration that changed since it was last
+/// applied
+fn apply_config(
+    config: Res<GameConfig>,
+    mut applied: Local<Option<GameConfig>>,
+    mut settings: ResMut<Settings>,
+    mut input_map: ResMut<InputMap>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    mut vehicles: ResMut<Vehicles>,
+    mut selected: ResMut<SelectedVehicle>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut CarCamera>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let previous = applied.replace(config.clone());
+    let first = previous.is_none();
+    let previous = previous.unwrap_or_default();
+
+    if first || previous.window != config.window || previous.graphics != config.graphics {
+        match config.apply_to_settings(&settings) {
+            Ok(updated) => {
+                settings.set_if_neq(updated);
+            }
+            Err(err) => warn!("Ignoring the graphics configuration: {}", err),
+        }
+        if let Ok(mut window) = window_query.single_mut() {
+            if window.title != config.window.title {
+                window.title = config.window.title.clone();
+            }
+        }
+    }
+    if first || previous.camera != config.camera {
+        for mut camera in camera_query.iter_mut() {
+            config.camera.apply(&mut camera);
+        }
+    }
+    if (first && !config.car.is_empty()) || (!first && previous.car != config.car) {
+        *vehicles = Vehicles::load(Path::new(VEHICLES_DIR), &config.car);
+        // Dress the car again with the new tunables
+        selected.set_changed();
+    }
+    if first || previous.keys != config.keys {
+        input_map.bindings.extend(config.keys.clone());
+    }
+    if (first || previous.ground != config.ground)
+        && ground_planes.step_tolerance != config.ground.step_tolerance
+    {
+        ground_planes.step_tolerance = config.ground.step_tolerance;
+    }
+}
+
+/// Give the configured chase view to cameras spawned later, like the second
+/// player's
+fn configure_new_cameras(
+    config: Res<GameConfig>,
+    mut camera_query: Query<&mut CarCamera, Added<CarCamera>>,
+) {
+    for mut camera in camera_query.iter_mut() {
+        config.camera.apply(&mut camera);
+    }
+}
@@ -0,0 +1,404 @@
+//! Developer console
+//!
+//! The backquote key (~) drops down a console across the top of the screen.
+//! A line typed into it is split at spaces and run as a command: the first
+//! word names the command and the rest are its arguments. Up and Down step
+//! through the lines entered before. The game ignores the keyboard while the
+//! console is open.
+//!
+//! Commands live in the `ConsoleCommands` registry, and any plugin can add
+//! its own with `add_console_command`. The built-in ones are:
+//!
+//! - `help` lists the commands
+//! - `clear` empties the console
+//! - `load <path> [add]` opens a splat, scene, track or ghost file, adding a
+//!   splat to the scene with `add`
+//! - `teleport <x> <y> <z>` puts the car at a position, standing still
+//! - `set car.<field> [value]` shows or changes one of the car's tunables
+//! - `timescale <factor>` speeds up or slows down the game
+//!
+//! There is no `spawn_ai` command, as the game has no AI drivers to spawn.
+
+use std::collections::BTreeMap;
+
+use avian3d::prelude::*;
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiGlobalSettings, EguiPlugin, EguiPrimaryContextPass};
+
+use crate::car::{Car, PrimaryCar};
+use crate::input_map::InputMap;
+use crate::splat_loader::FileOpener;
+
+/// Height of the console in pixels
+const CONSOLE_HEIGHT: f32 = 280.0;
+
+/// Most lines kept in the console
+const MAX_LOG_LINES: usize = 500;
+
+/// Slowest and fastest game speed `timescale` accepts
+const MIN_TIMESCALE: f64 = 0.05;
+const MAX_TIMESCALE: f64 = 10.0;
+
+/// Plugin for the developer console
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin::default());
+        }
+        // Keys typed into the console or the panels don't reach the game
+        if let Some(mut egui_settings) = app.world_mut().get_resource_mut::<EguiGlobalSettings>() {
+            egui_settings.enable_absorb_bevy_input_system = true;
+        }
+        app.init_resource::<Console>()
+            .add_console_command("help", "help", "List the commands", help_command)
+            .add_console_command("clear", "clear", "Empty the console", clear_command)
+            .add_console_command(
+                "load",
+                "load <path> [add]",
+                "Open a splat, scene, track or ghost file",
+                load_command,
+            )
+            .add_console_command(
+                "teleport",
+                "teleport <x> <y> <z>",
+                "Put the car at a position",
+                teleport_command,
+            )
+            .add_console_command(
+                "set",
+                "set car.<field> [value]",
+                "Show or change a tunable of the car",
+                set_command,
+            )
+            .add_console_command(
+                "timescale",
+                "timescale <factor>",
+                "Speed up or slow down the game",
+                timescale_command,
+            )
+            .add_systems(Update, (toggle_console, run_console_commands).chain())
+            .add_systems(
+                EguiPrimaryContextPass,
+                show_console.run_if(|console: Res<Console>| console.open),
+            );
+    }
+}
+
+/// A console command: runs with the arguments after its name and returns the
+/// text to show, or what went wrong
+pub type ConsoleHandler = fn(&mut World, &[&str]) -> Result<String, String>;
+
+/// A command registered with the console
+#[derive(Clone, Copy)]
+pub struct ConsoleCommand {
+    /// How the command is written, shown by `help`
+    pub usage: &'static str,
+    /// What the command does, shown by `help`
+    pub description: &'static str,
+    pub run: ConsoleHandler,
+}
+
+/// Resource with the console commands by name
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+impl ConsoleCommands {
+    /// Add a command, replacing any command of the same name
+    pub fn register(&mut self, name: &'static str, command: ConsoleCommand) {
+        if self.0.insert(name, command).is_some() {
+            warn!("Console command {} registered twice", name);
+        }
+    }
+
+    /// The command of the given name
+    pub fn get(&self, name: &str) -> Option<ConsoleCommand> {
+        self.0.get(name).copied()
+    }
+}
+
+/// Adding console commands while building the app
+pub trait ConsoleCommandsExt {
+    /// Add a command to the console
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        description: &'static str,
+        run: ConsoleHandler,
+    ) -> &mut Self;
+}
+
+impl ConsoleCommandsExt for App {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        description: &'static str,
+        run: ConsoleHandler,
+    ) -> &mut Self {
+        self.init_resource::<ConsoleCommands>();
+        self.world_mut().resource_mut::<ConsoleCommands>().register(
+            name,
+            ConsoleCommand {
+                usage,
+                description,
+                run,
+            },
+        );
+        self
+    }
+}
+
+/// Resource with the console's state
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    /// The line being typed
+    input: String,
+    /// Lines shown in the console, the oldest first
+    log: Vec<String>,
+    /// Lines entered before, the oldest first, and the one being recalled
+    history: Vec<String>,
+    recalled: Option<usize>,
+    /// Lines entered this frame, waiting to run
+    pending: Vec<String>,
+    /// Set for the frame the console opens in, so the key that opened it
+    /// doesn't close it again
+    just_opened: bool,
+}
+
+impl Console {
+    /// Add a line to the console
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > MAX_LOG_LINES {
+            let excess = self.log.len() - MAX_LOG_LINES;
+            self.log.drain(..excess);
+        }
+    }
+}
+
+/// Open the console with the backquote key
+///
+/// While it is open the console has the keyboard, so it closes itself.
+fn toggle_console(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut console: ResMut<Console>,
+    mut input_map: ResMut<InputMap>,
+) {
+    // Other screens that take the keyboard are left alone
+    if keyboard.just_pressed(KeyCode::Backquote) && !console.open && !input_map.suspended {
+        console.open = true;
+        console.just_opened = true;
+        input_map.suspended = true;
+    }
+}
+
+/// Draw the console, take the lines entered into it and close it with the
+/// backquote key
+fn show_console(
+    mut contexts: EguiContexts,
+    mut console: ResMut<Console>,
+    mut input_map: ResMut<InputMap>,
+) -> Result {
+    let console = &mut *console;
+    let just_opened = std::mem::take(&mut console.just_opened);
+    egui::TopBottomPanel::top("console")
+        .exact_height(CONSOLE_HEIGHT)
+        .resizable(false)
+        .frame(
+            egui::Frame::new()
+                .fill(egui::Color32::from_black_alpha(220))
+                .inner_margin(egui::Margin::same(8)),
+        )
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(CONSOLE_HEIGHT - 40.0)
+                .auto_shrink([false, false])
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in &console.log {
+                        ui.monospace(line);
+                    }
+                });
+            ui.separator();
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut console.input)
+                    .desired_width(f32::INFINITY)
+                    .font(egui::TextStyle::Monospace),
+            );
+            response.request_focus();
+            // The key that opens and closes the console isn't typed into it
+            console.input.retain(|character| character != '`');
+
+            let (enter, up, down, close) = ui.input(|input| {
+                (
+                    input.key_pressed(egui::Key::Enter),
+                    input.key_pressed(egui::Key::ArrowUp),
+                    input.key_pressed(egui::Key::ArrowDown),
+                    input.key_pressed(egui::Key::Backtick),
+                )
+            });
+            if close && !just_opened {
+                console.open = false;
+                input_map.suspended = false;
+            }
+            if enter && !console.input.trim().is_empty() {
+                let line = std::mem::take(&mut console.input);
+                console.history.push(line.clone());
+                console.recalled = None;
+                console.pending.push(line);
+            }
+            if up && !console.history.is_empty() {
+                let index = console
+                    .recalled
+                    .map_or(console.history.len() - 1, |index| index.saturating_sub(1));
+                console.recalled = Some(index);
+                console.input = console.history[index].clone();
+            }
+            if down {
+                if let Some(index) = console.recalled {
+                    if index + 1 < console.history.len() {
+                        console.recalled = Some(index + 1);
+                        console.input = console.history[index + 1].clone();
+                    } else {
+                        console.recalled = None;
+                        console.input.clear();
+                    }
+                }
+            }
+        });
+    Ok(())
+}
+
+/// Run the lines entered into the console
+fn run_console_commands(world: &mut World) {
+    let lines = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, arguments)) = words.split_first() else {
+            continue;
+        };
+        world.resource_mut::<Console>().print(format!("> {}", line));
+        let command = world.resource::<ConsoleCommands>().get(name);
+        let output = match command {
+            Some(command) => (command.run)(world, arguments).unwrap_or_else(|err| {
+                warn!("Console command {} failed: {}", name, err);
+                format!("Error: {}", err)
+            }),
+            None => format!("Unknown command {}, try help", name),
+        };
+        if !output.is_empty() {
+            world.resource_mut::<Console>().print(output);
+        }
+    }
+}
+
+/// Parse every argument as a number
+fn parse_numbers<T: std::str::FromStr>(arguments: &[&str]) -> Result<Vec<T>, String> {
+    arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse()
+                .map_err(|_| format!("{} is not a number", argument))
+        })
+        .collect()
+}
+
+/// List the commands with how they are written
+fn help_command(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let commands = world.resource::<ConsoleCommands>();
+    Ok(commands
+        .0
+        .values()
+        .map(|command| format!("{:<28} {}", command.usage, command.description))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Empty the console
+fn clear_command(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    world.resource_mut::<Console>().log.clear();
+    Ok(String::new())
+}
+
+/// Open a file as if it had been dropped on the window
+fn load_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let (path, additive) = match arguments {
+        [path] => (*path, false),
+        [path, "add"] => (*path, true),
+        _ => return Err("usage: load <path> [add]".to_string()),
+    };
+    let mut state = SystemState::<FileOpener>::new(world);
+    state.get_mut(world).open(path.to_string(), additive);
+    state.apply(world);
+    Ok(format!("Opening {}", path))
+}
+
+/// Put the car at a position, standing still
+fn teleport_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let numbers = parse_numbers::<f32>(arguments)?;
+    let [x, y, z] = numbers[..] else {
+        return Err("usage: teleport <x> <y> <z>".to_string());
+    };
+    let mut car_query = world.query_filtered::<(&mut Car, &mut Transform), With<PrimaryCar>>();
+    let (mut car, mut transform) = car_query
+        .single_mut(world)
+        .map_err(|_| "no car to teleport".to_string())?;
+    transform.translation = Vec3::new(x, y, z);
+    car.stop();
+    Ok(format!("Car at {:.1} {:.1} {:.1}", x, y, z))
+}
+
+/// Show or change one of the car's tunables
+fn set_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let (name, value) = match arguments {
+        [name] => (*name, None),
+        [name, value] => (*name, Some(*value)),
+        _ => return Err("usage: set car.<field> [value]".to_string()),
+    };
+    let Some(field) = name.strip_prefix("car.") else {
+        return Err(format!("{} is not a setting, only car.<field> is", name));
+    };
+    let mut car_query =
+        world.query_filtered::<(&mut Car, &mut Mass, &mut CenterOfMass), With<PrimaryCar>>();
+    let (mut car, mut mass, mut center_of_mass) = car_query
+        .single_mut(world)
+        .map_err(|_| "no car".to_string())?;
+    let Some(current) = car.tunable_mut(field) else {
+        return Err(format!("the car has no tunable {}", field));
+    };
+    let Some(value) = value else {
+        return Ok(format!("{} = {}", name, current));
+    };
+    *current = value
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))?;
+    let value = *current;
+    // The rigid body keeps its own copy of these
+    *mass = Mass(car.mass);
+    *center_of_mass = CenterOfMass(Vec3::Y * car.center_of_mass_height);
+    Ok(format!("{} = {}", name, value))
+}
+
+/// Change how fast the game runs relative to real time
+fn timescale_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let numbers = parse_numbers::<f64>(arguments)?;
+    let [factor] = numbers[..] else {
+        return Err("usage: timescale <factor>".to_string());
+    };
+    if !(MIN_TIMESCALE..=MAX_TIMESCALE).contains(&factor) {
+        return Err(format!(
+            "the timescale goes from {} to {}",
+            MIN_TIMESCALE, MAX_TIMESCALE
+        ));
+    }
+    world
+        .resource_mut::<Time<Virtual>>()
+        .set_relative_speed_f64(factor);
+    Ok(format!("Timescale {}", factor))
+}
@@ -0,0 +1,194 @@
+//! Crash damage
+//!
+//! Hitting a wall or landing hard damages the car, which lowers its top speed
+//! and steering lock. Impacts are found from sudden changes in the body's
+//! velocity over a physics step, far beyond what the tires can do. Each impact
+//! crumples the bodywork on the side that was hit, hard ones knock off
+//! spoilers and bumpers, and a badly damaged car smokes from under the hood.
+//! Respawning repairs the car.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::car::{Car, CarLanded, CarSet, PrimaryCar, CAR_HALF_EXTENTS};
+use crate::particles::{jitter, SpawnParticle};
+use crate::spawn_point::RespawnCar;
+use crate::vehicle::{CarPanel, SelectedVehicle};
+
+/// Velocity changes in one step below this are driving, not crashing, in m/s
+const IMPACT_THRESHOLD: f32 = 4.0;
+
+/// Landings softer than this don't hurt, in m/s
+const LANDING_THRESHOLD: f32 = 7.0;
+
+/// Damage per m/s of impact speed beyond the threshold
+const DAMAGE_PER_IMPACT_SPEED: f32 = 0.02;
+
+/// Impacts at least this hard knock off a detachable part, in m/s
+const DETACH_IMPACT_SPEED: f32 = 10.0;
+
+/// Smallest scale a panel is crumpled to along any axis
+const MAX_CRUMPLE: f32 = 0.7;
+
+/// Above this damage the engine smokes
+const SMOKE_DAMAGE: f32 = 0.5;
+
+/// Plugin for crash damage
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<CarImpact>()
+            .init_resource::<ImpactTracker>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    detect_impacts.before(CarSet::Physics),
+                    track_velocity.after(CarSet::Constraints),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    repair_on_respawn.in_set(CarSet::Input),
+                    damage_smoke.after(CarSet::Constraints),
+                ),
+            );
+    }
+}
+
+/// Sent when the car crashes into something or lands hard
+#[derive(Message, Debug, Clone, Copy)]
+pub struct CarImpact {
+    /// Impact speed in m/s
+    pub speed: f32,
+}
+
+/// Resource with the car's velocity at the end of the last simulation step
+#[derive(Resource, Default)]
+struct ImpactTracker {
+    last_velocity: Option<Vec3>,
+}
+
+/// Marker for parts knocked off the car
+#[derive(Component)]
+struct DetachedPart;
+
+/// Remember the velocity the car left the step with, after every system moved it
+fn track_velocity(
+    mut tracker: ResMut<ImpactTracker>,
+    car_query: Query<&LinearVelocity, With<PrimaryCar>>,
+) {
+    tracker.last_velocity = car_query.single().ok().map(|velocity| velocity.0);
+}
+
+/// Turn sudden velocity changes and hard landings into damage
+fn detect_impacts(
+    mut commands: Commands,
+    mut tracker: ResMut<ImpactTracker>,
+    mut landings: MessageReader<CarLanded>,
+    mut impact_messages: MessageWriter<CarImpact>,
+    mut car_query: Query<(&mut Car, &Transform, &LinearVelocity, &Children), With<PrimaryCar>>,
+    mut panel_query: Query<(&CarPanel, &mut Transform, &GlobalTransform), Without<Car>>,
+) {
+    let Ok((mut car, transform, velocity, children)) = car_query.single_mut() else {
+        return;
+    };
+
+    // Impact speed, how far it is past the threshold and the direction the car was pushed
+    let mut impacts = Vec::new();
+    if let Some(last_velocity) = tracker.last_velocity.take() {
+        let change = velocity.0 - last_velocity;
+        let speed = change.length();
+        if speed > IMPACT_THRESHOLD {
+            impacts.push((speed, speed - IMPACT_THRESHOLD, change / speed));
+        }
+    }
+    for landing in landings.read() {
+        if landing.impact_speed > LANDING_THRESHOLD {
+            let severity = landing.impact_speed - LANDING_THRESHOLD;
+            impacts.push((landing.impact_speed, severity, *transform.up()));
+        }
+    }
+
+    for (speed, severity, direction) in impacts {
+        car.damage = (car.damage + severity * DAMAGE_PER_IMPACT_SPEED).min(1.0);
+        info!("Crash! Damage {:.0}%", car.damage * 100.0);
+        impact_messages.write(CarImpact { speed });
+
+        // Panels are pushed in from the side that was hit, along the axis of the impact
+        let local_direction = transform.rotation.inverse() * direction;
+        let squash = (Vec3::ONE - local_direction.abs() * severity * 0.02)
+            .max(Vec3::splat(MAX_CRUMPLE));
+        let mut detach = speed >= DETACH_IMPACT_SPEED;
+        for &child in children {
+            let Ok((panel, mut panel_transform, global)) = panel_query.get_mut(child) else {
+                continue;
+            };
+            panel_transform.scale = (panel_transform.scale * squash).max(Vec3::splat(MAX_CRUMPLE));
+
+            if panel.detachable && detach {
+                detach = false;
+                commands.entity(child).remove::<ChildOf>().insert((
+                    DetachedPart,
+                    global.compute_transform(),
+                    RigidBody::Dynamic,
+                    Collider::cuboid(panel.size.x, panel.size.y, panel.size.z),
+                    LinearVelocity(velocity.0 - direction * 3.0 + Vec3::Y * 2.0),
+                ));
+            }
+        }
+    }
+}
+
+/// Repair the car when it respawns, clearing away the parts it lost
+fn repair_on_respawn(
+    mut commands: Commands,
+    mut respawns: MessageReader<RespawnCar>,
+    mut selected: ResMut<SelectedVehicle>,
+    mut tracker: ResMut<ImpactTracker>,
+    detached_query: Query<Entity, With<DetachedPart>>,
+) {
+    if respawns.read().count() == 0 {
+        return;
+    }
+    for entity in detached_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    // Applying the vehicle again rebuilds the bodywork and resets the damage
+    selected.set_changed();
+    tracker.last_velocity = None;
+}
+
+/// Smoke from under the hood of a badly damaged car
+fn damage_smoke(
+    time: Res<Time>,
+    car_query: Query<(&Car, &Transform), With<PrimaryCar>>,
+    mut particles: MessageWriter<SpawnParticle>,
+    mut pending: Local<f32>,
+) {
+    let Ok((car, transform)) = car_query.single() else {
+        return;
+    };
+    if car.damage < SMOKE_DAMAGE {
+        return;
+    }
+
+    // Thicker smoke the worse the damage
+    let rate = 10.0 + 40.0 * (car.damage - SMOKE_DAMAGE) / (1.0 - SMOKE_DAMAGE);
+    *pending += rate * time.delta_secs();
+    let hood = transform.translation
+        + transform.forward() * CAR_HALF_EXTENTS.z * 0.7
+        + transform.up() * CAR_HALF_EXTENTS.y * 1.5;
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+        particles.write(SpawnParticle {
+            position: hood + jitter() * 0.3,
+            velocity: Vec3::Y * 1.5 + jitter() * 0.4,
+            color: Color::srgba(0.2, 0.2, 0.2, 0.6),
+            size: 0.2,
+            lifetime: 1.5,
+            emissive: false,
+        });
+    }
+}
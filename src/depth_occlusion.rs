@@ -0,0 +1,213 @@
+//! Depth occlusion of meshes by the splat
+//!
+//! The splats are blended over the meshes without writing any depth, so a
+//! mesh behind a scanned wall only disappears as far as the wall's Gaussians
+//! happen to cover it, and the car, the gates and the other markers show
+//! through walls that came out thin or patchy in the scan. To composite them
+//! by depth, the dense parts of the splat are turned into an occluder: the
+//! Gaussians are binned into voxels, the voxels holding enough opacity become
+//! solid, and their outer faces are merged into one mesh that is drawn into
+//! the depth buffer only, with the opaque meshes and before the splats. Meshes
+//! behind it then fail the depth test as behind any other mesh, and the splats
+//! keep being depth tested against the meshes in front of them, so the car
+//! still hides the splats behind it. Each face sits a little behind the mean
+//! of the Gaussians in its voxel rather than on the voxel's side, so the
+//! Gaussians on the surface of a wall still draw in front of it and the
+//! occluder never rises above the road.
+//!
+//! Depth occlusion can be turned off on the settings screen for scans whose
+//! walls are mostly glass or foliage.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use bevy::asset::RenderAssetUsages;
+use bevy::light::{NotShadowCaster, NotShadowReceiver};
+use bevy::mesh::{Indices, MeshVertexBufferLayoutRef};
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    AsBindGroup, ColorWrites, PrimitiveTopology, RenderPipelineDescriptor,
+    SpecializedMeshPipelineError,
+};
+
+use crate::point_cloud::SplatPointCloud;
+use crate::settings::Settings;
+use crate::splat_loader::robust_bounds;
+
+/// Smallest voxel, in meters, and most voxels across the splat's largest side
+const MIN_VOXEL_SIZE: f32 = 0.5;
+const MAX_VOXELS_ACROSS: f32 = 256.0;
+
+/// Summed opacity of the Gaussians in a voxel that makes it solid
+const SOLID_OPACITY: f32 = 4.0;
+
+/// How far the occluder's faces sit behind the Gaussians of their voxels, as
+/// a fraction of the voxel size
+const OCCLUDER_INSET: f32 = 0.3;
+
+/// Plugin for the depth occluder of the splat
+pub struct DepthOcclusionPlugin;
+
+impl Plugin for DepthOcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<DepthOnlyMaterial>::default())
+            .add_systems(Update, (rebuild_occluder, show_occluder).chain());
+    }
+}
+
+/// Resource with the voxels of the splat dense enough to count as solid,
+/// while a splat is loaded
+#[derive(Resource)]
+pub struct SolidVoxels {
+    /// Edge length of a voxel in meters
+    pub size: f32,
+    /// Opacity-weighted mean position of the Gaussians in each solid voxel,
+    /// by the voxel's world-space grid index
+    pub cells: HashMap<IVec3, Vec3>,
+}
+
+impl SolidVoxels {
+    /// Bin the splat's Gaussians into voxels by opacity, if there are any
+    pub fn build(point_cloud: &SplatPointCloud) -> Option<Self> {
+        let (min, max) = robust_bounds(&point_cloud.positions)?;
+        let size = ((max - min).max_element() / MAX_VOXELS_ACROSS).max(MIN_VOXEL_SIZE);
+        let mut density: HashMap<IVec3, (f32, Vec3)> = HashMap::new();
+        for (&position, &opacity) in point_cloud.positions.iter().zip(&point_cloud.opacities) {
+            let (total, sum) = density
+                .entry((position / size).floor().as_ivec3())
+                .or_default();
+            *total += opacity;
+            *sum += position * opacity;
+        }
+        let cells: HashMap<IVec3, Vec3> = density
+            .into_iter()
+            .filter(|&(_, (total, _))| total >= SOLID_OPACITY)
+            .map(|(cell, (total, sum))| (cell, sum / total))
+            .collect();
+        (!cells.is_empty()).then_some(Self { size, cells })
+    }
+
+    /// Mesh of the faces between solid and empty voxels, each `inset` times the
+    /// voxel size behind the mean of its voxel's Gaussians
+    pub fn surface_mesh(&self, inset: f32) -> Mesh {
+        const DIRECTIONS: [IVec3; 6] = [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ];
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for (&cell, &mean) in &self.cells {
+            let corner = cell.as_vec3() * self.size;
+            let center = corner + Vec3::splat(0.5 * self.size);
+            for direction in DIRECTIONS {
+                if self.cells.contains_key(&(cell + direction)) {
+                    continue;
+                }
+                let normal = direction.as_vec3();
+                // Counter-clockwise seen from outside, so the inside is culled
+                let u = if direction.x != 0 { Vec3::Y } else { Vec3::X };
+                let v = normal.cross(u);
+                // Across the whole voxel, at the depth of its Gaussians
+                let depth = (mean - normal * inset * self.size)
+                    .clamp(corner, corner + Vec3::splat(self.size));
+                let face = center + normal * normal.dot(depth - center);
+                let (u, v) = (u * 0.5 * self.size, v * 0.5 * self.size);
+                let first = positions.len() as u32;
+                for corner in [face - u - v, face + u - v, face + u + v, face - u + v] {
+                    positions.push(corner.to_array());
+                    normals.push(normal.to_array());
+                }
+                indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+            }
+        }
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_indices(Indices::U32(indices))
+    }
+}
+
+/// Material writing depth and no color
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone, Default)]
+pub struct DepthOnlyMaterial {}
+
+impl Material for DepthOnlyMaterial {
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.write_mask = ColorWrites::empty();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Marker for the depth occluder of the splat
+#[derive(Component)]
+struct DepthOccluder;
+
+/// Rebuild the solid voxels and the occluder whenever the splat points change
+fn rebuild_occluder(
+    mut commands: Commands,
+    point_cloud: Res<SplatPointCloud>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<DepthOnlyMaterial>>,
+    occluder_query: Query<Entity, With<DepthOccluder>>,
+) {
+    if !point_cloud.is_changed() {
+        return;
+    }
+    for entity in occluder_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let start = Instant::now();
+    let Some(voxels) = SolidVoxels::build(&point_cloud) else {
+        commands.remove_resource::<SolidVoxels>();
+        return;
+    };
+    commands.spawn((
+        Mesh3d(meshes.add(voxels.surface_mesh(OCCLUDER_INSET))),
+        MeshMaterial3d(materials.add(DepthOnlyMaterial::default())),
+        Transform::default(),
+        NotShadowCaster,
+        NotShadowReceiver,
+        DepthOccluder,
+    ));
+    info!(
+        "Built depth occluder from {} solid voxels of {:.2} m in {:.0?}",
+        voxels.cells.len(),
+        voxels.size,
+        start.elapsed()
+    );
+    commands.insert_resource(voxels);
+}
+
+/// Show the occluder while depth occlusion is on
+fn show_occluder(
+    settings: Res<Settings>,
+    mut occluder_query: Query<&mut Visibility, With<DepthOccluder>>,
+) {
+    let visibility = if settings.depth_occlusion {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for mut occluder in occluder_query.iter_mut() {
+        occluder.set_if_neq(visibility);
+    }
+}
@@ -0,0 +1,263 @@
+//! Performance diagnostics overlay
+//!
+//! F3 shows an overlay with the frame rate, a graph of the last frame times,
+//! how many Gaussians are being drawn, how many splat chunks are visible and
+//! how long the physics took in the last frame, so a performance problem can
+//! be reported with numbers rather than "it stutters". The values are Bevy
+//! diagnostics, kept up to date whether or not the overlay is shown.
+
+use std::time::Instant;
+
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, FrameTimeDiagnosticsPlugin,
+    RegisterDiagnostic,
+};
+use bevy::prelude::*;
+use bevy_gaussian_splatting::{PlanarGaussian3d, PlanarGaussian3dHandle};
+
+/// Gaussians in the visible splat clouds
+pub const SPLAT_COUNT: DiagnosticPath = DiagnosticPath::const_new("gaussrace/splat_count");
+
+/// Splat clouds that are visible, chunks and levels of detail included
+pub const VISIBLE_CHUNKS: DiagnosticPath = DiagnosticPath::const_new("gaussrace/visible_chunks");
+
+/// Time spent in the fixed timestep steps of a frame, in milliseconds
+pub const PHYSICS_TIME: DiagnosticPath = DiagnosticPath::const_new("gaussrace/physics_time");
+
+/// Frames shown in the frame time graph
+const GRAPH_FRAMES: usize = 120;
+
+/// Size of the frame time graph in pixels, and the frame time at its top
+const GRAPH_WIDTH: f32 = 240.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const GRAPH_MAX_MS: f64 = 50.0;
+
+/// Frame times up to which a bar is green and yellow, in milliseconds
+const SMOOTH_FRAME_MS: f64 = 1000.0 / 60.0;
+const PLAYABLE_FRAME_MS: f64 = 1000.0 / 30.0;
+
+/// Plugin for the diagnostics overlay
+pub struct DiagnosticsOverlayPlugin;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin::new(GRAPH_FRAMES));
+        }
+        app.register_diagnostic(Diagnostic::new(SPLAT_COUNT))
+            .register_diagnostic(Diagnostic::new(VISIBLE_CHUNKS))
+            .register_diagnostic(Diagnostic::new(PHYSICS_TIME).with_suffix(" ms"))
+            .init_resource::<PhysicsStart>()
+            .add_systems(Startup, spawn_overlay)
+            .add_systems(
+                RunFixedMainLoop,
+                (
+                    start_physics_timer.in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop),
+                    stop_physics_timer.in_set(RunFixedMainLoopSystems::AfterFixedMainLoop),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    measure_splats,
+                    toggle_overlay,
+                    update_overlay_text,
+                    update_frame_graph,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Resource with when this frame's fixed timestep steps started
+#[derive(Resource, Default)]
+struct PhysicsStart(Option<Instant>);
+
+/// Marker for the overlay's root node
+#[derive(Component)]
+struct DiagnosticsOverlay;
+
+/// Marker for the overlay's text
+#[derive(Component)]
+struct DiagnosticsText;
+
+/// Bar of the frame time graph, the oldest frame first
+#[derive(Component)]
+struct FrameBar(usize);
+
+/// Note when the fixed timestep steps of the frame start
+fn start_physics_timer(mut start: ResMut<PhysicsStart>) {
+    start.0 = Some(Instant::now());
+}
+
+/// Measure how long the fixed timestep steps of the frame took
+fn stop_physics_timer(mut start: ResMut<PhysicsStart>, mut diagnostics: Diagnostics) {
+    if let Some(start) = start.0.take() {
+        diagnostics.add_measurement(&PHYSICS_TIME, || start.elapsed().as_secs_f64() * 1000.0);
+    }
+}
+
+/// Count the visible splat clouds and their Gaussians
+fn measure_splats(
+    mut diagnostics: Diagnostics,
+    clouds: Res<Assets<PlanarGaussian3d>>,
+    cloud_query: Query<(&PlanarGaussian3dHandle, &InheritedVisibility)>,
+) {
+    let (chunks, gaussians) = cloud_query
+        .iter()
+        .filter(|(_, visibility)| visibility.get())
+        .filter_map(|(handle, _)| clouds.get(&handle.0))
+        .fold((0, 0), |(chunks, gaussians), cloud| {
+            (chunks + 1, gaussians + cloud.position_visibility.len())
+        });
+    diagnostics.add_measurement(&VISIBLE_CHUNKS, || chunks as f64);
+    diagnostics.add_measurement(&SPLAT_COUNT, || gaussians as f64);
+}
+
+/// Spawn the hidden overlay at the top of the screen
+fn spawn_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            DiagnosticsOverlay,
+        ))
+        .with_children(|overlay| {
+            overlay
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(6.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+                ))
+                .with_children(|panel| {
+                    panel.spawn((
+                        Text::new(""),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                        TextColor(Color::WHITE),
+                        DiagnosticsText,
+                    ));
+                    panel
+                        .spawn(Node {
+                            width: Val::Px(GRAPH_WIDTH),
+                            height: Val::Px(GRAPH_HEIGHT),
+                            align_items: AlignItems::End,
+                            ..default()
+                        })
+                        .with_children(|graph| {
+                            for index in 0..GRAPH_FRAMES {
+                                graph.spawn((
+                                    Node {
+                                        width: Val::Px(GRAPH_WIDTH / GRAPH_FRAMES as f32),
+                                        height: Val::Px(0.0),
+                                        ..default()
+                                    },
+                                    BackgroundColor(Color::NONE),
+                                    FrameBar(index),
+                                ));
+                            }
+                        });
+                });
+        });
+}
+
+/// Show and hide the overlay with F3
+fn toggle_overlay(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut overlay_query: Query<&mut Visibility, With<DiagnosticsOverlay>>,
+) {
+    // Shift+F3 opens the tuning panel instead
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if shift || !keyboard.just_pressed(KeyCode::F3) {
+        return;
+    }
+    for mut visibility in overlay_query.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+/// Write the latest values into the overlay while it is shown
+fn update_overlay_text(
+    store: Res<DiagnosticsStore>,
+    overlay_query: Query<&Visibility, With<DiagnosticsOverlay>>,
+    mut text_query: Query<&mut Text, With<DiagnosticsText>>,
+) {
+    if overlay_query
+        .iter()
+        .all(|visibility| *visibility == Visibility::Hidden)
+    {
+        return;
+    }
+    let smoothed = |path: &DiagnosticPath| {
+        store
+            .get(path)
+            .and_then(Diagnostic::smoothed)
+            .unwrap_or(0.0)
+    };
+    let latest = |path: &DiagnosticPath| store.get(path).and_then(Diagnostic::value).unwrap_or(0.0);
+    for mut text in text_query.iter_mut() {
+        text.0 = format!(
+            "{:.0} fps  {:.1} ms\nSplats: {:.0}\nVisible chunks: {:.0}\nPhysics: {:.2} ms",
+            smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+            smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+            latest(&SPLAT_COUNT),
+            latest(&VISIBLE_CHUNKS),
+            smoothed(&PHYSICS_TIME),
+        );
+    }
+}
+
+/// Draw the recent frame times as bars, green while smooth, yellow while
+/// playable and red beyond
+fn update_frame_graph(
+    store: Res<DiagnosticsStore>,
+    overlay_query: Query<&Visibility, With<DiagnosticsOverlay>>,
+    mut bar_query: Query<(&FrameBar, &mut Node, &mut BackgroundColor)>,
+) {
+    if overlay_query
+        .iter()
+        .all(|visibility| *visibility == Visibility::Hidden)
+    {
+        return;
+    }
+    let Some(frame_time) = store.get(&FrameTimeDiagnosticsPlugin::FRAME_TIME) else {
+        return;
+    };
+    let times: Vec<f64> = frame_time.values().copied().collect();
+    // The newest frame is on the right, with empty bars before the history fills
+    let first = GRAPH_FRAMES.saturating_sub(times.len());
+    let skipped = times.len().saturating_sub(GRAPH_FRAMES);
+    for (bar, mut node, mut color) in bar_query.iter_mut() {
+        let Some(&ms) = bar
+            .0
+            .checked_sub(first)
+            .and_then(|index| times.get(skipped + index))
+        else {
+            node.height = Val::Px(0.0);
+            continue;
+        };
+        node.height = Val::Px((ms / GRAPH_MAX_MS).min(1.0) as f32 * GRAPH_HEIGHT);
+        color.0 = if ms <= SMOOTH_FRAME_MS {
+            Color::srgb(0.3, 0.9, 0.3)
+        } else if ms <= PLAYABLE_FRAME_MS {
+            Color::srgb(0.95, 0.8, 0.2)
+        } else {
+            Color::srgb(0.95, 0.3, 0.25)
+        };
+    }
+}
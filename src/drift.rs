@@ -0,0 +1,302 @@
+//! Drift scoring
+//!
+//! In the Drift game mode the first player's car scores points while it slides
+//! sideways on the ground: faster and at a wider angle scores more, and the
+//! longer a drift is held the more each second of it is worth. Starting a new
+//! drift within `CHAIN_WINDOW` of the last one chains it into the same combo
+//! and raises the combo's multiplier. Once the car stops drifting for longer
+//! than that, the combo is banked into the total with a popup; crashing or
+//! spinning out loses it instead.
+//!
+//! The running combo and the total are shown near the top of the screen.
+//! Restarting the race or changing the mode starts the score over.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::damage::CarImpact;
+use crate::game_mode::GameMode;
+use crate::race_start::RestartRace;
+
+/// Below this speed sliding doesn't count as drifting, in m/s
+const MIN_DRIFT_SPEED: f32 = 8.0;
+
+/// Angle between the heading and the direction of travel from which the car
+/// is drifting, and past which it has spun out, in degrees
+const MIN_DRIFT_ANGLE: f32 = 12.0;
+const SPIN_ANGLE: f32 = 100.0;
+
+/// Points per second for each m/s of speed and degree of drift angle
+const POINTS_RATE: f32 = 0.5;
+
+/// Extra points for each second a drift is held, as a fraction, and at most
+const DURATION_BONUS: f32 = 0.5;
+const MAX_DURATION_BONUS: f32 = 2.0;
+
+/// Seconds after a drift ends in which the next one continues the combo
+const CHAIN_WINDOW: f32 = 1.5;
+
+/// Highest combo multiplier
+const MAX_MULTIPLIER: u32 = 5;
+
+/// Seconds a popup stays up
+const POPUP_DURATION: f32 = 2.0;
+
+/// Plugin for drift scoring
+pub struct DriftPlugin;
+
+impl Plugin for DriftPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DriftScore>()
+            .add_systems(Startup, spawn_drift_panel)
+            .add_systems(
+                Update,
+                (
+                    reset_drift_score,
+                    score_drifts.run_if(resource_equals(GameMode::Drift)),
+                    update_drift_panel,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// A combo of chained drifts that hasn't been banked yet
+#[derive(Debug, Clone, Default)]
+struct DriftCombo {
+    /// Points scored by the combo's drifts, before the multiplier
+    points: f32,
+    /// Number of drifts chained, up to `MAX_MULTIPLIER`
+    multiplier: u32,
+    /// Seconds the current drift has been held, zero between drifts
+    drift_time: f32,
+    /// Seconds since the car last drifted
+    since_drift: f32,
+}
+
+impl DriftCombo {
+    fn score(&self) -> f32 {
+        self.points * self.multiplier as f32
+    }
+}
+
+/// Resource with the drift score
+#[derive(Resource, Default)]
+pub struct DriftScore {
+    /// Points banked from finished combos
+    pub total: f32,
+    /// Best single combo
+    pub best_combo: f32,
+    /// The combo being driven, if any
+    combo: Option<DriftCombo>,
+    /// Text and color of the popup and the seconds it stays up
+    popup: Option<(String, Color, f32)>,
+}
+
+impl DriftScore {
+    fn bank_combo(&mut self) {
+        let Some(combo) = self.combo.take() else {
+            return;
+        };
+        let score = combo.score();
+        self.total += score;
+        self.best_combo = self.best_combo.max(score);
+        info!(
+            "Drift combo x{}: {:.0} points, {:.0} total",
+            combo.multiplier, score, self.total
+        );
+        let text = format!("+{:.0}", score);
+        self.popup = Some((text, Color::srgb(0.3, 1.0, 0.4), POPUP_DURATION));
+    }
+
+    fn lose_combo(&mut self, reason: &str) {
+        if self.combo.take().is_some() {
+            info!("Drift combo lost: {}", reason);
+            let text = format!("Combo lost - {}", reason);
+            self.popup = Some((text, Color::srgb(1.0, 0.3, 0.2), POPUP_DURATION));
+        }
+    }
+}
+
+/// Marker for the drift panel
+#[derive(Component)]
+struct DriftPanel;
+
+/// Marker for the text of the running combo
+#[derive(Component)]
+struct DriftComboText;
+
+/// Marker for the popup text
+#[derive(Component)]
+struct DriftPopupText;
+
+/// Marker for the total score text
+#[derive(Component)]
+struct DriftTotalText;
+
+/// Start the score over when the race restarts or the mode changes
+fn reset_drift_score(
+    mode: Res<GameMode>,
+    mut restarts: MessageReader<RestartRace>,
+    mut score: ResMut<DriftScore>,
+) {
+    let restarted = restarts.read().count() > 0;
+    if restarted || (mode.is_changed() && !mode.is_added()) {
+        *score = DriftScore::default();
+    }
+}
+
+/// Score the first player's car while it drifts and bank or lose its combo
+fn score_drifts(
+    time: Res<Time>,
+    mut impacts: MessageReader<CarImpact>,
+    mut score: ResMut<DriftScore>,
+    car_query: Query<(&Car, &Transform, &LinearVelocity), With<PrimaryCar>>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    if let Some((_, _, seconds)) = &mut score.popup {
+        *seconds -= dt;
+        if *seconds <= 0.0 {
+            score.popup = None;
+        }
+    }
+    if impacts.read().count() > 0 {
+        score.lose_combo("crashed");
+    }
+    let Ok((car, transform, velocity)) = car_query.single() else {
+        return;
+    };
+
+    // Angle between where the car points and where it goes, across the ground
+    let travel = velocity.reject_from_normalized(*transform.up());
+    let speed = travel.length();
+    let angle = if speed > 0.1 {
+        transform.forward().angle_between(travel).to_degrees()
+    } else {
+        0.0
+    };
+    let reversing = car.gear < 0 || car.velocity < 0.0;
+    let sliding = car.airtime == 0.0 && !reversing && speed > MIN_DRIFT_SPEED;
+    if sliding && angle > SPIN_ANGLE {
+        score.lose_combo("spun out");
+        return;
+    }
+
+    if sliding && angle > MIN_DRIFT_ANGLE {
+        let combo = score.combo.get_or_insert_default();
+        // A drift starting inside the chain window raises the multiplier
+        if combo.drift_time == 0.0 {
+            combo.multiplier = (combo.multiplier + 1).min(MAX_MULTIPLIER);
+        }
+        combo.drift_time += dt;
+        combo.since_drift = 0.0;
+        let bonus = 1.0 + (combo.drift_time * DURATION_BONUS).min(MAX_DURATION_BONUS);
+        combo.points += speed * angle * POINTS_RATE * bonus * dt;
+    } else if let Some(combo) = &mut score.combo {
+        combo.drift_time = 0.0;
+        combo.since_drift += dt;
+        if combo.since_drift > CHAIN_WINDOW {
+            score.bank_combo();
+        }
+    }
+}
+
+/// Spawn the hidden drift panel near the top of the screen
+fn spawn_drift_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(20.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            DriftPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                drift_text(30.0, Color::srgb(1.0, 0.85, 0.2)),
+                DriftComboText,
+            ));
+            parent.spawn((drift_text(24.0, Color::WHITE), DriftPopupText));
+            parent.spawn((drift_text(18.0, Color::WHITE), DriftTotalText));
+        });
+}
+
+/// Text of the given size and color with a shadow
+fn drift_text(font_size: f32, color: Color) -> impl Bundle {
+    (
+        Text::new(""),
+        TextFont {
+            font_size,
+            ..default()
+        },
+        TextColor(color),
+        TextShadow::default(),
+    )
+}
+
+/// Show the running combo, the popup and the total in the Drift mode
+fn update_drift_panel(
+    mode: Res<GameMode>,
+    score: Res<DriftScore>,
+    mut panel_query: Query<&mut Visibility, With<DriftPanel>>,
+    mut combo_query: Query<&mut Text, With<DriftComboText>>,
+    mut popup_query: Query<
+        (&mut Text, &mut TextColor),
+        (With<DriftPopupText>, Without<DriftComboText>),
+    >,
+    mut total_query: Query<
+        &mut Text,
+        (
+            With<DriftTotalText>,
+            Without<DriftComboText>,
+            Without<DriftPopupText>,
+        ),
+    >,
+) {
+    let Ok(mut visibility) = panel_query.single_mut() else {
+        return;
+    };
+    let shown = *mode == GameMode::Drift;
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if !shown || (!score.is_changed() && !mode.is_changed()) {
+        return;
+    }
+
+    if let Ok(mut text) = combo_query.single_mut() {
+        text.0 = match &score.combo {
+            Some(combo) => format!("DRIFT {:.0} x{}", combo.points, combo.multiplier),
+            None => String::new(),
+        };
+    }
+    if let Ok((mut text, mut color)) = popup_query.single_mut() {
+        match &score.popup {
+            Some((popup, popup_color, seconds)) => {
+                text.0 = popup.clone();
+                // Fading out over the last half second
+                color.0 = popup_color.with_alpha((seconds * 2.0).min(1.0));
+            }
+            None => text.0 = String::new(),
+        }
+    }
+    if let Ok(mut text) = total_query.single_mut() {
+        text.0 = format!(
+            "Drift score {:.0}   Best combo {:.0}",
+            score.total, score.best_combo
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Game modes
+//!
+//! The game mode decides what a drive is scored on. Race is lap racing through
+//! the track's gates; Time trial is hot laps timed in three sectors, where
+//! leaving the drivable area invalidates the lap; Rally drives the gates once
+//! as a stage from the first to the last, for scans of long roads rather than
+//! loops; Drift scores sliding the car sideways, so a scan without a track,
+//! like an empty parking lot, is still a place to play; Collectibles gathers
+//! coins scattered over the scan against the clock, which needs no track
+//! either; Stunts scores air time, flips and spins off the scan's ramps and
+//! crests. The mode is picked on the Mode line of the main menu, and tracks
+//! saved as stages switch to Rally when loaded.
+//!
+//! Each mode also has its own rules for leaving the drivable area or driving
+//! the gates the wrong way (see `track_limits`): Time trial invalidates the
+//! lap, Race and Rally add a time penalty, and the rest only warn.
+
+use bevy::prelude::*;
+
+/// Plugin holding the current game mode
+pub struct GameModePlugin;
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameMode>()
+            .add_systems(Update, announce_game_mode);
+    }
+}
+
+/// Resource with the current game mode
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// Laps through the track's gates
+    #[default]
+    Race,
+    /// Hot laps timed in sectors, invalidated off the drivable area
+    TimeTrial,
+    /// One run from the first gate to the last
+    Rally,
+    /// Drift scoring, with or without a track
+    Drift,
+    /// Gathering the scene's coins against the clock
+    Collect,
+    /// Stunt scoring for jumps, flips and spins
+    Stunt,
+}
+
+/// What happens to a lap that breaks the track limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackLimitRule {
+    /// Only a warning
+    Warn,
+    /// Seconds added to the lap time
+    Penalty,
+    /// The lap can't set a best lap, a record or the ghost
+    Invalidate,
+}
+
+impl GameMode {
+    /// Every mode, in menu order
+    pub const ALL: [GameMode; 6] = [
+        GameMode::Race,
+        GameMode::TimeTrial,
+        GameMode::Rally,
+        GameMode::Drift,
+        GameMode::Collect,
+        GameMode::Stunt,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameMode::Race => "Race",
+            GameMode::TimeTrial => "Time trial",
+            GameMode::Rally => "Rally",
+            GameMode::Drift => "Drift",
+            GameMode::Collect => "Collectibles",
+            GameMode::Stunt => "Stunts",
+        }
+    }
+
+    /// What breaking the track limits does to a lap
+    pub fn track_limit_rule(self) -> TrackLimitRule {
+        match self {
+            GameMode::TimeTrial => TrackLimitRule::Invalidate,
+            GameMode::Race | GameMode::Rally => TrackLimitRule::Penalty,
+            GameMode::Drift | GameMode::Collect | GameMode::Stunt => TrackLimitRule::Warn,
+        }
+    }
+
+    /// Whether the gates are driven as a point-to-point stage rather than laps
+    pub fn is_stage(self) -> bool {
+        self == GameMode::Rally
+    }
+
+    /// The mode `direction` steps along `ALL`, wrapping around
+    pub fn step(self, direction: i32) -> Self {
+        let count = Self::ALL.len() as i32;
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0) as i32;
+        Self::ALL[(index + direction).rem_euclid(count) as usize]
+    }
+}
+
+/// Log the mode whenever it changes
+fn announce_game_mode(mode: Res<GameMode>) {
+    if mode.is_changed() && !mode.is_added() {
+        info!("Game mode: {}", mode.label());
+    }
+}
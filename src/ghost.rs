@@ -0,0 +1,401 @@
+//! Ghost laps and ghost files
+//!
+//! While a track is driven the car's path is recorded, and the fastest lap
+//! becomes a ghost: a see-through car that drives that lap again each time a
+//! new lap starts, to race against. F10 exports the ghost to a compact binary
+//! `.ghost` file; dropping someone else's `.ghost` file onto the window loads
+//! it as the ghost to beat, until a faster lap replaces it.
+//!
+//! A ghost file starts with a header of little-endian fields:
+//!
+//! | Field        | Type    | Contents                                  |
+//! |--------------|---------|-------------------------------------------|
+//! | magic        | 4 bytes | `GRGH`                                    |
+//! | version      | u16     | 1                                         |
+//! | scan hash    | u64     | FNV-1a of the scan's file name and size   |
+//! | track hash   | u64     | FNV-1a of the gate transforms             |
+//! | lap time     | f32     | seconds                                   |
+//! | sample rate  | f32     | samples per second                        |
+//! | sample count | u32     |                                           |
+//!
+//! followed by one position (3 × f32) and rotation (4 × f32) per sample.
+//! Ghosts recorded on other gates are refused; other scans only warn, as a
+//! scan may have been renamed or re-exported.
+//!
+//! An imported lap can only be raced against, not watched on its own: there
+//! is no replay viewer with its own camera and playback controls yet.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use crate::car::{CarSet, PrimaryCar, CAR_HALF_EXTENTS};
+use crate::game_mode::GameMode;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::sidecar::primary_splat_path;
+use crate::splat_loader::{resolve_local_path, LoadedSplat, LoadedSplats};
+use crate::track::{format_lap_time, CheckpointPassed, LapCompleted, LapTimer};
+
+/// Poses recorded per second of driving
+const SAMPLE_RATE: f32 = 20.0;
+
+/// First bytes of a ghost file
+const GHOST_MAGIC: &[u8; 4] = b"GRGH";
+
+/// Version of the ghost file format
+const GHOST_VERSION: u16 = 1;
+
+/// Bytes in the header before the samples
+const HEADER_SIZE: usize = 4 + 2 + 8 + 8 + 4 + 4 + 4;
+
+/// Bytes per recorded pose
+const SAMPLE_SIZE: usize = 7 * 4;
+
+/// Plugin recording, playing back and exchanging ghost laps
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GhostLaps>()
+            .add_message::<LoadGhost>()
+            .add_systems(Startup, spawn_ghost_car)
+            .add_systems(
+                Update,
+                (record_ghost, load_ghost, export_ghost, play_ghost)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Message requesting a ghost file to be loaded from a path
+#[derive(Message, Debug, Clone)]
+pub struct LoadGhost(pub String);
+
+/// A recorded lap
+#[derive(Debug, Clone, Default)]
+pub struct Ghost {
+    /// Lap time in seconds
+    pub lap_time: f32,
+    /// Car poses, `SAMPLE_RATE` per second from the start of the lap
+    pub samples: Vec<(Vec3, Quat)>,
+    /// Hash of the scan the lap was driven on
+    pub scan_hash: u64,
+    /// Hash of the gates the lap was driven through
+    pub track_hash: u64,
+}
+
+impl Ghost {
+    /// Car pose a given time into the lap, if the lap lasts that long
+    pub fn pose_at(&self, time: f32) -> Option<Transform> {
+        let position = time * SAMPLE_RATE;
+        let index = position.floor() as usize;
+        let &(from_translation, from_rotation) = self.samples.get(index)?;
+        let (to_translation, to_rotation) =
+            self.samples.get(index + 1).copied().unwrap_or((from_translation, from_rotation));
+        let t = position.fract();
+        Some(
+            Transform::from_translation(from_translation.lerp(to_translation, t))
+                .with_rotation(from_rotation.slerp(to_rotation, t)),
+        )
+    }
+
+    /// Index of the sample nearest to a position, among the ones up to
+    /// `seconds` of the lap after `start`
+    pub fn nearest_sample(&self, position: Vec3, start: usize, seconds: f32) -> Option<usize> {
+        let end = (start + (seconds * SAMPLE_RATE).ceil() as usize).min(self.samples.len());
+        (start.min(end)..end).min_by(|&a, &b| {
+            let a = self.samples[a].0.distance_squared(position);
+            let b = self.samples[b].0.distance_squared(position);
+            a.total_cmp(&b)
+        })
+    }
+
+    /// Speed in m/s at a sample, from the distance to the next one
+    pub fn speed_at_sample(&self, index: usize) -> Option<f32> {
+        let (from, _) = self.samples.get(index)?;
+        // The last sample has no next one, so it takes the previous one's speed
+        let (to, _) = self
+            .samples
+            .get(index + 1)
+            .or_else(|| self.samples.get(index.checked_sub(1)?))?;
+        Some(from.distance(*to) * SAMPLE_RATE)
+    }
+
+    /// Encode the ghost in the binary file format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.samples.len() * SAMPLE_SIZE);
+        bytes.extend_from_slice(GHOST_MAGIC);
+        bytes.extend_from_slice(&GHOST_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.scan_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.track_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.lap_time.to_le_bytes());
+        bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        bytes.extend_from_slice(&(self.samples.len() as u32).to_le_bytes());
+        for (translation, rotation) in &self.samples {
+            for value in translation.to_array().into_iter().chain(rotation.to_array()) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Decode a ghost from the binary file format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader { bytes };
+        if reader.take::<4>()? != *GHOST_MAGIC {
+            return Err("not a ghost file".to_string());
+        }
+        let version = u16::from_le_bytes(reader.take()?);
+        if version != GHOST_VERSION {
+            return Err(format!("unsupported ghost file version {}", version));
+        }
+        let scan_hash = u64::from_le_bytes(reader.take()?);
+        let track_hash = u64::from_le_bytes(reader.take()?);
+        let lap_time = f32::from_le_bytes(reader.take()?);
+        if !lap_time.is_finite() || lap_time <= 0.0 {
+            return Err(format!("invalid lap time {}", lap_time));
+        }
+        let sample_rate = f32::from_le_bytes(reader.take()?);
+        if sample_rate != SAMPLE_RATE {
+            return Err(format!("unsupported sample rate {}", sample_rate));
+        }
+        let count = u32::from_le_bytes(reader.take()?) as usize;
+        if reader.bytes.len() != count * SAMPLE_SIZE {
+            return Err("truncated ghost file".to_string());
+        }
+        let samples = (0..count)
+            .map(|_| {
+                let mut values = [0.0; 7];
+                for value in &mut values {
+                    *value = f32::from_le_bytes(reader.take()?);
+                }
+                let translation = Vec3::new(values[0], values[1], values[2]);
+                // A zero or infinite rotation normalizes to NaN
+                let rotation =
+                    Quat::from_xyzw(values[3], values[4], values[5], values[6]).normalize();
+                if !translation.is_finite() || !rotation.is_finite() {
+                    return Err("invalid pose in ghost file".to_string());
+                }
+                Ok((translation, rotation))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            lap_time,
+            samples,
+            scan_hash,
+            track_hash,
+        })
+    }
+}
+
+/// Whether a path points at a ghost file
+pub fn is_ghost_file(path: &str) -> bool {
+    path.to_lowercase().ends_with(".ghost")
+}
+
+/// Reads fixed-size fields from the front of a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.bytes.len() < N {
+            return Err("truncated ghost file".to_string());
+        }
+        let (field, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(field.try_into().expect("split at N"))
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and builds for file metadata
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hash identifying a scan by its file name and size, without reading it all
+pub fn scan_hash(path: Option<&str>) -> u64 {
+    let Some(local) = path.and_then(resolve_local_path) else {
+        return 0;
+    };
+    let name = local.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let size = std::fs::metadata(&local).map_or(0, |metadata| metadata.len());
+    fnv1a(name.bytes().chain(size.to_le_bytes()))
+}
+
+/// Hash identifying a track by its gates, and whether they are driven as a
+/// stage, since a stage time can't be compared with a lap time
+pub fn track_hash(checkpoints: &SceneCheckpoints, mode: GameMode) -> u64 {
+    let gates = checkpoints.0.iter().flat_map(|gate| {
+        gate.translation
+            .to_array()
+            .into_iter()
+            .chain(gate.rotation.to_array())
+            .flat_map(f32::to_le_bytes)
+    });
+    fnv1a(gates.chain(mode.is_stage().then_some(1)))
+}
+
+/// Resource with the lap being recorded and the ghost to race
+#[derive(Resource, Default)]
+pub struct GhostLaps {
+    /// Ghost driving alongside, the fastest lap so far or an imported one
+    pub ghost: Option<Ghost>,
+    /// Poses of the lap being driven
+    recording: Vec<(Vec3, Quat)>,
+}
+
+/// Marker for the ghost car
+#[derive(Component)]
+struct GhostCar;
+
+/// Spawn the hidden, see-through ghost car
+fn spawn_ghost_car(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        GhostCar,
+        Mesh3d(meshes.add(Cuboid::from_size(CAR_HALF_EXTENTS * 2.0))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgba(0.6, 0.8, 1.0, 0.35),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        })),
+        Transform::default(),
+        Visibility::Hidden,
+    ));
+}
+
+/// Record the car's path and keep the fastest lap as the ghost
+pub fn record_ghost(
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    mut laps: ResMut<GhostLaps>,
+    mut completed: MessageReader<LapCompleted>,
+    mut passed: MessageReader<CheckpointPassed>,
+    car_query: Query<(Entity, &Transform, &LapTimer), With<PrimaryCar>>,
+) {
+    // A different track, or driving it as a stage, makes the ghost meaningless
+    if checkpoints.is_changed() || mode.is_changed() {
+        *laps = GhostLaps::default();
+        return;
+    }
+
+    let Ok((car, transform, timer)) = car_query.single() else {
+        return;
+    };
+
+    for lap in completed.read().filter(|lap| lap.car == car) {
+        let faster =
+            lap.valid && laps.ghost.as_ref().is_none_or(|ghost| lap.time < ghost.lap_time);
+        if faster {
+            let samples = std::mem::take(&mut laps.recording);
+            laps.ghost = Some(Ghost {
+                lap_time: lap.time,
+                samples,
+                scan_hash: scan_hash(primary_splat_path(&loaded_splats, &splat_query)),
+                track_hash: track_hash(&checkpoints, *mode),
+            });
+            info!("New ghost: {}", format_lap_time(lap.time));
+        }
+    }
+    // Every crossing of the start line starts a new recording
+    if passed.read().any(|checkpoint| checkpoint.car == car && checkpoint.index == 0) {
+        laps.recording.clear();
+    }
+
+    if !timer.started() {
+        return;
+    }
+    while (laps.recording.len() as f32) <= timer.lap_time * SAMPLE_RATE {
+        laps.recording.push((transform.translation, transform.rotation));
+    }
+}
+
+/// Load ghost files dropped onto the window
+fn load_ghost(
+    mut requests: MessageReader<LoadGhost>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    mut laps: ResMut<GhostLaps>,
+) {
+    for LoadGhost(path) in requests.read() {
+        let ghost = std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| Ghost::from_bytes(&bytes));
+        let ghost = match ghost {
+            Ok(ghost) => ghost,
+            Err(err) => {
+                error!("Failed to load ghost {}: {}", path, err);
+                continue;
+            }
+        };
+        if ghost.track_hash != track_hash(&checkpoints, *mode) {
+            error!("Ghost {} was recorded on a different track", path);
+            continue;
+        }
+        if ghost.scan_hash != scan_hash(primary_splat_path(&loaded_splats, &splat_query)) {
+            warn!("Ghost {} was recorded on a different scan", path);
+        }
+        info!("Loaded ghost {} - {} to beat", path, format_lap_time(ghost.lap_time));
+        laps.ghost = Some(ghost);
+    }
+}
+
+/// Save the ghost to a `.ghost` file in the working directory with F10
+fn export_ghost(keyboard: Res<ButtonInput<KeyCode>>, laps: Res<GhostLaps>) {
+    // Shift+F10 records telemetry instead
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if shift || !keyboard.just_pressed(KeyCode::F10) {
+        return;
+    }
+    let Some(ghost) = &laps.ghost else {
+        warn!("No ghost to export - complete a lap first");
+        return;
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = format!("gaussrace-{}.ghost", seconds);
+    match std::fs::write(&path, ghost.to_bytes()) {
+        Ok(()) => info!("Saved the {} ghost to {}", format_lap_time(ghost.lap_time), path),
+        Err(err) => error!("Failed to save ghost {}: {}", path, err),
+    }
+}
+
+/// Drive the ghost car through its lap in step with the lap clock
+fn play_ghost(
+    laps: Res<GhostLaps>,
+    timer_query: Query<&LapTimer, With<PrimaryCar>>,
+    mut ghost_query: Query<(&mut Transform, &mut Visibility), With<GhostCar>>,
+) {
+    let Ok((mut transform, mut visibility)) = ghost_query.single_mut() else {
+        return;
+    };
+    let pose = laps
+        .ghost
+        .as_ref()
+        .zip(timer_query.single().ok())
+        .filter(|(_, timer)| timer.started())
+        .and_then(|(ghost, timer)| ghost.pose_at(timer.lap_time));
+    match pose {
+        Some(pose) => {
+            *transform = pose;
+            visibility.set_if_neq(Visibility::Inherited);
+        }
+        None => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
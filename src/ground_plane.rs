@@ -1,24 +1,58 @@
 //! Ground plane selection and management
 //!
-//! This module allows users to define a ground plane within the Gaussian splat
-//! that the car will drive on.
+//! This module allows users to define ground planes within the Gaussian splat
+//! that the car will drive on. Several bounded planes can be combined to model
+//! ramps and multi-level scans; the car drives on whichever one it is over.
 
+use bevy::input::mouse::AccumulatedMouseMotion;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CarCamera;
+use crate::cli;
+use crate::point_cloud::SplatPointCloud;
+
+/// Maximum distance from the camera at which clicks pick splat geometry
+const PICK_MAX_DISTANCE: f32 = 500.0;
+
+/// Distance by which a bounded plane extends past its selected points
+const PLANE_MARGIN: f32 = 2.0;
+
+/// How far above a point a plane may be and still count as the ground under
+/// it, unless the configuration says otherwise
+const STEP_TOLERANCE: f32 = 0.5;
+
+/// Distance in pixels from a marker within which a click grabs it
+const MARKER_GRAB_RADIUS: f32 = 15.0;
+
+/// Distance a plane moves along its normal per nudge, and with Shift held
+const NUDGE_STEP: f32 = 0.02;
+const NUDGE_STEP_COARSE: f32 = 0.1;
 
 /// Plugin for ground plane selection and management
 pub struct GroundPlanePlugin;
 
 impl Plugin for GroundPlanePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<GroundPlane>()
-            .add_systems(Update, handle_plane_selection)
+        let ground_planes = GroundPlanes {
+            planes: cli::args().planes.clone(),
+            ..default()
+        };
+        app.insert_resource(ground_planes)
+            .init_resource::<PlaneSelectionState>()
+            .add_systems(Startup, spawn_plane_preview)
+            .add_systems(Update, (
+                handle_plane_selection,
+                drag_plane_markers,
+                adjust_selected_plane,
+                update_plane_preview,
+            ).chain())
             .add_systems(Update, visualize_ground_plane);
     }
 }
 
-/// Resource defining the ground plane parameters
-/// The plane is defined by a point and a normal vector
-#[derive(Resource)]
+/// A ground plane, defined by a point and a normal vector
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct GroundPlane {
     /// A point on the plane
     pub origin: Vec3,
@@ -26,8 +60,10 @@ pub struct GroundPlane {
     pub normal: Vec3,
     /// The up direction for the plane (perpendicular to forward on the plane)
     pub up: Vec3,
-    /// Whether the plane has been selected
-    pub is_selected: bool,
+    /// Extent of the plane in its tangent coordinates around `origin`, or
+    /// `None` for an infinite plane
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extent: Option<Rect>,
 }
 
 impl Default for GroundPlane {
@@ -36,7 +72,7 @@ impl Default for GroundPlane {
             origin: Vec3::ZERO,
             normal: Vec3::Y, // Default to horizontal plane
             up: Vec3::Y,
-            is_selected: false,
+            extent: None,
         }
     }
 }
@@ -55,10 +91,33 @@ impl GroundPlane {
             origin: p1,
             normal,
             up: normal,
-            is_selected: true,
+            extent: None,
         }
     }
 
+    /// Bound the plane to the area around the given points
+    pub fn bounded_by(mut self, points: &[Vec3]) -> Self {
+        let extent = points.iter().fold(Rect::EMPTY, |extent, &point| {
+            extent.union_point(self.tangent_coordinates(point))
+        });
+        self.extent = Some(extent.inflate(PLANE_MARGIN));
+        self
+    }
+
+    /// Snap the normal to the nearest world axis, keeping the origin
+    pub fn snap_normal_to_axis(&mut self) {
+        let abs = self.normal.abs();
+        let axis = if abs.x >= abs.y && abs.x >= abs.z {
+            Vec3::X
+        } else if abs.y >= abs.z {
+            Vec3::Y
+        } else {
+            Vec3::Z
+        };
+        self.normal = axis * self.normal.dot(axis).signum();
+        self.up = self.normal;
+    }
+
     /// Project a world position onto the ground plane
     pub fn project_point(&self, point: Vec3) -> Vec3 {
         let to_point = point - self.origin;
@@ -71,17 +130,126 @@ impl GroundPlane {
         let to_point = point - self.origin;
         to_point.dot(self.normal)
     }
+
+    /// Two unit vectors spanning the plane, perpendicular to each other and the normal
+    pub fn tangents(&self) -> (Vec3, Vec3) {
+        let tangent1 = if self.normal.y.abs() < 0.9 {
+            self.normal.cross(Vec3::Y).normalize()
+        } else {
+            self.normal.cross(Vec3::X).normalize()
+        };
+        let tangent2 = self.normal.cross(tangent1).normalize();
+        (tangent1, tangent2)
+    }
+
+    /// Coordinates of a point's projection along the plane tangents, relative to `origin`
+    pub fn tangent_coordinates(&self, point: Vec3) -> Vec2 {
+        let (tangent1, tangent2) = self.tangents();
+        let to_point = point - self.origin;
+        Vec2::new(to_point.dot(tangent1), to_point.dot(tangent2))
+    }
+
+    /// Distance from a point's projection to the plane's extent, zero inside it
+    pub fn distance_outside(&self, point: Vec3) -> f32 {
+        let Some(extent) = self.extent else {
+            return 0.0;
+        };
+        let uv = self.tangent_coordinates(point);
+        uv.distance(uv.clamp(extent.min, extent.max))
+    }
+}
+
+/// Resource holding all defined ground planes
+///
+/// The first plane is the primary one, used for terrain extraction and as the
+/// fallback when picking. Without any planes the car drives on `y = 0`.
+#[derive(Resource)]
+pub struct GroundPlanes {
+    pub planes: Vec<GroundPlane>,
+    /// How far above a point a plane may be and still count as the ground
+    /// under it
+    pub step_tolerance: f32,
+}
+
+impl Default for GroundPlanes {
+    fn default() -> Self {
+        Self {
+            planes: Vec::new(),
+            step_tolerance: STEP_TOLERANCE,
+        }
+    }
+}
+
+impl GroundPlanes {
+    /// Whether any plane has been selected
+    pub fn is_selected(&self) -> bool {
+        !self.planes.is_empty()
+    }
+
+    /// The primary plane, or the default horizontal plane if none is selected
+    pub fn primary(&self) -> GroundPlane {
+        self.planes.first().copied().unwrap_or_default()
+    }
+
+    /// Index of the plane the given point is over
+    ///
+    /// Among the planes whose extent contains the point, this is the highest
+    /// one below it, so the car stays on an overpass or ramp rather than
+    /// dropping to the level underneath. Outside every extent the nearest
+    /// plane is used.
+    pub fn index_at(&self, point: Vec3) -> Option<usize> {
+        let below = self
+            .planes
+            .iter()
+            .enumerate()
+            .filter(|(_, plane)| {
+                plane.distance_outside(point) == 0.0
+                    && plane.height_at(point) >= -self.step_tolerance
+            })
+            .min_by(|(_, a), (_, b)| a.height_at(point).total_cmp(&b.height_at(point)));
+        if let Some((index, _)) = below {
+            return Some(index);
+        }
+
+        self.planes
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let distance = |plane: &GroundPlane| {
+                    plane.distance_outside(point) + plane.height_at(point).abs()
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// The plane the given point is over
+    pub fn plane_at(&self, point: Vec3) -> GroundPlane {
+        self.index_at(point)
+            .map(|index| self.planes[index])
+            .unwrap_or_default()
+    }
 }
 
 /// Component for plane selection mode markers
 #[derive(Component)]
 struct PlaneSelectionMarker(usize);
 
+/// Component for the translucent plane previewed before the third click
+#[derive(Component)]
+struct PlanePreview;
+
 /// Resource to track plane selection state
 #[derive(Resource, Default)]
 struct PlaneSelectionState {
     points: Vec<Vec3>,
     active: bool,
+    /// Whether the plane being selected is added to the existing ones
+    additive: bool,
+    /// Index in `GroundPlanes` of the plane defined by the current markers
+    plane_index: Option<usize>,
+    /// Number of the marker being dragged
+    dragging: Option<usize>,
 }
 
 /// Handle plane selection input
@@ -89,22 +257,32 @@ fn handle_plane_selection(
     mut commands: Commands,
     keyboard: Res<ButtonInput<KeyCode>>,
     mouse_button: Res<ButtonInput<MouseButton>>,
-    mut ground_plane: ResMut<GroundPlane>,
-    mut selection_state: Local<PlaneSelectionState>,
-    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    mut selection_state: ResMut<PlaneSelectionState>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
     windows: Query<&Window>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    markers: Query<Entity, With<PlaneSelectionMarker>>,
+    markers: Query<(Entity, &PlaneSelectionMarker)>,
 ) {
-    // Toggle plane selection mode with 'P' key
+    // Toggle plane selection mode with 'P' key; with Shift the new plane is
+    // added to the existing ones instead of replacing them
     if keyboard.just_pressed(KeyCode::KeyP) {
         selection_state.active = !selection_state.active;
         if selection_state.active {
-            info!("Plane selection mode ACTIVE - Click 3 points to define the ground plane");
+            selection_state.additive =
+                keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+            if selection_state.additive {
+                info!("Plane selection mode ACTIVE - Click 3 points to add a bounded ground plane");
+            } else {
+                info!("Plane selection mode ACTIVE - Click 3 points to define the ground plane");
+            }
+            info!("Ctrl+Z removes the last point");
             selection_state.points.clear();
+            selection_state.plane_index = None;
             // Remove old markers
-            for entity in markers.iter() {
+            for (entity, _) in markers.iter() {
                 commands.entity(entity).despawn();
             }
         } else {
@@ -112,114 +290,385 @@ fn handle_plane_selection(
         }
     }
 
-    // Reset plane with 'R' key
+    // Reset planes with 'R' key
     if keyboard.just_pressed(KeyCode::KeyR) {
-        *ground_plane = GroundPlane::default();
+        ground_planes.planes.clear();
         selection_state.points.clear();
-        for entity in markers.iter() {
+        selection_state.plane_index = None;
+        for (entity, _) in markers.iter() {
             commands.entity(entity).despawn();
         }
-        info!("Ground plane reset to default");
+        info!("Ground planes reset to default");
     }
 
     if !selection_state.active {
         return;
     }
 
+    // Undo the last clicked point with Ctrl+Z
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    if ctrl && keyboard.just_pressed(KeyCode::KeyZ) {
+        let count = selection_state.points.len();
+        if selection_state.points.pop().is_some() {
+            for (entity, marker) in markers.iter() {
+                if marker.0 == count {
+                    commands.entity(entity).despawn();
+                }
+            }
+            info!("Removed point {}", count);
+        }
+    }
+
     // Handle mouse clicks for point selection
+    if !mouse_button.just_pressed(MouseButton::Left) {
+        return;
+    }
+    // Pick the scanned surface under the cursor, falling back to the current
+    // plane where the ray misses the splats
+    let Some(hit_point) = cursor_ray(&camera_query, &windows)
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes))
+    else {
+        return;
+    };
+
+    selection_state.points.push(hit_point);
+    info!("Selected point {}: {:?}", selection_state.points.len(), hit_point);
+
+    // Spawn a visual marker
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(0.2))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.0, 0.0),
+            emissive: LinearRgba::rgb(1.0, 0.0, 0.0),
+            ..default()
+        })),
+        Transform::from_translation(hit_point),
+        PlaneSelectionMarker(selection_state.points.len()),
+    ));
+
+    // If we have 3 points, create the plane
+    if selection_state.points.len() >= 3 {
+        let points = &selection_state.points;
+        let plane = GroundPlane::from_three_points(points[0], points[1], points[2]);
+        if selection_state.additive {
+            ground_planes.planes.push(plane.bounded_by(points));
+        } else {
+            ground_planes.planes = vec![plane];
+        }
+        selection_state.active = false;
+        selection_state.plane_index = Some(ground_planes.planes.len() - 1);
+        info!(
+            "Ground plane {} defined! Normal: {:?}",
+            ground_planes.planes.len(),
+            plane.normal
+        );
+        info!("Drag the markers to adjust the plane (Shift: along the view ray)");
+        info!("PageUp/PageDown nudge the plane along its normal, N snaps it to the nearest axis");
+        info!("Press Ctrl+S to save the ground planes with this splat");
+    }
+}
+
+/// Drag the markers of a finished plane to adjust it, recomputing the plane live
+///
+/// Markers follow the surface under the cursor, or with Shift held move
+/// toward and away from the camera along the view ray.
+fn drag_plane_markers(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mut selection_state: ResMut<PlaneSelectionState>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+    mut markers: Query<(&PlaneSelectionMarker, &mut Transform)>,
+) {
+    if selection_state.active || selection_state.points.len() < 3 {
+        if selection_state.dragging.is_some() {
+            selection_state.dragging = None;
+        }
+        return;
+    }
+    let Some(plane_index) = selection_state
+        .plane_index
+        .filter(|&index| index < ground_planes.planes.len())
+    else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.single() else {
+        return;
+    };
+
+    // Grab the marker under the cursor
     if mouse_button.just_pressed(MouseButton::Left) {
-        let Ok((camera, camera_transform)) = camera_query.single() else {
-            return;
-        };
-        let Ok(window) = windows.single() else {
-            return;
-        };
+        let cursor = windows.single().ok().and_then(|window| viewport_cursor(camera, window));
+        selection_state.dragging = cursor.and_then(|cursor| {
+            selection_state
+                .points
+                .iter()
+                .enumerate()
+                .filter_map(|(index, &point)| {
+                    let screen = camera.world_to_viewport(camera_transform, point).ok()?;
+                    Some((index + 1, screen.distance(cursor)))
+                })
+                .filter(|&(_, distance)| distance <= MARKER_GRAB_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(number, _)| number)
+        });
+    }
+    if !mouse_button.pressed(MouseButton::Left) {
+        if let Some(number) = selection_state.dragging {
+            selection_state.dragging = None;
+            info!("Moved point {} to {:?}", number, selection_state.points[number - 1]);
+        }
+        return;
+    }
+    let Some(number) = selection_state.dragging else {
+        return;
+    };
 
-        if let Some(cursor_pos) = window.cursor_position() {
-            // Cast a ray from the camera through the cursor position
-            if let Ok(ray) = camera.viewport_to_world(camera_transform, cursor_pos) {
-                // For now, intersect with a horizontal plane at y=0 or the current plane
-                let plane_normal = ground_plane.normal;
-                let plane_origin = ground_plane.origin;
-                
-                let denom = plane_normal.dot(*ray.direction);
-                if denom.abs() > 0.0001 {
-                    let t = (plane_origin - ray.origin).dot(plane_normal) / denom;
-                    if t > 0.0 {
-                        let hit_point = ray.origin + *ray.direction * t;
-                        
-                        selection_state.points.push(hit_point);
-                        info!("Selected point {}: {:?}", selection_state.points.len(), hit_point);
-                        
-                        // Spawn a visual marker
-                        commands.spawn((
-                            Mesh3d(meshes.add(Sphere::new(0.2))),
-                            MeshMaterial3d(materials.add(StandardMaterial {
-                                base_color: Color::srgb(1.0, 0.0, 0.0),
-                                emissive: LinearRgba::rgb(1.0, 0.0, 0.0),
-                                ..default()
-                            })),
-                            Transform::from_translation(hit_point),
-                            PlaneSelectionMarker(selection_state.points.len()),
-                        ));
-
-                        // If we have 3 points, create the plane
-                        if selection_state.points.len() >= 3 {
-                            *ground_plane = GroundPlane::from_three_points(
-                                selection_state.points[0],
-                                selection_state.points[1],
-                                selection_state.points[2],
-                            );
-                            selection_state.active = false;
-                            info!("Ground plane defined! Normal: {:?}", ground_plane.normal);
-                        }
-                    }
-                }
-            }
+    let current = selection_state.points[number - 1];
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    let moved = if shift {
+        let camera_position = camera_transform.translation();
+        let distance = current.distance(camera_position);
+        let new_distance = (distance * (1.0 + mouse_motion.delta.y * 0.005)).max(0.5);
+        Some(camera_position + (current - camera_position).normalize() * new_distance)
+    } else {
+        cursor_ray(&camera_query, &windows)
+            .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes))
+    };
+    let Some(moved) = moved.filter(|&moved| moved != current) else {
+        return;
+    };
+
+    selection_state.points[number - 1] = moved;
+    for (marker, mut transform) in markers.iter_mut() {
+        if marker.0 == number {
+            transform.translation = moved;
         }
     }
+
+    let points = &selection_state.points;
+    let plane = GroundPlane::from_three_points(points[0], points[1], points[2]);
+    if plane.normal.is_finite() {
+        let bounded = ground_planes.planes[plane_index].extent.is_some();
+        ground_planes.planes[plane_index] =
+            if bounded { plane.bounded_by(points) } else { plane };
+    }
 }
 
-/// Visualize the ground plane when selected
-fn visualize_ground_plane(
-    mut gizmos: Gizmos,
-    ground_plane: Res<GroundPlane>,
+/// Nudge the selected plane along its normal with PageUp/PageDown (Shift for
+/// bigger steps) and snap its normal to the nearest world axis with 'N'
+///
+/// The selected plane is the one last defined or adjusted with the markers,
+/// or the primary plane otherwise.
+fn adjust_selected_plane(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut selection_state: ResMut<PlaneSelectionState>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    mut markers: Query<(&PlaneSelectionMarker, &mut Transform)>,
 ) {
-    if !ground_plane.is_selected {
+    if selection_state.active || ground_planes.planes.is_empty() {
         return;
     }
+    let plane_index = selection_state
+        .plane_index
+        .filter(|&index| index < ground_planes.planes.len())
+        .unwrap_or(0);
+    let has_markers = selection_state.plane_index == Some(plane_index);
 
-    // Draw a grid on the ground plane
-    let origin = ground_plane.origin;
-    let normal = ground_plane.normal;
-    
-    // Calculate tangent vectors
-    let tangent1 = if normal.y.abs() < 0.9 {
-        normal.cross(Vec3::Y).normalize()
+    let step = if keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight) {
+        NUDGE_STEP_COARSE
     } else {
-        normal.cross(Vec3::X).normalize()
+        NUDGE_STEP
     };
-    let tangent2 = normal.cross(tangent1).normalize();
+    let nudge = if keyboard.just_pressed(KeyCode::PageUp) {
+        step
+    } else if keyboard.just_pressed(KeyCode::PageDown) {
+        -step
+    } else {
+        0.0
+    };
+    let snap = keyboard.just_pressed(KeyCode::KeyN);
+    if nudge == 0.0 && !snap {
+        return;
+    }
 
-    let grid_size = 20.0;
-    let grid_spacing = 2.0;
-    let color = Color::srgba(0.0, 1.0, 0.0, 0.3);
+    // Borrowed mutably only now, as that marks the planes changed
+    let plane = &mut ground_planes.planes[plane_index];
+    if nudge != 0.0 {
+        plane.origin += plane.normal * nudge;
+        info!("Ground plane {} nudged to {:.2} m", plane_index + 1, plane.origin.dot(plane.normal));
+    }
+    if snap {
+        plane.snap_normal_to_axis();
+        info!("Ground plane {} normal snapped to {:?}", plane_index + 1, plane.normal);
+    }
 
-    let steps = (grid_size / grid_spacing) as i32;
-    for i in -steps..=steps {
-        let offset = i as f32 * grid_spacing;
-        
-        // Lines along tangent1
-        let start = origin + tangent1 * offset - tangent2 * grid_size;
-        let end = origin + tangent1 * offset + tangent2 * grid_size;
-        gizmos.line(start, end, color);
-        
-        // Lines along tangent2
-        let start = origin + tangent2 * offset - tangent1 * grid_size;
-        let end = origin + tangent2 * offset + tangent1 * grid_size;
-        gizmos.line(start, end, color);
+    // Keep the markers on the plane so dragging them continues from here
+    if has_markers {
+        let plane = *plane;
+        for point in selection_state.points.iter_mut() {
+            *point = plane.project_point(*point);
+        }
+        if plane.extent.is_some() {
+            ground_planes.planes[plane_index] = plane.bounded_by(&selection_state.points);
+        }
+        for (marker, mut transform) in markers.iter_mut() {
+            if let Some(&point) = selection_state.points.get(marker.0.wrapping_sub(1)) {
+                transform.translation = point;
+            }
+        }
+    }
+}
+
+/// Spawn the hidden preview plane shown while selecting points
+fn spawn_plane_preview(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(0.5)))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgba(0.0, 1.0, 0.0, 0.25),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        })),
+        Transform::default(),
+        Visibility::Hidden,
+        PlanePreview,
+    ));
+}
+
+/// Show the plane through the two placed points and the point under the cursor
+fn update_plane_preview(
+    selection_state: Res<PlaneSelectionState>,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+    mut preview_query: Query<(&mut Transform, &mut Visibility), With<PlanePreview>>,
+) {
+    let Ok((mut transform, mut visibility)) = preview_query.single_mut() else {
+        return;
+    };
+
+    let hover = (selection_state.active && selection_state.points.len() == 2)
+        .then(|| cursor_ray(&camera_query, &windows))
+        .flatten()
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes));
+    let Some(hover) = hover else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+
+    let points = [selection_state.points[0], selection_state.points[1], hover];
+    let normal = (points[1] - points[0]).cross(points[2] - points[0]);
+    if normal.length_squared() < 1e-6 {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+    let plane = GroundPlane::from_three_points(points[0], points[1], points[2]);
+    let center = (points[0] + points[1] + points[2]) / 3.0;
+    let size = points.iter().map(|p| p.distance(center)).fold(0.0, f32::max) * 2.0 + 4.0;
+
+    *transform = Transform::from_translation(center)
+        .with_rotation(Quat::from_rotation_arc(Vec3::Y, plane.normal))
+        .with_scale(Vec3::new(size, 1.0, size));
+    visibility.set_if_neq(Visibility::Visible);
+}
+
+/// Ray from the camera through the cursor, if the cursor is over the window
+pub fn cursor_ray(
+    camera_query: &Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: &Query<&Window>,
+) -> Option<Ray3d> {
+    let (camera, camera_transform) = camera_query.single().ok()?;
+    let cursor_pos = viewport_cursor(camera, windows.single().ok()?)?;
+    camera.viewport_to_world(camera_transform, cursor_pos).ok()
+}
+
+/// The cursor position in a camera's render target, which is smaller than the
+/// window while the render scale is below 1
+pub fn viewport_cursor(camera: &Camera, window: &Window) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let target_size = camera.logical_target_size().unwrap_or(window.size());
+    Some(cursor * target_size / window.size())
+}
+
+/// Point on the scanned surface hit by a ray, falling back to the primary plane
+/// where the ray misses the splats
+pub fn pick_ground_point(
+    ray: Ray3d,
+    point_cloud: &SplatPointCloud,
+    ground_planes: &GroundPlanes,
+) -> Option<Vec3> {
+    point_cloud
+        .raycast(ray, PICK_MAX_DISTANCE)
+        .or_else(|| intersect_plane(ray, &ground_planes.primary()))
+}
+
+/// Intersect a ray with the current ground plane
+fn intersect_plane(ray: Ray3d, ground_plane: &GroundPlane) -> Option<Vec3> {
+    let denom = ground_plane.normal.dot(*ray.direction);
+    if denom.abs() <= 0.0001 {
+        return None;
     }
+    let t = (ground_plane.origin - ray.origin).dot(ground_plane.normal) / denom;
+    (t > 0.0).then(|| ray.get_point(t))
+}
+
+/// Visualize the ground planes when selected
+fn visualize_ground_plane(
+    mut gizmos: Gizmos,
+    ground_planes: Res<GroundPlanes>,
+) {
+    for ground_plane in &ground_planes.planes {
+        // Draw a grid on the ground plane
+        let origin = ground_plane.origin;
+        let normal = ground_plane.normal;
+        let (tangent1, tangent2) = ground_plane.tangents();
+
+        let grid_size = 20.0;
+        let grid_spacing = 2.0;
+        let color = Color::srgba(0.0, 1.0, 0.0, 0.3);
+        let extent = ground_plane
+            .extent
+            .unwrap_or(Rect::from_center_half_size(Vec2::ZERO, Vec2::splat(grid_size)));
+        let point = |u: f32, v: f32| origin + tangent1 * u + tangent2 * v;
 
-    // Draw the normal vector
-    gizmos.arrow(origin, origin + normal * 3.0, Color::srgb(0.0, 0.0, 1.0));
+        // Lines along tangent2
+        let mut u = (extent.min.x / grid_spacing).ceil() * grid_spacing;
+        while u <= extent.max.x {
+            gizmos.line(point(u, extent.min.y), point(u, extent.max.y), color);
+            u += grid_spacing;
+        }
+
+        // Lines along tangent1
+        let mut v = (extent.min.y / grid_spacing).ceil() * grid_spacing;
+        while v <= extent.max.y {
+            gizmos.line(point(extent.min.x, v), point(extent.max.x, v), color);
+            v += grid_spacing;
+        }
+
+        // Outline the bounds of bounded planes
+        if ground_plane.extent.is_some() {
+            let corners = [
+                point(extent.min.x, extent.min.y),
+                point(extent.max.x, extent.min.y),
+                point(extent.max.x, extent.max.y),
+                point(extent.min.x, extent.max.y),
+                point(extent.min.x, extent.min.y),
+            ];
+            gizmos.linestrip(corners, Color::srgb(0.0, 1.0, 0.0));
+        }
+
+        // Draw the normal vector
+        gizmos.arrow(origin, origin + normal * 3.0, Color::srgb(0.0, 0.0, 1.0));
+    }
 }
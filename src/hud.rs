@@ -0,0 +1,338 @@
+//! Heads-up display
+//!
+//! While driving, the first player's lap is shown in the top left corner: the
+//! lap out of `RACE_LAPS` (or the stage in the Rally mode) and its time, the
+//! last and best laps, and a delta bar comparing the lap with the best one at
+//! each gate, growing green to the left when ahead and red to the right when
+//! behind. Racing other cars, the first player's position, like "P2/3", is
+//! shown above the lap. The bottom right corner shows the speed, the gear and the engine
+//! speed, with the rev bar turning red toward the redline. Press ';' to switch
+//! the speed between km/h and mph, which is also in the settings.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::leaderboard::RACE_LAPS;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::settings::Settings;
+use crate::standings::RaceStandings;
+use crate::track::{format_lap_time, CheckpointPassed, LapCompleted, LapTimer};
+
+/// Meters per second in a km/h and a mph
+pub const KMH: f32 = 1.0 / 3.6;
+pub const MPH: f32 = 0.447_04;
+
+/// Delta filling half the delta bar, in seconds
+const MAX_DELTA: f32 = 2.0;
+
+/// Width of the delta and rev bars, in pixels
+const BAR_WIDTH: f32 = 220.0;
+
+/// Fraction of the redline from which the rev bar turns red
+const SHIFT_LIGHT: f32 = 0.9;
+
+/// Plugin for the heads-up display
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LapSplits>()
+            .add_systems(Startup, spawn_hud)
+            .add_systems(
+                Update,
+                (
+                    toggle_speed_unit,
+                    track_splits,
+                    update_position_text,
+                    update_lap_panel,
+                    update_speed_panel,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the first player's times at each gate, to compare laps
+#[derive(Resource, Default)]
+struct LapSplits {
+    /// Lap time at each gate passed on this lap, after the start line
+    current: Vec<f32>,
+    /// Lap time at each gate on the best lap
+    best: Vec<f32>,
+    /// Time of the best lap
+    best_lap: Option<f32>,
+    /// Difference to the best lap at the last gate, negative when ahead
+    delta: Option<f32>,
+}
+
+/// Marker for the race position text
+#[derive(Component)]
+struct PositionText;
+
+/// Marker for the lap text
+#[derive(Component)]
+struct LapText;
+
+/// Marker for the part of the delta bar showing the time gained or lost
+#[derive(Component)]
+struct DeltaFill;
+
+/// Marker for the delta text
+#[derive(Component)]
+struct DeltaText;
+
+/// Marker for the speed text
+#[derive(Component)]
+struct SpeedText;
+
+/// Marker for the gear and engine speed text
+#[derive(Component)]
+struct GearText;
+
+/// Marker for the filled part of the rev bar
+#[derive(Component)]
+struct RevFill;
+
+/// A bar of `BAR_WIDTH` pixels with a dark background
+fn bar() -> impl Bundle {
+    (
+        Node {
+            width: Val::Px(BAR_WIDTH),
+            height: Val::Px(8.0),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.15)),
+    )
+}
+
+/// Text of the given size with a shadow
+fn hud_text(font_size: f32) -> impl Bundle {
+    (
+        Text::new(""),
+        TextFont {
+            font_size,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        TextShadow::default(),
+    )
+}
+
+/// Spawn the lap panel in the top left corner and the speed panel in the bottom right
+fn spawn_hud(mut commands: Commands) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(6.0),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel.spawn((hud_text(34.0), PositionText));
+            panel.spawn((hud_text(22.0), LapText));
+            panel.spawn(bar()).with_children(|bar| {
+                bar.spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                    DeltaFill,
+                ));
+                // Center line, where the delta is zero
+                bar.spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(BAR_WIDTH * 0.5 - 1.0),
+                        width: Val::Px(2.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::WHITE),
+                ));
+            });
+            panel.spawn((hud_text(18.0), DeltaText));
+        });
+
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.0),
+            right: Val::Px(12.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::End,
+            row_gap: Val::Px(6.0),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel.spawn((hud_text(40.0), SpeedText));
+            panel.spawn((hud_text(20.0), GearText));
+            panel.spawn(bar()).with_children(|bar| {
+                bar.spawn((
+                    Node {
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::WHITE),
+                    RevFill,
+                ));
+            });
+        });
+}
+
+/// Switch the speed between km/h and mph with ';'
+fn toggle_speed_unit(keyboard: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard.just_pressed(KeyCode::Semicolon) {
+        settings.imperial = !settings.imperial;
+        info!("Speed in {}", if settings.imperial { "mph" } else { "km/h" });
+    }
+}
+
+/// Note the first player's time at each gate and compare it with the best lap
+fn track_splits(
+    mode: Res<GameMode>,
+    checkpoints: Res<SceneCheckpoints>,
+    mut splits: ResMut<LapSplits>,
+    mut passed: MessageReader<CheckpointPassed>,
+    mut completed: MessageReader<LapCompleted>,
+    car_query: Query<Entity, With<PrimaryCar>>,
+) {
+    if checkpoints.is_changed() || mode.is_changed() {
+        *splits = LapSplits::default();
+    }
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+    for lap in completed.read().filter(|lap| lap.car == car) {
+        if let Some(best_lap) = splits.best_lap {
+            splits.delta = Some(lap.time - best_lap);
+        }
+        if lap.best {
+            splits.best = std::mem::take(&mut splits.current);
+            splits.best_lap = Some(lap.time);
+        }
+    }
+    for checkpoint in passed.read().filter(|checkpoint| checkpoint.car == car) {
+        // The start line begins a new lap
+        if checkpoint.index == 0 {
+            splits.current.clear();
+            continue;
+        }
+        splits.current.push(checkpoint.lap_time);
+        let gate = splits.current.len() - 1;
+        if let Some(&best) = splits.best.get(gate) {
+            splits.delta = Some(checkpoint.lap_time - best);
+        }
+    }
+}
+
+/// Show the first player's race position while racing other cars
+fn update_position_text(
+    standings: Res<RaceStandings>,
+    mut text_query: Query<(&mut Text, &mut Node), With<PositionText>>,
+) {
+    let Ok((mut text, mut node)) = text_query.single_mut() else {
+        return;
+    };
+    let position = standings.position();
+    let display = if position.is_some() { Display::Flex } else { Display::None };
+    if node.display != display {
+        node.display = display;
+    }
+    let line = position.map_or(String::new(), |(place, cars)| format!("P{}/{}", place, cars));
+    if text.0 != line {
+        text.0 = line;
+    }
+}
+
+/// Show the first player's lap, lap times and delta to the best lap
+fn update_lap_panel(
+    mode: Res<GameMode>,
+    checkpoints: Res<SceneCheckpoints>,
+    splits: Res<LapSplits>,
+    timer_query: Query<&LapTimer, With<PrimaryCar>>,
+    mut lap_query: Query<&mut Text, (With<LapText>, Without<DeltaText>)>,
+    mut delta_query: Query<&mut Text, (With<DeltaText>, Without<LapText>)>,
+    mut fill_query: Query<(&mut Node, &mut BackgroundColor), With<DeltaFill>>,
+) {
+    let (Ok(mut lap_text), Ok(mut delta_text), Ok((mut fill, mut fill_color))) =
+        (lap_query.single_mut(), delta_query.single_mut(), fill_query.single_mut())
+    else {
+        return;
+    };
+    let timer = timer_query.single().ok().filter(|_| !checkpoints.0.is_empty());
+    let Some(timer) = timer else {
+        lap_text.0.clear();
+        delta_text.0.clear();
+        fill.width = Val::Px(0.0);
+        return;
+    };
+
+    let lap = if !timer.started() {
+        "Cross the start line".to_string()
+    } else if mode.is_stage() {
+        format!("Stage  {}", format_lap_time(timer.lap_time))
+    } else if timer.lap <= RACE_LAPS {
+        format!("Lap {}/{}  {}", timer.lap, RACE_LAPS, format_lap_time(timer.lap_time))
+    } else {
+        format!("Lap {}  {}", timer.lap, format_lap_time(timer.lap_time))
+    };
+    let or_dash = |time: Option<f32>| time.map_or("-".to_string(), format_lap_time);
+    let (last, best) = (or_dash(timer.last_lap), or_dash(timer.best_lap));
+    lap_text.0 = format!("{}\nLast {}\nBest {}", lap, last, best);
+
+    let delta = splits.delta.unwrap_or(0.0);
+    let width = (delta.abs() / MAX_DELTA).min(1.0) * BAR_WIDTH * 0.5;
+    fill.width = Val::Px(width);
+    fill.left = Val::Px(if delta < 0.0 { BAR_WIDTH * 0.5 - width } else { BAR_WIDTH * 0.5 });
+    fill_color.0 = if delta < 0.0 {
+        Color::srgb(0.1, 0.9, 0.2)
+    } else {
+        Color::srgb(1.0, 0.15, 0.1)
+    };
+    delta_text.0 = match splits.delta {
+        Some(delta) => format!("{}{:.2}", if delta < 0.0 { "-" } else { "+" }, delta.abs()),
+        None => String::new(),
+    };
+}
+
+/// Show the first player's speed, gear and engine speed
+fn update_speed_panel(
+    settings: Res<Settings>,
+    car_query: Query<(&Car, &LinearVelocity), With<PrimaryCar>>,
+    mut speed_query: Query<&mut Text, (With<SpeedText>, Without<GearText>)>,
+    mut gear_query: Query<&mut Text, (With<GearText>, Without<SpeedText>)>,
+    mut rev_query: Query<(&mut Node, &mut BackgroundColor), With<RevFill>>,
+) {
+    let (Ok(mut speed_text), Ok(mut gear_text), Ok((mut rev, mut rev_color))) =
+        (speed_query.single_mut(), gear_query.single_mut(), rev_query.single_mut())
+    else {
+        return;
+    };
+    let Ok((car, velocity)) = car_query.single() else {
+        return;
+    };
+
+    let (unit, label) = if settings.imperial { (MPH, "mph") } else { (KMH, "km/h") };
+    speed_text.0 = format!("{:.0} {}", velocity.length() / unit, label);
+    let gear = match car.gear {
+        gear if gear < 0 => "R".to_string(),
+        0 => "N".to_string(),
+        gear => gear.to_string(),
+    };
+    gear_text.0 = format!("{}  {:?}  {:>4.0} rpm", gear, car.shift_mode, car.rpm);
+
+    let revs = (car.rpm / car.redline_rpm.max(1.0)).clamp(0.0, 1.0);
+    rev.width = Val::Px(revs * BAR_WIDTH);
+    rev_color.0 = if revs >= SHIFT_LIGHT {
+        Color::srgb(1.0, 0.15, 0.1)
+    } else {
+        Color::WHITE
+    };
+}
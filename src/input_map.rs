@@ -0,0 +1,319 @@
+//! Rebindable keyboard controls
+//!
+//! Driving code asks the `InputMap` whether an action is pressed instead of
+//! checking key codes, so players can bind any keys they like. Bindings are
+//! read from `input_map.json` in the working directory at startup, falling
+//! back to the defaults below.
+//!
+//! Press F1 to open the rebinding screen: Up/Down pick an action, Enter waits
+//! for the next key press and binds it in place of the current keys, Backspace
+//! clears the action and F1 closes the screen again. The Controls item of the
+//! settings screen opens it too. Every change is saved right away. Driving
+//! input is ignored while the screen is open.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// File the bindings are loaded from and saved to
+const INPUT_MAP_PATH: &str = "input_map.json";
+
+/// Plugin for the input map and its rebinding screen
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load())
+            .init_resource::<RebindScreen>()
+            .add_message::<OpenControls>()
+            .add_systems(Update, (rebind_controls, update_rebind_screen).chain());
+    }
+}
+
+/// Something the player can do with a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Accelerate,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    ShiftUp,
+    ShiftDown,
+    ToggleGearbox,
+    Boost,
+    Headlights,
+    CycleCamera,
+    LookBack,
+    RearViewMirror,
+    Minimap,
+    Spectate,
+    PhotoMode,
+    Respawn,
+    StartRace,
+}
+
+impl Action {
+    /// Every action, in the order shown on the rebinding screen
+    pub const ALL: [Action; 17] = [
+        Action::Accelerate,
+        Action::Brake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::ShiftUp,
+        Action::ShiftDown,
+        Action::ToggleGearbox,
+        Action::Boost,
+        Action::Headlights,
+        Action::CycleCamera,
+        Action::LookBack,
+        Action::RearViewMirror,
+        Action::Minimap,
+        Action::Spectate,
+        Action::PhotoMode,
+        Action::Respawn,
+        Action::StartRace,
+    ];
+
+    /// Keys bound to the action out of the box
+    fn default_keys(self) -> Vec<KeyCode> {
+        match self {
+            Action::Accelerate => vec![KeyCode::KeyW, KeyCode::ArrowUp],
+            Action::Brake => vec![KeyCode::KeyS, KeyCode::ArrowDown],
+            Action::SteerLeft => vec![KeyCode::KeyA, KeyCode::ArrowLeft],
+            Action::SteerRight => vec![KeyCode::KeyD, KeyCode::ArrowRight],
+            Action::ShiftUp => vec![KeyCode::KeyE],
+            Action::ShiftDown => vec![KeyCode::KeyQ],
+            Action::ToggleGearbox => vec![KeyCode::KeyM],
+            Action::Boost => vec![KeyCode::Space],
+            Action::Headlights => vec![KeyCode::KeyH],
+            // C toggles the collision proxy editor
+            Action::CycleCamera => vec![KeyCode::F4],
+            Action::LookBack => vec![KeyCode::KeyK],
+            Action::RearViewMirror => vec![KeyCode::F8],
+            Action::Minimap => vec![KeyCode::Backslash],
+            // F respawns the car
+            Action::Spectate => vec![KeyCode::F7],
+            Action::PhotoMode => vec![KeyCode::F9],
+            Action::Respawn => vec![KeyCode::KeyF],
+            Action::StartRace => vec![KeyCode::Enter],
+        }
+    }
+}
+
+/// Resource mapping actions to keys
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: HashMap<Action, Vec<KeyCode>>,
+    /// Set while the rebinding screen is open, so actions don't fire
+    #[serde(skip)]
+    pub suspended: bool,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_keys()))
+                .collect(),
+            suspended: false,
+        }
+    }
+}
+
+impl InputMap {
+    /// Read the bindings file, using the defaults for missing actions or if it doesn't exist
+    pub fn load() -> Self {
+        let mut map = Self::default();
+        let Ok(contents) = std::fs::read_to_string(INPUT_MAP_PATH) else {
+            return map;
+        };
+        match serde_json::from_str::<InputMap>(&contents) {
+            Ok(loaded) => {
+                info!("Loaded key bindings from {}", INPUT_MAP_PATH);
+                map.bindings.extend(loaded.bindings);
+            }
+            Err(err) => warn!("Ignoring unreadable key bindings {}: {}", INPUT_MAP_PATH, err),
+        }
+        map
+    }
+
+    /// Write the bindings file
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(INPUT_MAP_PATH, contents)
+    }
+
+    /// Keys bound to an action
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any key bound to the action is held
+    pub fn pressed(&self, action: Action, keyboard: &ButtonInput<KeyCode>) -> bool {
+        !self.suspended && keyboard.any_pressed(self.keys(action).iter().copied())
+    }
+
+    /// Whether any key bound to the action was pressed this frame
+    pub fn just_pressed(&self, action: Action, keyboard: &ButtonInput<KeyCode>) -> bool {
+        !self.suspended && keyboard.any_just_pressed(self.keys(action).iter().copied())
+    }
+
+    /// 1 while the action is held, 0 otherwise
+    pub fn value(&self, action: Action, keyboard: &ButtonInput<KeyCode>) -> f32 {
+        if self.pressed(action, keyboard) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Resource tracking the rebinding screen
+#[derive(Resource, Default)]
+pub struct RebindScreen {
+    /// The screen's root node while it is open
+    root: Option<Entity>,
+    /// Index into `Action::ALL` of the highlighted action
+    selected: usize,
+    /// Whether the next key press is bound to the highlighted action
+    capturing: bool,
+}
+
+impl RebindScreen {
+    /// Whether the screen is open
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+}
+
+/// Message opening the rebinding screen
+#[derive(Message, Debug, Clone, Copy)]
+pub struct OpenControls;
+
+/// Marker for the text listing the bindings
+#[derive(Component)]
+struct RebindText;
+
+/// Open and close the rebinding screen and capture new keys
+fn rebind_controls(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut screen: ResMut<RebindScreen>,
+    mut input_map: ResMut<InputMap>,
+    mut open: MessageReader<OpenControls>,
+) {
+    let open_requested = open.read().count() > 0;
+    if screen.capturing {
+        let Some(&key) = keyboard.get_just_pressed().next() else {
+            return;
+        };
+        screen.capturing = false;
+        if key == KeyCode::Escape {
+            return;
+        }
+        let action = Action::ALL[screen.selected];
+        input_map.bindings.insert(action, vec![key]);
+        info!("Bound {:?} to {:?}", action, key);
+        if let Err(err) = input_map.save() {
+            error!("Failed to save key bindings: {}", err);
+        }
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::F1) || (open_requested && screen.root.is_none()) {
+        if let Some(root) = screen.root.take() {
+            commands.entity(root).despawn();
+            input_map.suspended = false;
+        } else {
+            screen.root = Some(spawn_rebind_screen(&mut commands));
+            input_map.suspended = true;
+        }
+    }
+    // The Enter that chose Controls in the settings doesn't rebind anything
+    if screen.root.is_none() || open_requested {
+        return;
+    }
+
+    let count = Action::ALL.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        screen.selected = (screen.selected + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        screen.selected = (screen.selected + count - 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::Enter) {
+        screen.capturing = true;
+    }
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        let action = Action::ALL[screen.selected];
+        input_map.bindings.insert(action, Vec::new());
+        if let Err(err) = input_map.save() {
+            error!("Failed to save key bindings: {}", err);
+        }
+    }
+}
+
+/// Spawn the rebinding panel and return its root
+fn spawn_rebind_screen(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                RebindText,
+            ));
+        })
+        .id()
+}
+
+/// List the actions and their keys, marking the highlighted one
+fn update_rebind_screen(
+    screen: Res<RebindScreen>,
+    input_map: Res<InputMap>,
+    mut text_query: Query<(&mut Text, Ref<RebindText>)>,
+) {
+    let Ok((mut text, marker)) = text_query.single_mut() else {
+        return;
+    };
+    if !screen.is_changed() && !input_map.is_changed() && !marker.is_added() {
+        return;
+    }
+
+    let mut lines = vec![
+        "Controls - Up/Down: select, Enter: rebind, Backspace: clear, F1: close".to_string(),
+        String::new(),
+    ];
+    for (index, &action) in Action::ALL.iter().enumerate() {
+        let keys = if screen.capturing && index == screen.selected {
+            "press a key (Esc to cancel)".to_string()
+        } else {
+            let keys: Vec<String> =
+                input_map.keys(action).iter().map(|key| format!("{:?}", key)).collect();
+            if keys.is_empty() {
+                "unbound".to_string()
+            } else {
+                keys.join(", ")
+            }
+        };
+        let marker = if index == screen.selected { ">" } else { " " };
+        lines.push(format!("{} {:?}: {}", marker, action, keys));
+    }
+    text.0 = lines.join("\n");
+}
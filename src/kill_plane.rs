@@ -0,0 +1,131 @@
+//! Kill-plane detection and automatic respawn
+//!
+//! When the car ends up well below the ground or wanders far outside the
+//! scanned area, the screen fades to black, the car is respawned and the
+//! screen fades back in, instead of letting it drive into the void forever.
+
+use bevy::prelude::*;
+
+use crate::car::PrimaryCar;
+use crate::spawn_point::RespawnCar;
+use crate::splat_loader::SplatBounds;
+use crate::terrain::GroundSurface;
+
+/// Plugin for the kill plane and the respawn fade
+pub struct KillPlanePlugin;
+
+impl Plugin for KillPlanePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KillPlane>()
+            .init_resource::<RespawnFade>()
+            .add_systems(Startup, spawn_fade_overlay)
+            .add_systems(Update, (detect_kill_plane, update_respawn_fade).chain());
+    }
+}
+
+/// Resource configuring when the car counts as lost
+#[derive(Resource)]
+pub struct KillPlane {
+    /// Distance below the ground surface at which the car is respawned
+    pub depth: f32,
+    /// Horizontal distance outside the splat bounds at which the car is respawned
+    pub bounds_margin: f32,
+    /// Duration of the fade out, and of the fade in after the respawn, in seconds
+    pub fade_duration: f32,
+}
+
+impl Default for KillPlane {
+    fn default() -> Self {
+        Self {
+            depth: 10.0,
+            bounds_margin: 25.0,
+            fade_duration: 0.4,
+        }
+    }
+}
+
+/// Resource tracking a running respawn fade
+#[derive(Resource, Default)]
+struct RespawnFade {
+    /// Seconds since the fade started, `None` when no fade is running
+    elapsed: Option<f32>,
+}
+
+/// Marker for the full-screen fade overlay
+#[derive(Component)]
+struct FadeOverlay;
+
+/// Spawn the transparent overlay used for the fade
+fn spawn_fade_overlay(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        BackgroundColor(Color::NONE),
+        GlobalZIndex(100),
+        FadeOverlay,
+    ));
+}
+
+/// Start a respawn fade when the car falls below the kill plane or leaves the scan
+fn detect_kill_plane(
+    kill_plane: Res<KillPlane>,
+    mut fade: ResMut<RespawnFade>,
+    ground: GroundSurface,
+    bounds: Option<Res<SplatBounds>>,
+    car_query: Query<&Transform, With<PrimaryCar>>,
+) {
+    if fade.elapsed.is_some() {
+        return;
+    }
+    let Ok(transform) = car_query.single() else {
+        return;
+    };
+    let position = transform.translation;
+
+    let fell = ground.height_at(position) < -kill_plane.depth;
+    let outside = bounds.is_some_and(|bounds| {
+        let margin = Vec2::splat(kill_plane.bounds_margin);
+        let horizontal = position.xz();
+        horizontal.cmplt(bounds.min.xz() - margin).any()
+            || horizontal.cmpgt(bounds.max.xz() + margin).any()
+    });
+
+    if fell || outside {
+        info!("Car left the world, respawning");
+        fade.elapsed = Some(0.0);
+    }
+}
+
+/// Fade out, respawn the car at the darkest point, then fade back in
+fn update_respawn_fade(
+    time: Res<Time>,
+    kill_plane: Res<KillPlane>,
+    mut fade: ResMut<RespawnFade>,
+    mut respawns: MessageWriter<RespawnCar>,
+    mut overlay_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    let Some(elapsed) = fade.elapsed else {
+        return;
+    };
+    let duration = kill_plane.fade_duration.max(0.01);
+    let next = elapsed + time.delta_secs();
+
+    if elapsed < duration && next >= duration {
+        respawns.write(RespawnCar);
+    }
+
+    let alpha = if next < duration {
+        next / duration
+    } else {
+        1.0 - (next - duration) / duration
+    };
+    for mut background in overlay_query.iter_mut() {
+        background.0 = Color::BLACK.with_alpha(alpha.clamp(0.0, 1.0));
+    }
+
+    fade.elapsed = (next < 2.0 * duration).then_some(next);
+}
@@ -0,0 +1,321 @@
+//! Local leaderboards
+//!
+//! The best lap and the best time over `RACE_LAPS` laps from the start of the
+//! clock are kept for every combination of scan, track and vehicle, in
+//! `leaderboard.json` in the working directory, so records carry over between
+//! sessions. Scans and tracks are told apart by the same hashes as ghost files:
+//! the scan's file name and size and the gate transforms. Only the first
+//! player's laps are recorded, and invalidated laps don't count, nor does a
+//! race total including one.
+//!
+//! Press 'L' to show the leaderboard of the current scan and track, with the
+//! records of every vehicle driven on it, fastest lap first. Shift+L shows the
+//! top times on the online leaderboard server instead, if one is set.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::car::{CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::ghost::{scan_hash, track_hash};
+use crate::online_leaderboard::OnlineLeaderboard;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::sidecar::primary_splat_path;
+use crate::splat_loader::{LoadedSplat, LoadedSplats};
+use crate::track::{format_lap_time, LapCompleted};
+use crate::vehicle::{SelectedVehicle, Vehicles};
+
+/// File the records are loaded from and saved to
+const LEADERBOARD_PATH: &str = "leaderboard.json";
+
+/// Laps from the start of the clock that make up a race total
+pub const RACE_LAPS: u32 = 3;
+
+/// Plugin keeping lap records and showing the leaderboard
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Leaderboard::load())
+            .init_resource::<LeaderboardScreen>()
+            .add_systems(
+                Update,
+                (record_times.after(CarSet::Camera), toggle_leaderboard, update_leaderboard)
+                    .chain(),
+            );
+    }
+}
+
+/// Best times of one vehicle on one track of one scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    /// Hash of the scan, as in ghost files
+    pub scan_hash: u64,
+    /// Hash of the gates, as in ghost files
+    pub track_hash: u64,
+    /// Name of the vehicle
+    pub vehicle: String,
+    /// Fastest lap in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_lap: Option<f32>,
+    /// Fastest `RACE_LAPS` laps from the start of the clock, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_total: Option<f32>,
+}
+
+/// Resource with every record set on this machine
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    /// Read the records file, starting empty if it doesn't exist
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(LEADERBOARD_PATH) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Leaderboard>(&contents) {
+            Ok(leaderboard) => {
+                info!("Loaded {} leaderboard record(s)", leaderboard.entries.len());
+                leaderboard
+            }
+            Err(err) => {
+                warn!("Ignoring unreadable leaderboard {}: {}", LEADERBOARD_PATH, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the records file
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(LEADERBOARD_PATH, contents)
+    }
+
+    /// The records of a vehicle on a track, created empty if there are none yet
+    fn entry(&mut self, scan_hash: u64, track_hash: u64, vehicle: &str) -> &mut LeaderboardEntry {
+        let index = self.entries.iter().position(|entry| {
+            entry.scan_hash == scan_hash
+                && entry.track_hash == track_hash
+                && entry.vehicle == vehicle
+        });
+        let index = index.unwrap_or_else(|| {
+            self.entries.push(LeaderboardEntry {
+                scan_hash,
+                track_hash,
+                vehicle: vehicle.to_string(),
+                best_lap: None,
+                best_total: None,
+            });
+            self.entries.len() - 1
+        });
+        &mut self.entries[index]
+    }
+
+    /// Records of every vehicle on a track, fastest lap first
+    pub fn track_entries(&self, scan_hash: u64, track_hash: u64) -> Vec<&LeaderboardEntry> {
+        let mut entries: Vec<&LeaderboardEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.scan_hash == scan_hash && entry.track_hash == track_hash)
+            .collect();
+        entries.sort_by(|a, b| {
+            let lap = |entry: &LeaderboardEntry| entry.best_lap.unwrap_or(f32::INFINITY);
+            lap(a).total_cmp(&lap(b))
+        });
+        entries
+    }
+}
+
+/// Resource tracking the leaderboard screen
+#[derive(Resource, Default)]
+struct LeaderboardScreen {
+    /// The screen's root node while it is open
+    root: Option<Entity>,
+    /// Whether the open screen lists the online times rather than the local records
+    online: bool,
+}
+
+/// Marker for the text listing the records
+#[derive(Component)]
+struct LeaderboardText;
+
+/// Keep the first player's lap and race total if they beat the records
+fn record_times(
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    vehicles: Res<Vehicles>,
+    selected: Res<SelectedVehicle>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut completed: MessageReader<LapCompleted>,
+    car_query: Query<Entity, With<PrimaryCar>>,
+    mut race_time: Local<f32>,
+    mut race_valid: Local<bool>,
+) {
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+    let laps: Vec<&LapCompleted> = completed.read().filter(|lap| lap.car == car).collect();
+    if laps.is_empty() {
+        return;
+    }
+    let scan = scan_hash(primary_splat_path(&loaded_splats, &splat_query));
+    let track = track_hash(&checkpoints, *mode);
+    let vehicle = vehicles.0.get(selected.0).map_or("", |vehicle| vehicle.name.as_str());
+
+    let mut improved = false;
+    for lap in laps {
+        // The lap clock restarts from lap 1 whenever the track changes
+        *race_time = if lap.lap == 1 { lap.time } else { *race_time + lap.time };
+        *race_valid = lap.valid && (lap.lap == 1 || *race_valid);
+        let entry = leaderboard.entry(scan, track, vehicle);
+        if lap.valid && entry.best_lap.is_none_or(|best| lap.time < best) {
+            entry.best_lap = Some(lap.time);
+            improved = true;
+            info!("Lap record for {} on this track: {}", vehicle, format_lap_time(lap.time));
+        }
+        let race_record = *race_valid && entry.best_total.is_none_or(|best| *race_time < best);
+        if lap.lap == RACE_LAPS && race_record {
+            entry.best_total = Some(*race_time);
+            improved = true;
+            info!(
+                "{}-lap record for {} on this track: {}",
+                RACE_LAPS,
+                vehicle,
+                format_lap_time(*race_time)
+            );
+        }
+    }
+    if improved {
+        if let Err(err) = leaderboard.save() {
+            error!("Failed to save the leaderboard: {}", err);
+        }
+    }
+}
+
+/// Open and close the local leaderboard with 'L' and the online one with Shift+L
+fn toggle_leaderboard(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut screen: ResMut<LeaderboardScreen>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyL) {
+        return;
+    }
+    let online = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    // The other key switches boards rather than closing the screen
+    if let Some(root) = screen.root.take() {
+        commands.entity(root).despawn();
+        if screen.online == online {
+            return;
+        }
+    }
+    screen.online = online;
+    let root = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                LeaderboardText,
+            ));
+        })
+        .id();
+    screen.root = Some(root);
+}
+
+/// List the records of every vehicle on the current track
+fn update_leaderboard(
+    leaderboard: Res<Leaderboard>,
+    online: Res<OnlineLeaderboard>,
+    screen: Res<LeaderboardScreen>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    vehicles: Res<Vehicles>,
+    selected: Res<SelectedVehicle>,
+    mut text_query: Query<(&mut Text, Ref<LeaderboardText>)>,
+) {
+    let Ok((mut text, marker)) = text_query.single_mut() else {
+        return;
+    };
+    let changed = leaderboard.is_changed() || online.is_changed() || checkpoints.is_changed();
+    if !changed && !marker.is_added() {
+        return;
+    }
+
+    if screen.online {
+        text.0 = online_lines(&online).join("\n");
+        return;
+    }
+
+    let mut lines = vec!["Leaderboard - L: close".to_string(), String::new()];
+    if checkpoints.0.is_empty() {
+        lines.push("No track on this scan".to_string());
+        text.0 = lines.join("\n");
+        return;
+    }
+    let scan = scan_hash(primary_splat_path(&loaded_splats, &splat_query));
+    let entries = leaderboard.track_entries(scan, track_hash(&checkpoints, *mode));
+    if entries.is_empty() {
+        lines.push("No laps recorded on this track yet".to_string());
+    }
+    let driving = vehicles.0.get(selected.0).map(|vehicle| vehicle.name.as_str());
+    let or_dash = |time: Option<f32>| time.map_or("-".to_string(), format_lap_time);
+    for (rank, entry) in entries.iter().enumerate() {
+        let marker = if Some(entry.vehicle.as_str()) == driving { ">" } else { " " };
+        lines.push(format!(
+            "{} {}. {}  Lap {}  {} laps {}",
+            marker,
+            rank + 1,
+            entry.vehicle,
+            or_dash(entry.best_lap),
+            RACE_LAPS,
+            or_dash(entry.best_total)
+        ));
+    }
+    text.0 = lines.join("\n");
+}
+
+/// Lines listing the top times fetched from the online leaderboard server
+fn online_lines(online: &OnlineLeaderboard) -> Vec<String> {
+    let mut lines = vec!["Online leaderboard - Shift+L: close".to_string(), String::new()];
+    let Some(url) = &online.url else {
+        lines.push("No server set, start the game with --leaderboard=<url>".to_string());
+        return lines;
+    };
+    lines.push(url.clone());
+    if online.top.is_empty() {
+        lines.push("No times fetched".to_string());
+    }
+    for (rank, time) in online.top.iter().enumerate() {
+        let marker = if time.player == online.player { ">" } else { " " };
+        lines.push(format!(
+            "{} {}. {}  {}  {}",
+            marker,
+            rank + 1,
+            time.player,
+            time.vehicle,
+            format_lap_time(time.lap_time)
+        ));
+    }
+    lines
+}
@@ -0,0 +1,145 @@
+//! Loading progress overlay
+//!
+//! Shows a progress bar with the percentage and bytes read while a splat is
+//! being downloaded or loaded, so large files don't look like a frozen app.
+//! Loads that don't report their bytes, like PLY files below the streaming
+//! size, get a bar sweeping back and forth instead.
+
+use bevy::prelude::*;
+
+use crate::splat_loader::{SplatLoadProgress, SplatLoadState};
+
+/// Width of the sweeping bar in percent, and seconds for it to go and come back
+const SWEEP_WIDTH: f32 = 25.0;
+const SWEEP_SECONDS: f32 = 1.5;
+
+/// Plugin for the splat loading progress UI
+pub struct LoadingUiPlugin;
+
+impl Plugin for LoadingUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(SplatLoadState::Downloading), spawn_loading_ui)
+            .add_systems(OnEnter(SplatLoadState::Loading), spawn_loading_ui)
+            .add_systems(Update, update_loading_ui.run_if(
+                in_state(SplatLoadState::Downloading).or(in_state(SplatLoadState::Loading)),
+            ));
+    }
+}
+
+/// Marker for the filled part of the progress bar
+#[derive(Component)]
+struct LoadingBarFill;
+
+/// Marker for the progress label
+#[derive(Component)]
+struct LoadingText;
+
+/// Spawn the progress bar, removed automatically when the current state ends
+fn spawn_loading_ui(mut commands: Commands, state: Res<State<SplatLoadState>>) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.0),
+            width: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        DespawnOnExit(*state.get()),
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new(status_label(*state.get())),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            LoadingText,
+        ));
+
+        parent.spawn((
+            Node {
+                width: Val::Px(400.0),
+                height: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.2)),
+        )).with_children(|bar| {
+            bar.spawn((
+                Node {
+                    width: Val::Percent(0.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.8, 0.3)),
+                LoadingBarFill,
+            ));
+        });
+    });
+}
+
+/// Update the bar width and label from the loader's byte counters
+fn update_loading_ui(
+    time: Res<Time<Real>>,
+    progress: Res<SplatLoadProgress>,
+    state: Res<State<SplatLoadState>>,
+    mut fill_query: Query<&mut Node, With<LoadingBarFill>>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    let bytes_read = progress.bytes_read();
+
+    if let Ok(mut fill) = fill_query.single_mut() {
+        if bytes_read == 0 {
+            let phase = time.elapsed_secs() / SWEEP_SECONDS * std::f32::consts::TAU;
+            fill.width = Val::Percent(SWEEP_WIDTH);
+            fill.left = Val::Percent((0.5 - 0.5 * phase.cos()) * (100.0 - SWEEP_WIDTH));
+        } else {
+            fill.width = Val::Percent(progress.fraction().unwrap_or(0.0) * 100.0);
+            fill.left = Val::Px(0.0);
+        }
+    }
+
+    let Ok(mut text) = text_query.single_mut() else {
+        return;
+    };
+
+    let label = status_label(*state.get());
+    text.0 = match (progress.fraction(), progress.total_bytes()) {
+        (Some(fraction), Some(total)) if bytes_read > 0 => format!(
+            "{} {:.0}% ({} / {})",
+            label,
+            fraction * 100.0,
+            format_bytes(bytes_read),
+            format_bytes(total),
+        ),
+        (_, Some(total)) => format!("{} ({})", label, format_bytes(total)),
+        (_, None) => format!("{} {} read", label, format_bytes(bytes_read)),
+    };
+}
+
+/// Headline text for the progress overlay
+fn status_label(state: SplatLoadState) -> &'static str {
+    match state {
+        SplatLoadState::Downloading => "Downloading splat...",
+        _ => "Loading splat...",
+    }
+}
+
+/// Format a byte count with a human readable unit
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
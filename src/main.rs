@@ -1,65 +1,294 @@
 //! GaussRace - A racing game with Gaussian splatting
 //!
 //! This game allows the user to:
-//! 1. Load a Gaussian splat file (.ply)
+//! 1. Load a Gaussian splat file (.ply, .splat, .spz or .ksplat) or a .gaussrace scene
 //! 2. Select a ground plane within the splat
-//! 3. Drive a vehicle around on that plane
+//! 3. Drive a vehicle around on that plane and the terrain extracted around it
 
+use std::time::Duration;
+
+use avian3d::prelude::*;
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
 use bevy_gaussian_splatting::GaussianSplattingPlugin;
 
+mod assists;
+mod auto_flip;
+mod benchmark;
+mod blob_shadow;
+mod boost;
+mod boundary;
+mod camera;
+mod camera_effects;
 mod car;
+mod car_lights;
+mod cli;
+mod collectibles;
+mod collision_proxies;
+mod config;
+mod console;
+mod damage;
+mod depth_occlusion;
+mod diagnostics_overlay;
+mod drift;
+mod game_mode;
+mod ghost;
 mod ground_plane;
+mod hud;
+mod input_map;
+mod kill_plane;
+mod leaderboard;
+mod loading_ui;
+mod main_menu;
+mod minimap;
+mod network;
+mod obstacles;
+mod online_leaderboard;
+mod particles;
+mod pause_menu;
+mod photo_mode;
+mod point_cloud;
+mod post_processing;
+mod race_start;
+mod racing_line;
+mod racing_wheel;
+mod rally;
+mod rear_view_mirror;
+mod reflection_probe;
+mod render_scale;
+mod rolling_audio;
+mod scene_manifest;
+mod scripting;
+mod settings;
+mod shadow_proxy;
+mod sidecar;
+mod sky;
+mod spawn_point;
+mod spectator;
+mod standings;
+mod stunts;
+mod split_screen;
+mod spatial_index;
+mod splat_color;
+mod splat_editor;
+mod splat_formats;
 mod splat_loader;
+mod splat_lod;
+mod surface_zones;
+mod telemetry;
+mod telemetry_graphs;
+mod terrain;
+mod time_of_day;
+mod time_trial;
+mod track;
+mod track_file;
+mod track_limits;
+mod tuning_panel;
+mod vehicle;
+mod weather;
 
+use assists::AssistsPlugin;
+use auto_flip::AutoFlipPlugin;
+use benchmark::BenchmarkPlugin;
+use blob_shadow::BlobShadowPlugin;
+use boost::BoostPlugin;
+use boundary::BoundaryPlugin;
+use camera::{CameraPlugin, CarCamera};
+use camera_effects::CameraEffectsPlugin;
 use car::CarPlugin;
+use car_lights::CarLightsPlugin;
+use collectibles::CollectiblesPlugin;
+use collision_proxies::CollisionProxyPlugin;
+use config::{ConfigPlugin, GameConfig, DEFAULT_RESOLUTION};
+use console::ConsolePlugin;
+use damage::DamagePlugin;
+use depth_occlusion::DepthOcclusionPlugin;
+use diagnostics_overlay::DiagnosticsOverlayPlugin;
+use drift::DriftPlugin;
+use game_mode::GameModePlugin;
+use ghost::GhostPlugin;
 use ground_plane::GroundPlanePlugin;
+use hud::HudPlugin;
+use input_map::InputMapPlugin;
+use kill_plane::KillPlanePlugin;
+use leaderboard::LeaderboardPlugin;
+use loading_ui::LoadingUiPlugin;
+use main_menu::MainMenuPlugin;
+use minimap::MinimapPlugin;
+use network::NetworkPlugin;
+use obstacles::ObstacleCollisionPlugin;
+use online_leaderboard::OnlineLeaderboardPlugin;
+use particles::ParticlePlugin;
+use pause_menu::PauseMenuPlugin;
+use photo_mode::PhotoModePlugin;
+use point_cloud::PointCloudPlugin;
+use post_processing::PostProcessingPlugin;
+use race_start::RaceStartPlugin;
+use racing_line::RacingLinePlugin;
+use racing_wheel::RacingWheelPlugin;
+use rally::RallyPlugin;
+use rear_view_mirror::RearViewMirrorPlugin;
+use reflection_probe::ReflectionProbePlugin;
+use render_scale::RenderScalePlugin;
+use rolling_audio::RollingAudioPlugin;
+use scene_manifest::SceneManifestPlugin;
+use scripting::ScriptingPlugin;
+use settings::SettingsPlugin;
+use shadow_proxy::ShadowProxyPlugin;
+use sidecar::SidecarPlugin;
+use sky::SkyPlugin;
+use spawn_point::SpawnPointPlugin;
+use spectator::SpectatorPlugin;
+use standings::StandingsPlugin;
+use stunts::StuntPlugin;
+use split_screen::SplitScreenPlugin;
+use splat_color::SplatColorPlugin;
+use splat_editor::SplatEditorPlugin;
 use splat_loader::SplatLoaderPlugin;
+use splat_lod::SplatLodPlugin;
+use surface_zones::SurfaceZonePlugin;
+use telemetry::TelemetryPlugin;
+use telemetry_graphs::TelemetryGraphsPlugin;
+use terrain::TerrainPlugin;
+use time_of_day::TimeOfDayPlugin;
+use time_trial::TimeTrialPlugin;
+use track::TrackPlugin;
+use track_file::TrackFilePlugin;
+use track_limits::TrackLimitsPlugin;
+use tuning_panel::TuningPanelPlugin;
+use vehicle::VehiclePlugin;
+use weather::WeatherPlugin;
+
+/// Time between frames without a window
+const HEADLESS_FRAME_TIME: Duration = Duration::from_micros(16_667);
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "GaussRace - Gaussian Splat Racing".into(),
-                resolution: (1280, 720).into(),
-                ..default()
-            }),
+    // Parsed first, so --help and mistakes in the arguments don't open a window
+    let args = cli::args();
+    let mut config = GameConfig::load();
+    if args.fullscreen {
+        config.window.fullscreen = Some(true);
+    }
+    let resolution = config.window.resolution.unwrap_or(DEFAULT_RESOLUTION);
+    let mut plugins = DefaultPlugins.set(WindowPlugin {
+        primary_window: (!args.headless).then(|| Window {
+            title: config.window.title.clone(),
+            resolution: (resolution.x, resolution.y).into(),
             ..default()
-        }))
+        }),
+        exit_condition: if args.headless {
+            ExitCondition::DontExit
+        } else {
+            ExitCondition::OnAllClosed
+        },
+        ..default()
+    });
+    if args.headless {
+        // Without a window to drive the frames, they run on a timer
+        plugins = plugins
+            .disable::<WinitPlugin>()
+            .add(ScheduleRunnerPlugin::run_loop(HEADLESS_FRAME_TIME));
+    }
+    App::new()
+        .add_plugins(plugins)
+        .insert_resource(config)
         .add_plugins(GaussianSplattingPlugin)
+        .add_plugins(PhysicsPlugins::default())
         .add_plugins((
             SplatLoaderPlugin,
+            SplatLodPlugin,
+            LoadingUiPlugin,
+            PointCloudPlugin,
+            SidecarPlugin,
+            SceneManifestPlugin,
+            SplatEditorPlugin,
+            SplatColorPlugin,
             GroundPlanePlugin,
+            TerrainPlugin,
+            TimeOfDayPlugin,
+            WeatherPlugin,
+            SkyPlugin,
+            PostProcessingPlugin,
+            DepthOcclusionPlugin,
+        ))
+        .add_plugins((
+            InputMapPlugin,
             CarPlugin,
+            VehiclePlugin,
+            CarLightsPlugin,
+            CameraPlugin,
+            CameraEffectsPlugin,
+            SpectatorPlugin,
+            PhotoModePlugin,
+            RacingWheelPlugin,
+            AssistsPlugin,
+            BoostPlugin,
+            DamagePlugin,
+            ParticlePlugin,
+            RollingAudioPlugin,
+            AutoFlipPlugin,
+        ))
+        .add_plugins((
+            SpawnPointPlugin,
+            KillPlanePlugin,
+            BoundaryPlugin,
+            ObstacleCollisionPlugin,
+            CollisionProxyPlugin,
+            ShadowProxyPlugin,
+            ReflectionProbePlugin,
+            BlobShadowPlugin,
+            SurfaceZonePlugin,
+            TelemetryPlugin,
+            TelemetryGraphsPlugin,
+        ))
+        .add_plugins((
+            RearViewMirrorPlugin,
+            TrackPlugin,
+            TrackFilePlugin,
+            GhostPlugin,
+            RacingLinePlugin,
+            SplitScreenPlugin,
+            NetworkPlugin,
+            LeaderboardPlugin,
+            OnlineLeaderboardPlugin,
+            RaceStartPlugin,
+            HudPlugin,
+            MinimapPlugin,
+            DiagnosticsOverlayPlugin,
+            BenchmarkPlugin,
+            TuningPanelPlugin,
+        ))
+        .add_plugins((
+            PauseMenuPlugin,
+            MainMenuPlugin,
+            SettingsPlugin,
+            RenderScalePlugin,
+            GameModePlugin,
+            DriftPlugin,
+            TimeTrialPlugin,
+            RallyPlugin,
+            CollectiblesPlugin,
+            StuntPlugin,
+            TrackLimitsPlugin,
+            StandingsPlugin,
+            ConsolePlugin,
+            ScriptingPlugin,
+            ConfigPlugin,
         ))
         .add_systems(Startup, setup_scene)
         .run();
 }
 
-/// Sets up the initial scene with camera and lighting
+/// Sets up the initial scene with the camera; the lighting follows the time of day
 fn setup_scene(mut commands: Commands) {
-    // Spawn a 3D camera
+    // Spawn a 3D camera, following the car
     commands.spawn((
         Camera3d::default(),
+        CarCamera::default(),
+        // Holds the HUD, also when split-screen adds a second view
+        IsDefaultUiCamera,
         Transform::from_xyz(0.0, 10.0, 20.0).looking_at(Vec3::ZERO, Vec3::Y),
     ));
-
-    // Add ambient light
-    commands.spawn((
-        AmbientLight {
-            color: Color::WHITE,
-            brightness: 500.0,
-            affects_lightmapped_meshes: true,
-        },
-    ));
-
-    // Add directional light (sun)
-    commands.spawn((
-        DirectionalLight {
-            color: Color::WHITE,
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, -0.5, 0.5, 0.0)),
-    ));
 }
@@ -0,0 +1,563 @@
+//! Main menu and scene browser
+//!
+//! Unless a splat or scene is given on the command line, the game starts in
+//! the main menu. It lists the splats and scenes opened recently, newest
+//! first, with a thumbnail when a `.png` or `.jpg` of the same name sits next
+//! to the file. Up/Down pick an entry and Enter opens it; Browse opens a file
+//! dialog to find another one. Left/Right change the vehicle on the Vehicle
+//! line, the game mode on the Mode line and the weather on the Weather line,
+//! Settings opens the settings screen and Drive (or Escape) goes driving in
+//! whatever is loaded. The game is paused and driving input ignored while the
+//! menu is open.
+//!
+//! The recent files are kept in `recent_scenes.json` in the working directory.
+//! The pause menu's Change scene item opens the main menu again.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+
+use bevy::app::AppExit;
+use bevy::asset::RenderAssetUsages;
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use serde::{Deserialize, Serialize};
+
+use crate::cli;
+use crate::game_mode::GameMode;
+use crate::input_map::{InputMap, RebindScreen};
+use crate::scene_manifest::ActiveScene;
+use crate::settings::{navigate_settings, OpenSettings, SettingsScreen};
+use crate::sidecar::primary_splat_path;
+use crate::splat_loader::{is_url, resolve_local_path, FileOpener, LoadedSplat, LoadedSplats};
+use crate::vehicle::{SelectedVehicle, Vehicles};
+use crate::weather::Weather;
+
+/// File the recently opened splats and scenes are loaded from and saved to
+const RECENT_SCENES_PATH: &str = "recent_scenes.json";
+
+/// Most recent files listed
+const MAX_RECENT: usize = 8;
+
+/// Size of the thumbnails in logical pixels
+const THUMBNAIL_WIDTH: f32 = 96.0;
+const THUMBNAIL_HEIGHT: f32 = 54.0;
+
+/// Extensions the file dialog offers
+const BROWSE_EXTENSIONS: [&str; 6] = ["ply", "splat", "spz", "ksplat", "gaussrace", "json"];
+
+/// Plugin for the main menu
+pub struct MainMenuPlugin;
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MainMenu::from_args())
+            .insert_resource(RecentScenes::load())
+            .add_message::<OpenMainMenu>()
+            .add_systems(Startup, open_main_menu_at_start)
+            .add_systems(
+                Update,
+                (record_recent_scene, navigate_main_menu, update_main_menu)
+                    .chain()
+                    .before(navigate_settings),
+            );
+    }
+}
+
+/// Message opening the main menu
+#[derive(Message, Debug, Clone, Copy)]
+pub struct OpenMainMenu;
+
+/// Resource with the splats and scenes opened recently, newest first
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecentScenes {
+    pub paths: Vec<String>,
+}
+
+impl RecentScenes {
+    /// Read the recent files list, starting empty if it doesn't exist
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(RECENT_SCENES_PATH) else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            warn!("Ignoring unreadable recent files list {}: {}", RECENT_SCENES_PATH, err);
+            Self::default()
+        })
+    }
+
+    /// Write the recent files list
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(RECENT_SCENES_PATH, contents)
+    }
+
+    /// Move a path to the top of the list
+    fn record(&mut self, path: &str) {
+        self.paths.retain(|recent| recent != path);
+        self.paths.insert(0, path.to_string());
+        self.paths.truncate(MAX_RECENT);
+    }
+
+    /// The recent files that can still be opened
+    fn available(&self) -> Vec<&str> {
+        self.paths
+            .iter()
+            .map(String::as_str)
+            .filter(|path| is_url(path) || resolve_local_path(path).is_some())
+            .collect()
+    }
+}
+
+/// An entry of the main menu
+#[derive(Debug, Clone, PartialEq)]
+enum MainMenuItem {
+    Recent(String),
+    Browse,
+    Vehicle,
+    Mode,
+    Weather,
+    Settings,
+    Drive,
+    Quit,
+}
+
+/// Resource tracking the main menu
+#[derive(Resource)]
+pub struct MainMenu {
+    /// Whether the menu opens when the game starts
+    open_at_start: bool,
+    /// The menu's root node while it is open
+    root: Option<Entity>,
+    /// Index into the items of the highlighted one
+    highlighted: usize,
+    /// Whether the game was already paused when the menu opened
+    was_paused: bool,
+    /// Whether the menu closed last frame and the driving input is still held
+    resuming: bool,
+    /// Whether the file dialog is open
+    browsing: bool,
+    /// Thumbnails found for the recent files, by path
+    thumbnails: HashMap<String, Option<Handle<Image>>>,
+    sender: Sender<Option<PathBuf>>,
+    receiver: Mutex<Receiver<Option<PathBuf>>>,
+}
+
+impl MainMenu {
+    /// Open the menu at the start unless the command line names something to load
+    fn from_args() -> Self {
+        let open_at_start = !cli::args().opens_anything();
+        let (sender, receiver) = channel();
+        Self {
+            open_at_start,
+            root: None,
+            highlighted: 0,
+            was_paused: false,
+            resuming: false,
+            browsing: false,
+            thumbnails: HashMap::new(),
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Whether the menu is open
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+
+    /// Items of the menu, the recent files first
+    fn items(recent: &RecentScenes) -> Vec<MainMenuItem> {
+        recent
+            .available()
+            .into_iter()
+            .map(|path| MainMenuItem::Recent(path.to_string()))
+            .chain([
+                MainMenuItem::Browse,
+                MainMenuItem::Vehicle,
+                MainMenuItem::Mode,
+                MainMenuItem::Weather,
+                MainMenuItem::Settings,
+                MainMenuItem::Drive,
+                MainMenuItem::Quit,
+            ])
+            .collect()
+    }
+}
+
+/// Marker for the rows listing the items
+#[derive(Component)]
+struct MainMenuList;
+
+/// Find the image shown as the thumbnail of a splat or scene: a `.png` or
+/// `.jpg` with the same name next to it
+fn load_thumbnail(path: &str, images: &mut Assets<Image>) -> Option<Handle<Image>> {
+    let local = resolve_local_path(path)?;
+    ["png", "jpg", "jpeg"].into_iter().find_map(|extension| {
+        let candidate = local.with_extension(extension);
+        let bytes = std::fs::read(&candidate).ok()?;
+        let image = Image::from_buffer(
+            &bytes,
+            ImageType::Extension(extension),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        match image {
+            Ok(image) => Some(images.add(image)),
+            Err(err) => {
+                warn!("Ignoring thumbnail {}: {}", candidate.display(), err);
+                None
+            }
+        }
+    })
+}
+
+/// Open the menu when the game starts without anything to load
+pub fn open_main_menu_at_start(
+    mut commands: Commands,
+    mut menu: ResMut<MainMenu>,
+    mut input_map: ResMut<InputMap>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if menu.open_at_start {
+        open_main_menu(&mut commands, &mut menu, &mut input_map, &mut time);
+    }
+}
+
+/// Pause the game and spawn the menu
+fn open_main_menu(
+    commands: &mut Commands,
+    menu: &mut MainMenu,
+    input_map: &mut InputMap,
+    time: &mut Time<Virtual>,
+) {
+    if menu.is_open() {
+        return;
+    }
+    menu.highlighted = 0;
+    menu.was_paused = time.is_paused();
+    time.pause();
+    input_map.suspended = true;
+    menu.root = Some(spawn_main_menu(commands));
+}
+
+/// Add the opened splat or scene to the recent files once it is loaded
+fn record_recent_scene(
+    loaded_splats: Res<LoadedSplats>,
+    active_scene: Option<Res<ActiveScene>>,
+    splat_query: Query<&LoadedSplat>,
+    mut recent: ResMut<RecentScenes>,
+    mut recorded_path: Local<Option<String>>,
+) {
+    if !loaded_splats.is_changed() {
+        return;
+    }
+    let Some(path) = primary_splat_path(&loaded_splats, &splat_query) else {
+        return;
+    };
+    if Some(path) == recorded_path.as_deref() {
+        return;
+    }
+    *recorded_path = Some(path.to_string());
+
+    // Splats opened through a scene are remembered as the scene
+    let scene = active_scene
+        .filter(|scene| scene.splat_paths.iter().any(|scene_path| scene_path == path));
+    let opened = scene.as_ref().map_or(path, |scene| scene.path.as_str());
+    if recent.paths.first().map(String::as_str) == Some(opened) {
+        return;
+    }
+    recent.record(opened);
+    if let Err(err) = recent.save() {
+        error!("Failed to save the recent files list: {}", err);
+    }
+}
+
+/// Open the menu on request, move the highlight, change the vehicle and open
+/// the chosen file
+fn navigate_main_menu(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut input_map: ResMut<InputMap>,
+    mut time: ResMut<Time<Virtual>>,
+    mut menu: ResMut<MainMenu>,
+    mut requests: MessageReader<OpenMainMenu>,
+    recent: Res<RecentScenes>,
+    vehicles: Res<Vehicles>,
+    mut selected: ResMut<SelectedVehicle>,
+    mut mode: ResMut<GameMode>,
+    mut weather: ResMut<Weather>,
+    controls: Res<RebindScreen>,
+    settings_screen: Res<SettingsScreen>,
+    mut opener: FileOpener,
+    mut open_settings: MessageWriter<OpenSettings>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    if requests.read().count() > 0 {
+        open_main_menu(&mut commands, &mut menu, &mut input_map, &mut time);
+    }
+    let Some(root) = menu.root else {
+        // The Enter that closed the menu must not also start a race
+        if menu.resuming {
+            menu.resuming = false;
+            input_map.suspended = false;
+        }
+        return;
+    };
+
+    // A file picked in the dialog is opened right away
+    let browsed = menu.receiver.lock().unwrap().try_recv();
+    let mut open = match browsed {
+        Ok(picked) => {
+            menu.browsing = false;
+            picked.map(|path| path.to_string_lossy().to_string())
+        }
+        Err(TryRecvError::Empty | TryRecvError::Disconnected) => None,
+    };
+
+    let covered = controls.is_open() || settings_screen.is_open();
+    if covered && open.is_none() {
+        return;
+    }
+    if !input_map.suspended {
+        input_map.suspended = true;
+    }
+
+    let items = MainMenu::items(&recent);
+    let count = items.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        menu.highlighted = (menu.highlighted + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        menu.highlighted = (menu.highlighted + count - 1) % count;
+    }
+    // The list shrinks when recent files go missing
+    if menu.highlighted >= count {
+        menu.highlighted = count - 1;
+    }
+    let highlighted = &items[menu.highlighted];
+
+    let next = keyboard.just_pressed(KeyCode::ArrowRight) || keyboard.just_pressed(KeyCode::Enter);
+    let previous = keyboard.just_pressed(KeyCode::ArrowLeft);
+    if *highlighted == MainMenuItem::Vehicle {
+        let vehicle_count = vehicles.0.len();
+        let mut vehicle = selected.0;
+        if next {
+            vehicle = (vehicle + 1) % vehicle_count;
+        }
+        if previous {
+            vehicle = (vehicle + vehicle_count - 1) % vehicle_count;
+        }
+        selected.set_if_neq(SelectedVehicle(vehicle));
+    }
+    if *highlighted == MainMenuItem::Mode {
+        let stepped = mode.step(next as i32 - previous as i32);
+        mode.set_if_neq(stepped);
+    }
+    if *highlighted == MainMenuItem::Weather {
+        let stepped = weather.step(next as i32 - previous as i32);
+        weather.set_if_neq(stepped);
+    }
+
+    let mut close = keyboard.just_pressed(KeyCode::Escape);
+    if keyboard.just_pressed(KeyCode::Enter) {
+        match highlighted {
+            MainMenuItem::Recent(path) => open = Some(path.clone()),
+            MainMenuItem::Browse if !menu.browsing => {
+                menu.browsing = true;
+                let sender = menu.sender.clone();
+                AsyncComputeTaskPool::get()
+                    .spawn(async move {
+                        let picked = rfd::AsyncFileDialog::new()
+                            .set_title("Open a splat or scene")
+                            .add_filter("Splats and scenes", &BROWSE_EXTENSIONS)
+                            .pick_file()
+                            .await;
+                        let _ = sender.send(picked.map(|file| file.path().to_path_buf()));
+                    })
+                    .detach();
+            }
+            MainMenuItem::Browse
+            | MainMenuItem::Vehicle
+            | MainMenuItem::Mode
+            | MainMenuItem::Weather => {}
+            MainMenuItem::Settings => {
+                open_settings.write(OpenSettings);
+            }
+            MainMenuItem::Drive => close = true,
+            MainMenuItem::Quit => {
+                info!("Quitting");
+                exit.write(AppExit::Success);
+                return;
+            }
+        }
+    }
+
+    if let Some(path) = open {
+        opener.open(path, false);
+        close = true;
+    }
+    if close {
+        commands.entity(root).despawn();
+        menu.root = None;
+        menu.resuming = true;
+        if !menu.was_paused {
+            time.unpause();
+        }
+    }
+}
+
+/// Spawn the main menu panel and return its root
+fn spawn_main_menu(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("GaussRace - Up/Down: select, Enter: open, Left/Right: change"),
+                TextFont {
+                    font_size: 22.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            parent.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    ..default()
+                },
+                MainMenuList,
+            ));
+            parent.spawn((
+                Text::new("Files dropped on the window are opened too"),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.7, 0.7, 0.7)),
+            ));
+        })
+        .id()
+}
+
+/// A line of menu text
+fn menu_text(text: String) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+    )
+}
+
+/// List the items with their thumbnails, marking the highlighted one, and hide
+/// the menu behind the settings
+fn update_main_menu(
+    mut commands: Commands,
+    mut menu: ResMut<MainMenu>,
+    controls: Res<RebindScreen>,
+    settings_screen: Res<SettingsScreen>,
+    mut root_query: Query<&mut Visibility>,
+    recent: Res<RecentScenes>,
+    vehicles: Res<Vehicles>,
+    selected: Res<SelectedVehicle>,
+    mode: Res<GameMode>,
+    weather: Res<Weather>,
+    mut images: ResMut<Assets<Image>>,
+    list_query: Query<(Entity, Ref<MainMenuList>, Option<&Children>)>,
+) {
+    if let Some(mut visibility) = menu.root.and_then(|root| root_query.get_mut(root).ok()) {
+        visibility.set_if_neq(if controls.is_open() || settings_screen.is_open() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+    let Ok((list, marker, children)) = list_query.single() else {
+        return;
+    };
+    let changed = menu.is_changed()
+        || recent.is_changed()
+        || selected.is_changed()
+        || mode.is_changed()
+        || weather.is_changed();
+    if !changed && !marker.is_added() {
+        return;
+    }
+    for &child in children.into_iter().flatten() {
+        commands.entity(child).despawn();
+    }
+
+    let vehicle = vehicles.0.get(selected.0).map_or("", |vehicle| vehicle.name.as_str());
+    for (index, item) in MainMenu::items(&recent).into_iter().enumerate() {
+        let marker = if index == menu.highlighted { ">" } else { " " };
+        let label = match &item {
+            MainMenuItem::Recent(path) => {
+                let name = Path::new(path).file_name().map_or(path.clone(), |name| {
+                    name.to_string_lossy().to_string()
+                });
+                format!("{} {}  ({})", marker, name, path)
+            }
+            MainMenuItem::Browse if menu.browsing => {
+                format!("{} Browse... (choose a file in the dialog)", marker)
+            }
+            MainMenuItem::Browse => format!("{} Browse...", marker),
+            MainMenuItem::Vehicle => format!("{} Vehicle: < {} >", marker, vehicle),
+            MainMenuItem::Mode => format!("{} Mode: < {} >", marker, mode.label()),
+            MainMenuItem::Weather => format!("{} Weather: < {} >", marker, weather.label()),
+            MainMenuItem::Settings => format!("{} Settings", marker),
+            MainMenuItem::Drive => format!("{} Drive", marker),
+            MainMenuItem::Quit => format!("{} Quit", marker),
+        };
+
+        let row = commands
+            .spawn((
+                Node {
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                ChildOf(list),
+            ))
+            .id();
+        if let MainMenuItem::Recent(path) = &item {
+            let thumbnail = menu
+                .thumbnails
+                .entry(path.clone())
+                .or_insert_with(|| load_thumbnail(path, &mut images))
+                .clone();
+            let size = Node {
+                width: Val::Px(THUMBNAIL_WIDTH),
+                height: Val::Px(THUMBNAIL_HEIGHT),
+                ..default()
+            };
+            match thumbnail {
+                Some(image) => commands.spawn((ImageNode::new(image), size, ChildOf(row))),
+                None => commands.spawn((
+                    size,
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.1)),
+                    ChildOf(row),
+                )),
+            };
+        }
+        commands.spawn((menu_text(label), ChildOf(row)));
+    }
+}
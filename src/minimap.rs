@@ -0,0 +1,311 @@
+//! Minimap
+//!
+//! When the splats finish loading, a camera high above them looks straight
+//! down with an orthographic lens and renders the whole scan once into a
+//! texture. The texture is shown in the bottom left corner with north (-Z) up,
+//! and dots on top of it mark the checkpoint gates, the gate to drive through
+//! next, the first player's car and every other car: the second player's in
+//! split-screen and those of players joined over the network. Press '\'
+//! (rebindable) to show or hide it.
+
+use bevy::camera::{RenderTarget, ScalingMode};
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+use crate::network::RemoteCar;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::splat_loader::SplatBounds;
+use crate::track::LapTimer;
+
+/// Size of the capture texture in pixels
+const CAPTURE_RESOLUTION: u32 = 512;
+
+/// Size of the minimap on screen and its border, in logical pixels
+const MINIMAP_SIZE: f32 = 220.0;
+const MINIMAP_BORDER: f32 = 3.0;
+
+/// Frames the capture camera renders before it is switched off, so the
+/// splats have been sorted for its view
+const CAPTURE_FRAMES: u32 = 3;
+
+/// Height of the capture camera above the top of the scan, in meters
+const CAPTURE_HEIGHT: f32 = 10.0;
+
+/// Diameter of the dots in logical pixels
+const DOT_SIZE: f32 = 8.0;
+
+/// Plugin for the minimap
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Minimap>()
+            .add_systems(Startup, spawn_minimap)
+            .add_systems(
+                Update,
+                (toggle_minimap, capture_minimap, update_gate_dots, update_car_dots)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the minimap's settings and the area it shows
+#[derive(Resource)]
+pub struct Minimap {
+    pub visible: bool,
+    /// Center of the captured area on the ground and its width, once captured
+    area: Option<(Vec2, f32)>,
+    /// Frames left before the capture camera is switched off
+    frames_left: u32,
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            area: None,
+            frames_left: 0,
+        }
+    }
+}
+
+impl Minimap {
+    /// Where a world position is on the minimap, as a fraction of its size
+    /// from the top left corner, if it is on the map
+    fn map_position(&self, position: Vec3) -> Option<Vec2> {
+        let (center, width) = self.area?;
+        let fraction = (position.xz() - center) / width + Vec2::splat(0.5);
+        (fraction.cmpge(Vec2::ZERO).all() && fraction.cmple(Vec2::ONE).all()).then_some(fraction)
+    }
+}
+
+/// Marker for the top-down capture camera
+#[derive(Component)]
+struct MinimapCamera;
+
+/// Marker for the minimap on the HUD
+#[derive(Component)]
+struct MinimapWidget;
+
+/// Component on a dot marking a gate, with its index
+#[derive(Component)]
+struct GateDot(usize);
+
+/// Component on a dot marking a car
+#[derive(Component)]
+struct CarDot(Entity);
+
+/// Spawn the inactive capture camera, its render target and the hidden widget
+fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = images.add(Image::new_target_texture(
+        CAPTURE_RESOLUTION,
+        CAPTURE_RESOLUTION,
+        TextureFormat::bevy_default(),
+    ));
+
+    commands.spawn((
+        Camera3d::default(),
+        Camera {
+            order: -2,
+            target: RenderTarget::from(image.clone()),
+            is_active: false,
+            ..default()
+        },
+        Projection::Orthographic(OrthographicProjection::default_3d()),
+        MinimapCamera,
+    ));
+
+    // The dots are placed over the image, inside the border
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(12.0),
+                left: Val::Px(12.0),
+                width: Val::Px(MINIMAP_SIZE),
+                height: Val::Px(MINIMAP_SIZE),
+                border: UiRect::all(Val::Px(MINIMAP_BORDER)),
+                ..default()
+            },
+            BorderColor::all(Color::srgb(0.1, 0.1, 0.1)),
+            Visibility::Hidden,
+            MinimapWidget,
+        ))
+        .with_child((
+            ImageNode::new(image),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+        ));
+}
+
+/// Show or hide the minimap
+fn toggle_minimap(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut minimap: ResMut<Minimap>,
+    mut widget_query: Query<&mut Visibility, With<MinimapWidget>>,
+) {
+    if input_map.just_pressed(Action::Minimap, &keyboard) {
+        minimap.visible = !minimap.visible;
+        info!("Minimap {}", if minimap.visible { "ON" } else { "OFF" });
+    }
+    let shown = minimap.visible && minimap.area.is_some();
+    for mut visibility in widget_query.iter_mut() {
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Capture the scan from above whenever its bounds change
+fn capture_minimap(
+    bounds: Option<Res<SplatBounds>>,
+    mut minimap: ResMut<Minimap>,
+    mut camera_query: Query<(&mut Camera, &mut Transform, &mut Projection), With<MinimapCamera>>,
+) {
+    let Ok((mut camera, mut transform, mut projection)) = camera_query.single_mut() else {
+        return;
+    };
+    let Some(bounds) = bounds else {
+        if minimap.area.is_some() {
+            minimap.area = None;
+        }
+        camera.is_active = false;
+        return;
+    };
+
+    if bounds.is_changed() {
+        let center = bounds.center();
+        let size = bounds.size();
+        let width = size.x.max(size.z).max(1.0);
+        let top = bounds.max.y + CAPTURE_HEIGHT;
+        // Looking down with -Z at the top of the image
+        *transform = Transform::from_xyz(center.x, top, center.z)
+            .looking_at(Vec3::new(center.x, bounds.min.y, center.z), Vec3::NEG_Z);
+        *projection = Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width,
+                height: width,
+            },
+            far: size.y + CAPTURE_HEIGHT * 2.0,
+            ..OrthographicProjection::default_3d()
+        });
+        minimap.area = Some((center.xz(), width));
+        minimap.frames_left = CAPTURE_FRAMES;
+        info!("Capturing the minimap over {:.0} m", width);
+    }
+
+    camera.is_active = minimap.frames_left > 0;
+    minimap.frames_left = minimap.frames_left.saturating_sub(1);
+}
+
+/// A round dot of the given color on the minimap
+fn dot(color: Color) -> impl Bundle {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Px(DOT_SIZE),
+            height: Val::Px(DOT_SIZE),
+            ..default()
+        },
+        BackgroundColor(color),
+        BorderRadius::MAX,
+        Visibility::Hidden,
+    )
+}
+
+/// Put a dot where a world position is on the minimap, hiding it off the map
+fn place_dot(minimap: &Minimap, position: Vec3, node: &mut Node, visibility: &mut Mut<Visibility>) {
+    match minimap.map_position(position) {
+        Some(fraction) => {
+            let inner = MINIMAP_SIZE - MINIMAP_BORDER * 2.0;
+            node.left = Val::Px(fraction.x * inner - DOT_SIZE * 0.5);
+            node.top = Val::Px(fraction.y * inner - DOT_SIZE * 0.5);
+            visibility.set_if_neq(Visibility::Inherited);
+        }
+        None => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
+
+/// Mark the gates, the next one to drive through brighter
+fn update_gate_dots(
+    mut commands: Commands,
+    minimap: Res<Minimap>,
+    checkpoints: Res<SceneCheckpoints>,
+    timer_query: Query<&LapTimer, With<PrimaryCar>>,
+    widget_query: Query<Entity, With<MinimapWidget>>,
+    mut dot_query: Query<(Entity, &GateDot, &mut Node, &mut Visibility, &mut BackgroundColor)>,
+) {
+    let Ok(widget) = widget_query.single() else {
+        return;
+    };
+    if checkpoints.is_changed() {
+        for (entity, ..) in dot_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        for index in 0..checkpoints.0.len() {
+            commands.spawn((dot(Color::NONE), GateDot(index), ChildOf(widget)));
+        }
+        return;
+    }
+
+    let next_gate = timer_query.single().map_or(0, |timer| timer.next_gate);
+    for (_, gate, mut node, mut visibility, mut color) in dot_query.iter_mut() {
+        let Some(checkpoint) = checkpoints.0.get(gate.0) else {
+            continue;
+        };
+        place_dot(&minimap, checkpoint.translation, &mut node, &mut visibility);
+        let new_color = if gate.0 == next_gate {
+            Color::WHITE
+        } else {
+            Color::srgb(0.2, 0.5, 1.0)
+        };
+        color.set_if_neq(BackgroundColor(new_color));
+    }
+}
+
+/// Mark every car, the first player's on top in yellow
+fn update_car_dots(
+    mut commands: Commands,
+    minimap: Res<Minimap>,
+    car_query: Query<(Entity, &Transform, Has<PrimaryCar>), Or<(With<Car>, With<RemoteCar>)>>,
+    widget_query: Query<Entity, With<MinimapWidget>>,
+    mut dot_query: Query<(Entity, &CarDot, &mut Node, &mut Visibility)>,
+) {
+    let Ok(widget) = widget_query.single() else {
+        return;
+    };
+    for (entity, car_dot, mut node, mut visibility) in dot_query.iter_mut() {
+        match car_query.get(car_dot.0) {
+            Ok((_, transform, _)) => {
+                place_dot(&minimap, transform.translation, &mut node, &mut visibility);
+            }
+            Err(_) => commands.entity(entity).despawn(),
+        }
+    }
+    for (car, _, primary) in car_query.iter() {
+        if dot_query.iter().any(|(_, car_dot, ..)| car_dot.0 == car) {
+            continue;
+        }
+        let color = if primary {
+            Color::srgb(1.0, 0.9, 0.1)
+        } else {
+            Color::srgb(1.0, 0.4, 0.1)
+        };
+        let mut entity_commands = commands.spawn((dot(color), CarDot(car), ChildOf(widget)));
+        // The first player's dot is drawn over the others
+        if primary {
+            entity_commands.insert(ZIndex(1));
+        }
+    }
+}
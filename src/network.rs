@@ -0,0 +1,535 @@
+//! Networked multiplayer over UDP
+//!
+//! One player hosts a race with `--host=<port>` and friends join with
+//! `--join=<host>:<port>`, each opening the same scene as usual. Once its scene
+//! is loaded a joining game says hello until the host welcomes it with a player
+//! number and hashes of what it races on: the scene manifest's contents, or
+//! the primary splat's file name and size, and the checkpoint gates. A game on
+//! a different scene or track refuses to race and goes offline.
+//!
+//! From then on every game sends the pose and lap state of its car to the host
+//! twenty times a second, and the host passes everyone's on to everyone else.
+//! The other players' cars are drawn where they were last reported, eased
+//! toward each new update, and everyone's laps are listed in the top right
+//! corner. Remote cars are only drawn, so the local car drives through them.
+//! Players not heard from for a few seconds are dropped.
+//!
+//! Packets are small JSON datagrams, a few hundred bytes per car update.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::car::{CarSet, PrimaryCar, CAR_HALF_EXTENTS};
+use crate::cli;
+use crate::game_mode::GameMode;
+use crate::ghost::{fnv1a, scan_hash, track_hash};
+use crate::scene_manifest::{ActiveScene, SceneCheckpoints};
+use crate::sidecar::primary_splat_path;
+use crate::splat_loader::{resolve_local_path, LoadedSplat, LoadedSplats};
+use crate::track::{format_lap_time, LapTimer};
+
+/// Car updates sent per second
+const SEND_RATE: f32 = 20.0;
+
+/// Seconds without a packet after which a player is dropped
+const PLAYER_TIMEOUT: f64 = 5.0;
+
+/// How quickly remote cars ease toward their reported pose, per second
+const REMOTE_SMOOTHING: f32 = 15.0;
+
+/// Largest datagram read
+const MAX_PACKET_SIZE: usize = 2048;
+
+/// Most players in a race, the host included
+const MAX_PLAYERS: u8 = 8;
+
+/// Plugin for networked races
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Network>()
+            .add_systems(Startup, (start_network, spawn_network_display))
+            .add_systems(
+                Update,
+                (
+                    receive_packets,
+                    send_car_state,
+                    drop_silent_players,
+                    sync_remote_cars,
+                    update_network_display,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Which side of a race this game is on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NetworkRole {
+    #[default]
+    Offline,
+    /// Hosting on a port
+    Host { port: u16 },
+    /// Joined to a host
+    Client { host: SocketAddr },
+}
+
+/// Resource with the connection to the other players
+#[derive(Resource, Default)]
+pub struct Network {
+    pub role: NetworkRole,
+    /// This game's player number once the host has welcomed it; the host is 0
+    pub player: Option<u8>,
+    socket: Option<UdpSocket>,
+    /// Addresses of the joined games, on the host
+    clients: HashMap<SocketAddr, Client>,
+    /// Latest state of every other player
+    remotes: HashMap<u8, RemotePlayer>,
+}
+
+/// Where another player's car is in the race
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteProgress {
+    pub player: u8,
+    pub translation: Vec3,
+    /// Lap being driven, 0 before the start line
+    pub lap: u32,
+    /// Index of the gate the car drives through next
+    pub next_gate: usize,
+}
+
+impl Network {
+    /// The race progress of every other player
+    pub fn remote_progress(&self) -> impl Iterator<Item = RemoteProgress> + '_ {
+        self.remotes.values().map(|remote| RemoteProgress {
+            player: remote.state.player,
+            translation: remote.state.translation,
+            lap: remote.state.lap,
+            next_gate: remote.state.next_gate,
+        })
+    }
+
+    /// Send a packet, dropping it if the network is down; UDP may lose it anyway
+    fn send(&self, packet: &Packet, to: SocketAddr) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        if let Err(err) = socket.send_to(&packet.to_bytes(), to) {
+            debug!("Failed to send to {}: {}", to, err);
+        }
+    }
+}
+
+/// A game joined to this host
+struct Client {
+    player: u8,
+    /// Real time the game was last heard from, in seconds
+    heard_at: f64,
+}
+
+/// The latest state of another player
+struct RemotePlayer {
+    state: CarState,
+    /// Real time the state arrived, in seconds
+    heard_at: f64,
+}
+
+/// Pose and lap state of a player's car
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct CarState {
+    player: u8,
+    translation: Vec3,
+    rotation: Quat,
+    lap: u32,
+    /// Gate the car drives through next, for race positions
+    #[serde(default)]
+    next_gate: usize,
+    lap_time: f32,
+    best_lap: Option<f32>,
+}
+
+/// A datagram between the host and a joined game
+#[derive(Debug, Serialize, Deserialize)]
+enum Packet {
+    /// A game asking to join
+    Hello,
+    /// The host admitting a game, with its player number and what it races on
+    Welcome {
+        player: u8,
+        scene_hash: u64,
+        track_hash: u64,
+    },
+    /// The host turning a game away
+    Full,
+    /// A car's latest state
+    State(CarState),
+}
+
+impl Packet {
+    fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+/// Component for a car driven by another player
+#[derive(Component)]
+pub struct RemoteCar(pub u8);
+
+/// Marker for the list of players
+#[derive(Component)]
+struct NetworkDisplay;
+
+/// Hash identifying the scene: the manifest's contents, or the primary splat
+fn scene_hash(
+    active_scene: Option<&ActiveScene>,
+    loaded_splats: &LoadedSplats,
+    splat_query: &Query<&LoadedSplat>,
+) -> u64 {
+    match active_scene {
+        Some(scene) => resolve_local_path(&scene.path)
+            .and_then(|path| std::fs::read(path).ok())
+            .map_or(0, fnv1a),
+        None => scan_hash(primary_splat_path(loaded_splats, splat_query)),
+    }
+}
+
+/// Open a socket hosting a race on a port
+fn host(port: &str) -> Result<(UdpSocket, NetworkRole), String> {
+    let port = port.parse::<u16>().map_err(|_| format!("{} is not a port", port))?;
+    let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|err| err.to_string())?;
+    Ok((socket, NetworkRole::Host { port }))
+}
+
+/// Open a socket for joining the race at a host's address
+fn join(address: &str) -> Result<(UdpSocket, NetworkRole), String> {
+    let host = address
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| format!("{} not found", address))?;
+    let any = if host.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(any).map_err(|err| err.to_string())?;
+    Ok((socket, NetworkRole::Client { host }))
+}
+
+/// Host or join a race as asked on the command line
+fn start_network(mut network: ResMut<Network>) {
+    let args = cli::args();
+    let (arg, result) = if let Some(port) = &args.host {
+        (format!("--host={}", port), host(port))
+    } else if let Some(address) = &args.join {
+        (format!("--join={}", address), join(address))
+    } else {
+        return;
+    };
+    let (socket, role) = match result.and_then(|(socket, role)| {
+        socket.set_nonblocking(true).map_err(|err| err.to_string())?;
+        Ok((socket, role))
+    }) {
+        Ok(connection) => connection,
+        Err(err) => {
+            error!("Failed to start the network for {}: {}", arg, err);
+            return;
+        }
+    };
+    match role {
+        NetworkRole::Host { port } => info!("Hosting a race on port {}", port),
+        NetworkRole::Client { host } => info!("Joining the race at {}", host),
+        NetworkRole::Offline => {}
+    }
+    *network = Network {
+        role,
+        player: matches!(role, NetworkRole::Host { .. }).then_some(0),
+        socket: Some(socket),
+        ..default()
+    };
+}
+
+/// Spawn the list of players in the top right corner
+fn spawn_network_display(mut commands: Commands) {
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        TextShadow::default(),
+        TextLayout::new_with_justify(Justify::Right),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            right: Val::Px(12.0),
+            ..default()
+        },
+        NetworkDisplay,
+    ));
+}
+
+/// Handle every packet that arrived since the last frame
+fn receive_packets(
+    time: Res<Time<Real>>,
+    mut network: ResMut<Network>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    active_scene: Option<Res<ActiveScene>>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+) {
+    let now = time.elapsed_secs_f64();
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    loop {
+        let Some(socket) = &network.socket else {
+            return;
+        };
+        let (size, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+            Err(err) => {
+                debug!("Network error: {}", err);
+                return;
+            }
+        };
+        let Some(packet) = Packet::from_bytes(&buffer[..size]) else {
+            continue;
+        };
+
+        match (network.role, packet) {
+            (NetworkRole::Host { .. }, Packet::Hello) => {
+                let player = network.clients.get(&from).map(|client| client.player).or_else(|| {
+                    (1..MAX_PLAYERS).find(|&player| {
+                        !network.clients.values().any(|client| client.player == player)
+                    })
+                });
+                let reply = match player {
+                    Some(player) => {
+                        let client = Client { player, heard_at: now };
+                        if network.clients.insert(from, client).is_none() {
+                            info!("Player {} joined from {}", player + 1, from);
+                        }
+                        Packet::Welcome {
+                            player,
+                            scene_hash: scene_hash(
+                                active_scene.as_deref(),
+                                &loaded_splats,
+                                &splat_query,
+                            ),
+                            track_hash: track_hash(&checkpoints, *mode),
+                        }
+                    }
+                    None => Packet::Full,
+                };
+                network.send(&reply, from);
+            }
+            (NetworkRole::Host { .. }, Packet::State(state)) => {
+                // A game only reports its own car
+                let Some(client) = network.clients.get_mut(&from) else {
+                    continue;
+                };
+                if client.player == state.player {
+                    client.heard_at = now;
+                    network.remotes.insert(state.player, RemotePlayer { state, heard_at: now });
+                }
+            }
+            (NetworkRole::Client { host }, packet) if from == host => match packet {
+                Packet::Welcome {
+                    player,
+                    scene_hash: host_scene,
+                    track_hash: host_track,
+                } => {
+                    if network.player.is_some() {
+                        continue;
+                    }
+                    let scene = scene_hash(active_scene.as_deref(), &loaded_splats, &splat_query);
+                    let track = track_hash(&checkpoints, *mode);
+                    if host_scene != scene || host_track != track {
+                        let what = if host_scene != scene { "scene" } else { "track" };
+                        error!("The host is racing on a different {}, going offline", what);
+                        *network = Network::default();
+                        return;
+                    }
+                    info!("Joined the race as player {}", player + 1);
+                    network.player = Some(player);
+                }
+                Packet::Full => {
+                    error!("The race at {} is full, going offline", host);
+                    *network = Network::default();
+                    return;
+                }
+                Packet::State(state) if Some(state.player) != network.player => {
+                    network.remotes.insert(state.player, RemotePlayer { state, heard_at: now });
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// State of this game's car, as player `player`
+fn own_state(
+    player: u8,
+    car_query: &Query<(&Transform, Option<&LapTimer>), With<PrimaryCar>>,
+) -> Option<CarState> {
+    let (transform, timer) = car_query.single().ok()?;
+    Some(CarState {
+        player,
+        translation: transform.translation,
+        rotation: transform.rotation,
+        lap: timer.map_or(0, |timer| timer.lap),
+        next_gate: timer.map_or(0, |timer| timer.next_gate),
+        lap_time: timer.map_or(0.0, |timer| timer.lap_time),
+        best_lap: timer.and_then(|timer| timer.best_lap),
+    })
+}
+
+/// Send the car's state to the host, or everyone's states to every joined game
+fn send_car_state(
+    time: Res<Time<Real>>,
+    network: Res<Network>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    car_query: Query<(&Transform, Option<&LapTimer>), With<PrimaryCar>>,
+    mut since_send: Local<f32>,
+) {
+    *since_send += time.delta_secs();
+    if *since_send < 1.0 / SEND_RATE {
+        return;
+    }
+    *since_send = 0.0;
+
+    match (network.role, network.player) {
+        (NetworkRole::Offline, _) => {}
+        // Ask to join once the scene to compare with the host's is open
+        (NetworkRole::Client { host }, None) => {
+            if primary_splat_path(&loaded_splats, &splat_query).is_some() {
+                network.send(&Packet::Hello, host);
+            }
+        }
+        (NetworkRole::Client { host }, Some(player)) => {
+            if let Some(state) = own_state(player, &car_query) {
+                network.send(&Packet::State(state), host);
+            }
+        }
+        (NetworkRole::Host { .. }, _) => {
+            let states: Vec<CarState> = own_state(0, &car_query)
+                .into_iter()
+                .chain(network.remotes.values().map(|remote| remote.state))
+                .collect();
+            for (&address, client) in &network.clients {
+                for state in states.iter().filter(|state| state.player != client.player) {
+                    network.send(&Packet::State(*state), address);
+                }
+            }
+        }
+    }
+}
+
+/// Drop the players and joined games that haven't been heard from for a while
+fn drop_silent_players(time: Res<Time<Real>>, mut network: ResMut<Network>) {
+    let now = time.elapsed_secs_f64();
+    let silent: Vec<u8> = network
+        .remotes
+        .iter()
+        .filter(|(_, remote)| now - remote.heard_at > PLAYER_TIMEOUT)
+        .map(|(&player, _)| player)
+        .collect();
+    for player in silent {
+        network.remotes.remove(&player);
+        network.clients.retain(|_, client| client.player != player);
+        info!("Player {} left the race", player + 1);
+    }
+
+    // A game that joined but never sent its car, like one that went offline
+    // on finding a different track, gives its slot back too
+    let silent: Vec<SocketAddr> = network
+        .clients
+        .iter()
+        .filter(|(_, client)| now - client.heard_at > PLAYER_TIMEOUT)
+        .map(|(&address, _)| address)
+        .collect();
+    for address in silent {
+        if let Some(client) = network.clients.remove(&address) {
+            info!("Player {} at {} left before racing", client.player + 1, address);
+        }
+    }
+}
+
+/// Spawn, move and remove the other players' cars
+fn sync_remote_cars(
+    mut commands: Commands,
+    time: Res<Time>,
+    network: Res<Network>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut car_query: Query<(Entity, &RemoteCar, &mut Transform)>,
+) {
+    let blend = (REMOTE_SMOOTHING * time.delta_secs()).min(1.0);
+    for (entity, remote_car, mut transform) in car_query.iter_mut() {
+        let Some(remote) = network.remotes.get(&remote_car.0) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        transform.translation = transform.translation.lerp(remote.state.translation, blend);
+        transform.rotation = transform.rotation.slerp(remote.state.rotation, blend);
+    }
+
+    for (&player, remote) in &network.remotes {
+        if car_query.iter().any(|(_, remote_car, _)| remote_car.0 == player) {
+            continue;
+        }
+        // A different color for every player
+        let hue = (f32::from(player) * 137.5) % 360.0;
+        commands.spawn((
+            RemoteCar(player),
+            Mesh3d(meshes.add(Cuboid::from_size(CAR_HALF_EXTENTS * 2.0))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::hsl(hue, 0.7, 0.5),
+                ..default()
+            })),
+            Transform::from_translation(remote.state.translation)
+                .with_rotation(remote.state.rotation),
+        ));
+    }
+}
+
+/// List the connection and every player's lap
+fn update_network_display(
+    network: Res<Network>,
+    car_query: Query<(&Transform, Option<&LapTimer>), With<PrimaryCar>>,
+    mut display_query: Query<&mut Text, With<NetworkDisplay>>,
+) {
+    let Ok(mut text) = display_query.single_mut() else {
+        return;
+    };
+    let status = match (network.role, network.player) {
+        (NetworkRole::Offline, _) => {
+            text.0.clear();
+            return;
+        }
+        (NetworkRole::Host { port }, _) => format!("Hosting on port {}", port),
+        (NetworkRole::Client { host }, None) => format!("Joining {}...", host),
+        (NetworkRole::Client { .. }, Some(player)) => format!("Player {}", player + 1),
+    };
+
+    let mut states: Vec<CarState> = network.remotes.values().map(|remote| remote.state).collect();
+    states.extend(network.player.and_then(|player| own_state(player, &car_query)));
+    states.sort_by_key(|state| state.player);
+    let mut lines = vec![status];
+    for state in states {
+        let you = if Some(state.player) == network.player { " (you)" } else { "" };
+        let best = state.best_lap.map_or("-".to_string(), format_lap_time);
+        lines.push(format!("P{}{}  Lap {}  Best {}", state.player + 1, you, state.lap, best));
+    }
+    text.0 = lines.join("\n");
+}
@@ -0,0 +1,112 @@
+//! Collision with obstacles found in the splat
+//!
+//! Walls, trees and parked cars show up in the scan as dense clusters of
+//! Gaussians standing above the ground. Each physics step the spatial index is
+//! asked for the Gaussians around the car; the ones inside the car's footprint
+//! and within the obstacle height band push the car back out and bounce it off.
+
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, CAR_HALF_EXTENTS};
+use crate::point_cloud::SplatPointCloud;
+use crate::terrain::GroundSurface;
+
+/// Plugin for splat density collision
+pub struct ObstacleCollisionPlugin;
+
+impl Plugin for ObstacleCollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ObstacleCollision>()
+            .add_systems(FixedUpdate, collide_with_splat_obstacles.in_set(CarSet::Constraints));
+    }
+}
+
+/// Resource tuning collision with dense splat regions
+#[derive(Resource)]
+pub struct ObstacleCollision {
+    pub enabled: bool,
+    /// Height band above the ground in which Gaussians count as obstacles;
+    /// the lower bound keeps curbs and road texture out
+    pub min_height: f32,
+    pub max_height: f32,
+    /// Gaussians fainter than this are ignored
+    pub min_opacity: f32,
+    /// Accumulated opacity inside the car's footprint needed to count as a hit,
+    /// so lone floaters don't stop the car
+    pub density_threshold: f32,
+    /// Fraction of speed kept, reversed, after a head-on hit
+    pub restitution: f32,
+}
+
+impl Default for ObstacleCollision {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_height: 0.3,
+            max_height: 2.5,
+            min_opacity: 0.3,
+            density_threshold: 4.0,
+            restitution: 0.3,
+        }
+    }
+}
+
+/// Push the cars out of dense splat regions and bounce them off
+fn collide_with_splat_obstacles(
+    settings: Res<ObstacleCollision>,
+    point_cloud: Res<SplatPointCloud>,
+    ground: GroundSurface,
+    mut car_query: Query<(&mut Car, &mut Transform)>,
+) {
+    if !settings.enabled || point_cloud.is_empty() {
+        return;
+    }
+    let half = CAR_HALF_EXTENTS.xz();
+    for (mut car, mut transform) in car_query.iter_mut() {
+        let inverse = transform.compute_affine().inverse();
+
+        // Gaussians inside the footprint, in car-local coordinates, weighted by opacity
+        let mut weight = 0.0;
+        let mut centroid = Vec2::ZERO;
+        for index in point_cloud.within_radius(transform.translation, half.length()) {
+            let position = point_cloud.positions[index];
+            let opacity = point_cloud.opacities[index];
+            let height = ground.height_at(position);
+            if opacity < settings.min_opacity
+                || height < settings.min_height
+                || height > settings.max_height
+            {
+                continue;
+            }
+            let local = inverse.transform_point3(position).xz();
+            if local.abs().cmplt(half).all() {
+                weight += opacity;
+                centroid += local * opacity;
+            }
+        }
+        if weight < settings.density_threshold {
+            continue;
+        }
+        centroid /= weight;
+
+        // Push out along the side of the car the obstacle is closest to
+        let relative = centroid / half;
+        let (push, head_on) = if relative.y.abs() >= relative.x.abs() {
+            (Vec3::Z * -centroid.y.signum() * (half.y - centroid.y.abs()), true)
+        } else {
+            (Vec3::X * -centroid.x.signum() * (half.x - centroid.x.abs()), false)
+        };
+        let world_push = transform.rotation * push;
+        transform.translation += world_push;
+
+        if head_on {
+            // Only bounce when driving into the obstacle, not when backing away
+            let moving_into = (centroid.y < 0.0) == (car.velocity > 0.0);
+            if moving_into {
+                car.velocity = -car.velocity * settings.restitution;
+            }
+        } else {
+            car.velocity *= 0.8;
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Optional online leaderboard
+//!
+//! Start the game with `--leaderboard=<url>` to share lap times on a
+//! leaderboard server, optionally with `--name=<name>` for the name they are
+//! listed under (the user name by default). Each first-player lap that is the
+//! fastest of the session is submitted, and the server's top times for the
+//! scan and track are fetched whenever the track changes, after each
+//! submission and when Shift+L opens them on the leaderboard screen.
+//!
+//! The server speaks JSON over HTTP. Hashes are the ghost file hashes written
+//! as 16 hexadecimal digits, since not every JSON reader keeps 64-bit numbers.
+//!
+//! - `POST <url>/laps` with `{"scan_hash", "track_hash", "vehicle", "player",
+//!   "lap_time", "replay_hash"}`, where `replay_hash` is the FNV-1a hash of the
+//!   lap's `.ghost` file, or `null` if a faster loaded ghost kept the replay
+//! - `GET <url>/laps?scan_hash=..&track_hash=..&limit=..` answering with an
+//!   array of `{"player", "vehicle", "lap_time", "replay_hash"}`, fastest first
+//!
+//! Requests run in the background and failures only log a warning.
+
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use serde::{Deserialize, Serialize};
+
+use crate::car::{CarSet, PrimaryCar};
+use crate::cli;
+use crate::game_mode::GameMode;
+use crate::ghost::{fnv1a, record_ghost, scan_hash, track_hash, GhostLaps};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::sidecar::primary_splat_path;
+use crate::splat_loader::{LoadedSplat, LoadedSplats};
+use crate::track::{format_lap_time, LapCompleted};
+use crate::vehicle::{SelectedVehicle, Vehicles};
+
+/// Top times fetched for a track
+const TOP_TIMES: usize = 10;
+
+/// Longest a request may take before it is given up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Plugin submitting lap times to a leaderboard server and fetching the best
+pub struct OnlineLeaderboardPlugin;
+
+impl Plugin for OnlineLeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(OnlineLeaderboard::from_args()).add_systems(
+            Update,
+            (submit_laps.after(record_ghost), fetch_top_times, receive_responses)
+                .chain()
+                .after(CarSet::Camera),
+        );
+    }
+}
+
+/// A lap time on the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnlineLapTime {
+    pub player: String,
+    pub vehicle: String,
+    /// Lap time in seconds
+    pub lap_time: f32,
+    /// Hash of the lap's ghost file, if it was kept
+    #[serde(default)]
+    pub replay_hash: Option<String>,
+}
+
+/// A lap time sent to the server
+#[derive(Debug, Serialize)]
+struct LapSubmission {
+    scan_hash: String,
+    track_hash: String,
+    vehicle: String,
+    player: String,
+    lap_time: f32,
+    replay_hash: Option<String>,
+}
+
+/// Response from a background request
+enum OnlineResponse {
+    Submitted(Result<(), String>),
+    Fetched {
+        scan_hash: u64,
+        track_hash: u64,
+        times: Result<Vec<OnlineLapTime>, String>,
+    },
+}
+
+/// Resource with the leaderboard server and its top times for the current track
+#[derive(Resource)]
+pub struct OnlineLeaderboard {
+    /// Base URL of the server, `None` when playing offline
+    pub url: Option<String>,
+    /// Name the laps are submitted under
+    pub player: String,
+    /// Top times of the current scan and track, fastest first
+    pub top: Vec<OnlineLapTime>,
+    /// Whether the top times should be fetched again
+    stale: bool,
+    agent: ureq::Agent,
+    sender: Sender<OnlineResponse>,
+    receiver: Mutex<Receiver<OnlineResponse>>,
+}
+
+impl OnlineLeaderboard {
+    /// Read the server URL and player name from the command line
+    fn from_args() -> Self {
+        let args = cli::args();
+        let url = args
+            .leaderboard
+            .as_ref()
+            .map(|url| url.trim_end_matches('/').to_string());
+        let player = args.name.clone().unwrap_or_else(|| {
+            std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "anonymous".to_string())
+        });
+        if let Some(url) = &url {
+            info!("Submitting lap times to {} as {}", url, player);
+        }
+        let (sender, receiver) = channel();
+        Self {
+            url,
+            player,
+            top: Vec::new(),
+            stale: false,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Run a request in the background, passing its response back
+    fn request(&self, run: impl FnOnce(&ureq::Agent) -> OnlineResponse + Send + 'static) {
+        let agent = self.agent.clone();
+        let sender = self.sender.clone();
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let _ = sender.send(run(&agent));
+            })
+            .detach();
+    }
+}
+
+/// A hash as the server expects it
+fn hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+/// Ask the server for the top times of a track
+fn fetch(agent: &ureq::Agent, url: &str, scan_hash: u64, track_hash: u64) -> OnlineResponse {
+    let times = agent
+        .get(&format!("{}/laps", url))
+        .query("scan_hash", &hex(scan_hash))
+        .query("track_hash", &hex(track_hash))
+        .query("limit", &TOP_TIMES.to_string())
+        .call()
+        .map_err(|err| err.to_string())
+        .and_then(|response| response.into_string().map_err(|err| err.to_string()))
+        .and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()));
+    OnlineResponse::Fetched {
+        scan_hash,
+        track_hash,
+        times,
+    }
+}
+
+/// Submit the first player's fastest laps of the session
+fn submit_laps(
+    leaderboard: Res<OnlineLeaderboard>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    vehicles: Res<Vehicles>,
+    selected: Res<SelectedVehicle>,
+    ghost_laps: Res<GhostLaps>,
+    mut completed: MessageReader<LapCompleted>,
+    car_query: Query<Entity, With<PrimaryCar>>,
+) {
+    let Some(url) = &leaderboard.url else {
+        return;
+    };
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+    let vehicle = vehicles.0.get(selected.0).map_or("", |vehicle| vehicle.name.as_str());
+    for lap in completed.read().filter(|lap| lap.car == car && lap.best) {
+        // The ghost holds this lap unless a loaded ghost was faster
+        let replay_hash = ghost_laps
+            .ghost
+            .as_ref()
+            .filter(|ghost| ghost.lap_time == lap.time)
+            .map(|ghost| hex(fnv1a(ghost.to_bytes())));
+        let submission = LapSubmission {
+            scan_hash: hex(scan_hash(primary_splat_path(&loaded_splats, &splat_query))),
+            track_hash: hex(track_hash(&checkpoints, *mode)),
+            vehicle: vehicle.to_string(),
+            player: leaderboard.player.clone(),
+            lap_time: lap.time,
+            replay_hash,
+        };
+        info!("Submitting the {} lap to {}", format_lap_time(lap.time), url);
+        let url = format!("{}/laps", url);
+        leaderboard.request(move |agent| {
+            let result = serde_json::to_string(&submission)
+                .map_err(|err| err.to_string())
+                .and_then(|body| {
+                    agent
+                        .post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                        .map_err(|err| err.to_string())
+                })
+                .map(|_| ());
+            OnlineResponse::Submitted(result)
+        });
+    }
+}
+
+/// Fetch the top times when the track changes, after a submission and when
+/// Shift+L opens the online leaderboard
+fn fetch_top_times(
+    mut leaderboard: ResMut<OnlineLeaderboard>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+) {
+    let Some(url) = leaderboard.url.clone() else {
+        return;
+    };
+    if checkpoints.is_changed() || mode.is_changed() || loaded_splats.is_changed() {
+        leaderboard.top.clear();
+        leaderboard.stale = true;
+    }
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if !leaderboard.stale && !(shift && keyboard.just_pressed(KeyCode::KeyL)) {
+        return;
+    }
+    leaderboard.stale = false;
+    if checkpoints.0.is_empty() {
+        return;
+    }
+
+    let scan_hash = scan_hash(primary_splat_path(&loaded_splats, &splat_query));
+    let track_hash = track_hash(&checkpoints, *mode);
+    leaderboard.request(move |agent| fetch(agent, &url, scan_hash, track_hash));
+}
+
+/// Take in the responses of finished requests
+fn receive_responses(
+    mut leaderboard: ResMut<OnlineLeaderboard>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+) {
+    loop {
+        let response = leaderboard.receiver.lock().unwrap().try_recv();
+        match response {
+            Ok(OnlineResponse::Submitted(Ok(()))) => {
+                info!("Lap time submitted");
+                leaderboard.stale = true;
+            }
+            Ok(OnlineResponse::Submitted(Err(err))) => warn!("Failed to submit lap time: {}", err),
+            Ok(OnlineResponse::Fetched {
+                scan_hash: scan,
+                track_hash: track,
+                times,
+            }) => {
+                // Ignore answers about a track that has since been left
+                let current = (
+                    scan_hash(primary_splat_path(&loaded_splats, &splat_query)),
+                    track_hash(&checkpoints, *mode),
+                );
+                match times {
+                    Ok(times) if current == (scan, track) => leaderboard.top = times,
+                    Ok(_) => {}
+                    Err(err) => warn!("Failed to fetch the online leaderboard: {}", err),
+                }
+            }
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Lightweight particles for smoke, dust and flames
+//!
+//! Effects send `SpawnParticle` messages; each particle is a small sphere that
+//! drifts with its velocity, grows and fades out over its lifetime. There is a
+//! cap on live particles so heavy effects can't swamp the frame.
+
+use bevy::prelude::*;
+
+/// Most particles alive at once; new ones are dropped beyond this
+const MAX_PARTICLES: usize = 600;
+
+/// How quickly particles slow down in the air, per second
+const PARTICLE_DRAG: f32 = 1.5;
+
+/// Plugin spawning, moving and fading particles
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SpawnParticle>()
+            .add_systems(Startup, setup_particle_mesh)
+            .add_systems(Update, (spawn_particles, update_particles).chain());
+    }
+}
+
+/// Message requesting a particle
+#[derive(Message, Debug, Clone, Copy)]
+pub struct SpawnParticle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub color: Color,
+    /// Radius at birth; particles grow to twice this by the end of their life
+    pub size: f32,
+    /// Seconds until the particle disappears
+    pub lifetime: f32,
+    /// Whether the particle glows, like flames, instead of being lit, like smoke
+    pub emissive: bool,
+}
+
+/// Pseudo-random vector with components between -1 and 1, for spreading particles out
+pub fn jitter() -> Vec3 {
+    thread_local! {
+        static STATE: std::cell::Cell<u32> = const { std::cell::Cell::new(0x9e37_79b9) };
+    }
+    let next = || {
+        STATE.with(|state| {
+            // xorshift32
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            state.set(x);
+            x as f32 / u32::MAX as f32 * 2.0 - 1.0
+        })
+    };
+    Vec3::new(next(), next(), next())
+}
+
+/// Resource with the mesh shared by all particles
+#[derive(Resource)]
+struct ParticleMesh(Handle<Mesh>);
+
+/// Component of a live particle
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    size: f32,
+    age: f32,
+    lifetime: f32,
+    alpha: f32,
+}
+
+fn setup_particle_mesh(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ParticleMesh(meshes.add(Sphere::new(1.0).mesh().uv(8, 6))));
+}
+
+/// Spawn the requested particles, up to the cap
+fn spawn_particles(
+    mut commands: Commands,
+    mut requests: MessageReader<SpawnParticle>,
+    mesh: Res<ParticleMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    particle_query: Query<(), With<Particle>>,
+) {
+    let mut count = particle_query.iter().count();
+    for request in requests.read() {
+        if count >= MAX_PARTICLES {
+            continue;
+        }
+        count += 1;
+        let material = materials.add(StandardMaterial {
+            base_color: request.color,
+            emissive: if request.emissive {
+                request.color.to_linear() * 4.0
+            } else {
+                LinearRgba::BLACK
+            },
+            unlit: request.emissive,
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+        commands.spawn((
+            Particle {
+                velocity: request.velocity,
+                size: request.size,
+                age: 0.0,
+                lifetime: request.lifetime.max(0.01),
+                alpha: request.color.alpha(),
+            },
+            Mesh3d(mesh.0.clone()),
+            MeshMaterial3d(material),
+            Transform::from_translation(request.position).with_scale(Vec3::splat(request.size)),
+        ));
+    }
+}
+
+/// Move, grow and fade the particles, removing them at the end of their life
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut particle_query: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &MeshMaterial3d<StandardMaterial>,
+    )>,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut particle, mut transform, material) in particle_query.iter_mut() {
+        particle.age += dt;
+        let life = particle.age / particle.lifetime;
+        if life >= 1.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let drag = (1.0 - PARTICLE_DRAG * dt).max(0.0);
+        particle.velocity *= drag;
+        transform.translation += particle.velocity * dt;
+        transform.scale = Vec3::splat(particle.size * (1.0 + life));
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.base_color.set_alpha(particle.alpha * (1.0 - life));
+        }
+    }
+}
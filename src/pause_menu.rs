@@ -0,0 +1,235 @@
+//! Pause menu
+//!
+//! Press Escape to freeze the game and open the pause menu: Up/Down pick an
+//! item and Enter chooses it. Resume (or Escape again) carries on where the
+//! game left off, Restart race lines the car up for a new start, Change scene
+//! goes back to the main menu, Settings opens the settings screen and Quit
+//! closes the game. Driving input is ignored while the menu is open.
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::input_map::{InputMap, RebindScreen};
+use crate::main_menu::{MainMenu, OpenMainMenu};
+use crate::photo_mode::PhotoMode;
+use crate::race_start::RestartRace;
+use crate::settings::{navigate_settings, OpenSettings, SettingsScreen};
+use crate::vehicle::VehicleMenu;
+
+/// Plugin for the pause menu
+pub struct PauseMenuPlugin;
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .add_systems(
+                Update,
+                (navigate_pause_menu, update_pause_menu)
+                    .chain()
+                    .before(navigate_settings),
+            );
+    }
+}
+
+/// An entry of the pause menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PauseItem {
+    Resume,
+    RestartRace,
+    ChangeScene,
+    Settings,
+    Quit,
+}
+
+impl PauseItem {
+    /// Every item, in menu order
+    const ALL: [PauseItem; 5] = [
+        PauseItem::Resume,
+        PauseItem::RestartRace,
+        PauseItem::ChangeScene,
+        PauseItem::Settings,
+        PauseItem::Quit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            PauseItem::Resume => "Resume",
+            PauseItem::RestartRace => "Restart race",
+            PauseItem::ChangeScene => "Change scene",
+            PauseItem::Settings => "Settings",
+            PauseItem::Quit => "Quit",
+        }
+    }
+}
+
+/// Resource tracking the pause menu
+#[derive(Resource, Default)]
+struct PauseMenu {
+    /// The menu's root node while it is open
+    root: Option<Entity>,
+    /// Index into `PauseItem::ALL` of the highlighted item
+    highlighted: usize,
+    /// Whether the game was already paused when the menu opened
+    was_paused: bool,
+    /// Whether the menu closed last frame and the driving input is still held
+    resuming: bool,
+}
+
+/// Marker for the text listing the items
+#[derive(Component)]
+struct PauseMenuText;
+
+/// Open the menu with Escape, move the highlight and choose an item
+fn navigate_pause_menu(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut input_map: ResMut<InputMap>,
+    mut time: ResMut<Time<Virtual>>,
+    mut menu: ResMut<PauseMenu>,
+    photo: Res<PhotoMode>,
+    vehicle_menu: Res<VehicleMenu>,
+    main_menu: Res<MainMenu>,
+    controls: Res<RebindScreen>,
+    settings_screen: Res<SettingsScreen>,
+    mut restarts: MessageWriter<RestartRace>,
+    mut scene_changes: MessageWriter<OpenMainMenu>,
+    mut open_settings: MessageWriter<OpenSettings>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let Some(root) = menu.root else {
+        // The Enter that closed the menu must not also start a race
+        if menu.resuming {
+            menu.resuming = false;
+            input_map.suspended = false;
+        }
+        // Other screens that take the keyboard are left alone
+        let busy = input_map.suspended
+            || photo.active
+            || vehicle_menu.is_open()
+            || main_menu.is_open();
+        if keyboard.just_pressed(KeyCode::Escape) && !busy {
+            menu.highlighted = 0;
+            menu.was_paused = time.is_paused();
+            time.pause();
+            input_map.suspended = true;
+            menu.root = Some(spawn_pause_menu(&mut commands));
+            info!("Paused");
+        }
+        return;
+    };
+
+    // The settings opened from here take the keys until they close
+    if controls.is_open() || settings_screen.is_open() {
+        return;
+    }
+    if !input_map.suspended {
+        input_map.suspended = true;
+    }
+
+    let count = PauseItem::ALL.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        menu.highlighted = (menu.highlighted + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        menu.highlighted = (menu.highlighted + count - 1) % count;
+    }
+    let chosen = if keyboard.just_pressed(KeyCode::Escape) {
+        PauseItem::Resume
+    } else if keyboard.just_pressed(KeyCode::Enter) {
+        PauseItem::ALL[menu.highlighted]
+    } else {
+        return;
+    };
+
+    match chosen {
+        PauseItem::ChangeScene => {
+            scene_changes.write(OpenMainMenu);
+        }
+        PauseItem::Settings => {
+            open_settings.write(OpenSettings);
+            return;
+        }
+        PauseItem::Quit => {
+            info!("Quitting");
+            exit.write(AppExit::Success);
+            return;
+        }
+        PauseItem::RestartRace => {
+            restarts.write(RestartRace);
+        }
+        PauseItem::Resume => {}
+    }
+
+    commands.entity(root).despawn();
+    menu.root = None;
+    menu.resuming = true;
+    if !menu.was_paused {
+        time.unpause();
+    }
+    info!("Resumed");
+}
+
+/// Spawn the pause menu panel and return its root
+fn spawn_pause_menu(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                PauseMenuText,
+            ));
+        })
+        .id()
+}
+
+/// List the items, marking the highlighted one, and hide the menu behind the
+/// settings
+fn update_pause_menu(
+    menu: Res<PauseMenu>,
+    controls: Res<RebindScreen>,
+    settings_screen: Res<SettingsScreen>,
+    mut root_query: Query<&mut Visibility>,
+    mut text_query: Query<(&mut Text, Ref<PauseMenuText>)>,
+) {
+    let Some(root) = menu.root else {
+        return;
+    };
+    if let Ok(mut visibility) = root_query.get_mut(root) {
+        visibility.set_if_neq(if controls.is_open() || settings_screen.is_open() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+    let Ok((mut text, marker)) = text_query.single_mut() else {
+        return;
+    };
+    if !menu.is_changed() && !marker.is_added() {
+        return;
+    }
+
+    let mut lines = vec![
+        "Paused - Up/Down: select, Enter: choose, Esc: resume".to_string(),
+        String::new(),
+    ];
+    for (index, item) in PauseItem::ALL.iter().enumerate() {
+        let marker = if index == menu.highlighted { ">" } else { " " };
+        lines.push(format!("{} {}", marker, item.label()));
+    }
+    text.0 = lines.join("\n");
+}
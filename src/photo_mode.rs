@@ -0,0 +1,233 @@
+//! Photo mode
+//!
+//! Press F9 (rebindable) to freeze the game and frame a shot: the camera
+//! flies like the spectator camera, the HUD is hidden and depth of field
+//! blurs what is out of focus. Splat scans of real places make great
+//! screenshots.
+//!
+//! | Input       | Action                            |
+//! |-------------|-----------------------------------|
+//! | WASD, E/Q   | fly, Shift: faster                |
+//! | RMB drag    | look around                       |
+//! | Z / X       | roll left / right                 |
+//! | wheel       | zoom (field of view)              |
+//! | [ / ]       | focus closer / further            |
+//! | - / =       | open / close the aperture         |
+//! | Backspace   | reset roll, zoom and focus        |
+//! | F12         | save a screenshot                 |
+//!
+//! F9 again returns to the game where it left off.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::input::mouse::AccumulatedMouseScroll;
+use bevy::post_process::dof::{DepthOfField, DepthOfFieldMode};
+use bevy::prelude::*;
+use bevy::render::view::screenshot::{save_to_disk, Screenshot};
+
+use crate::camera::{CameraMode, CarCamera};
+use crate::car::CarSet;
+use crate::input_map::{Action, InputMap};
+use crate::spectator::{fly_spectator, Spectator};
+
+/// Roll speed in radians per second
+const ROLL_SPEED: f32 = 0.8;
+
+/// Narrowest and widest vertical field of view, in radians
+const MIN_FOV: f32 = 0.15;
+const MAX_FOV: f32 = 1.8;
+
+/// Focus distance change per second, as a fraction of the distance
+const FOCUS_SPEED: f32 = 1.0;
+
+/// Plugin for photo mode
+pub struct PhotoModePlugin;
+
+impl Plugin for PhotoModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhotoMode>().add_systems(
+            Update,
+            (
+                toggle_photo_mode.before(fly_spectator),
+                photo_controls.after(fly_spectator),
+            )
+                .in_set(CarSet::Camera),
+        );
+    }
+}
+
+/// Resource with the state of photo mode
+#[derive(Resource)]
+pub struct PhotoMode {
+    pub active: bool,
+    /// Camera roll in radians
+    pub roll: f32,
+    /// Vertical field of view in radians
+    pub fov: f32,
+    /// Distance to the plane in focus, in meters
+    pub focal_distance: f32,
+    /// Aperture in f-stops; lower blurs more
+    pub aperture_f_stops: f32,
+    /// View to return to when leaving photo mode
+    previous_mode: CameraMode,
+    /// Whether the game was already paused when photo mode started
+    was_paused: bool,
+    /// UI roots hidden during photo mode, with their visibility before
+    hidden_ui: Vec<(Entity, Visibility)>,
+}
+
+impl Default for PhotoMode {
+    fn default() -> Self {
+        Self {
+            active: false,
+            roll: 0.0,
+            fov: PerspectiveProjection::default().fov,
+            focal_distance: 10.0,
+            aperture_f_stops: 2.8,
+            previous_mode: CameraMode::Chase,
+            was_paused: false,
+            hidden_ui: Vec::new(),
+        }
+    }
+}
+
+impl PhotoMode {
+    /// Straighten the camera and reset the lens
+    fn reset_lens(&mut self) {
+        let defaults = PhotoMode::default();
+        self.roll = defaults.roll;
+        self.fov = defaults.fov;
+        self.focal_distance = defaults.focal_distance;
+        self.aperture_f_stops = defaults.aperture_f_stops;
+    }
+}
+
+/// Enter or leave photo mode, pausing the game and hiding the HUD
+fn toggle_photo_mode(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut time: ResMut<Time<Virtual>>,
+    mut photo: ResMut<PhotoMode>,
+    mut mode: ResMut<CameraMode>,
+    mut spectator: ResMut<Spectator>,
+    camera_query: Query<(Entity, &Transform), With<CarCamera>>,
+    mut ui_query: Query<(Entity, &mut Visibility), (With<Node>, Without<ChildOf>)>,
+) {
+    if !input_map.just_pressed(Action::PhotoMode, &keyboard) {
+        return;
+    }
+    let Ok((camera, transform)) = camera_query.single() else {
+        return;
+    };
+
+    if photo.active {
+        photo.active = false;
+        *mode = photo.previous_mode;
+        if !photo.was_paused {
+            time.unpause();
+        }
+        for (entity, visibility) in std::mem::take(&mut photo.hidden_ui) {
+            if let Ok((_, mut current)) = ui_query.get_mut(entity) {
+                *current = visibility;
+            }
+        }
+        commands.entity(camera).remove::<DepthOfField>();
+        info!("Photo mode OFF");
+        return;
+    }
+
+    photo.active = true;
+    photo.reset_lens();
+    photo.previous_mode = *mode;
+    photo.was_paused = time.is_paused();
+    time.pause();
+    *mode = CameraMode::Photo;
+    spectator.take_off(transform);
+    photo.hidden_ui = ui_query
+        .iter_mut()
+        .map(|(entity, mut visibility)| {
+            let previous = *visibility;
+            *visibility = Visibility::Hidden;
+            (entity, previous)
+        })
+        .collect();
+    commands.entity(camera).insert(DepthOfField {
+        mode: DepthOfFieldMode::Gaussian,
+        focal_distance: photo.focal_distance,
+        aperture_f_stops: photo.aperture_f_stops,
+        ..default()
+    });
+    info!("Photo mode ON - WASD/E/Q: fly, RMB drag: look, Z/X: roll, wheel: zoom");
+    info!("[ ]: focus, - =: aperture, Backspace: reset, F12: screenshot, F9: back to the game");
+}
+
+/// Roll, zoom and focus the camera and take screenshots
+fn photo_controls(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    time: Res<Time<Real>>,
+    mut photo: ResMut<PhotoMode>,
+    mut camera_query: Query<
+        (&mut Transform, &mut Projection, Option<&mut DepthOfField>),
+        With<CarCamera>,
+    >,
+) {
+    if !photo.active {
+        return;
+    }
+    let Ok((mut transform, mut projection, depth_of_field)) = camera_query.single_mut() else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    if keyboard.pressed(KeyCode::KeyZ) {
+        photo.roll += ROLL_SPEED * dt;
+    }
+    if keyboard.pressed(KeyCode::KeyX) {
+        photo.roll -= ROLL_SPEED * dt;
+    }
+    if mouse_scroll.delta.y != 0.0 {
+        photo.fov = (photo.fov * 0.9_f32.powf(mouse_scroll.delta.y)).clamp(MIN_FOV, MAX_FOV);
+    }
+    if keyboard.pressed(KeyCode::BracketLeft) {
+        photo.focal_distance = (photo.focal_distance * (1.0 - FOCUS_SPEED * dt)).max(0.3);
+    }
+    if keyboard.pressed(KeyCode::BracketRight) {
+        photo.focal_distance = (photo.focal_distance * (1.0 + FOCUS_SPEED * dt)).min(1000.0);
+    }
+    // One f-stop per press
+    if keyboard.just_pressed(KeyCode::Minus) {
+        photo.aperture_f_stops = (photo.aperture_f_stops / std::f32::consts::SQRT_2).max(0.7);
+        info!("Aperture f/{:.1}", photo.aperture_f_stops);
+    }
+    if keyboard.just_pressed(KeyCode::Equal) {
+        photo.aperture_f_stops = (photo.aperture_f_stops * std::f32::consts::SQRT_2).min(32.0);
+        info!("Aperture f/{:.1}", photo.aperture_f_stops);
+    }
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        photo.reset_lens();
+    }
+
+    // The spectator camera set the heading and pitch; roll on top of them
+    transform.rotate_local_z(photo.roll);
+    if let Projection::Perspective(perspective) = projection.as_mut() {
+        perspective.fov = photo.fov;
+    }
+    if let Some(mut depth_of_field) = depth_of_field {
+        depth_of_field.focal_distance = photo.focal_distance;
+        depth_of_field.aperture_f_stops = photo.aperture_f_stops;
+    }
+
+    if keyboard.just_pressed(KeyCode::F12) {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = format!("gaussrace-{}.png", seconds);
+        info!("Saving screenshot to {}", path);
+        commands
+            .spawn(Screenshot::primary_window())
+            .observe(save_to_disk(path));
+    }
+}
@@ -0,0 +1,257 @@
+//! CPU-side copy of the loaded splat points
+//!
+//! Plane fitting, collision and picking all need the raw Gaussian centers.
+//! `SplatPointCloud` holds them in world space, rebuilt whenever a splat
+//! finishes loading or is moved, along with their opacities, scales and base
+//! colors and a KD-tree for fast neighborhood and ray queries. A splat being
+//! dragged around is only caught up with once it has stopped moving, rather
+//! than on every frame of the drag.
+//!
+//! Each splat's KD-tree is built over its Gaussians in the splat's own space,
+//! and queries are carried into that space, so moving a splat only places its
+//! points again and the index is built just when splats are loaded.
+
+use bevy::math::Affine3A;
+use bevy::prelude::*;
+
+use crate::spatial_index::KdTree;
+use crate::splat_loader::{
+    robust_bounds, LoadedSplat, LoadedSplats, SplatBounds, SplatData, SplatLoadState,
+};
+
+/// Radius around a picking ray within which Gaussians are considered
+const PICK_SEARCH_RADIUS: f32 = 0.25;
+
+/// Accumulated opacity along a picking ray that counts as hitting a surface
+const PICK_OPACITY_THRESHOLD: f32 = 0.5;
+
+/// Seconds a moved splat must keep still before the point cloud follows it
+const SETTLE_SECONDS: f32 = 0.25;
+
+/// Plugin maintaining the CPU point cloud of the loaded splats
+pub struct PointCloudPlugin;
+
+impl Plugin for PointCloudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplatPointCloud>()
+            .add_systems(PostUpdate, rebuild_point_cloud);
+    }
+}
+
+/// Resource with the world-space Gaussians of all loaded splats
+#[derive(Resource, Default)]
+pub struct SplatPointCloud {
+    /// Gaussian centers in world space
+    pub positions: Vec<Vec3>,
+    /// Opacity of each Gaussian in 0..1
+    pub opacities: Vec<f32>,
+    /// Linear world-space scale of each Gaussian along its own axes
+    pub scales: Vec<Vec3>,
+    /// Base color of each Gaussian, without the view-dependent part
+    pub colors: Vec<Vec3>,
+    /// The Gaussians of each splat, in the order they appear in the vectors
+    parts: Vec<CloudPart>,
+}
+
+/// The Gaussians of one splat in the splat's own space, with the spatial
+/// index backing the queries below
+struct CloudPart {
+    entity: Entity,
+    /// Index of the part's first Gaussian in the cloud's vectors
+    start: usize,
+    local_positions: Vec<Vec3>,
+    local_scales: Vec<Vec3>,
+    index: KdTree,
+    /// From world space into the splat's space
+    to_local: Affine3A,
+    /// Smallest factor the splat's transform scales distances by
+    min_scale: f32,
+}
+
+impl CloudPart {
+    /// Index of a Gaussian of the part in the cloud's vectors
+    fn global(&self, local_index: usize) -> usize {
+        self.start + local_index
+    }
+}
+
+impl SplatPointCloud {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.positions.clear();
+        self.opacities.clear();
+        self.scales.clear();
+        self.colors.clear();
+        self.parts.clear();
+    }
+
+    /// Place a part's Gaussians in world space with its splat's transform
+    fn place(&mut self, part: usize, transform: &Transform) {
+        let part = &mut self.parts[part];
+        let range = part.start..part.start + part.local_positions.len();
+        for (position, local) in self.positions[range.clone()]
+            .iter_mut()
+            .zip(&part.local_positions)
+        {
+            *position = transform.transform_point(*local);
+        }
+        for (scale, local) in self.scales[range].iter_mut().zip(&part.local_scales) {
+            *scale = *local * transform.scale;
+        }
+        part.to_local = transform.compute_affine().inverse();
+        part.min_scale = transform.scale.abs().min_element().max(f32::EPSILON);
+    }
+
+    /// Indices of the Gaussians within `radius` of a point
+    pub fn within_radius(&self, center: Vec3, radius: f32) -> Vec<usize> {
+        let radius_squared = radius * radius;
+        self.parts
+            .iter()
+            .flat_map(|part| {
+                // The radius is widened for shrunk splats and the hits checked in world space
+                let local_center = part.to_local.transform_point3(center);
+                part.index
+                    .within_radius(&part.local_positions, local_center, radius / part.min_scale)
+                    .into_iter()
+                    .map(|local| part.global(local))
+            })
+            .filter(|&index| self.positions[index].distance_squared(center) <= radius_squared)
+            .collect()
+    }
+
+    /// Gaussians within `radius` of a ray, as `(index, distance along the ray)` sorted front to back
+    pub fn along_ray(&self, ray: Ray3d, max_distance: f32, radius: f32) -> Vec<(usize, f32)> {
+        let radius_squared = radius * radius;
+        let mut found: Vec<(usize, f32)> = self
+            .parts
+            .iter()
+            .flat_map(|part| {
+                let origin = part.to_local.transform_point3(ray.origin);
+                let direction = part.to_local.transform_vector3(*ray.direction);
+                let length = direction.length();
+                part.index
+                    .along_ray(
+                        &part.local_positions,
+                        origin,
+                        direction / length,
+                        max_distance * length,
+                        radius / part.min_scale,
+                    )
+                    .into_iter()
+                    .map(|(local, _)| part.global(local))
+            })
+            // Measured again in world space, where the distances along the ray are
+            .filter_map(|index| {
+                let to_point = self.positions[index] - ray.origin;
+                let t = to_point.dot(*ray.direction);
+                let hit = (0.0..=max_distance).contains(&t)
+                    && (to_point - *ray.direction * t).length_squared() <= radius_squared;
+                hit.then_some((index, t))
+            })
+            .collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    /// First point where a ray hits the splat surface
+    ///
+    /// Opacity is accumulated front to back over the Gaussians near the ray,
+    /// each weighted by how close the ray passes to its center relative to its
+    /// size, and the ray stops once it is mostly occluded.
+    pub fn raycast(&self, ray: Ray3d, max_distance: f32) -> Option<Vec3> {
+        let mut transmittance = 1.0;
+        for (index, t) in self.along_ray(ray, max_distance, PICK_SEARCH_RADIUS) {
+            let sigma = (self.scales[index].element_sum() / 3.0).max(0.001);
+            let offset = self.positions[index] - ray.get_point(t);
+            let falloff = (-0.5 * offset.length_squared() / (sigma * sigma)).exp();
+            transmittance *= 1.0 - self.opacities[index] * falloff;
+            if transmittance < 1.0 - PICK_OPACITY_THRESHOLD {
+                return Some(ray.get_point(t));
+            }
+        }
+        None
+    }
+}
+
+/// Rebuild the point cloud and `SplatBounds` when splats finish loading, or
+/// once moved splats have settled
+fn rebuild_point_cloud(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut point_cloud: ResMut<SplatPointCloud>,
+    mut settling: Local<Option<f32>>,
+    splat_data: SplatData,
+    loaded_splats: Res<LoadedSplats>,
+    load_state: Res<State<SplatLoadState>>,
+    splat_query: Query<&Transform, With<LoadedSplat>>,
+    changed_query: Query<(), (With<LoadedSplat>, Changed<Transform>)>,
+) {
+    // Every move restarts the wait, so a drag is followed once it ends
+    if !changed_query.is_empty() {
+        *settling = Some(SETTLE_SECONDS);
+    }
+    let settled = match settling.as_mut() {
+        Some(seconds) => {
+            *seconds -= time.delta_secs();
+            *seconds <= 0.0
+        }
+        None => false,
+    };
+    let splats_changed = loaded_splats.is_changed() || load_state.is_changed();
+    if !splats_changed && !settled {
+        return;
+    }
+    *settling = None;
+
+    let point_cloud = point_cloud.as_mut();
+    if splats_changed {
+        point_cloud.clear();
+        for &entity in &loaded_splats.0 {
+            let Ok(transform) = splat_query.get(entity) else {
+                continue;
+            };
+            let mut local_positions = Vec::new();
+            let mut local_scales = Vec::new();
+            splat_data.for_each_gaussian(entity, |position, scale, opacity, color| {
+                local_positions.push(position);
+                local_scales.push(scale);
+                point_cloud.opacities.push(opacity);
+                point_cloud.colors.push(color);
+            });
+            let start = point_cloud.positions.len();
+            let end = start + local_positions.len();
+            point_cloud.positions.resize(end, Vec3::ZERO);
+            point_cloud.scales.resize(end, Vec3::ZERO);
+            point_cloud.parts.push(CloudPart {
+                entity,
+                start,
+                index: KdTree::build(&local_positions),
+                local_positions,
+                local_scales,
+                to_local: Affine3A::IDENTITY,
+                min_scale: 1.0,
+            });
+            point_cloud.place(point_cloud.parts.len() - 1, transform);
+        }
+    } else {
+        // Moved splats keep their index, only their points are placed again
+        for part in 0..point_cloud.parts.len() {
+            if let Ok(transform) = splat_query.get(point_cloud.parts[part].entity) {
+                point_cloud.place(part, transform);
+            }
+        }
+    }
+
+    match robust_bounds(&point_cloud.positions) {
+        Some((min, max)) => commands.insert_resource(SplatBounds { min, max }),
+        None => commands.remove_resource::<SplatBounds>(),
+    }
+    info!("Splat point cloud rebuilt with {} Gaussians", point_cloud.len());
+}
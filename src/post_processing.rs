@@ -0,0 +1,139 @@
+//! Post-processing
+//!
+//! Three effects dress up the raw splats, each turned on and off on the
+//! settings screen: bloom makes the emissive lamps, flames and boost glow;
+//! motion blur smears the view the faster the car goes; and a vignette
+//! darkens the edges of the screen at speed. Bloom renders the view in HDR and
+//! motion blur adds depth and motion vector prepasses, so both cost frame rate
+//! on big scans.
+
+use bevy::core_pipeline::prepass::{DepthPrepass, MotionVectorPrepass};
+use bevy::post_process::bloom::Bloom;
+use bevy::post_process::motion_blur::MotionBlur;
+use bevy::prelude::*;
+use bevy::render::view::Hdr;
+
+use crate::camera::{CameraMode, CarCamera};
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::settings::Settings;
+use crate::split_screen::{SecondCamera, SecondCar};
+
+/// Shutter angle of the motion blur at top speed
+const MAX_SHUTTER_ANGLE: f32 = 1.0;
+
+/// Fraction of the top speed above which the vignette starts to show
+const VIGNETTE_START: f32 = 0.4;
+
+/// Opacity of the vignette's corners at top speed
+const MAX_VIGNETTE: f32 = 0.6;
+
+/// Plugin for bloom, motion blur and the speed vignette
+pub struct PostProcessingPlugin;
+
+impl Plugin for PostProcessingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_vignette).add_systems(
+            Update,
+            (apply_post_processing, scale_motion_blur, update_vignette)
+                .chain()
+                .after(CarSet::Camera),
+        );
+    }
+}
+
+/// Marker for the vignette over the screen
+#[derive(Component)]
+struct Vignette;
+
+/// Fraction of the top speed a car is going at
+fn speed_fraction(car: &Car) -> f32 {
+    (car.velocity.abs() / car.max_speed.max(1e-3)).min(1.0)
+}
+
+/// Add or remove bloom and motion blur on the driving cameras when the
+/// settings change, and on cameras added later
+fn apply_post_processing(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    camera_query: Query<(Entity, Ref<CarCamera>)>,
+) {
+    for (entity, camera) in camera_query.iter() {
+        if !settings.is_changed() && !camera.is_added() {
+            continue;
+        }
+        let mut entity = commands.entity(entity);
+        if settings.bloom {
+            entity.insert(Bloom::NATURAL);
+        } else {
+            entity.remove::<(Bloom, Hdr)>();
+        }
+        if settings.motion_blur {
+            entity.insert(MotionBlur::default());
+        } else {
+            entity.remove::<(MotionBlur, MotionVectorPrepass, DepthPrepass)>();
+        }
+    }
+}
+
+/// Blur each driving view more the faster its car goes
+fn scale_motion_blur(
+    car_query: Query<(&Car, Has<PrimaryCar>, Has<SecondCar>)>,
+    mut camera_query: Query<(&mut MotionBlur, Has<SecondCamera>), With<CarCamera>>,
+) {
+    for (mut blur, second_camera) in camera_query.iter_mut() {
+        let speed = car_query
+            .iter()
+            .find(|&(_, primary, second)| if second_camera { second } else { primary })
+            .map_or(0.0, |(car, _, _)| speed_fraction(car));
+        // Mostly at the top of the speed range, so cruising stays sharp
+        blur.shutter_angle = MAX_SHUTTER_ANGLE * speed * speed;
+    }
+}
+
+/// Spawn the hidden vignette over the whole screen, under the HUD
+fn spawn_vignette(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        BackgroundGradient::default(),
+        GlobalZIndex(-1),
+        Visibility::Hidden,
+        Vignette,
+    ));
+}
+
+/// Darken the edges of the screen with the first player's speed
+fn update_vignette(
+    settings: Res<Settings>,
+    mode: Res<CameraMode>,
+    car_query: Query<&Car, With<PrimaryCar>>,
+    mut vignette_query: Query<(&mut BackgroundGradient, &mut Visibility), With<Vignette>>,
+) {
+    let Ok((mut gradient, mut visibility)) = vignette_query.single_mut() else {
+        return;
+    };
+    let speed = car_query.single().map_or(0.0, speed_fraction);
+    let strength = ((speed - VIGNETTE_START) / (1.0 - VIGNETTE_START)).clamp(0.0, 1.0);
+    let shown = settings.speed_vignette && !mode.is_free() && strength > 0.0;
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if !shown {
+        return;
+    }
+    let edge = Color::BLACK.with_alpha(MAX_VIGNETTE * strength);
+    *gradient = BackgroundGradient::from(RadialGradient::new(
+        UiPosition::CENTER,
+        RadialGradientShape::FarthestCorner,
+        vec![
+            ColorStop::percent(Color::NONE, 55.0),
+            ColorStop::percent(edge, 100.0),
+        ],
+    ));
+}
@@ -0,0 +1,363 @@
+//! Race start countdown
+//!
+//! A race begins whenever a track is loaded, or when Enter (rebindable as
+//! `StartRace`) is pressed while there is one; editing the gates doesn't start
+//! one. The first player's car is put a few meters behind the start line and
+//! held there while three red lights come on one by one, each with a beep;
+//! when they turn green the cars can drive and the clock starts at the start
+//! line as usual. The driving controls are
+//! ignored until then, but pressing the throttle while the lights are red is a
+//! jump start, which adds `JUMP_START_PENALTY` to the first lap.
+//!
+//! Without a track the cars drive freely, as before. Restarting the race from
+//! the pause menu then puts the first player's car back at the spawn point.
+
+use std::time::Duration;
+
+use bevy::audio::{Pitch, Volume};
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, DriverInput, GamepadDeadzones, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::settings::Settings;
+use crate::spawn_point::SpawnPoint;
+use crate::track::{CheckpointPassed, LapTimer, TrackEditor};
+use crate::vehicle::VehicleMenu;
+
+/// Distance behind the start line the car is put for the start, in meters
+const STANDING_START_DISTANCE: f32 = 5.0;
+
+/// Seconds between the start and each red light, and then the green
+const LIGHT_INTERVAL: f32 = 1.0;
+
+/// Number of red lights
+const LIGHT_COUNT: usize = 3;
+
+/// Seconds the green lights stay up after the start
+const GREEN_DURATION: f32 = 1.5;
+
+/// Seconds added to the first lap for a jump start
+pub const JUMP_START_PENALTY: f32 = 5.0;
+
+/// Throttle counted as a jump start, to ignore trigger noise
+const JUMP_START_THROTTLE: f32 = 0.2;
+
+/// Plugin for the race start countdown
+pub struct RaceStartPlugin;
+
+impl Plugin for RaceStartPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RaceStart>()
+            .add_message::<RestartRace>()
+            .add_systems(Startup, spawn_start_lights)
+            .add_systems(
+                Update,
+                (
+                    (begin_race, run_countdown).chain().before(CarSet::Input),
+                    hold_cars_at_start.after(CarSet::Input).before(CarSet::Physics),
+                    apply_jump_start_penalty.after(CarSet::Camera),
+                    update_start_lights,
+                ),
+            );
+    }
+}
+
+/// Where a race stands
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RacePhase {
+    /// No race, the cars drive freely
+    #[default]
+    Free,
+    /// The lights are counting down, seconds since the race began
+    Countdown { elapsed: f32 },
+    /// The lights have gone green, seconds since they did
+    Racing { since_green: f32 },
+}
+
+/// Resource with the state of the race start
+#[derive(Resource, Default)]
+pub struct RaceStart {
+    pub phase: RacePhase,
+    /// Whether the first player jumped the start of the current race
+    pub jump_start: bool,
+    /// Whether the penalty still has to be added when the clock starts
+    penalty_pending: bool,
+}
+
+impl RaceStart {
+    /// Red lights lit, or `None` once they are green
+    fn red_lights(&self) -> Option<usize> {
+        match self.phase {
+            RacePhase::Countdown { elapsed } => {
+                Some(((elapsed / LIGHT_INTERVAL) as usize).min(LIGHT_COUNT))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Message asking for the race to start over
+#[derive(Message, Debug, Clone, Copy)]
+pub struct RestartRace;
+
+/// Run condition letting the driving controls through unless the lights are red
+pub fn driving_allowed(race: Res<RaceStart>) -> bool {
+    !matches!(race.phase, RacePhase::Countdown { .. })
+}
+
+/// Marker for the start lights panel
+#[derive(Component)]
+struct StartLights;
+
+/// Component on one of the start lights, with its position from the left
+#[derive(Component)]
+struct StartLight(usize);
+
+/// Marker for the text under the start lights
+#[derive(Component)]
+struct StartLightsText;
+
+/// Spawn the hidden start lights at the top of the screen
+fn spawn_start_lights(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(60.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            StartLights,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        column_gap: Val::Px(12.0),
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+                    BorderRadius::all(Val::Px(12.0)),
+                ))
+                .with_children(|panel| {
+                    for index in 0..LIGHT_COUNT {
+                        panel.spawn((
+                            Node {
+                                width: Val::Px(44.0),
+                                height: Val::Px(44.0),
+                                ..default()
+                            },
+                            BackgroundColor(Color::srgb(0.15, 0.02, 0.02)),
+                            BorderRadius::MAX,
+                            StartLight(index),
+                        ));
+                    }
+                });
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 26.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                TextShadow::default(),
+                StartLightsText,
+            ));
+        });
+}
+
+/// Begin a race when a track is loaded or the player asks for one, lining the
+/// car up behind the start line
+fn begin_race(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    checkpoints: Res<SceneCheckpoints>,
+    editor: Res<TrackEditor>,
+    spawn: Res<SpawnPoint>,
+    mut restarts: MessageReader<RestartRace>,
+    mut race: ResMut<RaceStart>,
+    mut car_query: Query<(&mut Car, &mut Transform, Option<&mut LapTimer>), With<PrimaryCar>>,
+) {
+    let restart = restarts.read().count() > 0;
+    let requested = restart || input_map.just_pressed(Action::StartRace, &keyboard);
+    if !checkpoints.is_changed() && !requested {
+        return;
+    }
+    let Some(start_line) = checkpoints.0.first().filter(|_| !editor.active) else {
+        if race.phase != RacePhase::Free {
+            *race = RaceStart::default();
+        }
+        if let Some((mut car, mut transform, _)) = car_query.single_mut().ok().filter(|_| restart) {
+            *transform = spawn.0;
+            car.stop();
+            info!("Car back at the spawn point");
+        }
+        return;
+    };
+    let Ok((mut car, mut transform, timer)) = car_query.single_mut() else {
+        return;
+    };
+
+    let mut start = start_line.with_scale(Vec3::ONE);
+    start.translation -= start.forward() * STANDING_START_DISTANCE;
+    *transform = start;
+    car.throttle = 0.0;
+    car.brake = 0.0;
+    car.stop();
+    if let Some(mut timer) = timer {
+        *timer = LapTimer::default();
+    }
+    *race = RaceStart {
+        phase: RacePhase::Countdown { elapsed: 0.0 },
+        ..default()
+    };
+    info!("Race starting - wait for the green lights");
+}
+
+/// Advance the countdown, beeping for each light and watching for jump starts
+fn run_countdown(
+    mut commands: Commands,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    gamepads: Query<&Gamepad>,
+    deadzones: Res<GamepadDeadzones>,
+    menu: Res<VehicleMenu>,
+    settings: Res<Settings>,
+    mut race: ResMut<RaceStart>,
+    mut pitches: ResMut<Assets<Pitch>>,
+) {
+    let dt = time.delta_secs();
+    match race.phase {
+        RacePhase::Free => {}
+        RacePhase::Racing { since_green } => {
+            race.phase = RacePhase::Racing {
+                since_green: since_green + dt,
+            };
+        }
+        // The lights wait for a vehicle to be chosen
+        RacePhase::Countdown { .. } if menu.is_open() => {
+            race.phase = RacePhase::Countdown { elapsed: 0.0 };
+        }
+        RacePhase::Countdown { elapsed } => {
+            let lights_before = race.red_lights();
+            let elapsed = elapsed + dt;
+            race.phase = if elapsed >= LIGHT_INTERVAL * (LIGHT_COUNT + 1) as f32 {
+                info!("Go!");
+                RacePhase::Racing { since_green: 0.0 }
+            } else {
+                RacePhase::Countdown { elapsed }
+            };
+
+            let lights = race.red_lights();
+            if lights != lights_before && lights != Some(0) {
+                let (frequency, length) = match lights {
+                    Some(_) => (440.0, 0.25),
+                    None => (880.0, 0.6),
+                };
+                let beep = Pitch::new(frequency, Duration::from_secs_f32(length));
+                let volume = Volume::Linear(settings.interface_gain());
+                commands.spawn((
+                    AudioPlayer(pitches.add(beep)),
+                    PlaybackSettings::DESPAWN.with_volume(volume),
+                ));
+            }
+
+            let throttle = DriverInput::from_keyboard(&input_map, &keyboard)
+                .forward
+                .max(DriverInput::from_gamepads(gamepads.iter(), &deadzones).forward);
+            let lit = lights.is_some_and(|lights| lights > 0);
+            if lit && throttle > JUMP_START_THROTTLE && !race.jump_start {
+                race.jump_start = true;
+                race.penalty_pending = true;
+                warn!("Jump start! {} s penalty on the first lap", JUMP_START_PENALTY);
+            }
+        }
+    }
+}
+
+/// Keep every car standing still while the lights are red
+fn hold_cars_at_start(race: Res<RaceStart>, mut car_query: Query<&mut Car>) {
+    if !matches!(race.phase, RacePhase::Countdown { .. }) {
+        return;
+    }
+    for mut car in car_query.iter_mut() {
+        car.throttle = 0.0;
+        car.brake = 0.0;
+        car.stop();
+    }
+}
+
+/// Add the jump start penalty to the first lap once its clock starts
+fn apply_jump_start_penalty(
+    mut race: ResMut<RaceStart>,
+    mut passed: MessageReader<CheckpointPassed>,
+    mut car_query: Query<(Entity, &mut LapTimer), With<PrimaryCar>>,
+) {
+    let Ok((car, mut timer)) = car_query.single_mut() else {
+        return;
+    };
+    let clock_started = passed
+        .read()
+        .any(|checkpoint| checkpoint.car == car && checkpoint.index == 0 && timer.lap == 1);
+    if race.penalty_pending && clock_started {
+        race.penalty_pending = false;
+        timer.lap_time += JUMP_START_PENALTY;
+        info!("{} s jump start penalty added", JUMP_START_PENALTY);
+    }
+}
+
+/// Light the start lights and show what the player should do
+fn update_start_lights(
+    race: Res<RaceStart>,
+    mut panel_query: Query<&mut Visibility, With<StartLights>>,
+    mut light_query: Query<(&StartLight, &mut BackgroundColor)>,
+    mut text_query: Query<&mut Text, With<StartLightsText>>,
+) {
+    if !race.is_changed() {
+        return;
+    }
+    let shown = match race.phase {
+        RacePhase::Free => false,
+        RacePhase::Countdown { .. } => true,
+        RacePhase::Racing { since_green } => since_green < GREEN_DURATION,
+    };
+    for mut visibility in panel_query.iter_mut() {
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+    if !shown {
+        return;
+    }
+
+    let red_lights = race.red_lights();
+    for (light, mut color) in light_query.iter_mut() {
+        let new_color = match red_lights {
+            Some(lit) if light.0 < lit => Color::srgb(1.0, 0.08, 0.05),
+            Some(_) => Color::srgb(0.15, 0.02, 0.02),
+            None => Color::srgb(0.1, 1.0, 0.2),
+        };
+        color.set_if_neq(BackgroundColor(new_color));
+    }
+    if let Ok(mut text) = text_query.single_mut() {
+        let message = if race.jump_start {
+            format!("JUMP START  +{} s", JUMP_START_PENALTY)
+        } else if red_lights.is_none() {
+            "GO!".to_string()
+        } else {
+            String::new()
+        };
+        if text.0 != message {
+            text.0 = message;
+        }
+    }
+}
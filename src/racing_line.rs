@@ -0,0 +1,295 @@
+//! Racing line through the checkpoint gates
+//!
+//! Press 'I' to show an approximate ideal line around the track as a ribbon
+//! on the ground: green where the car can be on the throttle, shading to red
+//! where it has to brake for the corner ahead.
+//!
+//! The line starts on a smooth curve through the middle of the gates and is
+//! relaxed toward the straightest path that still passes between every gate's
+//! posts and stays inside the drivable area, which cuts the apexes the way a
+//! driver would. A speed profile from the corner radii, limited by how hard
+//! the car accelerates and brakes, then tells where to brake.
+
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::Indices;
+use bevy::prelude::*;
+use bevy::render::render_resource::PrimitiveTopology;
+
+use crate::boundary::DrivableArea;
+use crate::car::{Car, PrimaryCar};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::terrain::GroundSurface;
+use crate::track::gate_width;
+
+/// Distance between points along the line, in meters
+const SAMPLE_SPACING: f32 = 2.0;
+
+/// Distance the line keeps from the gate posts, in meters
+const GATE_MARGIN: f32 = 1.5;
+
+/// Smoothing passes relaxing the line toward the straightest path
+const RELAX_ITERATIONS: usize = 300;
+
+/// Sideways acceleration the tires hold in a corner, in m/s²
+const CORNERING_GRIP: f32 = 9.0;
+
+/// Acceleration and deceleration assumed for the speed profile, in m/s²
+const ACCELERATION: f32 = 5.0;
+const BRAKING: f32 = 9.0;
+
+/// Width of the ribbon and its height above the ground, in meters
+const RIBBON_WIDTH: f32 = 0.6;
+const RIBBON_LIFT: f32 = 0.05;
+
+/// Plugin for the racing line
+pub struct RacingLinePlugin;
+
+impl Plugin for RacingLinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RacingLine>()
+            .add_systems(Update, (toggle_racing_line, rebuild_racing_line).chain());
+    }
+}
+
+/// Resource with the racing line of the current track
+#[derive(Resource, Default)]
+pub struct RacingLine {
+    /// Whether the ribbon is shown
+    pub visible: bool,
+    /// Points along the closed line with the target speed there, in m/s
+    pub points: Vec<(Vec3, f32)>,
+}
+
+/// Marker for the ribbon showing the racing line
+#[derive(Component)]
+struct RacingLineRibbon;
+
+/// A point of the line, free to move sideways within the track
+struct LinePoint {
+    /// Point on the curve through the middle of the gates
+    center: Vec3,
+    /// Unit vector pointing to the right of the driving direction
+    lateral: Vec3,
+    /// How far the line may move to either side of the center
+    half_width: f32,
+    /// How far to the right of the center the line passes
+    offset: f32,
+}
+
+impl LinePoint {
+    fn position(&self) -> Vec3 {
+        self.center + self.lateral * self.offset
+    }
+}
+
+/// Point `t` of the way between `p1` and `p2` on a Catmull-Rom spline, with its tangent
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> (Vec3, Vec3) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let point = 0.5
+        * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3);
+    let tangent = 0.5
+        * ((p2 - p0)
+            + 2.0 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t
+            + 3.0 * (3.0 * p1 - p0 - 3.0 * p2 + p3) * t2);
+    (point, tangent)
+}
+
+/// Curvature of the circle through three points, in 1/m
+fn curvature(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    let lengths = a.distance(b) * b.distance(c) * a.distance(c);
+    if lengths < 1e-6 {
+        return 0.0;
+    }
+    2.0 * (b - a).cross(c - b).length() / lengths
+}
+
+/// Compute a closed racing line through the gates with the target speed at
+/// each point, for a car with the given top speed
+///
+/// Needs at least three gates; fewer don't make a lap.
+pub fn compute_racing_line(
+    gates: &[Transform],
+    area: &DrivableArea,
+    top_speed: f32,
+) -> Vec<(Vec3, f32)> {
+    let count = gates.len();
+    if count < 3 {
+        return Vec::new();
+    }
+
+    // Sample a smooth curve through the middle of the gates
+    let mut points = Vec::new();
+    for i in 0..count {
+        let [g0, g1, g2, g3] = [i + count - 1, i, i + 1, i + 2].map(|j| &gates[j % count]);
+        let [p0, p1, p2, p3] = [g0, g1, g2, g3].map(|gate| gate.translation);
+        let steps = ((p1.distance(p2) / SAMPLE_SPACING).ceil() as usize).max(1);
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            let (center, tangent) = catmull_rom(p0, p1, p2, p3, t);
+            let up = (*g1.up()).lerp(*g2.up(), t).normalize_or(Vec3::Y);
+            let lateral = tangent.cross(up).normalize_or(*g1.right());
+            let width = gate_width(g1).lerp(gate_width(g2), t);
+            points.push(LinePoint {
+                center,
+                lateral,
+                half_width: (width * 0.5 - GATE_MARGIN).max(0.0),
+                offset: 0.0,
+            });
+        }
+    }
+
+    // Pull every point toward the middle of its neighbours, which straightens
+    // the line, keeping it between the posts and inside the drivable area
+    let total = points.len();
+    for _ in 0..RELAX_ITERATIONS {
+        for i in 0..total {
+            let previous = points[(i + total - 1) % total].position();
+            let next = points[(i + 1) % total].position();
+            let point = &points[i];
+            let target = (previous + next) * 0.5;
+            let mut offset = (target - point.center)
+                .dot(point.lateral)
+                .clamp(-point.half_width, point.half_width);
+            for _ in 0..5 {
+                if area.contains(point.center + point.lateral * offset) {
+                    break;
+                }
+                offset *= 0.5;
+            }
+            points[i].offset = offset;
+        }
+    }
+    let positions: Vec<Vec3> = points.iter().map(LinePoint::position).collect();
+
+    // Fastest speed through each corner, then how fast the car can get there
+    // accelerating out of the previous one and braking for the next; twice
+    // around so the limits carry over the start line
+    let mut speeds: Vec<f32> = (0..total)
+        .map(|i| {
+            let bend = curvature(
+                positions[(i + total - 1) % total],
+                positions[i],
+                positions[(i + 1) % total],
+            );
+            (CORNERING_GRIP / bend.max(1e-6)).sqrt().min(top_speed)
+        })
+        .collect();
+    for i in 0..2 * total {
+        let (from, to) = (i % total, (i + 1) % total);
+        let distance = positions[from].distance(positions[to]);
+        let reachable = (speeds[from].powi(2) + 2.0 * ACCELERATION * distance).sqrt();
+        speeds[to] = speeds[to].min(reachable);
+    }
+    for i in (0..2 * total).rev() {
+        let (from, to) = (i % total, (i + 1) % total);
+        let distance = positions[from].distance(positions[to]);
+        let stoppable = (speeds[to].powi(2) + 2.0 * BRAKING * distance).sqrt();
+        speeds[from] = speeds[from].min(stoppable);
+    }
+
+    positions.into_iter().zip(speeds).collect()
+}
+
+/// Show or hide the racing line with 'I'
+fn toggle_racing_line(keyboard: Res<ButtonInput<KeyCode>>, mut racing_line: ResMut<RacingLine>) {
+    if !keyboard.just_pressed(KeyCode::KeyI) {
+        return;
+    }
+    racing_line.visible = !racing_line.visible;
+    if !racing_line.visible {
+        info!("Racing line hidden");
+    } else if racing_line.points.is_empty() {
+        info!("Racing line needs a track with at least 3 gates");
+    } else {
+        info!("Racing line shown - green: throttle, red: brake");
+    }
+}
+
+/// Recompute the line when the track changes and lay the ribbon on the ground
+fn rebuild_racing_line(
+    mut commands: Commands,
+    mut racing_line: ResMut<RacingLine>,
+    checkpoints: Res<SceneCheckpoints>,
+    area: Res<DrivableArea>,
+    ground: GroundSurface,
+    car_query: Query<&Car, With<PrimaryCar>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ribbon_query: Query<(Entity, &mut Visibility), With<RacingLineRibbon>>,
+) {
+    let visibility = if racing_line.visible {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if !checkpoints.is_changed() && !area.is_changed() {
+        for (_, mut current) in ribbon_query.iter_mut() {
+            current.set_if_neq(visibility);
+        }
+        return;
+    }
+
+    for (entity, _) in ribbon_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let top_speed = car_query.single().map_or(30.0, |car| car.max_speed);
+    racing_line.points = compute_racing_line(&checkpoints.0, &area, top_speed);
+    let count = racing_line.points.len();
+    if count == 0 {
+        return;
+    }
+
+    let green = LinearRgba::rgb(0.1, 0.9, 0.2);
+    let red = LinearRgba::rgb(1.0, 0.1, 0.05);
+    let mut positions = Vec::with_capacity(count * 2);
+    let mut normals = Vec::with_capacity(count * 2);
+    let mut colors = Vec::with_capacity(count * 2);
+    for i in 0..count {
+        let (point, speed) = racing_line.points[i];
+        let (next, next_speed) = racing_line.points[(i + 1) % count];
+        let normal = ground.normal_at(point);
+        let on_ground = ground.project_point(point) + normal * RIBBON_LIFT;
+        let side = (next - point).cross(normal).normalize_or_zero() * RIBBON_WIDTH * 0.5;
+
+        // Redder the harder the car brakes toward the next point
+        let distance = point.distance(next).max(1e-3);
+        let braking = ((speed * speed - next_speed * next_speed) / (2.0 * BRAKING * distance))
+            .clamp(0.0, 1.0);
+        let color = green.mix(&red, braking).to_f32_array();
+
+        for vertex in [on_ground - side, on_ground + side] {
+            positions.push(vertex.to_array());
+            normals.push(normal.to_array());
+            colors.push(color);
+        }
+    }
+    let indices = (0..count as u32)
+        .flat_map(|i| {
+            let (a, b) = (i * 2, i * 2 + 1);
+            let (c, d) = ((i + 1) % count as u32 * 2, (i + 1) % count as u32 * 2 + 1);
+            [a, c, b, b, c, d]
+        })
+        .collect();
+    let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32(indices));
+
+    commands.spawn((
+        RacingLineRibbon,
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            unlit: true,
+            cull_mode: None,
+            ..default()
+        })),
+        Transform::default(),
+        visibility,
+    ));
+    info!("Racing line computed through {} gates", checkpoints.0.len());
+}
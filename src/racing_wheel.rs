@@ -0,0 +1,165 @@
+//! Racing wheel and pedal support
+//!
+//! Steering wheels show up as gamepads with the wheel and pedals on plain axes.
+//! A connected device whose name contains one of `RacingWheel::name_patterns`
+//! is driven as a wheel: its full rotation maps onto the car's steering through
+//! the steering ratio, and throttle, brake and clutch come from separate pedal
+//! axes. The axis assignment differs between devices and drivers, so it can be
+//! changed in the `RacingWheel` resource.
+//!
+//! Bevy only exposes the rumble motors of a device, not true force-feedback
+//! effects, so feedback is rendered through them: the strong motor follows the
+//! self-centering load of the front tires and the weak motor buzzes when they
+//! slide.
+
+use std::time::Duration;
+
+use bevy::input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest};
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::race_start::driving_allowed;
+
+/// How often the feedback effects are refreshed, in seconds
+const FEEDBACK_PERIOD: f32 = 0.1;
+
+/// Pedals pressed less than this leave the keyboard and gamepad in control
+const PEDAL_THRESHOLD: f32 = 0.02;
+
+/// Plugin for steering wheels, pedals and their feedback
+pub struct RacingWheelPlugin;
+
+impl Plugin for RacingWheelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RacingWheel>().add_systems(
+            Update,
+            (
+                read_racing_wheel
+                    .after(CarSet::Input)
+                    .before(CarSet::Physics)
+                    .run_if(driving_allowed),
+                racing_wheel_feedback.after(CarSet::Physics),
+            ),
+        );
+    }
+}
+
+/// Resource configuring racing wheel input
+#[derive(Resource)]
+pub struct RacingWheel {
+    /// Lowercase substrings of device names that are treated as wheels
+    pub name_patterns: Vec<String>,
+    pub steering_axis: GamepadAxis,
+    pub throttle_axis: GamepadAxis,
+    pub brake_axis: GamepadAxis,
+    pub clutch_axis: Option<GamepadAxis>,
+    /// Lock-to-lock rotation of the wheel in degrees
+    pub rotation_degrees: f32,
+    /// Degrees of wheel rotation per degree of steering at the road wheels
+    pub steering_ratio: f32,
+    /// Whether the pedal axes read -1 when pressed instead of when released
+    pub pedals_inverted: bool,
+    /// Strength of the feedback effects, from 0 to 1
+    pub feedback_strength: f32,
+}
+
+impl Default for RacingWheel {
+    fn default() -> Self {
+        Self {
+            name_patterns: ["wheel", "g29", "g920", "g923", "t300", "t150", "fanatec", "moza"]
+                .map(String::from)
+                .to_vec(),
+            steering_axis: GamepadAxis::LeftStickX,
+            throttle_axis: GamepadAxis::RightZ,
+            brake_axis: GamepadAxis::LeftZ,
+            clutch_axis: Some(GamepadAxis::RightStickY),
+            rotation_degrees: 900.0,
+            steering_ratio: 15.0,
+            pedals_inverted: false,
+            feedback_strength: 0.6,
+        }
+    }
+}
+
+impl RacingWheel {
+    /// Whether a device name looks like a racing wheel
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.name_patterns.iter().any(|pattern| name.contains(pattern.as_str()))
+    }
+
+    /// Pedal position from 0 (released) to 1 (fully pressed) for a raw axis value
+    fn pedal(&self, value: f32) -> f32 {
+        let value = if self.pedals_inverted { -value } else { value };
+        ((value + 1.0) * 0.5).clamp(0.0, 1.0)
+    }
+}
+
+/// Steer the car with the wheel and, while they are pressed, drive it with the pedals
+fn read_racing_wheel(
+    wheel: Res<RacingWheel>,
+    gamepads: Query<(&Gamepad, &Name)>,
+    mut car_query: Query<&mut Car, With<PrimaryCar>>,
+) {
+    let Ok(mut car) = car_query.single_mut() else {
+        return;
+    };
+    let Some((gamepad, _)) = gamepads.iter().find(|(_, name)| wheel.matches(name.as_str())) else {
+        car.clutch = 0.0;
+        return;
+    };
+
+    // Wheel rotation maps one to one onto the road wheels through the steering ratio
+    let rotation = gamepad.get(wheel.steering_axis).unwrap_or(0.0) * wheel.rotation_degrees * 0.5;
+    let limit = car.steering_limit();
+    car.steering = (-rotation / wheel.steering_ratio).to_radians().clamp(-limit, limit);
+
+    let pedal = |axis: GamepadAxis| gamepad.get(axis).map_or(0.0, |value| wheel.pedal(value));
+    let throttle = pedal(wheel.throttle_axis);
+    let brake = pedal(wheel.brake_axis);
+    car.clutch = wheel.clutch_axis.map_or(0.0, pedal);
+    if throttle > PEDAL_THRESHOLD || brake > PEDAL_THRESHOLD {
+        car.apply_pedals(throttle, brake);
+    }
+}
+
+/// Render centering force and tire slip through the wheel's rumble motors
+fn racing_wheel_feedback(
+    time: Res<Time>,
+    wheel: Res<RacingWheel>,
+    gamepads: Query<(Entity, &Name), With<Gamepad>>,
+    car_query: Query<&Car, With<PrimaryCar>>,
+    mut rumble: MessageWriter<GamepadRumbleRequest>,
+    mut since_update: Local<f32>,
+) {
+    *since_update += time.delta_secs();
+    if *since_update < FEEDBACK_PERIOD {
+        return;
+    }
+    *since_update = 0.0;
+
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+    let Some((gamepad, _)) = gamepads.iter().find(|(_, name)| wheel.matches(name.as_str())) else {
+        return;
+    };
+
+    // Self-centering grows with speed and steering angle, and fades once the
+    // front tires slide past their peak grip
+    let front_slip = (car.wheel_slip[0] + car.wheel_slip[1]) * 0.5;
+    let speed = (car.velocity.abs() / car.max_speed).min(1.0);
+    let steering = (car.steering.abs() / car.max_steering.max(1e-3)).min(1.0);
+    let centering = speed * steering / front_slip.max(1.0);
+    let slide = (front_slip - 1.0).clamp(0.0, 1.0);
+
+    rumble.write(GamepadRumbleRequest::Stop { gamepad });
+    rumble.write(GamepadRumbleRequest::Add {
+        gamepad,
+        intensity: GamepadRumbleIntensity {
+            strong_motor: centering * wheel.feedback_strength,
+            weak_motor: slide * wheel.feedback_strength,
+        },
+        duration: Duration::from_secs_f32(FEEDBACK_PERIOD * 1.5),
+    });
+}
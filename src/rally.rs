@@ -0,0 +1,222 @@
+//! Rally stages
+//!
+//! In the Rally game mode the gates are driven once, from the first to the
+//! last, as a point-to-point stage (see `track`). Every gate in between is an
+//! intermediate split, compared with the best stage on the HUD's delta bar.
+//! Crossing the finish brings up a finish screen with the stage time, the
+//! difference to the best stage of the session and each split against the
+//! best stage's; it goes away when the next stage starts.
+//!
+//! Track files and scene manifests saved in the Rally mode are marked as
+//! stages, so loading them switches to it.
+
+use bevy::prelude::*;
+
+use crate::car::{CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::input_map::{Action, InputMap};
+use crate::race_start::{RacePhase, RaceStart};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::track::{format_lap_time, CheckpointPassed, LapCompleted};
+
+/// Plugin for the rally stage finish screen
+pub struct RallyPlugin;
+
+impl Plugin for RallyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StageRecord>()
+            .add_systems(Startup, spawn_finish_screen)
+            .add_systems(
+                Update,
+                (
+                    record_stage.run_if(resource_equals(GameMode::Rally)),
+                    update_finish_screen,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// The result of the last stage, for the finish screen
+#[derive(Debug, Clone)]
+struct StageResult {
+    time: f32,
+    /// Best stage time before this one
+    previous_best: Option<f32>,
+    /// Lap time at each split and at the same split of the previous best stage
+    splits: Vec<(f32, Option<f32>)>,
+    valid: bool,
+}
+
+/// Resource with the first player's best stage of the session and the last result
+#[derive(Resource, Default)]
+pub struct StageRecord {
+    /// Fastest valid stage time
+    pub best: Option<f32>,
+    /// Lap time at each split of the fastest stage
+    best_splits: Vec<f32>,
+    /// Lap time at each split of the stage being driven
+    splits: Vec<f32>,
+    /// Result shown on the finish screen
+    result: Option<StageResult>,
+}
+
+/// Marker for the finish screen
+#[derive(Component)]
+struct FinishScreen;
+
+/// Marker for the text of the finish screen
+#[derive(Component)]
+struct FinishText;
+
+/// Note the first player's splits and keep the result of each stage
+fn record_stage(
+    checkpoints: Res<SceneCheckpoints>,
+    race: Res<RaceStart>,
+    mut record: ResMut<StageRecord>,
+    mut passed: MessageReader<CheckpointPassed>,
+    mut completed: MessageReader<LapCompleted>,
+    car_query: Query<Entity, With<PrimaryCar>>,
+) {
+    if checkpoints.is_changed() {
+        *record = StageRecord::default();
+    }
+    if race.is_changed() && matches!(race.phase, RacePhase::Countdown { .. }) {
+        record.result = None;
+    }
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+
+    for checkpoint in passed.read().filter(|checkpoint| checkpoint.car == car) {
+        // The start line begins a new stage
+        if checkpoint.index == 0 {
+            record.splits.clear();
+            record.result = None;
+        } else {
+            record.splits.push(checkpoint.lap_time);
+        }
+    }
+
+    for stage in completed.read().filter(|stage| stage.car == car) {
+        let splits = std::mem::take(&mut record.splits);
+        let result = StageResult {
+            time: stage.time,
+            previous_best: record.best,
+            splits: splits
+                .iter()
+                .enumerate()
+                .map(|(index, &split)| (split, record.best_splits.get(index).copied()))
+                .collect(),
+            valid: stage.valid,
+        };
+        if stage.valid && record.best.is_none_or(|best| stage.time < best) {
+            record.best = Some(stage.time);
+            record.best_splits = splits;
+        }
+        record.result = Some(result);
+    }
+}
+
+/// Spawn the hidden finish screen in the middle of the screen
+fn spawn_finish_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(25.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            FinishScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::all(Val::Px(16.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+                    BorderRadius::all(Val::Px(8.0)),
+                ))
+                .with_child((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    TextLayout::new_with_justify(Justify::Center),
+                    FinishText,
+                ));
+        });
+}
+
+/// Difference between two times with its sign
+fn format_delta(delta: f32) -> String {
+    format!("{}{:.2}", if delta < 0.0 { "-" } else { "+" }, delta.abs())
+}
+
+/// Show the result of the last stage in the Rally mode
+fn update_finish_screen(
+    mode: Res<GameMode>,
+    record: Res<StageRecord>,
+    input_map: Res<InputMap>,
+    mut screen_query: Query<&mut Visibility, With<FinishScreen>>,
+    mut text_query: Query<&mut Text, With<FinishText>>,
+) {
+    let Ok(mut visibility) = screen_query.single_mut() else {
+        return;
+    };
+    let result = record.result.as_ref().filter(|_| *mode == GameMode::Rally);
+    visibility.set_if_neq(if result.is_some() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    let (Some(result), Ok(mut text)) = (result, text_query.single_mut()) else {
+        return;
+    };
+    if !record.is_changed() && !mode.is_changed() {
+        return;
+    }
+
+    let mut lines = vec!["STAGE COMPLETE".to_string(), format_lap_time(result.time)];
+    lines.push(match result.previous_best {
+        _ if !result.valid => "Invalid - the time doesn't count".to_string(),
+        Some(best) if result.time < best => {
+            format!(
+                "New best stage time! ({})",
+                format_delta(result.time - best)
+            )
+        }
+        Some(best) => format!(
+            "Best {}  ({})",
+            format_lap_time(best),
+            format_delta(result.time - best)
+        ),
+        None => "First run of the stage".to_string(),
+    });
+    lines.push(String::new());
+    for (index, (split, best)) in result.splits.iter().enumerate() {
+        let delta = best.map_or(String::new(), |best| format_delta(split - best));
+        lines.push(format!(
+            "Split {}  {}  {}",
+            index + 1,
+            format_lap_time(*split),
+            delta
+        ));
+    }
+    let keys: Vec<String> = input_map
+        .keys(Action::StartRace)
+        .iter()
+        .map(|key| format!("{:?}", key))
+        .collect();
+    lines.push(String::new());
+    lines.push(format!("{}: run the stage again", keys.join("/")));
+    text.0 = lines.join("\n");
+}
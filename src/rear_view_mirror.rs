@@ -0,0 +1,146 @@
+//! Rear-view mirror
+//!
+//! A second camera on the back of the car looks behind it and renders into a
+//! texture, shown mirrored at the top of the screen like a real rear-view
+//! mirror, so cars coming up behind can be seen without looking back. Press F8
+//! (rebindable) to show or hide it. It is hidden while the camera flies freely.
+
+use bevy::camera::RenderTarget;
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+
+use crate::camera::CameraMode;
+use crate::car::{CarSet, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+
+/// Size of the mirror texture in pixels
+const MIRROR_RESOLUTION: UVec2 = UVec2::new(600, 200);
+
+/// Size of the mirror on screen in logical pixels
+const MIRROR_SIZE: Vec2 = Vec2::new(360.0, 120.0);
+
+/// Mirror camera position relative to the car, just behind the roof
+const MIRROR_OFFSET: Vec3 = Vec3::new(0.0, 1.2, 2.1);
+
+/// Plugin for the rear-view mirror
+pub struct RearViewMirrorPlugin;
+
+impl Plugin for RearViewMirrorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RearViewMirror>()
+            .add_systems(Startup, spawn_rear_view_mirror)
+            .add_systems(
+                Update,
+                (toggle_rear_view_mirror, update_rear_view_mirror)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the mirror's settings
+#[derive(Resource)]
+pub struct RearViewMirror {
+    pub visible: bool,
+}
+
+impl Default for RearViewMirror {
+    fn default() -> Self {
+        Self { visible: true }
+    }
+}
+
+/// Marker for the camera rendering the mirror
+#[derive(Component)]
+struct MirrorCamera;
+
+/// Marker for the mirror on the HUD
+#[derive(Component)]
+struct MirrorWidget;
+
+/// Spawn the mirror camera, its render target and the HUD widget showing it
+fn spawn_rear_view_mirror(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = images.add(Image::new_target_texture(
+        MIRROR_RESOLUTION.x,
+        MIRROR_RESOLUTION.y,
+        TextureFormat::bevy_default(),
+    ));
+
+    commands.spawn((
+        Camera3d::default(),
+        Camera {
+            // Render before the main view, which shows the result
+            order: -1,
+            target: RenderTarget::from(image.clone()),
+            ..default()
+        },
+        MirrorCamera,
+    ));
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Px(12.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            MirrorWidget,
+        ))
+        .with_child((
+            // Flipped, as a mirror shows the view behind
+            ImageNode::new(image).with_flip_x(),
+            Node {
+                width: Val::Px(MIRROR_SIZE.x),
+                height: Val::Px(MIRROR_SIZE.y),
+                border: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            BorderColor::all(Color::srgb(0.1, 0.1, 0.1)),
+            BorderRadius::all(Val::Px(8.0)),
+        ));
+}
+
+/// Show or hide the mirror
+fn toggle_rear_view_mirror(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut mirror: ResMut<RearViewMirror>,
+) {
+    if input_map.just_pressed(Action::RearViewMirror, &keyboard) {
+        mirror.visible = !mirror.visible;
+        info!("Rear-view mirror {}", if mirror.visible { "ON" } else { "OFF" });
+    }
+}
+
+/// Move the mirror camera with the car, rendering only while the mirror shows
+fn update_rear_view_mirror(
+    mirror: Res<RearViewMirror>,
+    mode: Res<CameraMode>,
+    car_query: Query<&Transform, (With<PrimaryCar>, Without<MirrorCamera>)>,
+    mut camera_query: Query<(&mut Camera, &mut Transform), With<MirrorCamera>>,
+    mut widget_query: Query<&mut Visibility, With<MirrorWidget>>,
+) {
+    let Ok((mut camera, mut camera_transform)) = camera_query.single_mut() else {
+        return;
+    };
+    let Ok(car_transform) = car_query.single() else {
+        return;
+    };
+
+    let show = mirror.visible && !mode.is_free();
+    camera.is_active = show;
+    for mut visibility in widget_query.iter_mut() {
+        visibility.set_if_neq(if show {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+    if show {
+        *camera_transform = car_transform.mul_transform(
+            Transform::from_translation(MIRROR_OFFSET).looking_to(Vec3::Z, Vec3::Y),
+        );
+    }
+}
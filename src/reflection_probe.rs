@@ -0,0 +1,383 @@
+//! Reflections of the scan on the cars
+//!
+//! The cars' paint is metallic, but with nothing to reflect it only ever
+//! mirrored the flat ambient light, so a car parked in a scanned street looked
+//! as if it stood in an empty studio. Each player's car carries a reflection
+//! probe: a cubemap of the splat around it is baked on a background thread
+//! every `BAKE_INTERVAL`, by drawing the Gaussians near the car as discs into
+//! the six faces, nearest first, and filling the texels they leave open with
+//! the sky. The cubemap is filtered on the GPU into an environment map that
+//! lights the meshes inside the probe's box around the car, so the paint
+//! picks up the buildings, the trees and the road it drives past.
+//!
+//! The bake uses the Gaussians' base colors, so reflections show the scan as
+//! it was lit when captured, dimmed at night. Reflections can be turned off on
+//! the settings screen.
+
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::asset::RenderAssetUsages;
+use bevy::light::{EnvironmentMapLight, GeneratedEnvironmentMapLight, LightProbe};
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+};
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::car::PrimaryCar;
+use crate::point_cloud::SplatPointCloud;
+use crate::settings::Settings;
+use crate::sky::SkyColors;
+use crate::split_screen::SecondCar;
+use crate::time_of_day::TimeOfDay;
+
+/// Texels across each face of the baked cubemap, a power of two
+const CUBEMAP_SIZE: u32 = 64;
+
+/// Time between bakes of a probe
+const BAKE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Distance the car has to move before its probe is baked again, in meters
+const BAKE_DISTANCE: f32 = 1.0;
+
+/// Gaussians within this distance of the car are baked, in meters
+const BAKE_RADIUS: f32 = 80.0;
+
+/// Gaussians closer than this to the probe are left out, in meters, so the
+/// ground right under the car doesn't cover the lower face
+const BAKE_NEAR: f32 = 0.5;
+
+/// Most Gaussians drawn into one bake; denser surroundings are thinned out
+const MAX_BAKE_GAUSSIANS: usize = 250_000;
+
+/// Gaussians fainter than this are left out of the bake
+const MIN_BAKE_OPACITY: f32 = 0.2;
+
+/// Largest radius of a Gaussian's disc on a face, in texels
+const MAX_DISC_TEXELS: f32 = 4.0;
+
+/// Side of the box around the car lit by its probe, in meters
+const PROBE_EXTENT: f32 = 8.0;
+
+/// Brightness of the reflections at full daylight, in cd/m²
+const REFLECTION_INTENSITY: f32 = 1000.0;
+
+/// Fraction of the reflections' brightness left at night
+const NIGHT_REFLECTION: f32 = 0.1;
+
+/// Plugin for the reflection probes of the cars
+pub struct ReflectionProbePlugin;
+
+impl Plugin for ReflectionProbePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                spawn_reflection_probes,
+                follow_cars,
+                start_bakes,
+                receive_bakes,
+                update_reflection_intensity,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Component of a car's reflection probe
+#[derive(Component)]
+struct ReflectionProbe {
+    car: Entity,
+    /// The cubemap the bakes are written to
+    cubemap: Handle<Image>,
+    /// Where the last bake was made, and how long ago
+    baked_at: Vec3,
+    since_bake: Duration,
+    /// Whether the surroundings have changed since the last bake
+    stale: bool,
+    bake: Option<Mutex<Receiver<Vec<u8>>>>,
+}
+
+/// A Gaussian to bake, relative to the probe
+struct BakeGaussian {
+    offset: Vec3,
+    color: Vec3,
+    radius: f32,
+}
+
+/// Spawn a probe for every player's car while reflections are on, and remove
+/// the probes of cars that are gone
+fn spawn_reflection_probes(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut images: ResMut<Assets<Image>>,
+    car_query: Query<(Entity, &Transform), Or<(With<PrimaryCar>, With<SecondCar>)>>,
+    probe_query: Query<(Entity, &ReflectionProbe)>,
+) {
+    for (entity, probe) in probe_query.iter() {
+        if !settings.scan_reflections || !car_query.contains(probe.car) {
+            commands.entity(entity).despawn();
+        }
+    }
+    if !settings.scan_reflections {
+        return;
+    }
+    for (car, transform) in car_query.iter() {
+        if probe_query.iter().any(|(_, probe)| probe.car == car) {
+            continue;
+        }
+        let cubemap = images.add(blank_cubemap());
+        commands.spawn((
+            LightProbe,
+            GeneratedEnvironmentMapLight {
+                environment_map: cubemap.clone(),
+                intensity: 0.0,
+                ..default()
+            },
+            Transform::from_translation(transform.translation)
+                .with_scale(Vec3::splat(PROBE_EXTENT)),
+            ReflectionProbe {
+                car,
+                cubemap,
+                baked_at: transform.translation,
+                since_bake: BAKE_INTERVAL,
+                stale: true,
+                bake: None,
+            },
+        ));
+    }
+}
+
+/// A mid grey cubemap standing in until the first bake is done
+fn blank_cubemap() -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: CUBEMAP_SIZE,
+            height: CUBEMAP_SIZE,
+            depth_or_array_layers: 6,
+        },
+        TextureDimension::D2,
+        &[128, 128, 128, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+    image
+}
+
+/// Keep each probe's box on its car, aligned with the world
+fn follow_cars(
+    car_query: Query<&Transform, Without<ReflectionProbe>>,
+    mut probe_query: Query<(&ReflectionProbe, &mut Transform)>,
+) {
+    for (probe, mut transform) in probe_query.iter_mut() {
+        if let Ok(car) = car_query.get(probe.car) {
+            transform.translation = car.translation;
+        }
+    }
+}
+
+/// Start a bake of every probe that is due and whose surroundings have
+/// changed: the car has moved, or the splat or the sky has changed
+fn start_bakes(
+    time: Res<Time>,
+    point_cloud: Res<SplatPointCloud>,
+    sky: Res<SkyColors>,
+    mut probe_query: Query<(&mut ReflectionProbe, &Transform)>,
+) {
+    for (mut probe, transform) in probe_query.iter_mut() {
+        probe.since_bake += time.delta();
+        let center = transform.translation;
+        if point_cloud.is_changed()
+            || sky.is_changed()
+            || probe.baked_at.distance(center) >= BAKE_DISTANCE
+        {
+            probe.stale = true;
+        }
+        if !probe.stale || probe.bake.is_some() || probe.since_bake < BAKE_INTERVAL {
+            continue;
+        }
+
+        let nearby = point_cloud.within_radius(center, BAKE_RADIUS);
+        let stride = nearby.len().div_ceil(MAX_BAKE_GAUSSIANS).max(1);
+        let gaussians: Vec<BakeGaussian> = nearby
+            .into_iter()
+            .step_by(stride)
+            .filter(|&index| point_cloud.opacities[index] >= MIN_BAKE_OPACITY)
+            .map(|index| BakeGaussian {
+                offset: point_cloud.positions[index] - center,
+                color: point_cloud.colors[index],
+                // Thinned out Gaussians grow to cover the gaps they leave
+                radius: point_cloud.scales[index].max_element() * (stride as f32).cbrt(),
+            })
+            .collect();
+        let horizon = sky.horizon.to_srgba().to_vec3();
+        let zenith = sky.zenith.to_srgba().to_vec3();
+
+        let (sender, receiver) = channel();
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let _ = sender.send(bake_cubemap(&gaussians, horizon, zenith));
+            })
+            .detach();
+        probe.bake = Some(Mutex::new(receiver));
+        probe.baked_at = center;
+        probe.since_bake = Duration::ZERO;
+        probe.stale = false;
+    }
+}
+
+/// Draw the Gaussians into the faces of a cubemap as discs, nearest first,
+/// and fill the rest with the sky, as sRGB texels in face order
+fn bake_cubemap(gaussians: &[BakeGaussian], horizon: Vec3, zenith: Vec3) -> Vec<u8> {
+    let size = CUBEMAP_SIZE as usize;
+    let mut depths = vec![f32::INFINITY; 6 * size * size];
+    let mut colors = vec![Vec3::ZERO; 6 * size * size];
+
+    for gaussian in gaussians {
+        let distance = gaussian.offset.length();
+        if distance < BAKE_NEAR {
+            continue;
+        }
+        let (face, uv) = cube_texel(gaussian.offset);
+        // A face spans 90 degrees, half its texels either side of its center
+        let disc = (gaussian.radius / distance * 0.5 * size as f32).clamp(0.5, MAX_DISC_TEXELS);
+        let texel = uv * size as f32;
+        let min = (texel - Vec2::splat(disc))
+            .floor()
+            .max(Vec2::ZERO)
+            .as_uvec2();
+        let max = (texel + Vec2::splat(disc))
+            .ceil()
+            .min(Vec2::splat(size as f32))
+            .as_uvec2();
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if center.distance_squared(texel) > disc * disc {
+                    continue;
+                }
+                let index = (face * size + y as usize) * size + x as usize;
+                if distance < depths[index] {
+                    depths[index] = distance;
+                    colors[index] = gaussian.color;
+                }
+            }
+        }
+    }
+
+    let mut data = Vec::with_capacity(colors.len() * 4);
+    for (index, (&depth, &color)) in depths.iter().zip(&colors).enumerate() {
+        let color = if depth.is_finite() {
+            color
+        } else {
+            let face = index / (size * size);
+            let x = index % size;
+            let y = index / size % size;
+            let uv = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / size as f32;
+            let height = texel_direction(face, uv).y.max(0.0);
+            horizon.lerp(zenith, height)
+        };
+        let [r, g, b] = color.clamp(Vec3::ZERO, Vec3::ONE).to_array();
+        data.extend([r, g, b, 1.0].map(|channel| (channel * 255.0).round() as u8));
+    }
+    data
+}
+
+/// The face and the position on it, from 0 to 1, that a world direction
+/// points to
+///
+/// Cubemaps are left-handed, so the direction's z is flipped, as the
+/// environment map shader does when sampling.
+fn cube_texel(direction: Vec3) -> (usize, Vec2) {
+    let c = Vec3::new(direction.x, direction.y, -direction.z);
+    let a = c.abs();
+    let (face, major, s, t) = if a.x >= a.y && a.x >= a.z {
+        if c.x > 0.0 {
+            (0, a.x, -c.z, -c.y)
+        } else {
+            (1, a.x, c.z, -c.y)
+        }
+    } else if a.y >= a.z {
+        if c.y > 0.0 {
+            (2, a.y, c.x, c.z)
+        } else {
+            (3, a.y, c.x, -c.z)
+        }
+    } else if c.z > 0.0 {
+        (4, a.z, c.x, -c.y)
+    } else {
+        (5, a.z, -c.x, -c.y)
+    };
+    let uv = (Vec2::new(s, t) / major.max(1e-6) + Vec2::ONE) * 0.5;
+    (face, uv.clamp(Vec2::ZERO, Vec2::splat(1.0 - 1e-6)))
+}
+
+/// The world direction through a position on a face, the inverse of
+/// `cube_texel`
+fn texel_direction(face: usize, uv: Vec2) -> Vec3 {
+    let Vec2 { x: s, y: t } = uv * 2.0 - Vec2::ONE;
+    let c = match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    };
+    Vec3::new(c.x, c.y, -c.z).normalize()
+}
+
+/// Write finished bakes into the probes' cubemaps
+fn receive_bakes(mut images: ResMut<Assets<Image>>, mut probe_query: Query<&mut ReflectionProbe>) {
+    for mut probe in probe_query.iter_mut() {
+        let Some(bake) = probe.bake.as_ref() else {
+            continue;
+        };
+        let message = bake.lock().unwrap().try_recv();
+        let data = match message {
+            Ok(data) => data,
+            Err(TryRecvError::Empty) => continue,
+            Err(TryRecvError::Disconnected) => {
+                probe.bake = None;
+                continue;
+            }
+        };
+        probe.bake = None;
+        if let Some(image) = images.get_mut(&probe.cubemap) {
+            image.data = Some(data);
+        }
+    }
+}
+
+/// Dim the reflections at night
+fn update_reflection_intensity(
+    time_of_day: Res<TimeOfDay>,
+    mut probe_query: Query<
+        (
+            &mut GeneratedEnvironmentMapLight,
+            Option<&mut EnvironmentMapLight>,
+        ),
+        With<ReflectionProbe>,
+    >,
+) {
+    let daylight = time_of_day.daylight();
+    let intensity = REFLECTION_INTENSITY * (NIGHT_REFLECTION + (1.0 - NIGHT_REFLECTION) * daylight);
+    for (mut generated, environment_map) in probe_query.iter_mut() {
+        if generated.intensity != intensity {
+            generated.intensity = intensity;
+        }
+        // The filtered map is added once from the generated one and keeps its
+        // own copy of the intensity
+        if let Some(mut environment_map) = environment_map {
+            if environment_map.intensity != intensity {
+                environment_map.intensity = intensity;
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Render scale
+//!
+//! The driving view can be rendered at a fraction of the window's resolution
+//! and stretched to fill it, trading sharpness for frame rate on big scans.
+//! Below a scale of 1 the first player's camera renders into a texture, which
+//! a 2D camera draws over the whole window before the HUD, so the HUD stays
+//! sharp. Split-screen always renders at full resolution.
+//!
+//! With dynamic resolution on, the scale follows the frame rate instead: it
+//! drops a step whenever the frame rate falls short of the target and climbs
+//! back, up to the render scale setting, once there is time to spare.
+
+use bevy::camera::visibility::RenderLayers;
+use bevy::camera::RenderTarget;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureFormat};
+
+use crate::camera::CarCamera;
+use crate::car::CarSet;
+use crate::split_screen::SecondCamera;
+
+/// Lowest scale dynamic resolution goes down to, and its change per step
+const MIN_DYNAMIC_SCALE: f32 = 0.4;
+const DYNAMIC_SCALE_STEP: f32 = 0.05;
+
+/// Seconds between changes of the dynamic resolution, giving the frame rate
+/// time to settle at the new scale
+const DYNAMIC_INTERVAL: f32 = 1.0;
+
+/// Render layer holding only the stretched view, so no other camera draws it
+const SCALED_VIEW_LAYER: usize = 30;
+
+/// Plugin rendering the driving view at a lower resolution
+pub struct RenderScalePlugin;
+
+impl Plugin for RenderScalePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderScale>()
+            .init_resource::<DynamicResolution>()
+            .add_systems(
+                Update,
+                (adjust_dynamic_resolution, apply_render_scale)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the fraction of the window's resolution the driving view renders at
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct RenderScale(pub f32);
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Resource with the dynamic resolution settings
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct DynamicResolution {
+    pub enabled: bool,
+    /// Frame rate the scale is lowered to reach
+    pub target_fps: f32,
+    /// Highest scale it goes back up to
+    pub max_scale: f32,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_fps: 60.0,
+            max_scale: 1.0,
+        }
+    }
+}
+
+/// The texture the driving view renders into and the entities stretching it
+/// over the window
+struct ScaledView {
+    image: Handle<Image>,
+    camera: Entity,
+    sprite: Entity,
+}
+
+/// Step the render scale down while the frame rate is below the target and
+/// back up while it is well above it
+fn adjust_dynamic_resolution(
+    time: Res<Time<Real>>,
+    dynamic: Res<DynamicResolution>,
+    mut scale: ResMut<RenderScale>,
+    mut frames: Local<(f32, u32)>,
+) {
+    let (elapsed, count) = &mut *frames;
+    if !dynamic.enabled {
+        *elapsed = 0.0;
+        *count = 0;
+        return;
+    }
+    *elapsed += time.delta_secs();
+    *count += 1;
+    if *elapsed < DYNAMIC_INTERVAL {
+        return;
+    }
+    let fps = *count as f32 / *elapsed;
+    *elapsed = 0.0;
+    *count = 0;
+
+    let min = MIN_DYNAMIC_SCALE.min(dynamic.max_scale);
+    let adjusted = if fps < dynamic.target_fps * 0.95 {
+        (scale.0 - DYNAMIC_SCALE_STEP).max(min)
+    } else if fps > dynamic.target_fps * 1.15 {
+        // Only with enough to spare, so it doesn't flip back and forth
+        (scale.0 + DYNAMIC_SCALE_STEP).min(dynamic.max_scale)
+    } else {
+        scale.0.min(dynamic.max_scale)
+    };
+    if scale.set_if_neq(RenderScale(adjusted)) {
+        info!("Dynamic resolution at {:.0}% for {:.0} fps", adjusted * 100.0, fps);
+    }
+}
+
+/// Render the first player's camera into a smaller texture while the scale is
+/// below 1, resizing it with the window
+fn apply_render_scale(
+    mut commands: Commands,
+    scale: Res<RenderScale>,
+    windows: Query<&Window>,
+    mut images: ResMut<Assets<Image>>,
+    mut view: Local<Option<ScaledView>>,
+    mut camera_query: Query<(Entity, &mut Camera), (With<CarCamera>, Without<SecondCamera>)>,
+    second_query: Query<(), With<SecondCamera>>,
+    mut sprite_query: Query<&mut Sprite>,
+) {
+    let (Ok(window), Ok((car_camera, mut camera))) = (windows.single(), camera_query.single_mut())
+    else {
+        return;
+    };
+    let scaled = scale.0 < 1.0 && second_query.is_empty();
+    if !scaled {
+        if let Some(old) = view.take() {
+            camera.target = RenderTarget::default();
+            commands.entity(car_camera).insert(IsDefaultUiCamera);
+            commands.entity(old.camera).despawn();
+            commands.entity(old.sprite).despawn();
+            info!("Rendering at full resolution");
+        }
+        return;
+    }
+
+    let size = (window.physical_size().as_vec2() * scale.0.max(0.1))
+        .max(Vec2::ONE)
+        .as_uvec2();
+    let view = view.get_or_insert_with(|| {
+        let image = images.add(Image::new_target_texture(
+            size.x,
+            size.y,
+            TextureFormat::bevy_default(),
+        ));
+        let layer = RenderLayers::layer(SCALED_VIEW_LAYER);
+        let view_camera = commands
+            .spawn((
+                Camera2d,
+                Camera {
+                    order: 1,
+                    ..default()
+                },
+                layer.clone(),
+                IsDefaultUiCamera,
+            ))
+            .id();
+        let sprite = commands
+            .spawn((Sprite::from_image(image.clone()), layer))
+            .id();
+        commands.entity(car_camera).remove::<IsDefaultUiCamera>();
+        camera.target = RenderTarget::from(image.clone());
+        info!("Rendering at {}x{}", size.x, size.y);
+        ScaledView {
+            image,
+            camera: view_camera,
+            sprite,
+        }
+    });
+
+    // Looking the image up mutably would upload it again every frame
+    let resized = images.get(&view.image).is_some_and(|image| image.size() != size);
+    if resized {
+        if let Some(image) = images.get_mut(&view.image) {
+            image.resize(Extent3d {
+                width: size.x,
+                height: size.y,
+                ..default()
+            });
+        }
+    }
+    if let Ok(mut sprite) = sprite_query.get_mut(view.sprite) {
+        if sprite.custom_size != Some(window.size()) {
+            sprite.custom_size = Some(window.size());
+        }
+    }
+}
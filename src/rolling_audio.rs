@@ -0,0 +1,180 @@
+//! Tire rolling noise
+//!
+//! Each surface has its own rolling loop: a low hum on asphalt, crunching on
+//! dirt and gravel, rustling on grass and a faint hiss on ice. The loops are
+//! synthesized rather than recorded, so they need no sound files. They all
+//! play at once and crossfade to the surface the car is on, as set by the
+//! surface zones, getting louder and higher pitched with speed and falling
+//! silent while the car is in the air.
+
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use avian3d::prelude::*;
+use bevy::audio::{AddAudioSource, Source, Volume};
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar, Surface};
+use crate::settings::Settings;
+
+/// Sample rate of the synthesized loops
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Speed at which the rolling noise reaches full volume, in m/s
+const FULL_VOLUME_SPEED: f32 = 25.0;
+
+/// How quickly the loops fade in and out, per second
+const CROSSFADE_RATE: f32 = 4.0;
+
+/// Plugin for the tire rolling noise
+pub struct RollingAudioPlugin;
+
+impl Plugin for RollingAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<RollingNoise>()
+            .add_systems(Startup, spawn_rolling_loops)
+            .add_systems(Update, update_rolling_loops.after(CarSet::Physics));
+    }
+}
+
+/// Endless synthesized rolling noise for one surface
+#[derive(Asset, TypePath, Debug, Clone, Copy)]
+pub struct RollingNoise {
+    pub surface: Surface,
+}
+
+impl Decodable for RollingNoise {
+    type DecoderItem = f32;
+    type Decoder = RollingNoiseDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        RollingNoiseDecoder {
+            surface: self.surface,
+            seed: 0x1234_5678 ^ self.surface as u32,
+            low: 0.0,
+            lower: 0.0,
+            crunch: 0.0,
+            sample: 0,
+        }
+    }
+}
+
+/// Sample generator behind `RollingNoise`
+pub struct RollingNoiseDecoder {
+    surface: Surface,
+    seed: u32,
+    /// Two one-pole low-pass filters of the white noise
+    low: f32,
+    lower: f32,
+    /// Envelope of the current gravel crunch
+    crunch: f32,
+    sample: u64,
+}
+
+impl RollingNoiseDecoder {
+    /// White noise between -1 and 1
+    fn white(&mut self) -> f32 {
+        // xorshift32
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl Iterator for RollingNoiseDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+        let white = self.white();
+        self.low += (white - self.low) * 0.05;
+        self.lower += (self.low - self.lower) * 0.05;
+        let rumble = self.lower * 8.0;
+        let hiss = white - self.low;
+
+        let value = match self.surface {
+            Surface::Asphalt => rumble * 0.6 + (TAU * 70.0 * t).sin() * 0.05,
+            Surface::Dirt => {
+                // Random bursts of crackle over a rougher rumble
+                if self.white() > 0.996 {
+                    self.crunch = 1.0;
+                }
+                self.crunch *= 0.997;
+                rumble * 0.5 + hiss * self.crunch * 0.6
+            }
+            Surface::Grass => {
+                let sway = 0.6 + 0.4 * (TAU * 2.5 * t).sin();
+                hiss * 0.2 * sway + rumble * 0.2
+            }
+            Surface::Ice => hiss * 0.06,
+        };
+        Some(value.clamp(-1.0, 1.0))
+    }
+}
+
+impl Source for RollingNoiseDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Component on the player of a surface's rolling loop
+#[derive(Component)]
+struct RollingLoop {
+    surface: Surface,
+    volume: f32,
+}
+
+/// Start every surface's loop, silent
+fn spawn_rolling_loops(mut commands: Commands, mut sources: ResMut<Assets<RollingNoise>>) {
+    for surface in Surface::ALL {
+        commands.spawn((
+            RollingLoop {
+                surface,
+                volume: 0.0,
+            },
+            AudioPlayer(sources.add(RollingNoise { surface })),
+            PlaybackSettings::LOOP.with_volume(Volume::SILENT),
+        ));
+    }
+}
+
+/// Fade in the loop of the surface under the car with speed, and fade out the others
+fn update_rolling_loops(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    car_query: Query<(&Car, &LinearVelocity), With<PrimaryCar>>,
+    mut loop_query: Query<(&mut RollingLoop, &mut AudioSink)>,
+) {
+    let Ok((car, velocity)) = car_query.single() else {
+        return;
+    };
+    let grounded = car.wheel_compression.iter().any(|&compression| compression > 0.0);
+    let speed = (velocity.length() / FULL_VOLUME_SPEED).min(1.0);
+    let fade = (CROSSFADE_RATE * time.delta_secs()).min(1.0);
+
+    for (mut rolling, mut sink) in loop_query.iter_mut() {
+        let target = if grounded && rolling.surface == car.surface {
+            speed
+        } else {
+            0.0
+        };
+        rolling.volume += (target - rolling.volume) * fade;
+        sink.set_volume(Volume::Linear(rolling.volume * settings.effects_gain()));
+        sink.set_speed(0.8 + 0.5 * speed);
+    }
+}
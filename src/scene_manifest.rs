@@ -0,0 +1,279 @@
+//! Scene manifests bundling everything needed for a playable level
+//!
+//! A `.gaussrace` (or `scene.json`) file lists the splats to load together with
+//! the ground planes, car spawn, scale, drivable area, collision proxies,
+//! surface zones, splat color adjustment and optional checkpoints and coins,
+//! so a complete level can be shared as one file. It can be passed on the
+//! command line or dropped onto the window like a splat. While a scene is open,
+//! Ctrl+S writes the edited level, including the track, back into its manifest.
+//!
+//! ```json
+//! {
+//!   "splats": [{ "path": "street.ply" }],
+//!   "scale": 1.0,
+//!   "ground_planes": [{ "origin": [0, 0, 0], "normal": [0, 1, 0], "up": [0, 1, 0] }],
+//!   "spawn": { "translation": [0, 0.5, 0], "rotation": [0, 0, 0, 1], "scale": [1, 1, 1] }
+//! }
+//! ```
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boundary::DrivableArea;
+use crate::car::{Car, PrimaryCar};
+use crate::collectibles::SceneCollectibles;
+use crate::collision_proxies::{CollisionProxies, CollisionProxy};
+use crate::game_mode::GameMode;
+use crate::ground_plane::{GroundPlane, GroundPlanes};
+use crate::spawn_point::SpawnPoint;
+use crate::splat_color::SplatColor;
+use crate::splat_loader::{
+    is_url, resolve_local_path, SplatLoadState, SplatPath, SplatQueue,
+};
+use crate::surface_zones::{SurfaceZone, SurfaceZones};
+use crate::track_file::{mode_for_track, ActiveTrack};
+
+/// Plugin loading scene manifests
+pub struct SceneManifestPlugin;
+
+impl Plugin for SceneManifestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<LoadSceneManifest>()
+            .init_resource::<SceneCheckpoints>()
+            .add_systems(Update, (load_scene_manifest, save_scene_manifest).chain());
+    }
+}
+
+/// Message requesting a scene manifest to be loaded from a path
+#[derive(Message, Debug, Clone)]
+pub struct LoadSceneManifest(pub String);
+
+/// A splat referenced by a manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSplat {
+    /// Path of the splat, relative to the manifest unless absolute or a URL
+    pub path: String,
+    /// Placement of the splat, before the manifest scale is applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+}
+
+/// Contents of a scene manifest
+///
+/// Ground planes, spawn and checkpoints are in world space, after `scale` has
+/// been applied to the splats.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneManifest {
+    pub splats: Vec<ManifestSplat>,
+    /// Uniform scale applied to every splat
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ground_planes: Vec<GroundPlane>,
+    /// Where the car starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<Transform>,
+    /// Checkpoint gates in driving order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checkpoints: Vec<Transform>,
+    /// Whether the gates make a point-to-point stage, driven in the Rally mode
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stage: bool,
+    /// Coins to collect, as points on the ground
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collectibles: Vec<Vec3>,
+    /// Corners of the drivable area
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boundary: Vec<Vec3>,
+    /// Hand-placed collision proxies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colliders: Vec<CollisionProxy>,
+    /// Regions of gravel, grass or ice
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub surfaces: Vec<SurfaceZone>,
+    /// Exposure, contrast and saturation of the splats
+    #[serde(default, skip_serializing_if = "SplatColor::is_neutral")]
+    pub color: SplatColor,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl SceneManifest {
+    /// Read and parse a manifest file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let local = resolve_local_path(path).ok_or_else(|| format!("{} not found", path))?;
+        let contents = std::fs::read_to_string(&local).map_err(|err| err.to_string())?;
+        serde_json::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    /// Write the manifest back to a file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let local = resolve_local_path(path).ok_or_else(|| format!("{} is not a local file", path))?;
+        let contents = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        std::fs::write(&local, contents).map_err(|err| err.to_string())
+    }
+
+    /// Splat load requests for the manifest, the first replacing the current scene
+    ///
+    /// Relative splat paths are resolved against the manifest's directory.
+    pub fn splat_requests(&self, manifest_path: &str) -> Vec<SplatPath> {
+        let directory = Path::new(manifest_path).parent().unwrap_or(Path::new(""));
+        self.splats
+            .iter()
+            .enumerate()
+            .map(|(index, splat)| {
+                let path = if is_url(&splat.path) || Path::new(&splat.path).is_absolute() {
+                    splat.path.clone()
+                } else {
+                    directory.join(&splat.path).to_string_lossy().to_string()
+                };
+                let mut transform = splat.transform.unwrap_or_default();
+                transform.translation *= self.scale;
+                transform.scale *= self.scale;
+
+                let request = if index == 0 {
+                    SplatPath::replace(path)
+                } else {
+                    SplatPath::additive(path)
+                };
+                request.with_transform(transform)
+            })
+            .collect()
+    }
+}
+
+/// Resource with the checkpoint gates of the current scene, in driving order
+#[derive(Resource, Default)]
+pub struct SceneCheckpoints(pub Vec<Transform>);
+
+/// Resource describing the scene manifest currently loaded
+#[derive(Resource)]
+pub struct ActiveScene {
+    /// Path of the manifest
+    pub path: String,
+    /// Splat paths the manifest loaded
+    pub splat_paths: Vec<String>,
+}
+
+/// Whether a path points at a scene manifest rather than a splat
+pub fn is_scene_manifest(path: &str) -> bool {
+    let lowercase = path.to_lowercase();
+    lowercase.ends_with(".gaussrace")
+        || lowercase.ends_with("/scene.json")
+        || lowercase == "scene.json"
+        || lowercase.ends_with(".scene.json")
+}
+
+/// Load requested manifests: queue their splats and apply planes, spawn, checkpoints, colliders,
+/// zones and colors
+fn load_scene_manifest(
+    mut commands: Commands,
+    mut requests: MessageReader<LoadSceneManifest>,
+    mut queue: ResMut<SplatQueue>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    mut spawn: ResMut<SpawnPoint>,
+    mut checkpoints: ResMut<SceneCheckpoints>,
+    mut mode: ResMut<GameMode>,
+    mut coins: ResMut<SceneCollectibles>,
+    mut area: ResMut<DrivableArea>,
+    mut proxies: ResMut<CollisionProxies>,
+    mut zones: ResMut<SurfaceZones>,
+    mut color: ResMut<SplatColor>,
+    mut car_query: Query<(&mut Car, &mut Transform), With<PrimaryCar>>,
+    mut next_state: ResMut<NextState<SplatLoadState>>,
+) {
+    for LoadSceneManifest(path) in requests.read() {
+        let manifest = match SceneManifest::load(path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                error!("Failed to load scene {}: {}", path, err);
+                continue;
+            }
+        };
+
+        let mut splats = manifest.splat_requests(path).into_iter();
+        let Some(first) = splats.next() else {
+            error!("Scene {} does not list any splats", path);
+            continue;
+        };
+        info!("Loading scene {} with {} splat(s)", path, manifest.splats.len());
+
+        commands.insert_resource(ActiveScene {
+            path: path.clone(),
+            splat_paths: std::iter::once(&first)
+                .chain(splats.as_slice())
+                .map(|request| request.path.clone())
+                .collect(),
+        });
+        commands.insert_resource(first);
+        // The scene brings its own track layout
+        commands.remove_resource::<ActiveTrack>();
+        queue.0.clear();
+        queue.0.extend(splats);
+        next_state.set(SplatLoadState::WaitingForPath);
+
+        ground_planes.planes = manifest.ground_planes;
+        checkpoints.0 = manifest.checkpoints;
+        let scene_mode = mode_for_track(*mode, manifest.stage);
+        mode.set_if_neq(scene_mode);
+        coins.0 = manifest.collectibles;
+        area.points = manifest.boundary;
+        proxies.0 = manifest.colliders;
+        zones.0 = manifest.surfaces;
+        color.set_if_neq(manifest.color);
+        if let Some(car_spawn) = manifest.spawn {
+            spawn.0 = car_spawn;
+            if let Ok((mut car, mut transform)) = car_query.single_mut() {
+                *transform = car_spawn;
+                car.stop();
+            }
+        }
+    }
+}
+
+/// Save the edited planes, spawn, track, boundary, colliders, zones and colors into the open
+/// manifest with Ctrl+S
+fn save_scene_manifest(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    active_scene: Option<Res<ActiveScene>>,
+    ground_planes: Res<GroundPlanes>,
+    spawn: Res<SpawnPoint>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    coins: Res<SceneCollectibles>,
+    area: Res<DrivableArea>,
+    proxies: Res<CollisionProxies>,
+    zones: Res<SurfaceZones>,
+    color: Res<SplatColor>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    // Ctrl+Shift+S saves the track file instead
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if !ctrl || shift || !keyboard.just_pressed(KeyCode::KeyS) {
+        return;
+    }
+    let Some(scene) = active_scene else {
+        return;
+    };
+
+    let result = SceneManifest::load(&scene.path).and_then(|mut manifest| {
+        manifest.ground_planes = ground_planes.planes.clone();
+        manifest.spawn = Some(spawn.0);
+        manifest.checkpoints = checkpoints.0.clone();
+        manifest.stage = mode.is_stage();
+        manifest.collectibles = coins.0.clone();
+        manifest.boundary = area.points.clone();
+        manifest.colliders = proxies.0.clone();
+        manifest.surfaces = zones.0.clone();
+        manifest.color = *color;
+        manifest.save(&scene.path)
+    });
+    match result {
+        Ok(()) => info!("Saved level to scene {}", scene.path),
+        Err(err) => error!("Failed to save scene {}: {}", scene.path, err),
+    }
+}
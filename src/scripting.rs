@@ -0,0 +1,418 @@
+//! Game mode scripts
+//!
+//! Every `.rhai` file in `assets/scripts/` is a [Rhai](https://rhai.rs)
+//! script loaded at startup, so a custom game mode doesn't need a fork of the
+//! game. A script reacts to the race by defining any of these functions:
+//!
+//! - `init()` once the car is on the road
+//! - `on_checkpoint(player, gate, lap_time)` when a car drives through its
+//!   next gate, gate 0 being the start/finish line
+//! - `on_lap(player, lap, time, best)` when a car completes a lap
+//! - `on_collision(speed)` when the first player's car crashes or lands hard
+//!
+//! `player` is 1 for the first player and 2 for the second. Inside these
+//! functions `this` is a map kept from call to call, for the script's own
+//! state. The game offers the scripts:
+//!
+//! - `car(field)` and `set_car(field, value)` read and change a tunable of
+//!   the first player's car, named as in a vehicle file; picking another
+//!   vehicle puts the tunables back
+//! - `car_position()`, the car's position as `[x, y, z]`
+//! - `teleport(x, y, z)` puts the car at a position, standing still
+//! - `spawn_block(x, y, z, size)` places a solid block and `clear_blocks()`
+//!   removes them all
+//! - `print(text)` writes to the log and the console
+//!
+//! ```rhai
+//! fn init() {
+//!     this.laps = 0;
+//! }
+//!
+//! fn on_lap(player, lap, time, best) {
+//!     this.laps += 1;
+//!     set_car("max_speed", car("max_speed") + 5.0);
+//!     print(`Lap ${this.laps}: faster!`);
+//! }
+//! ```
+//!
+//! `scripts reload` in the console reads the scripts again.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+
+use crate::car::{Car, PrimaryCar};
+use crate::console::{Console, ConsoleCommandsExt};
+use crate::damage::CarImpact;
+use crate::split_screen::SecondCar;
+use crate::track::{CheckpointPassed, LapCompleted};
+
+/// Directory the scripts are read from
+const SCRIPTS_DIR: &str = "assets/scripts";
+
+/// Most operations a script may run per call, so an endless loop stalls the
+/// game for a moment instead of hanging it
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Color of the blocks the scripts place
+const BLOCK_COLOR: Color = Color::srgb(0.9, 0.45, 0.1);
+
+/// Plugin running the game mode scripts
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        let mut scripts = Scripts::new();
+        scripts.load(Path::new(SCRIPTS_DIR));
+        app.insert_resource(scripts)
+            .add_console_command(
+                "scripts",
+                "scripts [reload]",
+                "List or reload the game mode scripts",
+                scripts_command,
+            )
+            .add_systems(Update, (run_scripts, apply_script_actions).chain());
+    }
+}
+
+/// A change to the game a script asked for, carried out after it returns
+enum ScriptAction {
+    Print(String),
+    SetCar(String, f32),
+    Teleport(Vec3),
+    SpawnBlock(Vec3, f32),
+    ClearBlocks,
+}
+
+/// What the scripts see of the game, and what they asked for
+#[derive(Default)]
+struct ScriptContext {
+    /// The first player's car as it was before the scripts ran, with their
+    /// changes
+    car: Option<Car>,
+    car_position: Vec3,
+    actions: Vec<ScriptAction>,
+}
+
+/// A loaded script
+struct Script {
+    path: PathBuf,
+    ast: AST,
+    /// The script's `this`
+    state: Dynamic,
+    initialized: bool,
+}
+
+/// Resource with the scripting engine and the loaded scripts
+#[derive(Resource)]
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    context: Arc<Mutex<ScriptContext>>,
+}
+
+impl Scripts {
+    /// An engine offering the game's functions, with no scripts loaded
+    fn new() -> Self {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let queue = |context: &Arc<Mutex<ScriptContext>>, action| {
+            context.lock().unwrap().actions.push(action);
+        };
+        let ctx = context.clone();
+        engine.on_print(move |text| queue(&ctx, ScriptAction::Print(text.to_string())));
+        let ctx = context.clone();
+        engine.register_fn("car", move |field: &str| -> Dynamic {
+            let mut context = ctx.lock().unwrap();
+            match context.car.as_mut().and_then(|car| car.tunable_mut(field)) {
+                Some(value) => Dynamic::from_float(*value as f64),
+                None => Dynamic::UNIT,
+            }
+        });
+        let ctx = context.clone();
+        engine.register_fn("set_car", move |field: &str, value: Dynamic| {
+            let Some(value) = number(&value) else {
+                return;
+            };
+            let mut context = ctx.lock().unwrap();
+            // Later reads in the same call see the change
+            if let Some(current) = context.car.as_mut().and_then(|car| car.tunable_mut(field)) {
+                *current = value;
+            }
+            context
+                .actions
+                .push(ScriptAction::SetCar(field.to_string(), value));
+        });
+        let ctx = context.clone();
+        engine.register_fn("car_position", move || -> Array {
+            let position = ctx.lock().unwrap().car_position;
+            position
+                .to_array()
+                .map(|value| Dynamic::from_float(value as f64))
+                .to_vec()
+        });
+        let ctx = context.clone();
+        engine.register_fn("teleport", move |x: Dynamic, y: Dynamic, z: Dynamic| {
+            if let Some(position) = vector(&x, &y, &z) {
+                queue(&ctx, ScriptAction::Teleport(position));
+            }
+        });
+        let ctx = context.clone();
+        engine.register_fn(
+            "spawn_block",
+            move |x: Dynamic, y: Dynamic, z: Dynamic, size: Dynamic| {
+                if let (Some(position), Some(size)) = (vector(&x, &y, &z), number(&size)) {
+                    queue(&ctx, ScriptAction::SpawnBlock(position, size.max(0.01)));
+                }
+            },
+        );
+        let ctx = context.clone();
+        engine.register_fn("clear_blocks", move || {
+            queue(&ctx, ScriptAction::ClearBlocks)
+        });
+
+        Self {
+            engine,
+            scripts: Vec::new(),
+            context,
+        }
+    }
+
+    /// Compile every script in a directory, in file name order, replacing
+    /// the ones loaded before
+    fn load(&mut self, dir: &Path) {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+
+        self.scripts.clear();
+        for path in paths {
+            match self.engine.compile_file(path.clone()) {
+                Ok(ast) => {
+                    info!("Loaded script {}", path.display());
+                    self.scripts.push(Script {
+                        path,
+                        ast,
+                        state: Dynamic::from_map(Map::new()),
+                        initialized: false,
+                    });
+                }
+                Err(err) => warn!("Ignoring script {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    /// Call a function in every script that defines it
+    fn call(&mut self, function: &str, arguments: impl FuncArgs + Clone) {
+        for script in &mut self.scripts {
+            if !script.ast.iter_functions().any(|f| f.name == function) {
+                continue;
+            }
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut script.state);
+            if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &script.ast,
+                function,
+                arguments.clone(),
+            ) {
+                warn!(
+                    "Script {} failed in {}: {}",
+                    script.path.display(),
+                    function,
+                    err
+                );
+            }
+        }
+    }
+
+    /// Run the top level and `init` of the scripts that haven't started yet
+    fn initialize(&mut self) {
+        for script in self.scripts.iter_mut().filter(|script| !script.initialized) {
+            script.initialized = true;
+            if let Err(err) = self.engine.run_ast(&script.ast) {
+                warn!("Script {} failed: {}", script.path.display(), err);
+                continue;
+            }
+            if script.ast.iter_functions().any(|f| f.name == "init") {
+                let options = CallFnOptions::new()
+                    .eval_ast(false)
+                    .bind_this_ptr(&mut script.state);
+                if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(
+                    options,
+                    &mut Scope::new(),
+                    &script.ast,
+                    "init",
+                    (),
+                ) {
+                    warn!("Script {} failed in init: {}", script.path.display(), err);
+                }
+            }
+        }
+    }
+}
+
+/// A script's number as an f32, whether written as an integer or not
+fn number(value: &Dynamic) -> Option<f32> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|value| value as f64))
+        .map(|value| value as f32)
+}
+
+/// Three of a script's numbers as a vector
+fn vector(x: &Dynamic, y: &Dynamic, z: &Dynamic) -> Option<Vec3> {
+    Some(Vec3::new(number(x)?, number(y)?, number(z)?))
+}
+
+/// Marker for the blocks placed by the scripts
+#[derive(Component)]
+struct ScriptBlock;
+
+/// Pass this frame's race events to the scripts
+fn run_scripts(
+    mut scripts: ResMut<Scripts>,
+    mut checkpoints: MessageReader<CheckpointPassed>,
+    mut laps: MessageReader<LapCompleted>,
+    mut impacts: MessageReader<CarImpact>,
+    car_query: Query<(Entity, &Car, &Transform), With<PrimaryCar>>,
+    second_query: Query<(), With<SecondCar>>,
+) {
+    let Ok((primary, car, transform)) = car_query.single() else {
+        return;
+    };
+    if scripts.scripts.is_empty() {
+        return;
+    }
+    {
+        let mut context = scripts.context.lock().unwrap();
+        context.car = Some(car.clone());
+        context.car_position = transform.translation;
+    }
+
+    scripts.initialize();
+    let player = |car: Entity| -> Option<i64> {
+        if car == primary {
+            Some(1)
+        } else if second_query.contains(car) {
+            Some(2)
+        } else {
+            None
+        }
+    };
+    for checkpoint in checkpoints.read() {
+        if let Some(player) = player(checkpoint.car) {
+            let arguments = (player, checkpoint.index as i64, checkpoint.lap_time as f64);
+            scripts.call("on_checkpoint", arguments);
+        }
+    }
+    for lap in laps.read() {
+        if let Some(player) = player(lap.car) {
+            let arguments = (player, lap.lap as i64, lap.time as f64, lap.best);
+            scripts.call("on_lap", arguments);
+        }
+    }
+    for impact in impacts.read() {
+        scripts.call("on_collision", (impact.speed as f64,));
+    }
+}
+
+/// Carry out what the scripts asked for
+fn apply_script_actions(world: &mut World) {
+    let actions = {
+        let scripts = world.resource::<Scripts>();
+        let mut context = scripts.context.lock().unwrap();
+        std::mem::take(&mut context.actions)
+    };
+    for action in actions {
+        match action {
+            ScriptAction::Print(text) => {
+                info!("{}", text);
+                world.resource_mut::<Console>().print(text);
+            }
+            ScriptAction::SetCar(field, value) => {
+                let mut car_query = world
+                    .query_filtered::<(&mut Car, &mut Mass, &mut CenterOfMass), With<PrimaryCar>>();
+                let Ok((mut car, mut mass, mut center_of_mass)) = car_query.single_mut(world)
+                else {
+                    continue;
+                };
+                let Some(current) = car.tunable_mut(&field) else {
+                    warn!(
+                        "Scripts can't set car.{}, the car has no such tunable",
+                        field
+                    );
+                    continue;
+                };
+                *current = value;
+                // The rigid body keeps its own copy of these
+                *mass = Mass(car.mass);
+                *center_of_mass = CenterOfMass(Vec3::Y * car.center_of_mass_height);
+            }
+            ScriptAction::Teleport(position) => {
+                let mut car_query =
+                    world.query_filtered::<(&mut Car, &mut Transform), With<PrimaryCar>>();
+                if let Ok((mut car, mut transform)) = car_query.single_mut(world) {
+                    transform.translation = position;
+                    car.stop();
+                }
+            }
+            ScriptAction::SpawnBlock(position, size) => {
+                let mesh = world
+                    .resource_mut::<Assets<Mesh>>()
+                    .add(Cuboid::from_length(size));
+                let material = world
+                    .resource_mut::<Assets<StandardMaterial>>()
+                    .add(BLOCK_COLOR);
+                world.spawn((
+                    Mesh3d(mesh),
+                    MeshMaterial3d(material),
+                    Transform::from_translation(position),
+                    RigidBody::Static,
+                    Collider::cuboid(size, size, size),
+                    ScriptBlock,
+                ));
+            }
+            ScriptAction::ClearBlocks => {
+                let blocks: Vec<Entity> = world
+                    .query_filtered::<Entity, With<ScriptBlock>>()
+                    .iter(world)
+                    .collect();
+                for block in blocks {
+                    world.despawn(block);
+                }
+            }
+        }
+    }
+}
+
+/// List the scripts, or read them again
+fn scripts_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let mut scripts = world.resource_mut::<Scripts>();
+    match arguments {
+        [] => {}
+        ["reload"] => scripts.load(Path::new(SCRIPTS_DIR)),
+        _ => return Err("usage: scripts [reload]".to_string()),
+    }
+    if scripts.scripts.is_empty() {
+        return Ok(format!("No scripts in {}", SCRIPTS_DIR));
+    }
+    Ok(scripts
+        .scripts
+        .iter()
+        .map(|script| script.path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -0,0 +1,752 @@
+//! Settings
+//!
+//! The settings screen is opened from the pause menu or the main menu: Up/Down
+//! pick a setting and Left/Right (or Enter) change it. It holds the window
+//! resolution, fullscreen, vsync, the quality preset, the render scale and
+//! dynamic resolution (see `render_scale`), the post-processing effects (see
+//! `post_processing`), depth occlusion (see `depth_occlusion`), the scan
+//! shadows (see `shadow_proxy`) and reflections (see `reflection_probe`), the
+//! master, effects and interface volumes, the speed units, the time of day
+//! (see `time_of_day`) and the distance fog (see `sky`), and Controls opens
+//! the key binding screen. Escape or Back closes it.
+//!
+//! Settings apply right away and are saved to `settings.json` in the working
+//! directory whenever they change.
+//!
+//! The quality presets, Low, Medium, High and Ultra, trade the look of the
+//! splats for frame rate so big scans stay playable on laptops. Lower presets
+//! hide the faintest Gaussians, which barely change the picture but still cost
+//! sorting and blending, drop the higher degrees of the spherical harmonics,
+//! which give the splats their view-dependent shine, sort the Gaussians by
+//! depth less often and render at a lower scale. Picking a preset sets the
+//! render scale, which can still be changed on its own afterwards.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, WindowMode};
+use bevy_gaussian_splatting::sort::SortConfig;
+use bevy_gaussian_splatting::PlanarGaussian3d;
+use serde::{Deserialize, Serialize};
+
+use crate::input_map::{InputMap, OpenControls, RebindScreen};
+use crate::render_scale::{DynamicResolution, RenderScale};
+use crate::time_of_day::{format_hour, TimeOfDay, AMBIENT_STEP, AZIMUTH_STEP, MAX_AMBIENT_LIGHT};
+
+/// File the settings are loaded from and saved to
+const SETTINGS_PATH: &str = "settings.json";
+
+/// Window sizes to choose from
+const RESOLUTIONS: [UVec2; 5] = [
+    UVec2::new(1280, 720),
+    UVec2::new(1600, 900),
+    UVec2::new(1920, 1080),
+    UVec2::new(2560, 1440),
+    UVec2::new(3840, 2160),
+];
+
+/// Render scales to choose from
+const RENDER_SCALES: [f32; 5] = [0.5, 0.67, 0.75, 0.85, 1.0];
+
+/// Frame rates dynamic resolution can aim for
+const TARGET_FRAME_RATES: [u32; 6] = [30, 45, 60, 90, 120, 144];
+
+/// Distances the fog fades meshes out at, in meters
+const FOG_DISTANCES: [f32; 5] = [150.0, 250.0, 400.0, 600.0, 900.0];
+
+/// Volume change per key press
+const VOLUME_STEP: f32 = 0.1;
+
+/// Plugin for the settings and their screen
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load())
+            .init_resource::<SettingsScreen>()
+            .add_message::<OpenSettings>()
+            .add_systems(
+                Update,
+                (
+                    navigate_settings,
+                    update_settings_screen,
+                    apply_settings,
+                    apply_splat_quality,
+                    save_settings,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Quality preset of the splat rendering
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplatQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Ultra,
+}
+
+impl SplatQuality {
+    /// Every quality, lowest first
+    const ALL: [SplatQuality; 4] = [
+        SplatQuality::Low,
+        SplatQuality::Medium,
+        SplatQuality::High,
+        SplatQuality::Ultra,
+    ];
+
+    /// Gaussians fainter than this are hidden
+    fn min_opacity(self) -> f32 {
+        match self {
+            SplatQuality::Low => 0.3,
+            SplatQuality::Medium => 0.1,
+            SplatQuality::High | SplatQuality::Ultra => 0.0,
+        }
+    }
+
+    /// Highest degree of the spherical harmonics kept, 3 being all of them
+    fn sh_degree(self) -> usize {
+        match self {
+            SplatQuality::Low => 0,
+            SplatQuality::Medium => 1,
+            SplatQuality::High | SplatQuality::Ultra => 3,
+        }
+    }
+
+    /// Milliseconds between sorts of the Gaussians by depth
+    fn sort_period_ms(self) -> usize {
+        match self {
+            SplatQuality::Low => 500,
+            SplatQuality::Medium => 250,
+            SplatQuality::High => 100,
+            SplatQuality::Ultra => 0,
+        }
+    }
+
+    /// Render scale the preset sets
+    fn render_scale(self) -> f32 {
+        match self {
+            SplatQuality::Low => 0.67,
+            SplatQuality::Medium => 0.85,
+            SplatQuality::High | SplatQuality::Ultra => 1.0,
+        }
+    }
+
+    fn step(self, direction: i32) -> Self {
+        let index = Self::ALL.iter().position(|&quality| quality == self).unwrap_or(0);
+        Self::ALL[step_index(index, Self::ALL.len(), direction)]
+    }
+}
+
+/// Resource with the player's settings
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Window size in logical pixels, when not fullscreen
+    pub resolution: UVec2,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Fraction of the window's resolution the driving view renders at, the
+    /// most dynamic resolution goes up to
+    pub render_scale: f32,
+    pub splat_quality: SplatQuality,
+    /// Whether the render scale follows the frame rate
+    pub dynamic_resolution: bool,
+    /// Frame rate dynamic resolution aims for
+    pub target_fps: u32,
+    pub bloom: bool,
+    pub motion_blur: bool,
+    pub speed_vignette: bool,
+    /// Whether the dense parts of the splat hide the meshes behind them
+    pub depth_occlusion: bool,
+    /// Whether the splat casts and receives the sun's shadows
+    pub scan_shadows: bool,
+    /// Whether the cars reflect the splat around them
+    pub scan_reflections: bool,
+    /// Volumes from 0 to 1
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub interface_volume: f32,
+    /// Whether speeds are shown in mph instead of km/h
+    pub imperial: bool,
+    /// Hour the scene is lit for, from 0 to 24
+    pub time_of_day: f32,
+    /// Whether the hour moves on by itself
+    pub day_night_cycle: bool,
+    /// Compass direction of the sun at noon, in degrees
+    pub sun_azimuth: f32,
+    /// Multiplier of the ambient brightness
+    pub ambient_light: f32,
+    /// Whether far meshes fade into the sky
+    pub distance_fog: bool,
+    /// Distance at which meshes have faded into the fog, in meters
+    pub fog_distance: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let time_of_day = TimeOfDay::default();
+        Self {
+            resolution: RESOLUTIONS[0],
+            fullscreen: false,
+            vsync: true,
+            render_scale: 1.0,
+            splat_quality: SplatQuality::High,
+            dynamic_resolution: false,
+            target_fps: 60,
+            bloom: true,
+            motion_blur: false,
+            speed_vignette: true,
+            depth_occlusion: true,
+            scan_shadows: true,
+            scan_reflections: true,
+            master_volume: 1.0,
+            effects_volume: 1.0,
+            interface_volume: 1.0,
+            imperial: false,
+            time_of_day: time_of_day.hour,
+            day_night_cycle: time_of_day.cycling,
+            sun_azimuth: time_of_day.sun_azimuth,
+            ambient_light: time_of_day.ambient_light,
+            distance_fog: true,
+            fog_distance: 400.0,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings file, starting from the defaults if it doesn't exist
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(SETTINGS_PATH) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Settings>(&contents) {
+            Ok(settings) => {
+                info!("Loaded settings from {}", SETTINGS_PATH);
+                settings
+            }
+            Err(err) => {
+                warn!("Ignoring unreadable settings {}: {}", SETTINGS_PATH, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the settings file
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(SETTINGS_PATH, contents)
+    }
+
+    /// Volume of the car and world sounds
+    pub fn effects_gain(&self) -> f32 {
+        self.master_volume * self.effects_volume
+    }
+
+    /// Volume of the beeps and other interface sounds
+    pub fn interface_gain(&self) -> f32 {
+        self.master_volume * self.interface_volume
+    }
+}
+
+/// Message opening the settings screen
+#[derive(Message, Debug, Clone, Copy)]
+pub struct OpenSettings;
+
+/// A line of the settings screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingsItem {
+    Resolution,
+    Fullscreen,
+    VSync,
+    SplatQuality,
+    RenderScale,
+    DynamicResolution,
+    TargetFrameRate,
+    Bloom,
+    MotionBlur,
+    SpeedVignette,
+    DepthOcclusion,
+    ScanShadows,
+    ScanReflections,
+    MasterVolume,
+    EffectsVolume,
+    InterfaceVolume,
+    Units,
+    TimeOfDay,
+    DayNightCycle,
+    SunDirection,
+    AmbientLight,
+    DistanceFog,
+    FogDistance,
+    Controls,
+    Back,
+}
+
+impl SettingsItem {
+    /// Every item, in screen order
+    const ALL: [SettingsItem; 25] = [
+        SettingsItem::Resolution,
+        SettingsItem::Fullscreen,
+        SettingsItem::VSync,
+        SettingsItem::SplatQuality,
+        SettingsItem::RenderScale,
+        SettingsItem::DynamicResolution,
+        SettingsItem::TargetFrameRate,
+        SettingsItem::Bloom,
+        SettingsItem::MotionBlur,
+        SettingsItem::SpeedVignette,
+        SettingsItem::DepthOcclusion,
+        SettingsItem::ScanShadows,
+        SettingsItem::ScanReflections,
+        SettingsItem::MasterVolume,
+        SettingsItem::EffectsVolume,
+        SettingsItem::InterfaceVolume,
+        SettingsItem::Units,
+        SettingsItem::TimeOfDay,
+        SettingsItem::DayNightCycle,
+        SettingsItem::SunDirection,
+        SettingsItem::AmbientLight,
+        SettingsItem::DistanceFog,
+        SettingsItem::FogDistance,
+        SettingsItem::Controls,
+        SettingsItem::Back,
+    ];
+
+    /// The item's name and current value
+    fn label(self, settings: &Settings) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let percent = |fraction: f32| format!("{:.0}%", fraction * 100.0);
+        match self {
+            SettingsItem::Resolution => format!(
+                "Resolution: {}x{}",
+                settings.resolution.x, settings.resolution.y
+            ),
+            SettingsItem::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            SettingsItem::VSync => format!("VSync: {}", on_off(settings.vsync)),
+            SettingsItem::RenderScale => {
+                format!("Render scale: {}", percent(settings.render_scale))
+            }
+            SettingsItem::SplatQuality => format!("Quality: {:?}", settings.splat_quality),
+            SettingsItem::DynamicResolution => {
+                format!("Dynamic resolution: {}", on_off(settings.dynamic_resolution))
+            }
+            SettingsItem::TargetFrameRate => format!("Target frame rate: {}", settings.target_fps),
+            SettingsItem::Bloom => format!("Bloom: {}", on_off(settings.bloom)),
+            SettingsItem::MotionBlur => format!("Motion blur: {}", on_off(settings.motion_blur)),
+            SettingsItem::SpeedVignette => {
+                format!("Speed vignette: {}", on_off(settings.speed_vignette))
+            }
+            SettingsItem::DepthOcclusion => {
+                format!("Depth occlusion: {}", on_off(settings.depth_occlusion))
+            }
+            SettingsItem::ScanShadows => format!("Scan shadows: {}", on_off(settings.scan_shadows)),
+            SettingsItem::ScanReflections => {
+                format!("Reflections: {}", on_off(settings.scan_reflections))
+            }
+            SettingsItem::MasterVolume => {
+                format!("Master volume: {}", percent(settings.master_volume))
+            }
+            SettingsItem::EffectsVolume => {
+                format!("Effects volume: {}", percent(settings.effects_volume))
+            }
+            SettingsItem::InterfaceVolume => {
+                format!("Interface volume: {}", percent(settings.interface_volume))
+            }
+            SettingsItem::Units => {
+                format!("Speed units: {}", if settings.imperial { "mph" } else { "km/h" })
+            }
+            SettingsItem::TimeOfDay => {
+                format!("Time of day: {}", format_hour(settings.time_of_day))
+            }
+            SettingsItem::DayNightCycle => {
+                format!("Day-night cycle: {}", on_off(settings.day_night_cycle))
+            }
+            SettingsItem::SunDirection => format!("Sun direction: {:.0}°", settings.sun_azimuth),
+            SettingsItem::AmbientLight => {
+                format!("Ambient light: {}", percent(settings.ambient_light))
+            }
+            SettingsItem::DistanceFog => format!("Distance fog: {}", on_off(settings.distance_fog)),
+            SettingsItem::FogDistance => format!("Fog distance: {:.0} m", settings.fog_distance),
+            SettingsItem::Controls => "Controls...".to_string(),
+            SettingsItem::Back => "Back".to_string(),
+        }
+    }
+
+    /// Change the item's setting one step up or down
+    fn change(self, settings: &mut Settings, direction: i32) {
+        // Rounded to whole steps so the volumes stay at tidy percentages
+        let volume = |volume: f32| {
+            let steps = (volume / VOLUME_STEP).round() + direction as f32;
+            (steps * VOLUME_STEP).clamp(0.0, 1.0)
+        };
+        match self {
+            SettingsItem::Resolution => {
+                let index = RESOLUTIONS
+                    .iter()
+                    .position(|&resolution| resolution == settings.resolution)
+                    .unwrap_or(0);
+                settings.resolution = RESOLUTIONS[step_index(index, RESOLUTIONS.len(), direction)];
+            }
+            SettingsItem::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            SettingsItem::VSync => settings.vsync = !settings.vsync,
+            SettingsItem::RenderScale => {
+                let index = RENDER_SCALES
+                    .iter()
+                    .position(|&scale| scale >= settings.render_scale)
+                    .unwrap_or(RENDER_SCALES.len() - 1);
+                settings.render_scale =
+                    RENDER_SCALES[step_index(index, RENDER_SCALES.len(), direction)];
+            }
+            SettingsItem::SplatQuality => {
+                settings.splat_quality = settings.splat_quality.step(direction);
+                settings.render_scale = settings.splat_quality.render_scale();
+            }
+            SettingsItem::DynamicResolution => {
+                settings.dynamic_resolution = !settings.dynamic_resolution
+            }
+            SettingsItem::TargetFrameRate => {
+                let index = TARGET_FRAME_RATES
+                    .iter()
+                    .position(|&fps| fps >= settings.target_fps)
+                    .unwrap_or(TARGET_FRAME_RATES.len() - 1);
+                settings.target_fps =
+                    TARGET_FRAME_RATES[step_index(index, TARGET_FRAME_RATES.len(), direction)];
+            }
+            SettingsItem::Bloom => settings.bloom = !settings.bloom,
+            SettingsItem::MotionBlur => settings.motion_blur = !settings.motion_blur,
+            SettingsItem::SpeedVignette => settings.speed_vignette = !settings.speed_vignette,
+            SettingsItem::DepthOcclusion => settings.depth_occlusion = !settings.depth_occlusion,
+            SettingsItem::ScanShadows => settings.scan_shadows = !settings.scan_shadows,
+            SettingsItem::ScanReflections => settings.scan_reflections = !settings.scan_reflections,
+            SettingsItem::MasterVolume => settings.master_volume = volume(settings.master_volume),
+            SettingsItem::EffectsVolume => {
+                settings.effects_volume = volume(settings.effects_volume)
+            }
+            SettingsItem::InterfaceVolume => {
+                settings.interface_volume = volume(settings.interface_volume)
+            }
+            SettingsItem::Units => settings.imperial = !settings.imperial,
+            SettingsItem::TimeOfDay => {
+                let hour = settings.time_of_day.round() + direction as f32;
+                settings.time_of_day = hour.rem_euclid(24.0)
+            }
+            SettingsItem::DayNightCycle => settings.day_night_cycle = !settings.day_night_cycle,
+            SettingsItem::SunDirection => {
+                settings.sun_azimuth =
+                    (settings.sun_azimuth + direction as f32 * AZIMUTH_STEP).rem_euclid(360.0)
+            }
+            SettingsItem::AmbientLight => {
+                settings.ambient_light = (settings.ambient_light + direction as f32 * AMBIENT_STEP)
+                    .clamp(0.0, MAX_AMBIENT_LIGHT)
+            }
+            SettingsItem::DistanceFog => settings.distance_fog = !settings.distance_fog,
+            SettingsItem::FogDistance => {
+                let index = FOG_DISTANCES
+                    .iter()
+                    .position(|&distance| distance >= settings.fog_distance)
+                    .unwrap_or(FOG_DISTANCES.len() - 1);
+                settings.fog_distance =
+                    FOG_DISTANCES[step_index(index, FOG_DISTANCES.len(), direction)];
+            }
+            SettingsItem::Controls | SettingsItem::Back => {}
+        }
+    }
+}
+
+/// An index moved one step in a direction, wrapping around `count`
+fn step_index(index: usize, count: usize, direction: i32) -> usize {
+    (index as i32 + direction).rem_euclid(count as i32) as usize
+}
+
+/// Resource tracking the settings screen
+#[derive(Resource, Default)]
+pub struct SettingsScreen {
+    /// The screen's root node while it is open
+    root: Option<Entity>,
+    /// Index into `SettingsItem::ALL` of the highlighted item
+    highlighted: usize,
+}
+
+impl SettingsScreen {
+    /// Whether the screen is open
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+}
+
+/// Marker for the text listing the settings
+#[derive(Component)]
+struct SettingsText;
+
+/// Open the screen on request, move the highlight and change the settings
+pub fn navigate_settings(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut input_map: ResMut<InputMap>,
+    mut screen: ResMut<SettingsScreen>,
+    mut settings: ResMut<Settings>,
+    mut requests: MessageReader<OpenSettings>,
+    controls: Res<RebindScreen>,
+    mut open_controls: MessageWriter<OpenControls>,
+) {
+    if requests.read().count() > 0 && !screen.is_open() {
+        screen.highlighted = 0;
+        screen.root = Some(spawn_settings_screen(&mut commands));
+        input_map.suspended = true;
+        // The key that opened the screen doesn't change anything
+        return;
+    }
+    let Some(root) = screen.root else {
+        return;
+    };
+    // The controls screen opened from here takes the keys until it closes
+    if controls.is_open() {
+        return;
+    }
+
+    let count = SettingsItem::ALL.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        screen.highlighted = (screen.highlighted + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        screen.highlighted = (screen.highlighted + count - 1) % count;
+    }
+    let item = SettingsItem::ALL[screen.highlighted];
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        item.change(&mut settings, 1);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        item.change(&mut settings, -1);
+    }
+
+    let mut close = keyboard.just_pressed(KeyCode::Escape);
+    if keyboard.just_pressed(KeyCode::Enter) {
+        match item {
+            SettingsItem::Controls => {
+                open_controls.write(OpenControls);
+            }
+            SettingsItem::Back => close = true,
+            _ => item.change(&mut settings, 1),
+        }
+    }
+    if close {
+        commands.entity(root).despawn();
+        screen.root = None;
+    }
+}
+
+/// Spawn the settings panel and return its root
+fn spawn_settings_screen(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                SettingsText,
+            ));
+        })
+        .id()
+}
+
+/// List the settings with their values, marking the highlighted one, and
+/// hide the screen behind the controls screen
+fn update_settings_screen(
+    screen: Res<SettingsScreen>,
+    settings: Res<Settings>,
+    controls: Res<RebindScreen>,
+    mut root_query: Query<&mut Visibility>,
+    mut text_query: Query<(&mut Text, Ref<SettingsText>)>,
+) {
+    let Some(root) = screen.root else {
+        return;
+    };
+    if let Ok(mut visibility) = root_query.get_mut(root) {
+        visibility.set_if_neq(if controls.is_open() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+    let Ok((mut text, marker)) = text_query.single_mut() else {
+        return;
+    };
+    if !screen.is_changed() && !settings.is_changed() && !marker.is_added() {
+        return;
+    }
+
+    let mut lines = vec![
+        "Settings - Up/Down: select, Left/Right: change, Esc: back".to_string(),
+        String::new(),
+    ];
+    for (index, item) in SettingsItem::ALL.iter().enumerate() {
+        let marker = if index == screen.highlighted { ">" } else { " " };
+        lines.push(format!("{} {}", marker, item.label(&settings)));
+    }
+    text.0 = lines.join("\n");
+}
+
+/// Apply the window and render settings when they change
+fn apply_settings(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window>,
+    mut render_scale: ResMut<RenderScale>,
+    mut dynamic_resolution: ResMut<DynamicResolution>,
+    mut applied: Local<Option<Settings>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let Ok(mut window) = windows.single_mut() else {
+        return;
+    };
+
+    let previous = applied.replace(settings.clone());
+    let mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+    } else {
+        WindowMode::Windowed
+    };
+    if window.mode != mode {
+        window.mode = mode;
+    }
+    let present_mode = if settings.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    };
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+    // A window resized by hand keeps its size until the resolution is changed here
+    let resized = previous.is_none_or(|previous| {
+        previous.resolution != settings.resolution || previous.fullscreen != settings.fullscreen
+    });
+    if resized && !settings.fullscreen {
+        let size = settings.resolution.as_vec2();
+        window.resolution.set(size.x, size.y);
+    }
+    render_scale.set_if_neq(RenderScale(settings.render_scale));
+    dynamic_resolution.set_if_neq(DynamicResolution {
+        enabled: settings.dynamic_resolution,
+        target_fps: settings.target_fps as f32,
+        max_scale: settings.render_scale,
+    });
+}
+
+/// Apply the quality preset to the splats: how often they are sorted, and on
+/// every cloud when the preset changes and on new clouds as they load, which
+/// Gaussians are hidden and how many spherical harmonics are kept
+fn apply_splat_quality(
+    settings: Res<Settings>,
+    mut clouds: ResMut<Assets<PlanarGaussian3d>>,
+    mut events: MessageReader<AssetEvent<PlanarGaussian3d>>,
+    sort_config: Option<ResMut<SortConfig>>,
+    mut stripped: Local<HashMap<AssetId<PlanarGaussian3d>, Vec<f32>>>,
+    mut applied: Local<Option<SplatQuality>>,
+) {
+    let quality = settings.splat_quality;
+    let mut added = Vec::new();
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } => added.push(*id),
+            AssetEvent::Removed { id } | AssetEvent::Unused { id } => {
+                stripped.remove(id);
+            }
+            _ => {}
+        }
+    }
+    if let Some(mut sort_config) = sort_config {
+        if sort_config.period_ms != quality.sort_period_ms() {
+            sort_config.period_ms = quality.sort_period_ms();
+        }
+    }
+
+    let ids: Vec<AssetId<PlanarGaussian3d>> = if *applied != Some(quality) {
+        *applied = Some(quality);
+        info!("Splat quality {:?}", quality);
+        clouds.ids().collect()
+    } else if quality.min_opacity() == 0.0 && quality.sh_degree() == 3 {
+        // Clouds are loaded with every Gaussian visible and all their harmonics
+        return;
+    } else {
+        added
+    };
+    for id in ids {
+        if let Some(cloud) = clouds.get_mut(id) {
+            cull_faint_gaussians(cloud, quality.min_opacity());
+            limit_sh_degree(cloud, quality.sh_degree(), stripped.entry(id).or_default());
+        }
+    }
+}
+
+/// Hide the Gaussians of a cloud fainter than `min_opacity` and show the rest
+fn cull_faint_gaussians(cloud: &mut PlanarGaussian3d, min_opacity: f32) {
+    for (point, scale_opacity) in cloud.position_visibility.iter_mut().zip(&cloud.scale_opacity) {
+        point.visibility = if scale_opacity.opacity >= min_opacity {
+            1.0
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Zero the spherical harmonics of a cloud above `degree`, keeping the
+/// coefficients taken out in `stripped` so a higher degree can put them back
+fn limit_sh_degree(cloud: &mut PlanarGaussian3d, degree: usize, stripped: &mut Vec<f32>) {
+    let Some(count) = cloud.spherical_harmonic.first().map(|sh| sh.coefficients.len()) else {
+        return;
+    };
+    // Built with the base color only
+    if count <= 3 {
+        return;
+    }
+    // The coefficients are interleaved by channel, the base color first
+    let kept = ((degree + 1) * (degree + 1) * 3).min(count);
+    if stripped.is_empty() {
+        if kept == count {
+            return;
+        }
+        *stripped = cloud
+            .spherical_harmonic
+            .iter()
+            .flat_map(|sh| sh.coefficients[3..count].iter().copied())
+            .collect();
+    }
+    for (sh, original) in cloud
+        .spherical_harmonic
+        .iter_mut()
+        .zip(stripped.chunks_exact(count - 3))
+    {
+        for index in 3..count {
+            sh.set(index, if index < kept { original[index - 3] } else { 0.0 });
+        }
+    }
+}
+
+/// Save the settings whenever they change
+fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        if let Err(err) = settings.save() {
+            error!("Failed to save the settings: {}", err);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Shadow proxy of the splat
+//!
+//! The splats don't cast or receive the sun's shadows, so the car drove
+//! through the shade of scanned buildings in full sunlight and cast no shadow
+//! onto the road. The solid voxels of the splat (see `depth_occlusion`) are
+//! turned into a coarse proxy of its surfaces that stands in for it in the
+//! sun's shadows, as two meshes on the same surface:
+//!
+//! - the caster is drawn into the sun's shadow map only, so the scan shades
+//!   the car, the gates and the other meshes;
+//! - the catcher is drawn over the splats, clear where the sun reaches it and
+//!   darkened where the car or the scan shades it.
+//!
+//! The catcher's shadows fade with the daylight and under an overcast sky.
+//! Both can be turned off on the settings screen.
+
+use bevy::asset::embedded_asset;
+use bevy::light::{NotShadowCaster, NotShadowReceiver};
+use bevy::mesh::MeshVertexBufferLayoutRef;
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, MeshPipelineKey};
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    AsBindGroup, ColorWrites, CompareFunction, RenderPipelineDescriptor,
+    SpecializedMeshPipelineError,
+};
+use bevy::shader::ShaderRef;
+
+use crate::depth_occlusion::SolidVoxels;
+use crate::settings::Settings;
+use crate::time_of_day::TimeOfDay;
+use crate::weather::Weather;
+
+/// Opacity of the catcher's shadows in full daylight
+const SHADOW_DARKNESS: f32 = 0.5;
+
+/// Added to the catcher's distance from the camera when sorting transparent
+/// meshes, so it is drawn after the splats
+const CATCHER_SORT_BIAS: f32 = 1.0e5;
+
+/// Plugin for the shadow proxy of the splat
+pub struct ShadowProxyPlugin;
+
+impl Plugin for ShadowProxyPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shadow_proxy.wgsl");
+        app.add_plugins((
+            MaterialPlugin::<ShadowCasterMaterial>::default(),
+            MaterialPlugin::<ShadowCatcherMaterial>::default(),
+        ))
+        .add_systems(
+            Update,
+            (
+                rebuild_shadow_proxy,
+                show_shadow_proxy,
+                update_shadow_darkness,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Material drawn into the shadow maps of directional lights only
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone, Default)]
+pub struct ShadowCasterMaterial {}
+
+impl Material for ShadowCasterMaterial {
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Only the shadow maps of directional lights render unclipped depth
+        if key
+            .mesh_key
+            .contains(MeshPipelineKey::UNCLIPPED_DEPTH_ORTHO)
+        {
+            return Ok(());
+        }
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_write_enabled = false;
+            depth_stencil.depth_compare = CompareFunction::Never;
+        }
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.write_mask = ColorWrites::empty();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Material showing only the shadows falling on a mesh
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct ShadowCatcherMaterial {
+    /// Color of the shadows, with the opacity of a full shadow
+    #[uniform(0)]
+    pub shadow_color: LinearRgba,
+}
+
+impl Material for ShadowCatcherMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "embedded://gaussrace/shadow_proxy.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn depth_bias(&self) -> f32 {
+        CATCHER_SORT_BIAS
+    }
+}
+
+/// Marker for the meshes of the shadow proxy
+#[derive(Component)]
+struct ShadowProxy;
+
+/// Rebuild the proxy from the solid voxels whenever they change
+fn rebuild_shadow_proxy(
+    mut commands: Commands,
+    voxels: Option<Res<SolidVoxels>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut casters: ResMut<Assets<ShadowCasterMaterial>>,
+    mut catchers: ResMut<Assets<ShadowCatcherMaterial>>,
+    proxy_query: Query<Entity, With<ShadowProxy>>,
+) {
+    let changed = voxels.as_ref().is_some_and(|voxels| voxels.is_changed());
+    let removed = voxels.is_none() && !proxy_query.is_empty();
+    if !changed && !removed {
+        return;
+    }
+    for entity in proxy_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let Some(voxels) = voxels else {
+        return;
+    };
+
+    let mesh = meshes.add(voxels.surface_mesh(0.0));
+    commands.spawn((
+        Mesh3d(mesh.clone()),
+        MeshMaterial3d(casters.add(ShadowCasterMaterial::default())),
+        Transform::default(),
+        NotShadowReceiver,
+        ShadowProxy,
+    ));
+    commands.spawn((
+        Mesh3d(mesh),
+        MeshMaterial3d(catchers.add(ShadowCatcherMaterial {
+            shadow_color: LinearRgba::NONE,
+        })),
+        Transform::default(),
+        NotShadowCaster,
+        ShadowProxy,
+    ));
+}
+
+/// Show the proxy while the scan shadows are on
+fn show_shadow_proxy(
+    settings: Res<Settings>,
+    mut proxy_query: Query<&mut Visibility, With<ShadowProxy>>,
+) {
+    let visibility = if settings.scan_shadows {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for mut proxy in proxy_query.iter_mut() {
+        proxy.set_if_neq(visibility);
+    }
+}
+
+/// Darken the catcher's shadows with the sunlight
+fn update_shadow_darkness(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    mut catchers: ResMut<Assets<ShadowCatcherMaterial>>,
+    catcher_query: Query<Ref<MeshMaterial3d<ShadowCatcherMaterial>>>,
+) {
+    let darkness = SHADOW_DARKNESS * time_of_day.daylight() * weather.sunlight();
+    for catcher in catcher_query.iter() {
+        if !time_of_day.is_changed() && !weather.is_changed() && !catcher.is_added() {
+            continue;
+        }
+        if let Some(material) = catchers.get_mut(&catcher.0) {
+            material.shadow_color = LinearRgba::BLACK.with_alpha(darkness);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// Shadow catcher over the splat: clear where the sun reaches the proxy and
+// darkened where something casts a shadow on it
+
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    mesh_view_bindings::{view, lights},
+    mesh_view_types::DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT,
+    shadows::fetch_directional_shadow,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<uniform> shadow_color: vec4<f32>;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view_z = dot(vec4<f32>(
+        view.view_from_world[0].z,
+        view.view_from_world[1].z,
+        view.view_from_world[2].z,
+        view.view_from_world[3].z
+    ), in.world_position);
+
+    var lit = 1.0;
+    for (var i: u32 = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        if (lights.directional_lights[i].flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u {
+            lit = min(lit, fetch_directional_shadow(i, in.world_position, in.world_normal, view_z));
+        }
+    }
+    return vec4<f32>(shadow_color.rgb, shadow_color.a * (1.0 - lit));
+}
@@ -0,0 +1,206 @@
+//! Per-splat settings saved in a JSON file next to the splat
+//!
+//! For `scans/street.ply` the settings live in `scans/street.ply.sidecar.json`,
+//! so they follow the splat around and are picked up whenever it is opened again.
+//! Besides the alignment saved by the splat editor, the sidecar of the primary
+//! (first loaded) splat holds the ground planes, car spawn, drivable area,
+//! collision proxies and surface zones, saved with Ctrl+S. Levels opened from a scene manifest are
+//! saved into the manifest instead. While a `.track` file is being driven, its
+//! spawn, drivable area and surface zones take the place of the sidecar's.
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boundary::DrivableArea;
+use crate::car::{Car, PrimaryCar};
+use crate::cli;
+use crate::collision_proxies::{CollisionProxies, CollisionProxy};
+use crate::ground_plane::{GroundPlane, GroundPlanes};
+use crate::scene_manifest::ActiveScene;
+use crate::spawn_point::SpawnPoint;
+use crate::splat_loader::{resolve_local_path, LoadedSplat, LoadedSplats};
+use crate::surface_zones::{SurfaceZone, SurfaceZones};
+use crate::track_file::ActiveTrack;
+
+/// Plugin saving and restoring the ground planes and car spawn of the primary splat
+pub struct SidecarPlugin;
+
+impl Plugin for SidecarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (restore_level_from_sidecar, save_level_to_sidecar).chain());
+    }
+}
+
+/// Settings remembered for a single splat file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SplatSidecar {
+    /// Alignment transform applied to the splat entity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+    /// Ground planes defined on this splat
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ground_planes: Vec<GroundPlane>,
+    /// Where the car starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub car_spawn: Option<Transform>,
+    /// Corners of the drivable area
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boundary: Vec<Vec3>,
+    /// Hand-placed collision proxies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colliders: Vec<CollisionProxy>,
+    /// Regions of gravel, grass or ice
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub surfaces: Vec<SurfaceZone>,
+}
+
+impl SplatSidecar {
+    /// Location of the sidecar file for a splat, if the splat is a local file
+    pub fn path_for(splat_path: &str) -> Option<PathBuf> {
+        let local = resolve_local_path(splat_path)?;
+        let mut file_name = local.file_name()?.to_os_string();
+        file_name.push(".sidecar.json");
+        Some(local.with_file_name(file_name))
+    }
+
+    /// Read the sidecar for a splat, if one exists and parses
+    pub fn load(splat_path: &str) -> Option<Self> {
+        let path = Self::path_for(splat_path)?;
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(sidecar) => Some(sidecar),
+            Err(err) => {
+                warn!("Ignoring unreadable sidecar {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// Write the sidecar next to the splat
+    pub fn save(&self, splat_path: &str) -> std::io::Result<PathBuf> {
+        let path = Self::path_for(splat_path).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "splat is not a local file")
+        })?;
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Load the sidecar (or start a new one), modify it and save it back
+    pub fn update(splat_path: &str, modify: impl FnOnce(&mut Self)) -> std::io::Result<PathBuf> {
+        let mut sidecar = Self::load(splat_path).unwrap_or_default();
+        modify(&mut sidecar);
+        sidecar.save(splat_path)
+    }
+}
+
+/// Path of the primary splat, the first one loaded into the scene
+pub fn primary_splat_path<'a>(
+    loaded_splats: &LoadedSplats,
+    splat_query: &'a Query<&LoadedSplat>,
+) -> Option<&'a str> {
+    let &entity = loaded_splats.0.first()?;
+    splat_query.get(entity).ok().map(|splat| splat.path.as_str())
+}
+
+/// Apply the saved ground planes, car spawn, boundary, colliders and surface zones when a new primary splat is opened
+///
+/// Splats opened through a scene manifest take these from the manifest instead.
+fn restore_level_from_sidecar(
+    loaded_splats: Res<LoadedSplats>,
+    active_scene: Option<Res<ActiveScene>>,
+    active_track: Option<Res<ActiveTrack>>,
+    splat_query: Query<&LoadedSplat>,
+    mut restored_path: Local<Option<String>>,
+    mut ground_planes: ResMut<GroundPlanes>,
+    mut spawn: ResMut<SpawnPoint>,
+    mut area: ResMut<DrivableArea>,
+    mut proxies: ResMut<CollisionProxies>,
+    mut zones: ResMut<SurfaceZones>,
+    mut car_query: Query<(&mut Car, &mut Transform), With<PrimaryCar>>,
+) {
+    if !loaded_splats.is_changed() {
+        return;
+    }
+    let path = primary_splat_path(&loaded_splats, &splat_query);
+    if path == restored_path.as_deref() {
+        return;
+    }
+    *restored_path = path.map(str::to_string);
+    if let (Some(path), Some(scene)) = (path, &active_scene) {
+        if scene.splat_paths.iter().any(|scene_path| scene_path == path) {
+            return;
+        }
+    }
+
+    let Some(sidecar) = path.and_then(SplatSidecar::load) else {
+        return;
+    };
+    // Planes given on the command line take the place of the saved ones
+    if !sidecar.ground_planes.is_empty() && cli::args().planes.is_empty() {
+        info!("Restoring {} saved ground plane(s)", sidecar.ground_planes.len());
+        ground_planes.planes = sidecar.ground_planes;
+    }
+    if !sidecar.colliders.is_empty() {
+        info!("Restoring {} saved collision proxies", sidecar.colliders.len());
+        proxies.0 = sidecar.colliders;
+    }
+    // The rest is the track layout, which an open track file provides
+    if active_track.is_some() {
+        return;
+    }
+    if !sidecar.boundary.is_empty() {
+        info!("Restoring saved drivable area with {} corners", sidecar.boundary.len());
+        area.points = sidecar.boundary;
+    }
+    if !sidecar.surfaces.is_empty() {
+        info!("Restoring {} saved surface zones", sidecar.surfaces.len());
+        zones.0 = sidecar.surfaces;
+    }
+    if let Some(car_spawn) = sidecar.car_spawn {
+        info!("Restoring saved car spawn at {:?}", car_spawn.translation);
+        spawn.0 = car_spawn;
+        if let Ok((mut car, mut transform)) = car_query.single_mut() {
+            *transform = car_spawn;
+            car.stop();
+        }
+    }
+}
+
+/// Save the ground planes, spawn point, drivable area, colliders and surface zones with Ctrl+S
+fn save_level_to_sidecar(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    loaded_splats: Res<LoadedSplats>,
+    active_scene: Option<Res<ActiveScene>>,
+    splat_query: Query<&LoadedSplat>,
+    ground_planes: Res<GroundPlanes>,
+    spawn: Res<SpawnPoint>,
+    area: Res<DrivableArea>,
+    proxies: Res<CollisionProxies>,
+    zones: Res<SurfaceZones>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    // Ctrl+Shift+S saves the track file instead
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if !ctrl || shift || !keyboard.just_pressed(KeyCode::KeyS) || active_scene.is_some() {
+        return;
+    }
+    let Some(path) = primary_splat_path(&loaded_splats, &splat_query) else {
+        warn!("No splat loaded to save the ground planes for");
+        return;
+    };
+
+    let result = SplatSidecar::update(path, |sidecar| {
+        sidecar.ground_planes = ground_planes.planes.clone();
+        sidecar.car_spawn = Some(spawn.0);
+        sidecar.boundary = area.points.clone();
+        sidecar.colliders = proxies.0.clone();
+        sidecar.surfaces = zones.0.clone();
+    });
+    match result {
+        Ok(path) => info!("Saved the level to {}", path.display()),
+        Err(err) => error!("Failed to save ground planes: {}", err),
+    }
+}
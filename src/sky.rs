@@ -0,0 +1,205 @@
+//! Sky and distance fog
+//!
+//! A scan ends abruptly where the capture stopped, and behind its edges there
+//! used to be nothing but the flat clear color. A sky dome now surrounds the
+//! camera instead, shading from the horizon up to the zenith, with the clear
+//! color set to the horizon's so no gap shows. Its colors follow the time of
+//! day, from blue by day through the warm glow of sunrise and sunset to the
+//! dark of night, and turn grey under an overcast sky (see `weather`).
+//!
+//! Distance fog in the horizon color fades the meshes far from the camera
+//! into the sky, so the gates and other meshes beyond the scan don't stand out
+//! against it. It can be turned off and its distance set on the settings
+//! screen; fog, rain and snow bring it closer.
+
+use bevy::light::NotShadowCaster;
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::car::CarSet;
+use crate::settings::Settings;
+use crate::split_screen::SecondCamera;
+use crate::time_of_day::TimeOfDay;
+use crate::weather::Weather;
+
+/// Radius of the sky dome, inside the cameras' far plane
+const SKY_RADIUS: f32 = 900.0;
+
+/// Colors of the sky by day, at sunrise and sunset and at night
+const DAY_ZENITH: Color = Color::srgb(0.25, 0.45, 0.8);
+const DAY_HORIZON: Color = Color::srgb(0.7, 0.8, 0.9);
+const SUNSET_ZENITH: Color = Color::srgb(0.25, 0.3, 0.55);
+const SUNSET_HORIZON: Color = Color::srgb(0.95, 0.6, 0.4);
+const NIGHT_ZENITH: Color = Color::srgb(0.01, 0.01, 0.04);
+const NIGHT_HORIZON: Color = Color::srgb(0.05, 0.06, 0.1);
+
+/// Brightness of the dome below the horizon, relative to the horizon
+const GROUND_SHADE: f32 = 0.6;
+
+/// Plugin for the sky dome and the distance fog
+pub struct SkyPlugin;
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SkyColors>()
+            .add_systems(Startup, spawn_sky)
+            .add_systems(
+                Update,
+                (update_sky_colors, follow_camera, apply_distance_fog)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the current colors of the sky
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct SkyColors {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for SkyColors {
+    fn default() -> Self {
+        Self {
+            horizon: DAY_HORIZON,
+            zenith: DAY_ZENITH,
+        }
+    }
+}
+
+impl SkyColors {
+    /// The sky for a time of day and weather
+    fn new(time_of_day: &TimeOfDay, weather: Weather) -> Self {
+        // Warmer the lower the sun stands
+        let warmth = 1.0 - (time_of_day.sun_elevation() / 0.5).clamp(0.0, 1.0);
+        let overcast = weather.overcast();
+        let daylight = time_of_day.daylight();
+        let sky = |day: Color, sunset: Color, night: Color| {
+            let day = day.mix(&sunset, warmth).mix(&weather.fog_color(), overcast);
+            night.mix(&day, daylight)
+        };
+        Self {
+            horizon: sky(DAY_HORIZON, SUNSET_HORIZON, NIGHT_HORIZON),
+            zenith: sky(DAY_ZENITH, SUNSET_ZENITH, NIGHT_ZENITH),
+        }
+    }
+
+    /// Color of the dome in a direction with the given height, from -1 straight
+    /// down to 1 straight up
+    fn at_height(&self, y: f32) -> Color {
+        if y >= 0.0 {
+            self.horizon.mix(&self.zenith, y.sqrt())
+        } else {
+            let ground = self.horizon.mix(&Color::BLACK, 1.0 - GROUND_SHADE);
+            self.horizon.mix(&ground, (-y * 4.0).min(1.0))
+        }
+    }
+}
+
+/// Marker for the sky dome
+#[derive(Component)]
+struct SkyDome;
+
+/// Spawn the sky dome around the origin, colored by `update_sky_colors`
+fn spawn_sky(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(SKY_RADIUS).mesh().uv(32, 16))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            unlit: true,
+            // Seen from inside
+            cull_mode: None,
+            fog_enabled: false,
+            ..default()
+        })),
+        Transform::default(),
+        NotShadowCaster,
+        SkyDome,
+    ));
+}
+
+/// Color the sky and the clear color for the time of day and the weather
+fn update_sky_colors(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    mut colors: ResMut<SkyColors>,
+    mut clear_color: ResMut<ClearColor>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    dome_query: Query<Ref<Mesh3d>, With<SkyDome>>,
+) {
+    let Ok(dome) = dome_query.single() else {
+        return;
+    };
+    if !time_of_day.is_changed() && !weather.is_changed() && !dome.is_added() {
+        return;
+    }
+    *colors = SkyColors::new(&time_of_day, *weather);
+    clear_color.0 = colors.horizon;
+
+    let Some(mesh) = meshes.get_mut(&dome.0) else {
+        return;
+    };
+    let Some(positions) = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .and_then(|positions| positions.as_float3())
+    else {
+        return;
+    };
+    let vertex_colors: Vec<[f32; 4]> = positions
+        .iter()
+        .map(|position| {
+            let y = position[1] / SKY_RADIUS;
+            colors.at_height(y).to_linear().to_f32_array()
+        })
+        .collect();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vertex_colors);
+}
+
+/// Keep the dome centered on the first player's camera
+fn follow_camera(
+    camera_query: Query<&Transform, (With<CarCamera>, Without<SecondCamera>)>,
+    mut dome_query: Query<&mut Transform, (With<SkyDome>, Without<CarCamera>)>,
+) {
+    let (Ok(camera), Ok(mut transform)) = (camera_query.single(), dome_query.single_mut()) else {
+        return;
+    };
+    transform.translation = camera.translation;
+}
+
+/// Give the 3D cameras distance fog in the horizon color, closer in bad
+/// weather, also the ones added later
+fn apply_distance_fog(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    weather: Res<Weather>,
+    colors: Res<SkyColors>,
+    camera_query: Query<(Entity, Ref<Camera3d>)>,
+) {
+    let changed = settings.is_changed() || weather.is_changed() || colors.is_changed();
+    let setting = settings.distance_fog.then_some(settings.fog_distance);
+    let visibility = match (setting, weather.visibility()) {
+        (Some(setting), Some(weather)) => Some(setting.min(weather)),
+        (setting, weather) => setting.or(weather),
+    };
+    for (entity, camera) in camera_query.iter() {
+        if !changed && !camera.is_added() {
+            continue;
+        }
+        match visibility {
+            Some(visibility) => {
+                commands.entity(entity).insert(DistanceFog {
+                    color: colors.horizon,
+                    falloff: FogFalloff::from_visibility(visibility),
+                    ..default()
+                });
+            }
+            None => {
+                commands.entity(entity).remove::<DistanceFog>();
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Spatial index over splat centers
+//!
+//! A KD-tree with bounding boxes on every node, so radius and ray queries
+//! only touch the few leaves near the query instead of scanning millions of
+//! Gaussians.
+
+use bevy::prelude::*;
+
+/// Maximum number of points stored in a leaf
+const LEAF_SIZE: usize = 32;
+
+/// A node of the tree, covering `indices[start..end]`
+#[derive(Debug, Clone, Copy)]
+struct KdNode {
+    min: Vec3,
+    max: Vec3,
+    start: u32,
+    end: u32,
+    /// Child node indices, `u32::MAX` for leaves
+    left: u32,
+    right: u32,
+}
+
+impl KdNode {
+    fn is_leaf(&self) -> bool {
+        self.left == u32::MAX
+    }
+
+    /// Squared distance from a point to this node's box
+    fn distance_squared(&self, point: Vec3) -> f32 {
+        (self.min - point).max(point - self.max).max(Vec3::ZERO).length_squared()
+    }
+}
+
+/// KD-tree over a slice of points, which is passed back in to every query
+#[derive(Debug, Clone, Default)]
+pub struct KdTree {
+    nodes: Vec<KdNode>,
+    indices: Vec<u32>,
+}
+
+impl KdTree {
+    /// Build a tree over the given points
+    pub fn build(points: &[Vec3]) -> Self {
+        let mut tree = Self {
+            nodes: Vec::with_capacity(2 * points.len() / LEAF_SIZE + 1),
+            indices: (0..points.len() as u32).collect(),
+        };
+        if !points.is_empty() {
+            tree.build_node(points, 0, points.len());
+        }
+        tree
+    }
+
+    fn build_node(&mut self, points: &[Vec3], start: usize, end: usize) -> u32 {
+        let (min, max) = self.indices[start..end].iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &i| (min.min(points[i as usize]), max.max(points[i as usize])),
+        );
+
+        let node_index = self.nodes.len() as u32;
+        self.nodes.push(KdNode {
+            min,
+            max,
+            start: start as u32,
+            end: end as u32,
+            left: u32::MAX,
+            right: u32::MAX,
+        });
+
+        if end - start <= LEAF_SIZE {
+            return node_index;
+        }
+
+        // Split at the median along the longest axis
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let mid = (end - start) / 2;
+        self.indices[start..end].select_nth_unstable_by(mid, |&a, &b| {
+            points[a as usize][axis].total_cmp(&points[b as usize][axis])
+        });
+
+        let left = self.build_node(points, start, start + mid);
+        let right = self.build_node(points, start + mid, end);
+        let node = &mut self.nodes[node_index as usize];
+        node.left = left;
+        node.right = right;
+        node_index
+    }
+
+    /// Indices of the points within `radius` of `center`
+    pub fn within_radius(&self, points: &[Vec3], center: Vec3, radius: f32) -> Vec<usize> {
+        let radius_squared = radius * radius;
+        let mut found = Vec::new();
+        self.visit_leaves(
+            |node| node.distance_squared(center) <= radius_squared,
+            |index| {
+                if points[index].distance_squared(center) <= radius_squared {
+                    found.push(index);
+                }
+            },
+        );
+        found
+    }
+
+    /// Points within `radius` of the ray segment `origin + t * direction` for `t` in `0..max_t`
+    ///
+    /// Returns `(index, t)` pairs sorted by distance along the ray. `direction`
+    /// must be normalized.
+    pub fn along_ray(
+        &self,
+        points: &[Vec3],
+        origin: Vec3,
+        direction: Vec3,
+        max_t: f32,
+        radius: f32,
+    ) -> Vec<(usize, f32)> {
+        let radius_squared = radius * radius;
+        let inverse = direction.recip();
+        let mut found = Vec::new();
+        self.visit_leaves(
+            |node| {
+                // Slab test against the node box inflated by the search radius
+                let t1 = (node.min - Vec3::splat(radius) - origin) * inverse;
+                let t2 = (node.max + Vec3::splat(radius) - origin) * inverse;
+                let t_near = t1.min(t2).max_element().max(0.0);
+                let t_far = t1.max(t2).min_element().min(max_t);
+                t_near <= t_far
+            },
+            |index| {
+                let to_point = points[index] - origin;
+                let t = to_point.dot(direction);
+                if (0.0..=max_t).contains(&t)
+                    && (to_point - direction * t).length_squared() <= radius_squared
+                {
+                    found.push((index, t));
+                }
+            },
+        );
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    /// Depth-first walk calling `visit` on the points of every leaf whose box passes `accept`
+    fn visit_leaves(&self, accept: impl Fn(&KdNode) -> bool, mut visit: impl FnMut(usize)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if !accept(node) {
+                continue;
+            }
+            if node.is_leaf() {
+                for &i in &self.indices[node.start as usize..node.end as usize] {
+                    visit(i as usize);
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+    }
+}
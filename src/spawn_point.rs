@@ -0,0 +1,166 @@
+//! Car spawn point and the tool for placing it
+//!
+//! Press 'O', then click the spawn location on the ground and drag in the
+//! direction the car should face. The spawn is used when the car is created
+//! and whenever it is respawned, which 'F' does on demand, putting the car
+//! back at the last checkpoint it passed or at the spawn.
+
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::car::{Car, PrimaryCar, RIDE_HEIGHT};
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::input_map::{Action, InputMap};
+use crate::point_cloud::SplatPointCloud;
+use crate::terrain::GroundSurface;
+
+/// Minimum drag distance for the drag direction to set the heading
+const MIN_HEADING_DRAG: f32 = 0.5;
+
+/// Plugin for the spawn point and its placement tool
+pub struct SpawnPointPlugin;
+
+impl Plugin for SpawnPointPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnPoint>()
+            .init_resource::<SpawnPointTool>()
+            .init_resource::<LastCheckpoint>()
+            .add_message::<RespawnCar>()
+            .add_systems(Update, (
+                toggle_spawn_tool,
+                place_spawn_point,
+                request_respawn,
+                respawn_car,
+            ).chain());
+    }
+}
+
+/// Resource with the transform the car starts from
+#[derive(Resource, Clone, Copy)]
+pub struct SpawnPoint(pub Transform);
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self(Transform::from_xyz(0.0, RIDE_HEIGHT, 0.0))
+    }
+}
+
+/// Resource with the transform of the last checkpoint the car passed, if any
+///
+/// Respawning prefers this over the spawn point.
+#[derive(Resource, Default)]
+pub struct LastCheckpoint(pub Option<Transform>);
+
+/// Message requesting the car to be put back at the last checkpoint or the spawn
+#[derive(Message, Debug, Clone, Copy)]
+pub struct RespawnCar;
+
+/// Resource tracking the spawn placement tool
+#[derive(Resource, Default)]
+pub struct SpawnPointTool {
+    /// Whether the tool is active
+    pub active: bool,
+    /// Ground point clicked as the new spawn location, while dragging the heading
+    anchor: Option<Vec3>,
+}
+
+/// Toggle the spawn placement tool with 'O'
+fn toggle_spawn_tool(keyboard: Res<ButtonInput<KeyCode>>, mut tool: ResMut<SpawnPointTool>) {
+    if keyboard.just_pressed(KeyCode::KeyO) {
+        tool.active = !tool.active;
+        tool.anchor = None;
+        if tool.active {
+            info!("Spawn placement ACTIVE - click the spawn location and drag to set the heading");
+        } else {
+            info!("Spawn placement INACTIVE");
+        }
+    }
+}
+
+/// Click and drag on the ground to place the spawn point, then move the car there
+fn place_spawn_point(
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut tool: ResMut<SpawnPointTool>,
+    mut spawn: ResMut<SpawnPoint>,
+    ground: GroundSurface,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+    mut car_query: Query<(&mut Car, &mut Transform), With<PrimaryCar>>,
+    mut gizmos: Gizmos,
+) {
+    if !tool.active {
+        return;
+    }
+
+    // Show the current spawn while the tool is active
+    let current = spawn.0;
+    gizmos.arrow(
+        current.translation,
+        current.translation + current.forward() * 3.0,
+        Color::srgb(0.2, 0.6, 1.0),
+    );
+
+    let cursor_point = cursor_ray(&camera_query, &windows)
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes));
+
+    if mouse_button.just_pressed(MouseButton::Left) {
+        tool.anchor = cursor_point;
+    }
+    let Some(anchor) = tool.anchor else {
+        return;
+    };
+
+    // Heading from the anchor toward the cursor, flattened onto the ground
+    let up = ground.normal_at(anchor);
+    let heading = cursor_point
+        .map(|point| (point - anchor).reject_from_normalized(up))
+        .filter(|drag| drag.length() >= MIN_HEADING_DRAG)
+        .map(Vec3::normalize)
+        .unwrap_or_else(|| current.forward().reject_from_normalized(up).normalize_or(Vec3::NEG_Z));
+
+    gizmos.arrow(anchor, anchor + heading * 3.0, Color::srgb(1.0, 0.8, 0.0));
+
+    if mouse_button.just_released(MouseButton::Left) {
+        spawn.0 = Transform::from_translation(anchor + up * RIDE_HEIGHT).looking_to(heading, up);
+        tool.active = false;
+        tool.anchor = None;
+        if let Ok((mut car, mut transform)) = car_query.single_mut() {
+            *transform = spawn.0;
+            car.stop();
+        }
+        info!("Spawn point set at {:?}", anchor);
+        info!("Press Ctrl+S to save it with this splat");
+    }
+}
+
+/// Respawn the car with the respawn key, 'F' by default
+fn request_respawn(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut respawns: MessageWriter<RespawnCar>,
+) {
+    if input_map.just_pressed(Action::Respawn, &keyboard) {
+        respawns.write(RespawnCar);
+    }
+}
+
+/// Teleport the car to the last checkpoint or the spawn point and stop it
+fn respawn_car(
+    mut respawns: MessageReader<RespawnCar>,
+    spawn: Res<SpawnPoint>,
+    last_checkpoint: Res<LastCheckpoint>,
+    mut car_query: Query<(&mut Car, &mut Transform), With<PrimaryCar>>,
+) {
+    if respawns.read().count() == 0 {
+        return;
+    }
+    let Ok((mut car, mut transform)) = car_query.single_mut() else {
+        return;
+    };
+
+    *transform = last_checkpoint.0.unwrap_or(spawn.0);
+    car.stop();
+    info!("Car respawned at {:?}", transform.translation);
+}
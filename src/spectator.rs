@@ -0,0 +1,161 @@
+//! Free-fly spectator camera
+//!
+//! Press F7 (rebindable) to leave the car and fly through the splat, to
+//! inspect the scan, pick ground plane points from a better angle or line up
+//! cinematic shots. The driving keys fly (W/S forwards and backwards, A/D
+//! sideways), E and Q rise and sink, Shift flies faster, dragging with the
+//! right mouse button looks around and the mouse wheel changes the flying
+//! speed. The car waits where it was; F7 again returns to the previous view.
+//! Photo mode flies its camera the same way.
+
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll};
+use bevy::prelude::*;
+
+use crate::camera::{CameraMode, CarCamera};
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::input_map::{Action, InputMap};
+
+/// Radians of view rotation per pixel of mouse movement
+const LOOK_SENSITIVITY: f32 = 0.004;
+
+/// Speed multiplier while Shift is held
+const FAST_MULTIPLIER: f32 = 4.0;
+
+/// Plugin for the spectator camera
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spectator>().add_systems(
+            Update,
+            (
+                (toggle_spectator, fly_spectator).chain().in_set(CarSet::Camera),
+                hold_car_while_spectating.after(CarSet::Input).before(CarSet::Physics),
+            ),
+        );
+    }
+}
+
+/// Resource with the spectator camera's state
+#[derive(Resource)]
+pub struct Spectator {
+    /// Flying speed in m/s
+    pub speed: f32,
+    /// Heading around the vertical axis, in radians
+    yaw: f32,
+    /// Angle above the horizon, in radians
+    pitch: f32,
+    /// View to return to when leaving the spectator camera
+    previous_mode: CameraMode,
+}
+
+impl Spectator {
+    /// Start flying from wherever the camera is looking
+    pub fn take_off(&mut self, transform: &Transform) {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        self.yaw = yaw;
+        self.pitch = pitch;
+    }
+}
+
+impl Default for Spectator {
+    fn default() -> Self {
+        Self {
+            speed: 10.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            previous_mode: CameraMode::Chase,
+        }
+    }
+}
+
+/// Switch between the spectator camera and the previous view
+fn toggle_spectator(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut mode: ResMut<CameraMode>,
+    mut spectator: ResMut<Spectator>,
+    camera_query: Query<&Transform, With<CarCamera>>,
+) {
+    if !input_map.just_pressed(Action::Spectate, &keyboard) || *mode == CameraMode::Photo {
+        return;
+    }
+    if *mode == CameraMode::FreeFly {
+        *mode = spectator.previous_mode;
+        info!("Spectator camera OFF");
+        return;
+    }
+
+    spectator.previous_mode = *mode;
+    if let Ok(transform) = camera_query.single() {
+        spectator.take_off(transform);
+    }
+    *mode = CameraMode::FreeFly;
+    info!("Spectator camera ON - WASD: fly, E/Q: up/down, Shift: fast");
+    info!("RMB drag: look around, wheel: flying speed");
+}
+
+/// Fly the camera with the driving keys and look around with the mouse, in
+/// real time so it also flies while the game is paused
+pub fn fly_spectator(
+    mode: Res<CameraMode>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    time: Res<Time<Real>>,
+    mut spectator: ResMut<Spectator>,
+    mut camera_query: Query<&mut Transform, With<CarCamera>>,
+) {
+    if !mode.is_free() {
+        return;
+    }
+    let Ok(mut transform) = camera_query.single_mut() else {
+        return;
+    };
+
+    if mouse_button.pressed(MouseButton::Right) {
+        spectator.yaw -= mouse_motion.delta.x * LOOK_SENSITIVITY;
+        spectator.pitch =
+            (spectator.pitch - mouse_motion.delta.y * LOOK_SENSITIVITY).clamp(-1.5, 1.5);
+    }
+    // Photo mode zooms with the wheel instead
+    if mouse_scroll.delta.y != 0.0 && *mode == CameraMode::FreeFly {
+        spectator.speed = (spectator.speed * 1.2_f32.powf(mouse_scroll.delta.y)).clamp(0.5, 200.0);
+        info!("Spectator speed {:.1} m/s", spectator.speed);
+    }
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, spectator.yaw, spectator.pitch, 0.0);
+
+    let forward = input_map.value(Action::Accelerate, &keyboard)
+        - input_map.value(Action::Brake, &keyboard);
+    let sideways = input_map.value(Action::SteerRight, &keyboard)
+        - input_map.value(Action::SteerLeft, &keyboard);
+    let mut vertical = 0.0;
+    if keyboard.pressed(KeyCode::KeyE) {
+        vertical += 1.0;
+    }
+    if keyboard.pressed(KeyCode::KeyQ) {
+        vertical -= 1.0;
+    }
+    let direction =
+        transform.forward() * forward + transform.right() * sideways + Vec3::Y * vertical;
+    let fast = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    let speed = spectator.speed * if fast { FAST_MULTIPLIER } else { 1.0 };
+    transform.translation += direction.normalize_or_zero() * speed * time.delta_secs();
+}
+
+/// Keep the car standing still while the driving keys fly the camera
+fn hold_car_while_spectating(
+    mode: Res<CameraMode>,
+    mut car_query: Query<&mut Car, With<PrimaryCar>>,
+) {
+    if *mode != CameraMode::FreeFly {
+        return;
+    }
+    if let Ok(mut car) = car_query.single_mut() {
+        car.throttle = 0.0;
+        car.brake = 0.0;
+        car.stop();
+    }
+}
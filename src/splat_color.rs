@@ -0,0 +1,225 @@
+//! Splat exposure, contrast and saturation
+//!
+//! Scans often come out too dark or washed out. The colors of every loaded
+//! splat can be adjusted while driving: 5 and 6 lower and raise the exposure,
+//! 7 and 8 the contrast, 9 and 0 the saturation, and '/' resets all three.
+//! The adjustment is made to the Gaussians' base colors, starting over from
+//! the colors they were loaded with, so it can be undone at any time; the
+//! view-dependent part of the color is left alone. It is saved with the scene
+//! manifest and applied again when the scene is opened.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_gaussian_splatting::PlanarGaussian3d;
+use serde::{Deserialize, Serialize};
+
+use crate::input_map::InputMap;
+use crate::splat_formats::SH_C0;
+
+/// Change per key press of the exposure, in stops, and of the contrast and
+/// saturation
+const EXPOSURE_STEP: f32 = 0.25;
+const CONTRAST_STEP: f32 = 0.1;
+const SATURATION_STEP: f32 = 0.1;
+
+/// Seconds the values stay on screen after a change
+const NOTICE_DURATION: f32 = 2.0;
+
+/// Plugin for the splat color adjustment
+pub struct SplatColorPlugin;
+
+impl Plugin for SplatColorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplatColor>()
+            .init_resource::<LoadedColors>()
+            .add_systems(Startup, spawn_color_notice)
+            .add_systems(
+                Update,
+                (adjust_splat_color, apply_splat_color, update_color_notice).chain(),
+            );
+    }
+}
+
+/// Resource with the color adjustment of the splats
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplatColor {
+    /// Brightening in stops, each doubling the brightness
+    pub exposure: f32,
+    /// Spread of the colors around mid grey, 1 as captured
+    pub contrast: f32,
+    /// Colorfulness, 0 for greyscale and 1 as captured
+    pub saturation: f32,
+}
+
+impl Default for SplatColor {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl SplatColor {
+    /// Whether the colors are left as captured
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// An RGB color with the adjustment applied
+    fn adjust(&self, color: Vec3) -> Vec3 {
+        let exposed = color * self.exposure.exp2();
+        let contrasted = (exposed - Vec3::splat(0.5)) * self.contrast + Vec3::splat(0.5);
+        let luminance = contrasted.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+        Vec3::splat(luminance).lerp(contrasted, self.saturation)
+    }
+}
+
+/// Resource with the base color of every Gaussian as loaded, by cloud
+#[derive(Resource, Default)]
+pub struct LoadedColors(HashMap<AssetId<PlanarGaussian3d>, Vec<Vec3>>);
+
+impl LoadedColors {
+    /// The colors a cloud was loaded with, before any adjustment
+    pub fn original(&self, id: AssetId<PlanarGaussian3d>) -> Option<&[Vec3]> {
+        self.0.get(&id).map(Vec::as_slice)
+    }
+}
+
+/// Marker for the text showing the values after a change
+#[derive(Component)]
+struct ColorNotice;
+
+/// Step the exposure, contrast and saturation with the number keys and reset
+/// them with '/'
+fn adjust_splat_color(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut color: ResMut<SplatColor>,
+) {
+    if input_map.suspended {
+        return;
+    }
+    let mut adjusted = *color;
+    let step = |down: KeyCode, up: KeyCode, size: f32| {
+        keyboard.just_pressed(up) as i32 as f32 * size
+            - keyboard.just_pressed(down) as i32 as f32 * size
+    };
+    adjusted.exposure = (adjusted.exposure + step(KeyCode::Digit5, KeyCode::Digit6, EXPOSURE_STEP))
+        .clamp(-3.0, 3.0);
+    adjusted.contrast =
+        (adjusted.contrast + step(KeyCode::Digit7, KeyCode::Digit8, CONTRAST_STEP)).clamp(0.3, 2.0);
+    adjusted.saturation = (adjusted.saturation
+        + step(KeyCode::Digit9, KeyCode::Digit0, SATURATION_STEP))
+    .clamp(0.0, 2.0);
+    if keyboard.just_pressed(KeyCode::Slash) {
+        adjusted = SplatColor::default();
+    }
+    if color.set_if_neq(adjusted) {
+        info!(
+            "Splat exposure {:+.2}, contrast {:.1}, saturation {:.1}",
+            color.exposure, color.contrast, color.saturation
+        );
+    }
+}
+
+/// Remember the colors of newly loaded clouds and recolor them, and every
+/// cloud whenever the adjustment changes
+fn apply_splat_color(
+    color: Res<SplatColor>,
+    mut loaded: ResMut<LoadedColors>,
+    mut clouds: ResMut<Assets<PlanarGaussian3d>>,
+    mut events: MessageReader<AssetEvent<PlanarGaussian3d>>,
+) {
+    let mut added = Vec::new();
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } => added.push(*id),
+            AssetEvent::Removed { id } | AssetEvent::Unused { id } => {
+                loaded.0.remove(id);
+            }
+            _ => {}
+        }
+    }
+    for &id in &added {
+        if let Some(cloud) = clouds.get(id) {
+            let colors = cloud
+                .spherical_harmonic
+                .iter()
+                .map(|sh| Vec3::from_array(std::array::from_fn(|channel| sh.coefficients[channel])))
+                .map(|dc| dc * SH_C0 + Vec3::splat(0.5))
+                .collect();
+            loaded.0.insert(id, colors);
+        }
+    }
+
+    let recolor: Vec<AssetId<PlanarGaussian3d>> = if color.is_changed() {
+        loaded.0.keys().copied().collect()
+    } else if color.is_neutral() {
+        // New clouds are already as captured
+        return;
+    } else {
+        added
+    };
+    for id in recolor {
+        let (Some(cloud), Some(colors)) = (clouds.get_mut(id), loaded.0.get(&id)) else {
+            continue;
+        };
+        for (sh, &original) in cloud.spherical_harmonic.iter_mut().zip(colors) {
+            let adjusted = color.adjust(original);
+            for channel in 0..3 {
+                sh.set(channel, (adjusted[channel] - 0.5) / SH_C0);
+            }
+        }
+    }
+}
+
+/// Spawn the hidden notice across the top of the screen
+fn spawn_color_notice(mut commands: Commands) {
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        TextShadow::default(),
+        TextLayout::new_with_justify(Justify::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(12.0),
+            width: Val::Percent(100.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        ColorNotice,
+    ));
+}
+
+/// Show the values for a moment after they change
+fn update_color_notice(
+    time: Res<Time>,
+    color: Res<SplatColor>,
+    mut remaining: Local<f32>,
+    mut notice_query: Query<(&mut Text, &mut Visibility), With<ColorNotice>>,
+) {
+    let Ok((mut text, mut visibility)) = notice_query.single_mut() else {
+        return;
+    };
+    if color.is_changed() && !color.is_added() {
+        *remaining = NOTICE_DURATION;
+        text.0 = format!(
+            "Exposure {:+.2}  Contrast {:.1}  Saturation {:.1}",
+            color.exposure, color.contrast, color.saturation
+        );
+    }
+    *remaining = (*remaining - time.delta_secs()).max(0.0);
+    visibility.set_if_neq(if *remaining > 0.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+}
@@ -0,0 +1,163 @@
+//! Splat alignment editor
+//!
+//! Scans rarely come aligned to a sensible origin. This edit mode lets the user
+//! move, rotate and uniformly scale a loaded splat and save the result into the
+//! splat's sidecar file so it is reapplied the next time the splat is opened.
+
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll};
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::sidecar::SplatSidecar;
+use crate::splat_loader::{LoadedSplat, LoadedSplats};
+
+/// Plugin for the splat transform editor
+pub struct SplatEditorPlugin;
+
+impl Plugin for SplatEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplatEditMode>()
+            .add_systems(Update, (
+                toggle_splat_edit_mode,
+                edit_splat_transform,
+                draw_splat_gizmo,
+            ).chain());
+    }
+}
+
+/// Resource tracking the splat alignment edit mode
+#[derive(Resource, Default)]
+pub struct SplatEditMode {
+    /// Whether the edit mode is active
+    pub active: bool,
+    /// Index into `LoadedSplats` of the splat being edited
+    pub selected: usize,
+}
+
+/// Toggle the edit mode with 'T' and cycle the selected splat with Tab
+fn toggle_splat_edit_mode(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut edit_mode: ResMut<SplatEditMode>,
+    loaded_splats: Res<LoadedSplats>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyT) {
+        edit_mode.active = !edit_mode.active;
+        if edit_mode.active {
+            info!("Splat edit mode ACTIVE - LMB drag: move (Shift: vertical), RMB drag: rotate, wheel: scale");
+            info!("X/Y/Z: rotate 90 degrees, Tab: next splat, Backspace: reset, Enter: save");
+        } else {
+            info!("Splat edit mode INACTIVE");
+        }
+    }
+
+    if edit_mode.active && keyboard.just_pressed(KeyCode::Tab) && !loaded_splats.0.is_empty() {
+        edit_mode.selected = (edit_mode.selected + 1) % loaded_splats.0.len();
+        info!("Editing splat {} of {}", edit_mode.selected + 1, loaded_splats.0.len());
+    }
+}
+
+/// Apply mouse and keyboard edits to the selected splat's transform
+fn edit_splat_transform(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    edit_mode: Res<SplatEditMode>,
+    loaded_splats: Res<LoadedSplats>,
+    mut splat_query: Query<(&LoadedSplat, &mut Transform)>,
+    camera_query: Query<&GlobalTransform, With<CarCamera>>,
+) {
+    if !edit_mode.active {
+        return;
+    }
+    let Some(&entity) = loaded_splats.0.get(edit_mode.selected) else {
+        return;
+    };
+    let Ok((splat, mut transform)) = splat_query.get_mut(entity) else {
+        return;
+    };
+    let Ok(camera_transform) = camera_query.single() else {
+        return;
+    };
+
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    let delta = mouse_motion.delta;
+
+    // Scale drag speed with distance so both near and far edits feel the same
+    let distance = camera_transform.translation().distance(transform.translation).max(1.0);
+    let move_speed = distance * 0.002;
+    let rotate_speed = 0.005;
+
+    if mouse_button.pressed(MouseButton::Left) && delta != Vec2::ZERO {
+        if shift {
+            transform.translation.y -= delta.y * move_speed;
+        } else {
+            // Move in the horizontal plane relative to the camera
+            let right = camera_transform.right().with_y(0.0).normalize_or_zero();
+            let forward = camera_transform.forward().with_y(0.0).normalize_or_zero();
+            transform.translation += (right * delta.x - forward * delta.y) * move_speed;
+        }
+    }
+
+    if mouse_button.pressed(MouseButton::Right) && delta != Vec2::ZERO {
+        let yaw = Quat::from_rotation_y(delta.x * rotate_speed);
+        let pitch = Quat::from_axis_angle(*camera_transform.right(), delta.y * rotate_speed);
+        transform.rotation = (yaw * pitch * transform.rotation).normalize();
+    }
+
+    if mouse_scroll.delta.y != 0.0 {
+        let factor = (1.0 + mouse_scroll.delta.y * 0.1).max(0.1);
+        transform.scale = (transform.scale * factor).clamp(Vec3::splat(0.001), Vec3::splat(1000.0));
+    }
+
+    // Quarter turns around the world axes, handy for Z-up scans
+    let quarter = if shift {
+        -std::f32::consts::FRAC_PI_2
+    } else {
+        std::f32::consts::FRAC_PI_2
+    };
+    for (key, axis) in [(KeyCode::KeyX, Vec3::X), (KeyCode::KeyY, Vec3::Y), (KeyCode::KeyZ, Vec3::Z)] {
+        if keyboard.just_pressed(key) {
+            transform.rotation = Quat::from_axis_angle(axis, quarter) * transform.rotation;
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        *transform = Transform::default();
+        info!("Splat transform reset");
+    }
+
+    if keyboard.just_pressed(KeyCode::Enter) {
+        let saved = *transform;
+        match SplatSidecar::update(&splat.path, |sidecar| sidecar.transform = Some(saved)) {
+            Ok(path) => info!("Saved splat alignment to {}", path.display()),
+            Err(err) => error!("Failed to save splat alignment: {}", err),
+        }
+    }
+}
+
+/// Draw the transform axes of the splat being edited
+fn draw_splat_gizmo(
+    mut gizmos: Gizmos,
+    edit_mode: Res<SplatEditMode>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&Transform, With<LoadedSplat>>,
+) {
+    if !edit_mode.active {
+        return;
+    }
+    let Some(transform) = loaded_splats
+        .0
+        .get(edit_mode.selected)
+        .and_then(|&entity| splat_query.get(entity).ok())
+    else {
+        return;
+    };
+
+    gizmos.axes(*transform, 2.0 / transform.scale.x.max(0.001));
+    gizmos.circle(
+        Isometry3d::new(transform.translation, Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)),
+        3.0,
+        Color::srgb(1.0, 0.8, 0.0),
+    );
+}
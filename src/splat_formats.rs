@@ -0,0 +1,798 @@
+//! Decoders for splat file formats not handled by bevy_gaussian_splatting
+//!
+//! Each format is decoded into a `PlanarGaussian3d` cloud so the rest of the
+//! game can treat every splat the same way once it is loaded.
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use bevy::asset::{io::Reader, AssetLoader, LoadContext};
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::AsyncReadExt;
+use bevy_gaussian_splatting::{Gaussian3d, PlanarGaussian3d};
+
+use crate::splat_loader::SplatLoadProgress;
+
+/// How many bytes the asset loaders read between progress updates
+const READ_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Zeroth order spherical harmonic basis constant, used to turn RGB into SH DC terms
+pub const SH_C0: f32 = 0.282_094_8;
+
+/// The splat file formats the game knows how to load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplatFormat {
+    /// Standard 3DGS `.ply`, loaded by bevy_gaussian_splatting itself
+    Ply,
+    /// antimatter15 `.splat` binary
+    SplatBinary,
+    /// Niantic's gzip-compressed `.spz`
+    Spz,
+    /// GaussianSplats3D `.ksplat`
+    KSplat,
+}
+
+impl SplatFormat {
+    /// Guess the format from a file path's extension, defaulting to PLY
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("splat") => SplatFormat::SplatBinary,
+            Some("spz") => SplatFormat::Spz,
+            Some("ksplat") => SplatFormat::KSplat,
+            _ => SplatFormat::Ply,
+        }
+    }
+}
+
+/// Errors that can occur while decoding a splat file
+#[derive(Debug)]
+pub enum SplatFormatError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for SplatFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplatFormatError::Io(err) => write!(f, "failed to read splat file: {err}"),
+            SplatFormatError::Invalid(msg) => write!(f, "invalid splat file: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SplatFormatError {}
+
+impl From<std::io::Error> for SplatFormatError {
+    fn from(err: std::io::Error) -> Self {
+        SplatFormatError::Io(err)
+    }
+}
+
+/// Read a whole asset into memory, reporting the bytes read to the progress counters
+async fn read_with_progress(
+    reader: &mut dyn Reader,
+    progress: &SplatLoadProgress,
+) -> Result<Vec<u8>, SplatFormatError> {
+    let mut bytes = Vec::new();
+    let mut buffer = vec![0u8; READ_CHUNK_BYTES];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buffer[..read]);
+        progress.add_bytes(read as u64);
+    }
+    Ok(bytes)
+}
+
+/// Build a single Gaussian from already-activated values
+///
+/// `scale` is linear (not log), `opacity` is in 0..1, `rotation` is (w, x, y, z)
+/// and `color` is linear RGB in 0..1.
+pub fn make_gaussian(
+    position: [f32; 3],
+    scale: [f32; 3],
+    rotation: [f32; 4],
+    opacity: f32,
+    color: [f32; 3],
+) -> Gaussian3d {
+    let mut gaussian = Gaussian3d::default();
+    gaussian.position_visibility.position = position;
+    gaussian.position_visibility.visibility = 1.0;
+    gaussian.scale_opacity.scale = scale;
+    gaussian.scale_opacity.opacity = opacity;
+    gaussian.rotation.rotation = normalize_quat(rotation);
+    for (channel, value) in color.iter().enumerate() {
+        gaussian.spherical_harmonic.set(channel, (value - 0.5) / SH_C0);
+    }
+    gaussian
+}
+
+/// Normalize a (w, x, y, z) quaternion, falling back to identity when degenerate
+fn normalize_quat(q: [f32; 4]) -> [f32; 4] {
+    let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    if length < 1e-6 {
+        [1.0, 0.0, 0.0, 0.0]
+    } else {
+        [q[0] / length, q[1] / length, q[2] / length, q[3] / length]
+    }
+}
+
+/// Read a little-endian f32 at the given byte offset
+pub fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Read a little-endian u32 at the given byte offset
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Read a little-endian u16 at the given byte offset
+pub fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Convert an IEEE 754 half-precision float to f32
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Size in bytes of one record in the `.splat` format
+const SPLAT_RECORD_SIZE: usize = 32;
+
+/// Decode the antimatter15 `.splat` format
+///
+/// Each record is 32 bytes: position (3 x f32), linear scale (3 x f32),
+/// RGBA color (4 x u8, alpha is opacity) and rotation (4 x u8, w first).
+pub fn decode_splat_binary(bytes: &[u8]) -> Result<Vec<Gaussian3d>, SplatFormatError> {
+    if bytes.len() % SPLAT_RECORD_SIZE != 0 {
+        return Err(SplatFormatError::Invalid(format!(
+            ".splat size {} is not a multiple of {} bytes",
+            bytes.len(),
+            SPLAT_RECORD_SIZE
+        )));
+    }
+
+    let gaussians = bytes
+        .chunks_exact(SPLAT_RECORD_SIZE)
+        .map(|record| {
+            let position = [read_f32(record, 0), read_f32(record, 4), read_f32(record, 8)];
+            let scale = [read_f32(record, 12), read_f32(record, 16), read_f32(record, 20)];
+            let color = [
+                record[24] as f32 / 255.0,
+                record[25] as f32 / 255.0,
+                record[26] as f32 / 255.0,
+            ];
+            let opacity = record[27] as f32 / 255.0;
+            let rotation = [
+                (record[28] as f32 - 128.0) / 128.0,
+                (record[29] as f32 - 128.0) / 128.0,
+                (record[30] as f32 - 128.0) / 128.0,
+                (record[31] as f32 - 128.0) / 128.0,
+            ];
+            make_gaussian(position, scale, rotation, opacity, color)
+        })
+        .collect();
+
+    Ok(gaussians)
+}
+
+/// Asset loader for antimatter15 `.splat` files
+pub struct SplatBinaryLoader {
+    pub progress: SplatLoadProgress,
+}
+
+impl AssetLoader for SplatBinaryLoader {
+    type Asset = PlanarGaussian3d;
+    type Settings = ();
+    type Error = SplatFormatError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let bytes = read_with_progress(reader, &self.progress).await?;
+        let gaussians = decode_splat_binary(&bytes)?;
+        info!("Decoded {} Gaussians from .splat file", gaussians.len());
+        Ok(PlanarGaussian3d::from(gaussians))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["splat"]
+    }
+}
+
+/// Magic number at the start of a decompressed `.spz` stream ("NGSP")
+const SPZ_MAGIC: u32 = 0x5053_474e;
+/// Size in bytes of the `.spz` header
+const SPZ_HEADER_SIZE: usize = 16;
+/// Most fractional bits of the fixed point positions; a 24 bit position
+/// can't have more
+const SPZ_MAX_FRACTIONAL_BITS: u8 = 24;
+/// Scale applied to SH DC colors when they were quantized
+const SPZ_COLOR_SCALE: f32 = 0.15;
+
+/// Number of higher-order SH coefficients per color channel for a given degree
+fn sh_rest_count(degree: u8) -> usize {
+    match degree {
+        0 => 0,
+        1 => 3,
+        2 => 8,
+        _ => 15,
+    }
+}
+
+/// Decode Niantic's `.spz` format (versions 1 to 3)
+///
+/// The file is a gzip stream holding a 16 byte header followed by the
+/// attributes stored column-wise: positions, alphas, colors, scales,
+/// rotations and spherical harmonics. Only the DC color term is kept.
+pub fn decode_spz(compressed: &[u8]) -> Result<Vec<Gaussian3d>, SplatFormatError> {
+    let mut bytes = Vec::new();
+    flate2::read::GzDecoder::new(compressed).read_to_end(&mut bytes)?;
+
+    if bytes.len() < SPZ_HEADER_SIZE || read_u32(&bytes, 0) != SPZ_MAGIC {
+        return Err(SplatFormatError::Invalid("missing .spz header".into()));
+    }
+
+    let version = read_u32(&bytes, 4);
+    let count = read_u32(&bytes, 8) as usize;
+    let sh_degree = bytes[12];
+    let fractional_bits = bytes[13];
+
+    if !(1..=3).contains(&version) {
+        return Err(SplatFormatError::Invalid(format!(
+            "unsupported .spz version {version}"
+        )));
+    }
+    if fractional_bits > SPZ_MAX_FRACTIONAL_BITS {
+        return Err(SplatFormatError::Invalid(format!(
+            "unsupported .spz fixed point precision of {fractional_bits} bits"
+        )));
+    }
+
+    let position_size = if version == 1 { 6 } else { 9 };
+    let rotation_size = if version >= 3 { 4 } else { 3 };
+    let sh_size = sh_rest_count(sh_degree) * 3;
+
+    let positions_start = SPZ_HEADER_SIZE;
+    let alphas_start = positions_start + count * position_size;
+    let colors_start = alphas_start + count;
+    let scales_start = colors_start + count * 3;
+    let rotations_start = scales_start + count * 3;
+    let expected_size = rotations_start + count * rotation_size + count * sh_size;
+
+    if bytes.len() < expected_size {
+        return Err(SplatFormatError::Invalid(format!(
+            ".spz data is truncated: expected {expected_size} bytes, found {}",
+            bytes.len()
+        )));
+    }
+
+    let fixed_point_scale = 1.0 / (1u32 << fractional_bits) as f32;
+
+    let gaussians = (0..count)
+        .map(|i| {
+            let p = &bytes[positions_start + i * position_size..][..position_size];
+            let position: [f32; 3] = std::array::from_fn(|axis| {
+                if version == 1 {
+                    f16_to_f32(u16::from_le_bytes([p[axis * 2], p[axis * 2 + 1]]))
+                } else {
+                    let raw = p[axis * 3] as i32
+                        | (p[axis * 3 + 1] as i32) << 8
+                        | (p[axis * 3 + 2] as i32) << 16;
+                    // Sign-extend the 24 bit fixed point value
+                    let signed = (raw << 8) >> 8;
+                    signed as f32 * fixed_point_scale
+                }
+            });
+
+            let opacity = bytes[alphas_start + i] as f32 / 255.0;
+
+            let color: [f32; 3] = std::array::from_fn(|channel| {
+                let quantized = bytes[colors_start + i * 3 + channel] as f32 / 255.0;
+                let dc = (quantized - 0.5) / SPZ_COLOR_SCALE;
+                0.5 + dc * SH_C0
+            });
+
+            let scale: [f32; 3] = std::array::from_fn(|axis| {
+                (bytes[scales_start + i * 3 + axis] as f32 / 16.0 - 10.0).exp()
+            });
+
+            let r = &bytes[rotations_start + i * rotation_size..][..rotation_size];
+            let rotation = if version >= 3 {
+                decode_spz_smallest_three([r[0], r[1], r[2], r[3]])
+            } else {
+                let x = r[0] as f32 / 127.5 - 1.0;
+                let y = r[1] as f32 / 127.5 - 1.0;
+                let z = r[2] as f32 / 127.5 - 1.0;
+                let w = (1.0 - (x * x + y * y + z * z)).max(0.0).sqrt();
+                [w, x, y, z]
+            };
+
+            make_gaussian(position, scale, rotation, opacity, color)
+        })
+        .collect();
+
+    Ok(gaussians)
+}
+
+/// Decode a `.spz` v3 "smallest three" packed quaternion into (w, x, y, z)
+fn decode_spz_smallest_three(packed: [u8; 4]) -> [f32; 4] {
+    const MAGNITUDE_MASK: u32 = (1 << 9) - 1;
+
+    let mut bits = u32::from_le_bytes(packed);
+    let largest = (bits >> 30) as usize;
+    // Components in x, y, z, w order, as stored in the file
+    let mut xyzw = [0.0f32; 4];
+    let mut sum_squares = 0.0;
+
+    for i in (0..4).rev() {
+        if i == largest {
+            continue;
+        }
+        let magnitude = bits & MAGNITUDE_MASK;
+        let negative = (bits >> 9) & 1 == 1;
+        bits >>= 10;
+
+        let value = std::f32::consts::FRAC_1_SQRT_2 * magnitude as f32 / MAGNITUDE_MASK as f32;
+        xyzw[i] = if negative { -value } else { value };
+        sum_squares += xyzw[i] * xyzw[i];
+    }
+    xyzw[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+
+    [xyzw[3], xyzw[0], xyzw[1], xyzw[2]]
+}
+
+/// Asset loader for Niantic `.spz` files
+pub struct SpzLoader {
+    pub progress: SplatLoadProgress,
+}
+
+impl AssetLoader for SpzLoader {
+    type Asset = PlanarGaussian3d;
+    type Settings = ();
+    type Error = SplatFormatError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let bytes = read_with_progress(reader, &self.progress).await?;
+        let gaussians = decode_spz(&bytes)?;
+        info!("Decoded {} Gaussians from .spz file", gaussians.len());
+        Ok(PlanarGaussian3d::from(gaussians))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["spz"]
+    }
+}
+
+/// Size in bytes of the `.ksplat` main header
+const KSPLAT_MAIN_HEADER_SIZE: usize = 4096;
+/// Size in bytes of each `.ksplat` section header
+const KSPLAT_SECTION_HEADER_SIZE: usize = 1024;
+
+/// Number of SH components stored per splat for a given degree
+fn ksplat_sh_components(degree: u16) -> usize {
+    match degree {
+        0 => 0,
+        1 => 9,
+        _ => 24,
+    }
+}
+
+/// Decode the GaussianSplats3D `.ksplat` format (compression levels 0 to 2)
+///
+/// The file is made of a 4 KiB main header, a table of section headers and
+/// then per-section bucket data followed by the packed splats. Compressed
+/// levels store positions as 16 bit offsets from their bucket center and
+/// the remaining attributes as half floats. Only the DC color term is kept.
+pub fn decode_ksplat(bytes: &[u8]) -> Result<Vec<Gaussian3d>, SplatFormatError> {
+    if bytes.len() < KSPLAT_MAIN_HEADER_SIZE {
+        return Err(SplatFormatError::Invalid("missing .ksplat header".into()));
+    }
+
+    let version_major = bytes[0];
+    let max_section_count = read_u32(bytes, 4) as usize;
+    let section_count = read_u32(bytes, 8) as usize;
+    let splat_count = read_u32(bytes, 16) as usize;
+    let compression_level = read_u16(bytes, 20);
+
+    if version_major != 0 || compression_level > 2 {
+        return Err(SplatFormatError::Invalid(format!(
+            "unsupported .ksplat version {version_major} / compression level {compression_level}"
+        )));
+    }
+
+    let truncated = || SplatFormatError::Invalid(".ksplat data is truncated".into());
+
+    // The header's count only sizes the allocation, so it has to fit the file
+    let min_bytes_per_splat = if compression_level == 0 { 44 } else { 24 };
+    if splat_count.saturating_mul(min_bytes_per_splat) > bytes.len() {
+        return Err(truncated());
+    }
+
+    let mut gaussians = Vec::with_capacity(splat_count);
+    let mut section_base = KSPLAT_MAIN_HEADER_SIZE + max_section_count * KSPLAT_SECTION_HEADER_SIZE;
+
+    for section in 0..section_count {
+        let header_offset = KSPLAT_MAIN_HEADER_SIZE + section * KSPLAT_SECTION_HEADER_SIZE;
+        if bytes.len() < header_offset + KSPLAT_SECTION_HEADER_SIZE {
+            return Err(truncated());
+        }
+        let header = &bytes[header_offset..header_offset + KSPLAT_SECTION_HEADER_SIZE];
+
+        let section_splat_count = read_u32(header, 0) as usize;
+        let section_max_splat_count = read_u32(header, 4) as usize;
+        let bucket_size = read_u32(header, 8) as usize;
+        let bucket_count = read_u32(header, 12) as usize;
+        let half_bucket_block_size = read_f32(header, 16) / 2.0;
+        let bucket_storage_size = read_u16(header, 20) as usize;
+        let compression_scale_range = match read_u32(header, 24) {
+            0 => 32767.0,
+            range => range as f32,
+        };
+        let full_bucket_count = read_u32(header, 32) as usize;
+        let partial_bucket_count = read_u32(header, 36) as usize;
+        let sh_degree = read_u16(header, 40);
+
+        let sh_components = ksplat_sh_components(sh_degree);
+        let bytes_per_splat = match compression_level {
+            0 => 44 + sh_components * 4,
+            1 => 24 + sh_components * 2,
+            _ => 24 + sh_components,
+        };
+
+        let partial_lengths_size = partial_bucket_count * 4;
+        let buckets_size = bucket_storage_size * bucket_count + partial_lengths_size;
+        let data_base = section_base + buckets_size;
+        let section_end = data_base + bytes_per_splat * section_max_splat_count;
+        if bytes.len() < data_base + bytes_per_splat * section_splat_count {
+            return Err(truncated());
+        }
+
+        let partial_lengths: Vec<usize> = (0..partial_bucket_count)
+            .map(|i| read_u32(bytes, section_base + i * 4) as usize)
+            .collect();
+        let bucket_center = |bucket: usize| -> Result<[f32; 3], SplatFormatError> {
+            let offset = section_base + partial_lengths_size + bucket * 12;
+            if bucket >= bucket_count || offset + 12 > data_base {
+                return Err(SplatFormatError::Invalid(format!(
+                    ".ksplat splat is in bucket {bucket} of {bucket_count}"
+                )));
+            }
+            Ok([
+                read_f32(bytes, offset),
+                read_f32(bytes, offset + 4),
+                read_f32(bytes, offset + 8),
+            ])
+        };
+        let bucket_of = |index: usize| -> usize {
+            if index < full_bucket_count * bucket_size {
+                return index / bucket_size;
+            }
+            let mut bucket = full_bucket_count;
+            let mut first = full_bucket_count * bucket_size;
+            for length in &partial_lengths {
+                if index < first + length {
+                    break;
+                }
+                bucket += 1;
+                first += length;
+            }
+            bucket
+        };
+        let scale_factor = half_bucket_block_size / compression_scale_range;
+
+        for index in 0..section_splat_count {
+            let splat = &bytes[data_base + index * bytes_per_splat..][..bytes_per_splat];
+
+            let (position, scale, rotation, color_offset) = if compression_level == 0 {
+                let position = std::array::from_fn(|axis| read_f32(splat, axis * 4));
+                let scale = std::array::from_fn(|axis| read_f32(splat, 12 + axis * 4));
+                let rotation = std::array::from_fn(|i| read_f32(splat, 24 + i * 4));
+                (position, scale, rotation, 40)
+            } else {
+                let center = if bucket_count > 0 {
+                    bucket_center(bucket_of(index))?
+                } else {
+                    [0.0; 3]
+                };
+                let position = std::array::from_fn(|axis| {
+                    let quantized = read_u16(splat, axis * 2) as f32;
+                    (quantized - compression_scale_range) * scale_factor + center[axis]
+                });
+                let scale = std::array::from_fn(|axis| f16_to_f32(read_u16(splat, 6 + axis * 2)));
+                let rotation = std::array::from_fn(|i| f16_to_f32(read_u16(splat, 12 + i * 2)));
+                (position, scale, rotation, 20)
+            };
+
+            let color = [
+                splat[color_offset] as f32 / 255.0,
+                splat[color_offset + 1] as f32 / 255.0,
+                splat[color_offset + 2] as f32 / 255.0,
+            ];
+            let opacity = splat[color_offset + 3] as f32 / 255.0;
+
+            gaussians.push(make_gaussian(position, scale, rotation, opacity, color));
+        }
+
+        section_base = section_end;
+    }
+
+    Ok(gaussians)
+}
+
+/// Asset loader for GaussianSplats3D `.ksplat` files
+pub struct KSplatLoader {
+    pub progress: SplatLoadProgress,
+}
+
+impl AssetLoader for KSplatLoader {
+    type Asset = PlanarGaussian3d;
+    type Settings = ();
+    type Error = SplatFormatError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let bytes = read_with_progress(reader, &self.progress).await?;
+        let gaussians = decode_ksplat(&bytes)?;
+        info!("Decoded {} Gaussians from .ksplat file", gaussians.len());
+        Ok(PlanarGaussian3d::from(gaussians))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ksplat"]
+    }
+}
+
+/// Scalar property types that can appear in a binary PLY header
+#[derive(Debug, Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(PlyScalar::I8),
+            "uchar" | "uint8" => Some(PlyScalar::U8),
+            "short" | "int16" => Some(PlyScalar::I16),
+            "ushort" | "uint16" => Some(PlyScalar::U16),
+            "int" | "int32" => Some(PlyScalar::I32),
+            "uint" | "uint32" => Some(PlyScalar::U32),
+            "float" | "float32" => Some(PlyScalar::F32),
+            "double" | "float64" => Some(PlyScalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+
+    fn read(self, bytes: &[u8], offset: usize) -> f32 {
+        match self {
+            PlyScalar::I8 => bytes[offset] as i8 as f32,
+            PlyScalar::U8 => bytes[offset] as f32,
+            PlyScalar::I16 => read_u16(bytes, offset) as i16 as f32,
+            PlyScalar::U16 => read_u16(bytes, offset) as f32,
+            PlyScalar::I32 => read_u32(bytes, offset) as i32 as f32,
+            PlyScalar::U32 => read_u32(bytes, offset) as f32,
+            PlyScalar::F32 => read_f32(bytes, offset),
+            PlyScalar::F64 => {
+                let mut raw = [0u8; 8];
+                raw.copy_from_slice(&bytes[offset..offset + 8]);
+                f64::from_le_bytes(raw) as f32
+            }
+        }
+    }
+}
+
+/// Vertex attributes of a 3DGS PLY file that the game understands
+#[derive(Debug, Clone, Copy)]
+enum PlyField {
+    Position(usize),
+    Scale(usize),
+    Rotation(usize),
+    Opacity,
+    Dc(usize),
+}
+
+impl PlyField {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "x" => Some(PlyField::Position(0)),
+            "y" => Some(PlyField::Position(1)),
+            "z" => Some(PlyField::Position(2)),
+            "opacity" => Some(PlyField::Opacity),
+            _ => {
+                let (prefix, index) = name.rsplit_once('_')?;
+                let index: usize = index.parse().ok()?;
+                match (prefix, index) {
+                    ("scale", 0..=2) => Some(PlyField::Scale(index)),
+                    ("rot", 0..=3) => Some(PlyField::Rotation(index)),
+                    ("f_dc", 0..=2) => Some(PlyField::Dc(index)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Largest vertex record accepted, in bytes. A 3DGS vertex with degree 3
+/// spherical harmonics and normals takes 248 bytes.
+const PLY_MAX_STRIDE: usize = 4096;
+
+/// Parsed header of a binary little-endian 3DGS PLY file
+#[derive(Debug, Clone)]
+pub struct PlyHeader {
+    /// Number of Gaussians in the vertex element
+    pub vertex_count: usize,
+    /// Size in bytes of one vertex record
+    pub stride: usize,
+    /// Size in bytes of the header, i.e. the offset of the first vertex record
+    pub header_len: usize,
+    /// Known fields with their byte offset and storage type
+    fields: Vec<(PlyField, usize, PlyScalar)>,
+}
+
+impl PlyHeader {
+    /// Read a PLY header from the start of a stream, leaving it positioned at the vertex data
+    pub fn read_from(reader: &mut impl std::io::BufRead) -> Result<Self, SplatFormatError> {
+        let invalid = |msg: &str| SplatFormatError::Invalid(format!("PLY header: {msg}"));
+
+        let mut header_len = 0;
+        let mut line = String::new();
+        let mut vertex_count = None;
+        let mut in_vertex_element = false;
+        let mut stride = 0;
+        let mut fields = Vec::new();
+
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                return Err(invalid("missing end_header"));
+            }
+            header_len += read;
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                ["ply"] | ["comment", ..] | ["obj_info", ..] => {}
+                ["format", "binary_little_endian", _] => {}
+                ["format", other, _] => {
+                    return Err(invalid(&format!("unsupported format {other}")));
+                }
+                ["element", "vertex", count] => {
+                    vertex_count = Some(count.parse().map_err(|_| invalid("bad vertex count"))?);
+                    in_vertex_element = true;
+                }
+                ["element", ..] => {
+                    if vertex_count.is_none() {
+                        return Err(invalid("vertex element must come first"));
+                    }
+                    in_vertex_element = false;
+                }
+                ["property", "list", ..] if in_vertex_element => {
+                    return Err(invalid("list properties are not supported on vertices"));
+                }
+                ["property", ty, name] if in_vertex_element => {
+                    let scalar = PlyScalar::parse(ty)
+                        .ok_or_else(|| invalid(&format!("unknown property type {ty}")))?;
+                    if let Some(field) = PlyField::from_name(name) {
+                        fields.push((field, stride, scalar));
+                    }
+                    stride += scalar.size();
+                }
+                ["property", ..] => {}
+                ["end_header"] => break,
+                _ => return Err(invalid(&format!("unexpected line {:?}", line.trim()))),
+            }
+        }
+
+        // Having the position also rules out empty records
+        for (axis, name) in ["x", "y", "z"].into_iter().enumerate() {
+            let found = fields
+                .iter()
+                .any(|(field, _, _)| matches!(field, PlyField::Position(a) if *a == axis));
+            if !found {
+                return Err(invalid(&format!("missing vertex property {name}")));
+            }
+        }
+        if stride > PLY_MAX_STRIDE {
+            return Err(invalid(&format!(
+                "vertex records of {stride} bytes are too large"
+            )));
+        }
+
+        Ok(Self {
+            vertex_count: vertex_count.ok_or_else(|| invalid("no vertex element"))?,
+            stride,
+            header_len,
+            fields,
+        })
+    }
+
+    /// Decode a contiguous run of vertex records into Gaussians
+    pub fn decode_records(&self, records: &[u8]) -> Vec<Gaussian3d> {
+        records
+            .chunks_exact(self.stride)
+            .map(|record| {
+                let mut position = [0.0; 3];
+                let mut log_scale = [0.0; 3];
+                let mut rotation = [1.0, 0.0, 0.0, 0.0];
+                let mut logit_opacity = 0.0;
+                let mut dc = [0.0; 3];
+
+                for &(field, offset, scalar) in &self.fields {
+                    let value = scalar.read(record, offset);
+                    match field {
+                        PlyField::Position(i) => position[i] = value,
+                        PlyField::Scale(i) => log_scale[i] = value,
+                        PlyField::Rotation(i) => rotation[i] = value,
+                        PlyField::Opacity => logit_opacity = value,
+                        PlyField::Dc(i) => dc[i] = value,
+                    }
+                }
+
+                make_gaussian(
+                    position,
+                    log_scale.map(f32::exp),
+                    rotation,
+                    1.0 / (1.0 + (-logit_opacity).exp()),
+                    dc.map(|value| 0.5 + value * SH_C0),
+                )
+            })
+            .collect()
+    }
+}
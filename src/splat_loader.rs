@@ -1,20 +1,68 @@
 //! Gaussian splat file loading and management
 
+use std::collections::VecDeque;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_gaussian_splatting::{GaussianScene, GaussianSceneHandle};
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy_gaussian_splatting::{
+    CloudSettings, Gaussian3d, PlanarGaussian3d, PlanarGaussian3dHandle,
+};
+
+use crate::cli;
+use crate::ghost::{is_ghost_file, LoadGhost};
+use crate::scene_manifest::{is_scene_manifest, LoadSceneManifest};
+use crate::track_file::{is_track_file, LoadTrack};
+use crate::sidecar::SplatSidecar;
+use crate::splat_formats::{
+    KSplatLoader, PlyHeader, SplatBinaryLoader, SplatFormat, SplatFormatError, SpzLoader, SH_C0,
+};
+
+/// PLY files at least this large are streamed in chunks instead of loaded in one go
+const STREAMING_THRESHOLD_BYTES: u64 = 256 * 1024 * 1024;
+/// Number of Gaussians decoded and spawned per streamed chunk
+const STREAM_CHUNK_SPLATS: usize = 1 << 18;
+/// Folder inside `assets/` where splats fetched over HTTP are cached
+const DOWNLOAD_DIR: &str = "downloads";
 
 /// Plugin for loading and managing Gaussian splat files
 pub struct SplatLoaderPlugin;
 
 impl Plugin for SplatLoaderPlugin {
     fn build(&self, app: &mut App) {
+        let progress = SplatLoadProgress::default();
+
         app.init_state::<SplatLoadState>()
+            .init_resource::<LoadedSplats>()
+            .init_resource::<SplatQueue>()
+            .init_resource::<UpAxisCorrection>()
+            .init_resource::<SplatNormalization>()
+            .insert_resource(progress.clone())
+            .register_asset_loader(SplatBinaryLoader { progress: progress.clone() })
+            .register_asset_loader(SpzLoader { progress: progress.clone() })
+            .register_asset_loader(KSplatLoader { progress })
             .add_systems(Startup, load_from_cli_args)
             .add_systems(Update, (
                 load_splat_on_demand,
                 handle_file_drop,
+                redetect_up_axis,
+                (apply_auto_up_axis, normalize_loaded_splats).chain(),
             ))
-            .add_systems(Update, check_splat_loaded.run_if(in_state(SplatLoadState::Loading)));
+            .add_systems(Update, (
+                check_splat_loaded,
+                receive_streamed_chunks,
+            ).run_if(in_state(SplatLoadState::Loading)))
+            .add_systems(Update, receive_download.run_if(in_state(SplatLoadState::Downloading)))
+            .add_systems(Update, advance_splat_queue.run_if(
+                in_state(SplatLoadState::Loaded).or(in_state(SplatLoadState::Failed)),
+            ));
     }
 
 }
@@ -24,24 +72,339 @@ impl Plugin for SplatLoaderPlugin {
 pub enum SplatLoadState {
     #[default]
     WaitingForPath,
+    Downloading,
     Loading,
     Loaded,
     Failed,
 }
 
-/// Component marking the currently loaded splat entity
+/// Component marking a loaded splat entity
+#[derive(Component)]
+pub struct LoadedSplat {
+    /// The path the splat was loaded from
+    pub path: String,
+}
+
+/// Component marking a splat entity whose asset is still loading
+#[derive(Component)]
+pub struct PendingSplat;
+
+/// Resource listing the loaded splat entities in the order they were added
+#[derive(Resource, Default)]
+pub struct LoadedSplats(pub Vec<Entity>);
+
+/// Resource to hold the splat file to load next
+#[derive(Resource, Clone)]
+pub struct SplatPath {
+    pub path: String,
+    /// Transform given to the spawned splat entity
+    pub transform: Transform,
+    /// Keep the already loaded splats and add this one alongside them
+    pub additive: bool,
+    /// The transform is final: skip the sidecar alignment, up-axis correction
+    /// and normalization
+    pub placed: bool,
+}
+
+impl SplatPath {
+    /// Load a splat, replacing everything loaded so far
+    pub fn replace(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            transform: Transform::default(),
+            additive: false,
+            placed: false,
+        }
+    }
+
+    /// Load a splat alongside the ones already in the world
+    pub fn additive(path: impl Into<String>) -> Self {
+        Self {
+            additive: true,
+            ..Self::replace(path)
+        }
+    }
+
+    /// Place the splat with exactly this transform
+    pub fn with_transform(self, transform: Transform) -> Self {
+        Self {
+            transform,
+            placed: true,
+            ..self
+        }
+    }
+}
+
+/// How to correct the up axis of newly loaded splats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpAxisMode {
+    /// Splats are already Y-up
+    #[default]
+    None,
+    /// Splats are Z-up (COLMAP, RealityCapture) and get a 90 degree rotation
+    ZUp,
+    /// Detect the up axis from the splat positions once loaded
+    Auto,
+}
+
+/// Resource selecting the up-axis correction applied on load
+#[derive(Resource, Default)]
+pub struct UpAxisCorrection(pub UpAxisMode);
+
+/// Component marking a splat whose up axis should be detected once its data is available
+#[derive(Component)]
+pub struct AutoUpAxis;
+
+/// Component marking a splat to be recentered (and optionally rescaled) once loaded
+#[derive(Component)]
+pub struct AutoNormalize;
+
+/// Resource controlling the post-load recentering and rescaling step
+#[derive(Resource)]
+pub struct SplatNormalization {
+    /// Move the splat so it is centered on the origin with its floor at y = 0
+    pub recenter: bool,
+    /// Rescale the splat so its largest horizontal extent matches this size in meters
+    pub target_size: Option<f32>,
+}
+
+impl Default for SplatNormalization {
+    fn default() -> Self {
+        Self {
+            recenter: true,
+            target_size: None,
+        }
+    }
+}
+
+/// Resource holding the world-space bounding box of all loaded splats
+///
+/// Only present while at least one splat with point data is loaded. The box
+/// ignores the outermost percent of points so stray floaters don't inflate it.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SplatBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl SplatBounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+}
+
+/// System param giving CPU access to the points of loaded splat entities
+#[derive(SystemParam)]
+pub struct SplatData<'w, 's> {
+    clouds: Res<'w, Assets<PlanarGaussian3d>>,
+    handles: Query<'w, 's, &'static PlanarGaussian3dHandle>,
+    chunks: Query<'w, 's, &'static PlanarGaussian3dHandle, With<SplatChunk>>,
+    children: Query<'w, 's, &'static Children>,
+}
+
+impl SplatData<'_, '_> {
+    /// The clouds making up a splat entity: its own, or those of its chunks
+    pub fn clouds(&self, entity: Entity) -> Vec<&PlanarGaussian3d> {
+        if let Ok(handle) = self.handles.get(entity) {
+            return self.clouds.get(&handle.0).into_iter().collect();
+        }
+        self.children
+            .get(entity)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter_map(|child| self.chunks.get(child).ok())
+                    .filter_map(|handle| self.clouds.get(&handle.0))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Visit every Gaussian of a splat entity as (local position, linear scale, opacity,
+    /// base color)
+    pub fn for_each_gaussian(&self, entity: Entity, mut visit: impl FnMut(Vec3, Vec3, f32, Vec3)) {
+        for cloud in self.clouds(entity) {
+            let gaussians = cloud
+                .position_visibility
+                .iter()
+                .zip(&cloud.scale_opacity)
+                .zip(&cloud.spherical_harmonic);
+            for ((point, scale_opacity), sh) in gaussians {
+                let dc = Vec3::from_array(std::array::from_fn(|channel| sh.coefficients[channel]));
+                visit(
+                    Vec3::from(point.position),
+                    Vec3::from(scale_opacity.scale),
+                    scale_opacity.opacity,
+                    dc * SH_C0 + Vec3::splat(0.5),
+                );
+            }
+        }
+    }
+
+    /// Splat centers in the splat entity's local space, if any data has loaded
+    pub fn local_positions(&self, entity: Entity) -> Option<Vec<Vec3>> {
+        let positions: Vec<Vec3> = self
+            .clouds(entity)
+            .into_iter()
+            .flat_map(|cloud| cloud.position_visibility.iter())
+            .map(|point| Vec3::from(point.position))
+            .collect();
+        (!positions.is_empty()).then_some(positions)
+    }
+}
+
+/// Bounding box of a point set, trimmed to the 1st..99th percentile on each axis
+pub fn robust_bounds(positions: &[Vec3]) -> Option<(Vec3, Vec3)> {
+    if positions.is_empty() {
+        return None;
+    }
+
+    let stride = (positions.len() / 100_000).max(1);
+    let mut min = Vec3::ZERO;
+    let mut max = Vec3::ZERO;
+    for axis in 0..3 {
+        let mut values: Vec<f32> = positions.iter().step_by(stride).map(|p| p[axis]).collect();
+        values.sort_by(f32::total_cmp);
+        let last = values.len() - 1;
+        min[axis] = values[last / 100];
+        max[axis] = values[last - last / 100];
+    }
+    Some((min, max))
+}
+
+/// Resource holding splats waiting to be loaded after the current one
+#[derive(Resource, Default)]
+pub struct SplatQueue(pub VecDeque<SplatPath>);
+
+/// Byte counters for the splat currently being loaded
+///
+/// Cloned into the asset loaders and the streaming task, which update it from
+/// background threads while the UI reads it every frame.
+#[derive(Resource, Clone, Default)]
+pub struct SplatLoadProgress(Arc<ProgressCounters>);
+
+#[derive(Default)]
+struct ProgressCounters {
+    bytes_read: AtomicU64,
+    total_bytes: AtomicU64,
+}
+
+impl SplatLoadProgress {
+    /// Start tracking a new load, with the total size if it is known up front
+    pub fn reset(&self, total_bytes: Option<u64>) {
+        self.0.bytes_read.store(0, Ordering::Relaxed);
+        self.0.total_bytes.store(total_bytes.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Record that more bytes have been read
+    pub fn add_bytes(&self, bytes: u64) {
+        self.0.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes read so far
+    pub fn bytes_read(&self) -> u64 {
+        self.0.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Total size of the file being loaded, if known
+    pub fn total_bytes(&self) -> Option<u64> {
+        match self.0.total_bytes.load(Ordering::Relaxed) {
+            0 => None,
+            total => Some(total),
+        }
+    }
+
+    /// Fraction of the file read so far, if the total size is known
+    pub fn fraction(&self) -> Option<f32> {
+        self.total_bytes()
+            .map(|total| (self.bytes_read() as f64 / total as f64).min(1.0) as f32)
+    }
+}
+
+/// Component marking one chunk of a large splat, streamed or cut out for the
+/// levels of detail (see `splat_lod`)
 #[derive(Component)]
-pub struct LoadedSplat;
+pub struct SplatChunk;
+
+/// Messages sent from the background PLY streaming task
+enum StreamMessage {
+    Chunk(Vec<Gaussian3d>),
+    Done,
+    Failed(String),
+}
+
+/// Resource tracking an in-progress streamed PLY load
+#[derive(Resource)]
+struct SplatStream {
+    receiver: Mutex<Receiver<StreamMessage>>,
+    /// The `LoadedSplat` entity that streamed chunks are parented to
+    root: Entity,
+    chunks_received: usize,
+}
+
+/// Messages sent from the background HTTP download task
+enum DownloadMessage {
+    /// The file was saved; the path is relative to `assets/`
+    Finished(String),
+    Failed(String),
+}
 
-/// Resource to hold the path of the splat file to load
+/// Resource tracking an in-progress HTTP download
 #[derive(Resource)]
-pub struct SplatPath(pub String);
+struct SplatDownload {
+    receiver: Mutex<Receiver<DownloadMessage>>,
+    /// The request being downloaded, reissued with the local path once done
+    request: SplatPath,
+}
+
+/// Whether a splat path refers to a remote file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Name of the cached copy of a remote splat, keeping the extension for format detection
+fn download_file_name(url: &str) -> String {
+    let without_query = url.split(['?', '#']).next().unwrap_or(url);
+    let file_name = without_query
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("splat.ply");
+
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    format!("{:016x}-{}", hasher.finish(), file_name)
+}
+
+/// Resolve a splat path to a file on disk, trying the working directory and then `assets/`
+pub fn resolve_local_path(path: &str) -> Option<PathBuf> {
+    let direct = PathBuf::from(path);
+    if direct.is_file() {
+        return Some(direct);
+    }
+    let in_assets = Path::new("assets").join(path);
+    in_assets.is_file().then_some(in_assets)
+}
+
+/// Return the on-disk path of a PLY file large enough to be worth streaming
+fn streaming_candidate(path: &str) -> Option<PathBuf> {
+    let local = resolve_local_path(path)?;
+    let size = std::fs::metadata(&local).ok()?.len();
+    (size >= STREAMING_THRESHOLD_BYTES).then_some(local)
+}
 
 /// System to load a splat when a path is provided
 fn load_splat_on_demand(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     splat_path: Option<Res<SplatPath>>,
+    progress: Res<SplatLoadProgress>,
+    up_axis: Res<UpAxisCorrection>,
+    mut loaded_splats: ResMut<LoadedSplats>,
     mut next_state: ResMut<NextState<SplatLoadState>>,
     current_state: Res<State<SplatLoadState>>,
     existing_splats: Query<Entity, With<LoadedSplat>>,
@@ -51,40 +414,128 @@ fn load_splat_on_demand(
         return;
     }
 
-    if let Some(path) = splat_path {
-        // Remove any existing splat
-        for entity in existing_splats.iter() {
-            commands.entity(entity).despawn();
+    if let Some(request) = splat_path {
+        let path = &request.path;
+
+        // Remote files are downloaded first and then loaded like any local file
+        if is_url(path) {
+            let (sender, receiver) = channel();
+            let url = path.clone();
+            let progress = progress.clone();
+            AsyncComputeTaskPool::get()
+                .spawn(async move { download_splat(url, sender, progress) })
+                .detach();
+
+            commands.insert_resource(SplatDownload {
+                receiver: Mutex::new(receiver),
+                request: request.clone(),
+            });
+            next_state.set(SplatLoadState::Downloading);
+            info!("Downloading Gaussian splat from: {}", path);
+            return;
         }
 
-        // Load the new splat
-        let handle: Handle<GaussianScene> = asset_server.load(&path.0);
-        
-        commands.spawn((
-            GaussianSceneHandle(handle),
-            Transform::default(),
-            LoadedSplat,
-        ));
+        // Remove the existing splats unless this one is added alongside them
+        if !request.additive {
+            for entity in existing_splats.iter() {
+                commands.entity(entity).despawn();
+            }
+            loaded_splats.0.clear();
+        }
+
+        commands.remove_resource::<SplatStream>();
+
+        let file_size = resolve_local_path(path)
+            .and_then(|local| std::fs::metadata(local).ok())
+            .map(|metadata| metadata.len());
+        progress.reset(file_size);
+
+        // Load the new splat, picking the asset type from the file extension
+        // A transform saved next to the splat wins over the default alignment
+        let mut request = request.clone();
+        let mut aligned = request.placed || request.transform != Transform::default();
+        if !aligned {
+            if let Some(saved) = SplatSidecar::load(path).and_then(|sidecar| sidecar.transform) {
+                info!("Applying saved alignment for {}", path);
+                request.transform = saved;
+                aligned = true;
+            }
+        }
+        if !aligned && up_axis.0 == UpAxisMode::ZUp {
+            request.transform.rotation = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+        }
+
+        let splat = LoadedSplat { path: path.clone() };
+        let entity = match (SplatFormat::from_path(path), streaming_candidate(path)) {
+            (SplatFormat::Ply, Some(local)) => {
+                let root = commands.spawn((
+                    request.transform,
+                    Visibility::default(),
+                    splat,
+                )).id();
+
+                let (sender, receiver) = channel();
+                let progress = progress.clone();
+                AsyncComputeTaskPool::get()
+                    .spawn(async move { stream_ply_file(local, sender, progress) })
+                    .detach();
+
+                commands.insert_resource(SplatStream {
+                    receiver: Mutex::new(receiver),
+                    root,
+                    chunks_received: 0,
+                });
+                info!("Streaming large PLY in chunks of {} Gaussians", STREAM_CHUNK_SPLATS);
+                root
+            }
+            // Everything else is a single cloud asset, which keeps the point data on the CPU.
+            // PLYs used to load as a `GaussianScene`, whose clouds the up axis detection,
+            // the point cloud and the editors can't read
+            _ => {
+                let handle: Handle<PlanarGaussian3d> = asset_server.load(path);
+
+                commands.spawn((
+                    PlanarGaussian3dHandle(handle),
+                    CloudSettings::default(),
+                    request.transform,
+                    splat,
+                    PendingSplat,
+                )).id()
+            }
+        };
+        loaded_splats.0.push(entity);
+        if !aligned && up_axis.0 == UpAxisMode::Auto {
+            commands.entity(entity).insert(AutoUpAxis);
+        }
+        // Composed splats keep their placement relative to what is already loaded
+        if !aligned && !request.additive {
+            commands.entity(entity).insert(AutoNormalize);
+        }
 
         next_state.set(SplatLoadState::Loading);
-        info!("Loading Gaussian splat from: {}", path.0);
+        info!("Loading Gaussian splat from: {}", path);
     }
 }
 
-/// Check if the splat has finished loading
+/// Check if the pending splat has finished loading
 fn check_splat_loaded(
+    mut commands: Commands,
     asset_server: Res<AssetServer>,
-    splat_query: Query<&GaussianSceneHandle, With<LoadedSplat>>,
+    cloud_query: Query<(Entity, &PlanarGaussian3dHandle), With<PendingSplat>>,
+    mut loaded_splats: ResMut<LoadedSplats>,
     mut next_state: ResMut<NextState<SplatLoadState>>,
 ) {
-    for handle in splat_query.iter() {
+    for (entity, handle) in cloud_query.iter() {
         match asset_server.get_load_state(handle.0.id()) {
             Some(bevy::asset::LoadState::Loaded) => {
                 info!("Gaussian splat loaded successfully!");
+                commands.entity(entity).remove::<PendingSplat>();
                 next_state.set(SplatLoadState::Loaded);
             }
             Some(bevy::asset::LoadState::Failed(_)) => {
                 error!("Failed to load Gaussian splat!");
+                commands.entity(entity).despawn();
+                loaded_splats.0.retain(|&loaded| loaded != entity);
                 next_state.set(SplatLoadState::Failed);
             }
             _ => {}
@@ -92,33 +543,435 @@ fn check_splat_loaded(
     }
 }
 
-/// Load splat from command line arguments
+/// Parse a PLY file in slices on a background task, sending each slice as it is decoded
+fn stream_ply_file(path: PathBuf, sender: Sender<StreamMessage>, progress: SplatLoadProgress) {
+    let result = (|| -> Result<(), SplatFormatError> {
+        let mut reader = BufReader::new(File::open(&path)?);
+        let header = PlyHeader::read_from(&mut reader)?;
+        progress.add_bytes(header.header_len as u64);
+
+        let mut remaining = header.vertex_count;
+        let mut buffer = vec![0u8; STREAM_CHUNK_SPLATS * header.stride];
+        while remaining > 0 {
+            let count = remaining.min(STREAM_CHUNK_SPLATS);
+            let bytes = &mut buffer[..count * header.stride];
+            reader.read_exact(bytes)?;
+            remaining -= count;
+            progress.add_bytes(bytes.len() as u64);
+
+            if sender.send(StreamMessage::Chunk(header.decode_records(bytes))).is_err() {
+                // The load was cancelled, e.g. by dropping another file
+                return Ok(());
+            }
+        }
+        Ok(())
+    })();
+
+    let message = match result {
+        Ok(()) => StreamMessage::Done,
+        Err(err) => StreamMessage::Failed(err.to_string()),
+    };
+    let _ = sender.send(message);
+}
+
+/// Spawn streamed PLY chunks as they arrive from the background task
+fn receive_streamed_chunks(
+    mut commands: Commands,
+    stream: Option<ResMut<SplatStream>>,
+    mut clouds: ResMut<Assets<PlanarGaussian3d>>,
+    mut next_state: ResMut<NextState<SplatLoadState>>,
+) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+
+    loop {
+        let message = stream.receiver.lock().unwrap().try_recv();
+        match message {
+            Ok(StreamMessage::Chunk(gaussians)) => {
+                let cloud = clouds.add(PlanarGaussian3d::from(gaussians));
+                let chunk = commands.spawn((
+                    PlanarGaussian3dHandle(cloud),
+                    CloudSettings::default(),
+                    Transform::default(),
+                    SplatChunk,
+                )).id();
+                commands.entity(stream.root).add_child(chunk);
+                stream.chunks_received += 1;
+            }
+            Ok(StreamMessage::Done) => {
+                info!("Gaussian splat streamed successfully in {} chunks!", stream.chunks_received);
+                commands.remove_resource::<SplatStream>();
+                next_state.set(SplatLoadState::Loaded);
+                return;
+            }
+            Ok(StreamMessage::Failed(err)) => {
+                error!("Failed to stream Gaussian splat: {}", err);
+                commands.remove_resource::<SplatStream>();
+                next_state.set(SplatLoadState::Failed);
+                return;
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                commands.remove_resource::<SplatStream>();
+                return;
+            }
+        }
+    }
+}
+
+/// Fetch a splat over HTTP into the download cache, reporting progress as it goes
+///
+/// A cached copy is used instead when the server reports the same size for
+/// it, or when the server can't be reached.
+fn download_splat(url: String, sender: Sender<DownloadMessage>, progress: SplatLoadProgress) {
+    let relative = Path::new(DOWNLOAD_DIR).join(download_file_name(&url));
+    let destination = Path::new("assets").join(&relative);
+    // Downloads are written here and only renamed once complete, so the
+    // destination never holds a partial file
+    let mut partial = destination.clone().into_os_string();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let result = (|| -> Result<(), String> {
+        // A cached file is only reused when the server confirms its size
+        if let Ok(metadata) = std::fs::metadata(&destination) {
+            let cached_bytes = metadata.len();
+            let length = ureq::head(&url).call().ok().and_then(|response| {
+                response
+                    .header("Content-Length")
+                    .and_then(|length| length.parse::<u64>().ok())
+            });
+            if length == Some(cached_bytes) {
+                info!("Using the cached download {}", destination.display());
+                progress.reset(Some(cached_bytes));
+                progress.add_bytes(cached_bytes);
+                return Ok(());
+            }
+        }
+
+        let response = ureq::get(&url).call().map_err(|err| err.to_string())?;
+        let total_bytes = response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
+        progress.reset(total_bytes);
+
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut file = File::create(&partial).map_err(|err| err.to_string())?;
+        let mut reader = response.into_reader();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut written = 0;
+        loop {
+            let read = reader.read(&mut buffer).map_err(|err| err.to_string())?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buffer[..read]).map_err(|err| err.to_string())?;
+            written += read as u64;
+            progress.add_bytes(read as u64);
+        }
+        if let Some(total) = total_bytes.filter(|&total| total != written) {
+            return Err(format!("download ended after {written} of {total} bytes"));
+        }
+        file.sync_all().map_err(|err| err.to_string())?;
+        drop(file);
+        std::fs::rename(&partial, &destination).map_err(|err| err.to_string())
+    })();
+
+    let message = match result {
+        Ok(()) => DownloadMessage::Finished(relative.to_string_lossy().to_string()),
+        Err(err) => {
+            let _ = std::fs::remove_file(&partial);
+            DownloadMessage::Failed(err)
+        }
+    };
+    let _ = sender.send(message);
+}
+
+/// Hand a finished download over to the regular loading path
+fn receive_download(
+    mut commands: Commands,
+    download: Option<Res<SplatDownload>>,
+    mut next_state: ResMut<NextState<SplatLoadState>>,
+) {
+    let Some(download) = download else {
+        return;
+    };
+
+    let message = download.receiver.lock().unwrap().try_recv();
+    match message {
+        Ok(DownloadMessage::Finished(path)) => {
+            info!("Download finished, loading splat: {}", path);
+            commands.insert_resource(SplatPath {
+                path,
+                ..download.request.clone()
+            });
+            commands.remove_resource::<SplatDownload>();
+            next_state.set(SplatLoadState::WaitingForPath);
+        }
+        Ok(DownloadMessage::Failed(err)) => {
+            error!("Failed to download Gaussian splat: {}", err);
+            commands.remove_resource::<SplatDownload>();
+            next_state.set(SplatLoadState::Failed);
+        }
+        Err(TryRecvError::Empty) => {}
+        Err(TryRecvError::Disconnected) => {
+            commands.remove_resource::<SplatDownload>();
+            next_state.set(SplatLoadState::Failed);
+        }
+    }
+}
+
+/// Guess which direction is up in a scan from the distribution of splat centers
+///
+/// The world axis with the least spread is taken as vertical, since scans are
+/// wider than they are tall. It points away from the side where most points
+/// sit, as the ground is usually denser than whatever stands on it.
+pub fn detect_up_axis(positions: &[Vec3]) -> Vec3 {
+    if positions.is_empty() {
+        return Vec3::Y;
+    }
+
+    // A subsample is plenty for a global statistic on millions of points
+    let stride = (positions.len() / 100_000).max(1);
+    let sample: Vec<Vec3> = positions.iter().step_by(stride).copied().collect();
+    let count = sample.len() as f32;
+
+    let mean = sample.iter().copied().sum::<Vec3>() / count;
+    let variance = sample
+        .iter()
+        .map(|p| (*p - mean) * (*p - mean))
+        .sum::<Vec3>()
+        / count;
+
+    let axis = if variance.x <= variance.y && variance.x <= variance.z {
+        0
+    } else if variance.y <= variance.z {
+        1
+    } else {
+        2
+    };
+
+    let mut values: Vec<f32> = sample.iter().map(|p| p[axis]).collect();
+    values.sort_by(f32::total_cmp);
+    let median = values[values.len() / 2];
+
+    let mut up = Vec3::ZERO;
+    up[axis] = if mean[axis] >= median { 1.0 } else { -1.0 };
+    up
+}
+
+/// Rotate splats marked with `AutoUpAxis` upright once their points are available
+fn apply_auto_up_axis(
+    mut commands: Commands,
+    splat_data: SplatData,
+    mut splat_query: Query<(Entity, &mut Transform), With<AutoUpAxis>>,
+) {
+    for (entity, mut transform) in splat_query.iter_mut() {
+        // For streamed splats the first chunks are already a good enough sample
+        let Some(positions) = splat_data.local_positions(entity) else {
+            continue;
+        };
+        let up = detect_up_axis(&positions);
+
+        transform.rotation = Quat::from_rotation_arc(up, Vec3::Y);
+        commands.entity(entity).remove::<AutoUpAxis>();
+        info!("Detected splat up axis {:?}, rotating it upright", up);
+    }
+}
+
+/// Recenter and optionally rescale newly loaded splats marked with `AutoNormalize`
+fn normalize_loaded_splats(
+    mut commands: Commands,
+    settings: Res<SplatNormalization>,
+    splat_data: SplatData,
+    stream: Option<Res<SplatStream>>,
+    mut splat_query: Query<
+        (Entity, &mut Transform),
+        (With<AutoNormalize>, Without<AutoUpAxis>, Without<PendingSplat>),
+    >,
+) {
+    for (entity, mut transform) in splat_query.iter_mut() {
+        // Wait for streamed splats to finish so the bounds cover the whole scan
+        if stream.as_ref().is_some_and(|stream| stream.root == entity) {
+            continue;
+        }
+        let Some(positions) = splat_data.local_positions(entity) else {
+            continue;
+        };
+        commands.entity(entity).remove::<AutoNormalize>();
+
+        let oriented: Vec<Vec3> = positions
+            .iter()
+            .map(|p| transform.rotation * (transform.scale * *p))
+            .collect();
+        let Some((min, max)) = robust_bounds(&oriented) else {
+            continue;
+        };
+
+        let factor = settings
+            .target_size
+            .map(|target| target / (max.x - min.x).max(max.z - min.z).max(1e-3))
+            .unwrap_or(1.0);
+
+        if settings.recenter {
+            let center = (min + max) * 0.5;
+            transform.translation = Vec3::new(-center.x, -min.y, -center.z) * factor;
+        } else {
+            transform.translation *= factor;
+        }
+        transform.scale *= factor;
+
+        info!(
+            "Normalized splat: size {:?}, scale factor {:.3}",
+            (max - min) * factor,
+            factor
+        );
+    }
+}
+
+/// Re-run up axis detection on all loaded splats with 'U'
+fn redetect_up_axis(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut splat_query: Query<(Entity, &mut Transform), With<LoadedSplat>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyU) {
+        return;
+    }
+
+    for (entity, mut transform) in splat_query.iter_mut() {
+        transform.rotation = Quat::IDENTITY;
+        commands.entity(entity).insert(AutoUpAxis);
+    }
+    info!("Detecting up axis of loaded splats");
+}
+
+/// Start loading the next queued splat once the previous one is done
+fn advance_splat_queue(
+    mut commands: Commands,
+    mut queue: ResMut<SplatQueue>,
+    mut next_state: ResMut<NextState<SplatLoadState>>,
+) {
+    if let Some(request) = queue.0.pop_front() {
+        commands.insert_resource(request);
+        next_state.set(SplatLoadState::WaitingForPath);
+    }
+}
+
+/// Load splats from command line arguments
+///
+/// The first splat replaces the world, any further ones are composed into it.
+/// `--z-up` rotates Z-up scans upright and `--auto-up` detects the up axis.
+/// `--fit=<meters>` rescales the scan to that size and `--no-recenter` keeps
+/// its original position.
 fn load_from_cli_args(
     mut commands: Commands,
+    mut queue: ResMut<SplatQueue>,
+    mut up_axis: ResMut<UpAxisCorrection>,
+    mut normalization: ResMut<SplatNormalization>,
     mut next_state: ResMut<NextState<SplatLoadState>>,
+    mut manifests: MessageWriter<LoadSceneManifest>,
+    mut tracks: MessageWriter<LoadTrack>,
 ) {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        let path = args[1].clone();
+    let args = cli::args();
+    let (manifest_paths, paths): (Vec<&String>, Vec<&String>) =
+        args.paths.iter().partition(|path| is_scene_manifest(path));
+    let (track_paths, paths): (Vec<&String>, Vec<&String>) =
+        paths.into_iter().partition(|path| is_track_file(path));
+
+    if args.z_up {
+        up_axis.0 = UpAxisMode::ZUp;
+    } else if args.auto_up {
+        up_axis.0 = UpAxisMode::Auto;
+    }
+    if args.no_recenter {
+        normalization.recenter = false;
+    }
+    match args.fit {
+        Some(size) if size > 0.0 => normalization.target_size = Some(size),
+        Some(size) => warn!("Ignoring --fit={}, the size must be positive", size),
+        None => {}
+    }
+
+    let mut paths = paths.into_iter().chain(&args.splats).cloned();
+    if let Some(path) = paths.next() {
         info!("Found CLI argument, loading splat: {}", path);
-        commands.insert_resource(SplatPath(path));
+        commands.insert_resource(SplatPath::replace(path));
+        queue.0.extend(paths.map(SplatPath::additive));
         next_state.set(SplatLoadState::WaitingForPath);
     }
+
+    // A scene manifest brings its own splats, so it takes over from any paths above
+    if let Some(path) = args.scene.as_ref().or(manifest_paths.first().copied()) {
+        info!("Found CLI argument, loading scene: {}", path);
+        manifests.write(LoadSceneManifest(path.clone()));
+    }
+
+    if let Some(path) = track_paths.first() {
+        info!("Found CLI argument, loading track: {}", path);
+        tracks.write(LoadTrack(path.to_string()));
+    }
+}
+
+/// System parameter opening any file the game understands
+///
+/// Scene manifests are handed to the scene loader, track and ghost files to
+/// their loaders and anything else is loaded as a splat.
+#[derive(SystemParam)]
+pub struct FileOpener<'w, 's> {
+    commands: Commands<'w, 's>,
+    next_state: ResMut<'w, NextState<SplatLoadState>>,
+    manifests: MessageWriter<'w, LoadSceneManifest>,
+    tracks: MessageWriter<'w, LoadTrack>,
+    ghosts: MessageWriter<'w, LoadGhost>,
+}
+
+impl FileOpener<'_, '_> {
+    /// Open a file, adding a splat to the scene instead of replacing it if `additive`
+    pub fn open(&mut self, path: String, additive: bool) {
+        if is_scene_manifest(&path) {
+            info!("Loading scene: {}", path);
+            self.manifests.write(LoadSceneManifest(path));
+            return;
+        }
+        if is_track_file(&path) {
+            info!("Loading track: {}", path);
+            self.tracks.write(LoadTrack(path));
+            return;
+        }
+        if is_ghost_file(&path) {
+            info!("Loading ghost: {}", path);
+            self.ghosts.write(LoadGhost(path));
+            return;
+        }
+        info!("Loading splat: {}", path);
+        self.commands.insert_resource(if additive {
+            SplatPath::additive(path)
+        } else {
+            SplatPath::replace(path)
+        });
+        // Reset state to trigger loading
+        self.next_state.set(SplatLoadState::WaitingForPath);
+    }
 }
 
 /// Handle file drag and drop events
+///
+/// Holding Shift while dropping adds the splat to the scene instead of replacing it.
 fn handle_file_drop(
-    mut events: EventReader<FileDragAndDrop>,
-    mut commands: Commands,
-    mut next_state: ResMut<NextState<SplatLoadState>>,
+    mut events: MessageReader<FileDragAndDrop>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut opener: FileOpener,
 ) {
+    let additive = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+
     for event in events.read() {
         if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
-            let path = path_buf.to_string_lossy().to_string();
-            info!("File dropped, loading splat: {}", path);
-            commands.insert_resource(SplatPath(path));
-            // Reset state to trigger loading
-            next_state.set(SplatLoadState::WaitingForPath);
+            info!("File dropped: {}", path_buf.display());
+            opener.open(path_buf.to_string_lossy().to_string(), additive);
         }
     }
 }
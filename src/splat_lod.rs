@@ -0,0 +1,346 @@
+//! Level of detail for big splats
+//!
+//! City-scale scans hold far more Gaussians than a laptop can sort and blend
+//! every frame, and most of them are so far away that their detail is lost
+//! anyway. Once a splat of at least `LOD_MIN_GAUSSIANS` has loaded, it is cut
+//! into chunks on a grid, and a background task builds coarser versions of
+//! every chunk: the Gaussians falling into the same cell of a voxel grid are
+//! merged into one larger Gaussian covering them all. Every frame each chunk
+//! is drawn at the detail its distance from the nearest camera calls for, so
+//! the road ahead stays sharp while the skyline costs little.
+//!
+//! The coarse versions keep only the base color; the view-dependent shine of
+//! the spherical harmonics can't be made out at that distance.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy_gaussian_splatting::{
+    CloudSettings, Gaussian3d, PlanarGaussian3d, PlanarGaussian3dHandle,
+};
+
+use crate::camera::CarCamera;
+use crate::splat_color::LoadedColors;
+use crate::splat_formats::SH_C0;
+use crate::splat_loader::{robust_bounds, LoadedSplat, PendingSplat, SplatChunk, SplatLoadState};
+
+/// Splats with fewer Gaussians than this are drawn in full everywhere
+const LOD_MIN_GAUSSIANS: usize = 1_000_000;
+
+/// Chunks across the largest side of a splat
+const LOD_CHUNKS_ACROSS: f32 = 8.0;
+
+/// Voxels across a chunk for each coarser level, the Gaussians in a voxel
+/// being merged into one
+const LOD_RESOLUTIONS: [f32; 2] = [48.0, 12.0];
+
+/// Distances from the camera beyond which a chunk drops to each coarser
+/// level, in meters
+const LOD_DISTANCES: [f32; 2] = [60.0, 180.0];
+
+/// Plugin for the splat levels of detail
+pub struct SplatLodPlugin;
+
+impl Plugin for SplatLodPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_lod_builds.run_if(in_state(SplatLoadState::Loaded)),
+                receive_lod_builds,
+                select_lod_levels,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Component of one level of detail of a chunk, with the chunk's box in the
+/// splat's local space; level 0 holds every Gaussian
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SplatLod {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub level: usize,
+}
+
+/// Marker for a loaded splat that has been checked for levels of detail
+#[derive(Component)]
+struct LodChecked;
+
+/// Component of a splat whose levels of detail are being built
+#[derive(Component)]
+struct LodBuild(Mutex<Receiver<Vec<LodChunk>>>);
+
+/// A chunk with its Gaussians at every level, the full detail first
+struct LodChunk {
+    min: Vec3,
+    max: Vec3,
+    levels: Vec<Vec<Gaussian3d>>,
+}
+
+/// Running sums of the Gaussians merged into one voxel, weighted by opacity
+#[derive(Default)]
+struct VoxelMerge {
+    weight: f32,
+    /// Position relative to the voxel's corner, and its square
+    offset: Vec3,
+    offset_squared: Vec3,
+    color: Vec3,
+    scale: Vec3,
+    opacity: f32,
+}
+
+/// Start building the levels of detail of every big splat that has loaded,
+/// from the colors it was loaded with
+fn start_lod_builds(
+    mut commands: Commands,
+    clouds: Res<Assets<PlanarGaussian3d>>,
+    colors: Res<LoadedColors>,
+    splat_query: Query<
+        (Entity, Option<&PlanarGaussian3dHandle>, Option<&Children>),
+        (
+            With<LoadedSplat>,
+            Without<LodChecked>,
+            Without<PendingSplat>,
+        ),
+    >,
+    chunk_query: Query<&PlanarGaussian3dHandle, With<SplatChunk>>,
+) {
+    for (entity, handle, children) in splat_query.iter() {
+        commands.entity(entity).insert(LodChecked);
+        let mut ids: Vec<AssetId<PlanarGaussian3d>> =
+            handle.map(|handle| handle.0.id()).into_iter().collect();
+        if let Some(children) = children {
+            ids.extend(
+                children
+                    .iter()
+                    .filter_map(|child| chunk_query.get(child).ok())
+                    .map(|handle| handle.0.id()),
+            );
+        }
+        let total: usize = ids
+            .iter()
+            .filter_map(|&id| clouds.get(id))
+            .map(|cloud| cloud.position_visibility.len())
+            .sum();
+        if total < LOD_MIN_GAUSSIANS {
+            continue;
+        }
+
+        let mut gaussians = Vec::with_capacity(total);
+        for id in ids {
+            let Some(cloud) = clouds.get(id) else {
+                continue;
+            };
+            let original = colors.original(id);
+            for index in 0..cloud.position_visibility.len() {
+                let mut gaussian = Gaussian3d {
+                    position_visibility: cloud.position_visibility[index],
+                    spherical_harmonic: cloud.spherical_harmonic[index],
+                    rotation: cloud.rotation[index],
+                    scale_opacity: cloud.scale_opacity[index],
+                };
+                if let Some(color) = original.and_then(|colors| colors.get(index)) {
+                    for channel in 0..3 {
+                        gaussian
+                            .spherical_harmonic
+                            .set(channel, (color[channel] - 0.5) / SH_C0);
+                    }
+                }
+                gaussians.push(gaussian);
+            }
+        }
+
+        let (sender, receiver) = channel();
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let _ = sender.send(build_lod_chunks(gaussians));
+            })
+            .detach();
+        commands
+            .entity(entity)
+            .insert(LodBuild(Mutex::new(receiver)));
+        info!("Building levels of detail for {} Gaussians", total);
+    }
+}
+
+/// Cut the Gaussians into chunks on a grid and build the coarser levels of
+/// each chunk
+fn build_lod_chunks(gaussians: Vec<Gaussian3d>) -> Vec<LodChunk> {
+    let positions: Vec<Vec3> = gaussians
+        .iter()
+        .map(|gaussian| Vec3::from(gaussian.position_visibility.position))
+        .collect();
+    let Some((min, max)) = robust_bounds(&positions) else {
+        return Vec::new();
+    };
+    let size = ((max - min).max_element() / LOD_CHUNKS_ACROSS).max(1e-3);
+    let cells = ((max - min) / size).ceil().as_ivec3().max(IVec3::ONE);
+
+    // Floaters outside the bounds go to the chunks at the edge
+    let mut chunks: HashMap<IVec3, Vec<Gaussian3d>> = HashMap::new();
+    for (gaussian, position) in gaussians.into_iter().zip(positions) {
+        let cell = ((position - min) / size)
+            .floor()
+            .as_ivec3()
+            .clamp(IVec3::ZERO, cells - IVec3::ONE);
+        chunks.entry(cell).or_default().push(gaussian);
+    }
+
+    chunks
+        .into_iter()
+        .map(|(cell, detail)| {
+            let coarse: Vec<Vec<Gaussian3d>> = LOD_RESOLUTIONS
+                .iter()
+                .map(|&resolution| decimate(&detail, size / resolution))
+                .collect();
+            let chunk_min = min + cell.as_vec3() * size;
+            LodChunk {
+                min: chunk_min,
+                max: chunk_min + Vec3::splat(size),
+                levels: std::iter::once(detail).chain(coarse).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Merge the Gaussians in each voxel into one, spread over the volume they
+/// covered together
+fn decimate(gaussians: &[Gaussian3d], voxel: f32) -> Vec<Gaussian3d> {
+    let mut voxels: HashMap<IVec3, VoxelMerge> = HashMap::new();
+    for gaussian in gaussians {
+        let position = Vec3::from(gaussian.position_visibility.position);
+        let cell = (position / voxel).floor().as_ivec3();
+        let offset = position - cell.as_vec3() * voxel;
+        let opacity = gaussian.scale_opacity.opacity;
+        let weight = opacity.max(1e-3);
+        let color = Vec3::from_array(std::array::from_fn(|channel| {
+            gaussian.spherical_harmonic.coefficients[channel]
+        }));
+
+        let merge = voxels.entry(cell).or_default();
+        merge.weight += weight;
+        merge.offset += offset * weight;
+        merge.offset_squared += offset * offset * weight;
+        merge.color += color * weight;
+        merge.scale += Vec3::from(gaussian.scale_opacity.scale) * weight;
+        merge.opacity = merge.opacity.max(opacity);
+    }
+
+    voxels
+        .into_iter()
+        .map(|(cell, merge)| {
+            let offset = merge.offset / merge.weight;
+            let spread = (merge.offset_squared / merge.weight - offset * offset).max(Vec3::ZERO);
+            let own_scale = merge.scale / merge.weight;
+            let color = merge.color / merge.weight;
+
+            let mut gaussian = Gaussian3d::default();
+            gaussian.position_visibility.position = (cell.as_vec3() * voxel + offset).to_array();
+            gaussian.position_visibility.visibility = 1.0;
+            gaussian.scale_opacity.scale = (own_scale * own_scale + spread).map(f32::sqrt).to_array();
+            gaussian.scale_opacity.opacity = merge.opacity;
+            gaussian.rotation.rotation = [1.0, 0.0, 0.0, 0.0];
+            for channel in 0..3 {
+                gaussian.spherical_harmonic.set(channel, color[channel]);
+            }
+            gaussian
+        })
+        .collect()
+}
+
+/// Replace the clouds of a splat with its chunks once their levels of
+/// detail are built
+fn receive_lod_builds(
+    mut commands: Commands,
+    mut clouds: ResMut<Assets<PlanarGaussian3d>>,
+    build_query: Query<(Entity, &LodBuild, Option<&Children>)>,
+    chunk_query: Query<(), With<SplatChunk>>,
+) {
+    for (entity, build, children) in build_query.iter() {
+        let message = build.0.lock().unwrap().try_recv();
+        let chunks = match message {
+            Ok(chunks) => chunks,
+            Err(TryRecvError::Empty) => continue,
+            Err(TryRecvError::Disconnected) => {
+                commands.entity(entity).remove::<LodBuild>();
+                continue;
+            }
+        };
+
+        commands
+            .entity(entity)
+            .remove::<(LodBuild, PlanarGaussian3dHandle)>();
+        for child in children.into_iter().flat_map(|children| children.iter()) {
+            if chunk_query.contains(child) {
+                commands.entity(child).despawn();
+            }
+        }
+        let chunk_count = chunks.len();
+        for chunk in chunks {
+            for (level, gaussians) in chunk.levels.into_iter().enumerate() {
+                let cloud = clouds.add(PlanarGaussian3d::from(gaussians));
+                let mut lod = commands.spawn((
+                    PlanarGaussian3dHandle(cloud),
+                    CloudSettings::default(),
+                    Transform::default(),
+                    SplatLod {
+                        min: chunk.min,
+                        max: chunk.max,
+                        level,
+                    },
+                    ChildOf(entity),
+                ));
+                // The full detail stands in for the splat's points
+                if level == 0 {
+                    lod.insert(SplatChunk);
+                } else {
+                    lod.insert(Visibility::Hidden);
+                }
+            }
+        }
+        info!(
+            "Built {} levels of detail for {} chunks",
+            LOD_RESOLUTIONS.len() + 1,
+            chunk_count
+        );
+    }
+}
+
+/// Show each chunk at the level of detail for its distance from the nearest
+/// driving camera
+fn select_lod_levels(
+    camera_query: Query<&GlobalTransform, With<CarCamera>>,
+    mut lod_query: Query<(&SplatLod, &GlobalTransform, &mut Visibility)>,
+) {
+    let cameras: Vec<Vec3> = camera_query
+        .iter()
+        .map(|transform| transform.translation())
+        .collect();
+    if cameras.is_empty() {
+        return;
+    }
+    for (lod, transform, mut visibility) in lod_query.iter_mut() {
+        let to_local = transform.affine().inverse();
+        let distance = cameras
+            .iter()
+            .map(|&camera| {
+                let nearest = to_local.transform_point3(camera).clamp(lod.min, lod.max);
+                transform.transform_point(nearest).distance(camera)
+            })
+            .fold(f32::INFINITY, f32::min);
+        let level = LOD_DISTANCES
+            .iter()
+            .filter(|&&threshold| distance >= threshold)
+            .count();
+        visibility.set_if_neq(if level == lod.level {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
@@ -0,0 +1,245 @@
+//! Local split-screen for two players
+//!
+//! Press F11 to bring in a second car for a player on a gamepad. The window
+//! splits into a top half following the first car and a bottom half following
+//! the second. The first player keeps the keyboard, while the gamepads now
+//! drive the second car: the sticks and triggers as usual, and West (X/Square)
+//! puts it back at the last gate it passed. Both cars are timed through the
+//! checkpoint gates, each with their lap times in their own half. F11 again
+//! takes the second car out.
+//!
+//! The second car drives the vehicle selected when it came in, and like the
+//! first it stays inside the drivable area, feels the surface zones and bumps
+//! into the scan and the other car. The camera views, the HUD, boost, damage,
+//! lights, audio and ghost follow the first player only.
+
+use bevy::camera::Viewport;
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::car::{car_body, Car, CarSet};
+use crate::kill_plane::KillPlane;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::spawn_point::SpawnPoint;
+use crate::terrain::GroundSurface;
+use crate::track::{lap_summary, LapTimer};
+use crate::vehicle::{spawn_procedural_body, SelectedVehicle, Vehicles};
+
+/// Distance to the right of the spawn point the second car starts at, in meters
+const SECOND_CAR_OFFSET: f32 = 4.0;
+
+/// Body color of the second car, in sRGB, to tell the cars apart
+const SECOND_CAR_COLOR: [f32; 3] = [0.95, 0.45, 0.05];
+
+/// Plugin for two-player split-screen
+pub struct SplitScreenPlugin;
+
+impl Plugin for SplitScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                toggle_split_screen,
+                respawn_second_car.after(CarSet::Constraints),
+                (follow_second_car, update_viewports, update_second_lap_display)
+                    .chain()
+                    .in_set(CarSet::Camera),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Marker for the second player's car
+#[derive(Component)]
+pub struct SecondCar;
+
+/// Marker for the camera following the second car
+#[derive(Component)]
+pub struct SecondCamera;
+
+/// Marker for the second player's lap times text
+#[derive(Component)]
+struct SecondLapDisplay;
+
+/// Where the second car starts, next to the first one's spawn
+fn second_spawn(spawn: &SpawnPoint) -> Transform {
+    let mut transform = spawn.0;
+    transform.translation += transform.right() * SECOND_CAR_OFFSET;
+    transform
+}
+
+/// Bring the second car in or take it out with F11
+fn toggle_split_screen(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    spawn: Res<SpawnPoint>,
+    vehicles: Res<Vehicles>,
+    selected: Res<SelectedVehicle>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    second_query: Query<Entity, Or<(With<SecondCar>, With<SecondCamera>, With<SecondLapDisplay>)>>,
+    mut camera_query: Query<&mut Camera, With<CarCamera>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F11) {
+        return;
+    }
+
+    if !second_query.is_empty() {
+        for entity in second_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        for mut camera in camera_query.iter_mut() {
+            camera.viewport = None;
+        }
+        info!("Split-screen OFF");
+        return;
+    }
+
+    let vehicle = vehicles.0.get(selected.0).cloned().unwrap_or_default();
+    let mut appearance = vehicle.appearance;
+    appearance.body_color = SECOND_CAR_COLOR;
+    let mut entity_commands =
+        commands.spawn((car_body(vehicle.car.clone(), second_spawn(&spawn)), SecondCar));
+    spawn_procedural_body(
+        &mut entity_commands,
+        &vehicle.car,
+        &appearance,
+        &mut meshes,
+        &mut materials,
+    );
+
+    let camera = commands
+        .spawn((
+            Camera3d::default(),
+            Camera {
+                // Render after the first player's half, which clears the window
+                order: 1,
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            Transform::from_translation(second_spawn(&spawn).translation + Vec3::Y * 5.0),
+            SecondCamera,
+        ))
+        .id();
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: 22.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        TextShadow::default(),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        },
+        UiTargetCamera(camera),
+        SecondLapDisplay,
+    ));
+    info!("Split-screen ON - the gamepad drives the second car, West respawns it");
+}
+
+/// Put the second car back at its last gate when its player asks or it falls
+/// through the world, and next to the spawn when the spawn moves
+fn respawn_second_car(
+    spawn: Res<SpawnPoint>,
+    checkpoints: Res<SceneCheckpoints>,
+    kill_plane: Res<KillPlane>,
+    ground: GroundSurface,
+    gamepads: Query<&Gamepad>,
+    mut car_query: Query<(&mut Car, &mut Transform, Option<&LapTimer>), With<SecondCar>>,
+) {
+    let Ok((mut car, mut transform, timer)) = car_query.single_mut() else {
+        return;
+    };
+    if spawn.is_changed() {
+        *transform = second_spawn(&spawn);
+        car.stop();
+        return;
+    }
+
+    let requested = gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::West));
+    let fell = ground.height_at(transform.translation) < -kill_plane.depth;
+    if !requested && !fell {
+        return;
+    }
+    let count = checkpoints.0.len();
+    let last_gate = timer
+        .filter(|timer| timer.started() && count > 0)
+        .map(|timer| checkpoints.0[(timer.next_gate + count - 1) % count].with_scale(Vec3::ONE));
+    *transform = last_gate.unwrap_or_else(|| second_spawn(&spawn));
+    car.stop();
+    info!("Second car respawned at {:?}", transform.translation);
+}
+
+/// Chase the second car like the first player's chase view
+fn follow_second_car(
+    time: Res<Time>,
+    car_query: Query<&Transform, (With<SecondCar>, Without<SecondCamera>)>,
+    mut camera_query: Query<&mut Transform, With<SecondCamera>>,
+) {
+    let (Ok(car_transform), Ok(mut camera_transform)) =
+        (car_query.single(), camera_query.single_mut())
+    else {
+        return;
+    };
+    let chase = CarCamera::default();
+    let target_position = car_transform.translation + car_transform.rotation * chase.offset;
+    camera_transform.translation = camera_transform
+        .translation
+        .lerp(target_position, (chase.smoothness * time.delta_secs()).min(1.0));
+    let look_target =
+        car_transform.translation + car_transform.rotation * Vec3::NEG_Z * chase.look_ahead;
+    camera_transform.look_at(look_target, Vec3::Y);
+}
+
+/// Give the first player the top half of the window and the second the bottom
+fn update_viewports(
+    windows: Query<&Window>,
+    mut first_query: Query<&mut Camera, (With<CarCamera>, Without<SecondCamera>)>,
+    mut second_query: Query<&mut Camera, With<SecondCamera>>,
+) {
+    let Ok(mut second) = second_query.single_mut() else {
+        return;
+    };
+    let (Ok(window), Ok(mut first)) = (windows.single(), first_query.single_mut()) else {
+        return;
+    };
+    let size = window.physical_size();
+    let half = UVec2::new(size.x, size.y / 2);
+    if half.y == 0 {
+        return;
+    }
+    let halves = [(first.reborrow(), UVec2::ZERO), (second.reborrow(), UVec2::new(0, half.y))];
+    for (mut camera, position) in halves {
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != Some((position, half)) {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: half,
+                ..default()
+            });
+        }
+    }
+}
+
+/// Show the second player's lap times in their half while there is a track
+fn update_second_lap_display(
+    checkpoints: Res<SceneCheckpoints>,
+    timer_query: Query<&LapTimer, With<SecondCar>>,
+    mut display_query: Query<&mut Text, With<SecondLapDisplay>>,
+) {
+    let Ok(mut text) = display_query.single_mut() else {
+        return;
+    };
+    match timer_query.single() {
+        Ok(timer) if !checkpoints.0.is_empty() => text.0 = lap_summary(timer),
+        _ => text.0.clear(),
+    }
+}
@@ -0,0 +1,300 @@
+//! Race positions and the final standings
+//!
+//! In the Race mode with more than one car on the track, whether the second
+//! split-screen player or other players over the network, every car is placed
+//! by how far it has got: laps first, then gates passed on the lap, then
+//! distance to its next gate. Cars that have finished `RACE_LAPS` laps come
+//! first, in the order they crossed the line. The first player's position is
+//! shown on the HUD, like "P2/3".
+//!
+//! Once the first player finishes, a standings screen lists every car with
+//! its gap to the winner, or the lap it is still on, and is kept up to date as
+//! the others come in. It goes away when the next race starts.
+
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+
+use crate::car::{CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::leaderboard::RACE_LAPS;
+use crate::network::Network;
+use crate::race_start::{RacePhase, RaceStart, RestartRace};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::split_screen::SecondCar;
+use crate::track::LapTimer;
+
+/// Plugin for race positions and the standings screen
+pub struct StandingsPlugin;
+
+impl Plugin for StandingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RaceStandings>()
+            .add_systems(Startup, spawn_standings_screen)
+            .add_systems(
+                Update,
+                (
+                    reset_standings,
+                    update_standings.run_if(resource_equals(GameMode::Race)),
+                    update_standings_screen,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// A car in the race
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Racer {
+    /// A car driven on this computer
+    Local(Entity),
+    /// Another player's car over the network, by player number
+    Remote(u8),
+}
+
+/// A car's place in the race
+#[derive(Debug, Clone)]
+pub struct Standing {
+    pub name: String,
+    /// Whether this is the first player's car
+    pub you: bool,
+    /// Lap being driven, 0 before the start line
+    pub lap: u32,
+    /// Race time at which the car finished, if it has
+    pub finish: Option<f32>,
+}
+
+/// Resource with the cars in race order
+#[derive(Resource, Default)]
+pub struct RaceStandings {
+    /// Every car, the leader first; empty unless more than one car races
+    pub order: Vec<Standing>,
+    /// Seconds since the race began, to time the finishes against each other
+    clock: f32,
+    /// Finish time of each car that has finished
+    finishes: Vec<(Racer, f32)>,
+}
+
+impl RaceStandings {
+    /// The first player's position counting from 1 and the number of cars
+    pub fn position(&self) -> Option<(usize, usize)> {
+        let index = self.order.iter().position(|standing| standing.you)?;
+        Some((index + 1, self.order.len()))
+    }
+
+    /// Whether the first player has finished the race
+    pub fn you_finished(&self) -> bool {
+        self.order
+            .iter()
+            .any(|standing| standing.you && standing.finish.is_some())
+    }
+}
+
+/// Marker for the standings screen
+#[derive(Component)]
+struct StandingsScreen;
+
+/// Marker for the text of the standings screen
+#[derive(Component)]
+struct StandingsText;
+
+/// How far a car has got: gates passed since the start, then how close it is
+/// to the next one
+fn progress(lap: u32, next_gate: usize, position: Vec3, gates: &[Transform]) -> (i64, f32) {
+    let distance = gates
+        .get(next_gate)
+        .map_or(0.0, |gate| gate.translation.distance(position));
+    if lap == 0 {
+        return (-1, -distance);
+    }
+    let count = gates.len().max(1);
+    // Gates after the start line passed on this lap
+    let passed = if next_gate == 0 {
+        count - 1
+    } else {
+        next_gate - 1
+    };
+    ((lap as i64 - 1) * count as i64 + passed as i64, -distance)
+}
+
+/// Start the standings over when a race begins or the track or mode changes
+fn reset_standings(
+    mode: Res<GameMode>,
+    race: Res<RaceStart>,
+    checkpoints: Res<SceneCheckpoints>,
+    mut restarts: MessageReader<RestartRace>,
+    mut standings: ResMut<RaceStandings>,
+) {
+    let restarted = restarts.read().count() > 0;
+    let countdown = race.is_changed() && matches!(race.phase, RacePhase::Countdown { .. });
+    if restarted || countdown || checkpoints.is_changed() || mode.is_changed() {
+        *standings = RaceStandings::default();
+    }
+}
+
+/// Place every car in the race and note the ones crossing the finish
+fn update_standings(
+    time: Res<Time>,
+    checkpoints: Res<SceneCheckpoints>,
+    network: Res<Network>,
+    mut standings: ResMut<RaceStandings>,
+    car_query: Query<(
+        Entity,
+        &Transform,
+        &LapTimer,
+        Has<PrimaryCar>,
+        Has<SecondCar>,
+    )>,
+) {
+    let gates = &checkpoints.0;
+    let mut racers = Vec::new();
+    for (entity, transform, timer, primary, second) in car_query.iter() {
+        let name = match (primary, network.player) {
+            (true, Some(player)) => format!("Player {} (you)", player + 1),
+            (true, None) => "Player 1".to_string(),
+            _ if second => "Player 2".to_string(),
+            _ => "Car".to_string(),
+        };
+        let progress = progress(timer.lap, timer.next_gate, transform.translation, gates);
+        let standing = Standing {
+            name,
+            you: primary,
+            lap: timer.lap,
+            finish: None,
+        };
+        racers.push((Racer::Local(entity), progress, standing));
+    }
+    for remote in network.remote_progress() {
+        let name = format!("Player {}", remote.player + 1);
+        let progress = progress(remote.lap, remote.next_gate, remote.translation, gates);
+        let standing = Standing {
+            name,
+            you: false,
+            lap: remote.lap,
+            finish: None,
+        };
+        racers.push((Racer::Remote(remote.player), progress, standing));
+    }
+    if gates.is_empty() || racers.len() < 2 {
+        standings.order.clear();
+        return;
+    }
+
+    if racers.iter().any(|(_, _, standing)| standing.lap > 0) {
+        standings.clock += time.delta_secs();
+    }
+    for (racer, _, standing) in &mut racers {
+        let finish = standings
+            .finishes
+            .iter()
+            .find(|(done, _)| done == racer)
+            .map(|&(_, time)| time);
+        standing.finish = match finish {
+            Some(time) => Some(time),
+            None if standing.lap > RACE_LAPS => {
+                let clock = standings.clock;
+                standings.finishes.push((*racer, clock));
+                info!(
+                    "{} finished in place {}",
+                    standing.name,
+                    standings.finishes.len()
+                );
+                Some(clock)
+            }
+            None => None,
+        };
+    }
+
+    // Finished cars first in the order they finished, then by progress
+    racers.sort_by(
+        |(_, a_progress, a), (_, b_progress, b)| match (a.finish, b.finish) {
+            (Some(a_finish), Some(b_finish)) => a_finish.total_cmp(&b_finish),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => b_progress
+                .0
+                .cmp(&a_progress.0)
+                .then(b_progress.1.total_cmp(&a_progress.1)),
+        },
+    );
+    standings.order = racers
+        .into_iter()
+        .map(|(_, _, standing)| standing)
+        .collect();
+}
+
+/// Spawn the hidden standings screen in the middle of the screen
+fn spawn_standings_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(25.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            StandingsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::all(Val::Px(16.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+                    BorderRadius::all(Val::Px(8.0)),
+                ))
+                .with_child((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    StandingsText,
+                ));
+        });
+}
+
+/// List every car with its gap to the winner once the first player finishes
+fn update_standings_screen(
+    mode: Res<GameMode>,
+    standings: Res<RaceStandings>,
+    mut screen_query: Query<&mut Visibility, With<StandingsScreen>>,
+    mut text_query: Query<&mut Text, With<StandingsText>>,
+) {
+    let Ok(mut visibility) = screen_query.single_mut() else {
+        return;
+    };
+    let shown = *mode == GameMode::Race && standings.you_finished();
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    let Ok(mut text) = text_query.single_mut() else {
+        return;
+    };
+    if !shown || !standings.is_changed() {
+        return;
+    }
+
+    let winner = standings.order.first();
+    let mut lines = vec!["RACE FINISHED".to_string(), String::new()];
+    for (index, standing) in standings.order.iter().enumerate() {
+        let gap = match (standing.finish, winner.and_then(|winner| winner.finish)) {
+            (Some(_), _) if index == 0 => "Winner".to_string(),
+            (Some(finish), Some(first)) => format!("+{:.2}", finish - first),
+            _ => format!("Lap {}/{}", standing.lap.clamp(1, RACE_LAPS), RACE_LAPS),
+        };
+        lines.push(format!("{}. {}  {}", index + 1, standing.name, gap));
+    }
+    let new_text = lines.join("\n");
+    if text.0 != new_text {
+        text.0 = new_text;
+    }
+}
@@ -0,0 +1,365 @@
+//! Stunt scoring
+//!
+//! In the Stunts game mode every jump of the first player's car scores: points
+//! for each second in the air, and more for the flips, barrel rolls and spins
+//! done on the way. The car's turning is added up about its own axes from the
+//! moment all four wheels leave the ground, and a turn counts once it is within
+//! `TURN_TOLERANCE` of complete, so a slightly short landing still scores.
+//! Landing on the wheels banks the jump with a popup naming the tricks; landing
+//! on the roof or side, hitting something in the air or respawning loses it.
+//!
+//! The jump in progress and the total are shown near the top of the screen.
+//! Restarting the race or changing the mode starts the score over.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::damage::CarImpact;
+use crate::game_mode::GameMode;
+use crate::race_start::RestartRace;
+use crate::spawn_point::RespawnCar;
+use crate::terrain::GroundSurface;
+
+/// Jumps shorter than this are bumps and don't score, in seconds
+const MIN_STUNT_AIRTIME: f32 = 0.5;
+
+/// Points per second in the air
+const AIR_POINTS: f32 = 100.0;
+
+/// Points for each flip or barrel roll, and each half turn of spin
+const FLIP_POINTS: f32 = 500.0;
+const SPIN_POINTS: f32 = 200.0;
+
+/// How far short of a whole flip, or a half spin, still counts, in radians
+const TURN_TOLERANCE: f32 = 0.8;
+
+/// The car has landed on its wheels while its up axis is this close to the ground's
+const MIN_LANDING_UPRIGHT: f32 = 0.7;
+
+/// Seconds a popup stays up
+const POPUP_DURATION: f32 = 2.5;
+
+/// Plugin for stunt scoring
+pub struct StuntPlugin;
+
+impl Plugin for StuntPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StuntScore>()
+            .add_systems(Startup, spawn_stunt_panel)
+            .add_systems(
+                Update,
+                (
+                    reset_stunt_score,
+                    score_stunts.run_if(resource_equals(GameMode::Stunt)),
+                    update_stunt_panel,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// A jump in progress
+#[derive(Debug, Clone)]
+struct Jump {
+    /// Seconds in the air so far
+    airtime: f32,
+    /// Radians turned about the car's own X, Y and Z axes, i.e. pitch, yaw and
+    /// roll, signed so turning back and forth cancels out
+    turned: Vec3,
+    /// Rotation of the car last frame
+    last_rotation: Quat,
+}
+
+impl Jump {
+    /// Whole backflips, negative for front flips
+    fn flips(&self) -> i32 {
+        whole_turns(self.turned.x, TAU)
+    }
+
+    /// Half turns of spin, either way
+    fn half_spins(&self) -> i32 {
+        whole_turns(self.turned.y, TAU * 0.5).abs()
+    }
+
+    /// Whole barrel rolls, either way
+    fn rolls(&self) -> i32 {
+        whole_turns(self.turned.z, TAU).abs()
+    }
+
+    fn score(&self) -> f32 {
+        let tricks = (self.flips().abs() + self.rolls()) as f32 * FLIP_POINTS
+            + self.half_spins() as f32 * SPIN_POINTS;
+        self.airtime * AIR_POINTS + tricks
+    }
+
+    /// Names of the tricks done so far, like "Backflip" or "2x Barrel roll"
+    fn tricks(&self) -> Vec<String> {
+        let named = |count: i32, name: &str| match count {
+            0 => None,
+            1 => Some(name.to_string()),
+            _ => Some(format!("{}x {}", count, name)),
+        };
+        let flip = if self.flips() > 0 {
+            "Backflip"
+        } else {
+            "Front flip"
+        };
+        let spin = (self.half_spins() > 0).then(|| format!("{} spin", self.half_spins() * 180));
+        [
+            named(self.flips().abs(), flip),
+            named(self.rolls(), "Barrel roll"),
+            spin,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Turns of `turn` radians in an angle, counting one that is nearly complete
+fn whole_turns(angle: f32, turn: f32) -> i32 {
+    ((angle.abs() + TURN_TOLERANCE) / turn).floor() as i32 * angle.signum() as i32
+}
+
+/// Resource with the stunt score
+#[derive(Resource, Default)]
+pub struct StuntScore {
+    /// Points banked from landed jumps
+    pub total: f32,
+    /// Best single jump
+    pub best_jump: f32,
+    /// The jump in the air, if any
+    jump: Option<Jump>,
+    /// Whether the jump in the air has been lost, so the rest of it doesn't score
+    lost_in_air: bool,
+    /// Text and color of the popup and the seconds it stays up
+    popup: Option<(String, Color, f32)>,
+}
+
+impl StuntScore {
+    fn bank_jump(&mut self) {
+        let Some(jump) = self.jump.take() else {
+            return;
+        };
+        if jump.airtime < MIN_STUNT_AIRTIME {
+            return;
+        }
+        let score = jump.score();
+        self.total += score;
+        self.best_jump = self.best_jump.max(score);
+        let tricks = jump.tricks();
+        info!(
+            "Stunt {:.1} s{}{}: {:.0} points, {:.0} total",
+            jump.airtime,
+            if tricks.is_empty() { "" } else { " " },
+            tricks.join(", "),
+            score,
+            self.total
+        );
+        let mut lines = vec![format!("{:.1} s air", jump.airtime)];
+        lines.extend(tricks);
+        let text = format!("{}\n+{:.0}", lines.join("  "), score);
+        self.popup = Some((text, Color::srgb(0.3, 1.0, 0.4), POPUP_DURATION));
+    }
+
+    fn lose_jump(&mut self, reason: &str) {
+        let Some(jump) = self.jump.take() else {
+            return;
+        };
+        if jump.airtime >= MIN_STUNT_AIRTIME {
+            info!("Stunt lost: {}", reason);
+            let text = format!("Stunt lost - {}", reason);
+            self.popup = Some((text, Color::srgb(1.0, 0.3, 0.2), POPUP_DURATION));
+        }
+    }
+}
+
+/// Marker for the stunt panel
+#[derive(Component)]
+struct StuntPanel;
+
+/// Marker for the text of the jump in progress
+#[derive(Component)]
+struct StuntJumpText;
+
+/// Marker for the popup text
+#[derive(Component)]
+struct StuntPopupText;
+
+/// Marker for the total score text
+#[derive(Component)]
+struct StuntTotalText;
+
+/// Start the score over when the race restarts or the mode changes
+fn reset_stunt_score(
+    mode: Res<GameMode>,
+    mut restarts: MessageReader<RestartRace>,
+    mut score: ResMut<StuntScore>,
+) {
+    let restarted = restarts.read().count() > 0;
+    if restarted || (mode.is_changed() && !mode.is_added()) {
+        *score = StuntScore::default();
+    }
+}
+
+/// Follow the first player's car through the air and bank or lose its jumps
+fn score_stunts(
+    time: Res<Time>,
+    ground: GroundSurface,
+    mut impacts: MessageReader<CarImpact>,
+    mut respawns: MessageReader<RespawnCar>,
+    mut score: ResMut<StuntScore>,
+    car_query: Query<(&Car, &Transform), With<PrimaryCar>>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    if let Some((_, _, seconds)) = &mut score.popup {
+        *seconds -= dt;
+        if *seconds <= 0.0 {
+            score.popup = None;
+        }
+    }
+    let respawned = respawns.read().count() > 0;
+    let impacted = impacts.read().count() > 0;
+    let Ok((car, transform)) = car_query.single() else {
+        return;
+    };
+    if respawned && score.jump.is_some() {
+        score.lose_jump("respawned");
+        score.lost_in_air = true;
+    }
+
+    if car.airtime > 0.0 {
+        // Hitting something on the way loses the jump; landing hard doesn't
+        if impacted {
+            score.lose_jump("crashed");
+            score.lost_in_air = true;
+        }
+        if score.lost_in_air {
+            return;
+        }
+        let jump = score.jump.get_or_insert(Jump {
+            airtime: 0.0,
+            turned: Vec3::ZERO,
+            last_rotation: transform.rotation,
+        });
+        // The turn since last frame, about the car's own axes, taking the short
+        // way round
+        let delta = jump.last_rotation.inverse() * transform.rotation;
+        let delta = if delta.w < 0.0 { -delta } else { delta };
+        jump.turned += delta.to_scaled_axis();
+        jump.last_rotation = transform.rotation;
+        jump.airtime = car.airtime;
+    } else if score.lost_in_air {
+        score.lost_in_air = false;
+    } else if score.jump.is_some() {
+        let upright = transform.up().dot(ground.normal_at(transform.translation));
+        if upright >= MIN_LANDING_UPRIGHT {
+            score.bank_jump();
+        } else {
+            score.lose_jump("crash landing");
+        }
+    }
+}
+
+/// Spawn the hidden stunt panel near the top of the screen
+fn spawn_stunt_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(20.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            StuntPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((stunt_text(30.0, Color::srgb(0.3, 0.8, 1.0)), StuntJumpText));
+            parent.spawn((stunt_text(24.0, Color::WHITE), StuntPopupText));
+            parent.spawn((stunt_text(18.0, Color::WHITE), StuntTotalText));
+        });
+}
+
+/// Centered text of the given size and color with a shadow
+fn stunt_text(font_size: f32, color: Color) -> impl Bundle {
+    (
+        Text::new(""),
+        TextFont {
+            font_size,
+            ..default()
+        },
+        TextColor(color),
+        TextShadow::default(),
+        TextLayout::new_with_justify(Justify::Center),
+    )
+}
+
+/// Show the jump in the air, the popup and the total in the Stunts mode
+fn update_stunt_panel(
+    mode: Res<GameMode>,
+    score: Res<StuntScore>,
+    mut panel_query: Query<&mut Visibility, With<StuntPanel>>,
+    mut jump_query: Query<&mut Text, With<StuntJumpText>>,
+    mut popup_query: Query<
+        (&mut Text, &mut TextColor),
+        (With<StuntPopupText>, Without<StuntJumpText>),
+    >,
+    mut total_query: Query<
+        &mut Text,
+        (
+            With<StuntTotalText>,
+            Without<StuntJumpText>,
+            Without<StuntPopupText>,
+        ),
+    >,
+) {
+    let Ok(mut visibility) = panel_query.single_mut() else {
+        return;
+    };
+    let shown = *mode == GameMode::Stunt;
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if !shown || (!score.is_changed() && !mode.is_changed()) {
+        return;
+    }
+
+    if let Ok(mut text) = jump_query.single_mut() {
+        text.0 = match &score.jump {
+            Some(jump) if jump.airtime >= MIN_STUNT_AIRTIME => {
+                let mut lines = vec![format!("AIR {:.1} s", jump.airtime)];
+                lines.extend(jump.tricks());
+                lines.join("  ")
+            }
+            _ => String::new(),
+        };
+    }
+    if let Ok((mut text, mut color)) = popup_query.single_mut() {
+        match &score.popup {
+            Some((popup, popup_color, seconds)) => {
+                text.0 = popup.clone();
+                // Fading out over the last half second
+                color.0 = popup_color.with_alpha((seconds * 2.0).min(1.0));
+            }
+            None => text.0 = String::new(),
+        }
+    }
+    if let Ok(mut text) = total_query.single_mut() {
+        text.0 = format!(
+            "Stunt score {:.0}   Best jump {:.0}",
+            score.total, score.best_jump
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Surface-type zones on the ground and their editor
+//!
+//! The scanned road is asphalt, but the verge next to it may be gravel, grass
+//! or ice. Level authors mark such regions with polygons on the ground; the
+//! car takes the tire grip and rolling resistance of the zone it is in, and of
+//! asphalt everywhere else. Where zones overlap the one drawn last wins.
+//!
+//! Press 'G' to draw a zone: 1-4 pick asphalt, dirt/gravel, grass or ice,
+//! clicks add corners, Ctrl+Z removes the last one and 'G' again closes the
+//! polygon. Backspace in the editor removes the zone under the cursor. Zones
+//! are saved with the splat sidecar and scene manifest.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boundary::polygon_contains;
+use crate::camera::CarCamera;
+use crate::car::{Car, CarSet, Surface};
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::point_cloud::SplatPointCloud;
+
+/// Plugin for surface zones and their editor
+pub struct SurfaceZonePlugin;
+
+impl Plugin for SurfaceZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SurfaceZones>()
+            .init_resource::<SurfaceZoneEditor>()
+            .add_systems(Update, (edit_surface_zones, draw_surface_zones).chain())
+            .add_systems(
+                Update,
+                update_car_surface.after(CarSet::Input).before(CarSet::Physics),
+            );
+    }
+}
+
+/// A region of the ground with its own surface type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurfaceZone {
+    pub surface: Surface,
+    /// Corners of the polygon, tested in the horizontal (XZ) plane
+    pub points: Vec<Vec3>,
+}
+
+/// Resource with all surface zones of the level
+#[derive(Resource, Default)]
+pub struct SurfaceZones(pub Vec<SurfaceZone>);
+
+impl SurfaceZones {
+    /// Index of the topmost zone containing a point
+    fn index_at(&self, point: Vec3) -> Option<usize> {
+        self.0
+            .iter()
+            .rposition(|zone| zone.points.len() >= 3 && polygon_contains(&zone.points, point))
+    }
+
+    /// Surface at a point, asphalt outside every zone
+    pub fn surface_at(&self, point: Vec3) -> Surface {
+        self.index_at(point).map_or(Surface::Asphalt, |index| self.0[index].surface)
+    }
+}
+
+/// Resource tracking the surface zone editor
+#[derive(Resource, Default)]
+pub struct SurfaceZoneEditor {
+    /// Whether the editor is active
+    pub active: bool,
+    /// Surface of the zone being drawn
+    surface: Surface,
+    /// Corners of the zone being drawn
+    points: Vec<Vec3>,
+}
+
+/// Toggle the editor with 'G', pick the surface with 1-4, add corners by
+/// clicking, undo with Ctrl+Z and remove zones with Backspace
+fn edit_surface_zones(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut editor: ResMut<SurfaceZoneEditor>,
+    mut zones: ResMut<SurfaceZones>,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyG) {
+        if !editor.active {
+            editor.active = true;
+            editor.points.clear();
+            info!(
+                "Surface zone editor ACTIVE - drawing {:?}; 1-4: asphalt/dirt/grass/ice, click: add corner, G: finish",
+                editor.surface
+            );
+            info!("Backspace removes the zone under the cursor");
+        } else {
+            editor.active = false;
+            if editor.points.len() >= 3 {
+                let zone = SurfaceZone {
+                    surface: editor.surface,
+                    points: std::mem::take(&mut editor.points),
+                };
+                info!("Added {:?} zone with {} corners", zone.surface, zone.points.len());
+                info!("Press Ctrl+S to save it with this splat");
+                zones.0.push(zone);
+            } else {
+                editor.points.clear();
+                info!("Surface zone editor INACTIVE - at least 3 corners are needed");
+            }
+        }
+    }
+
+    if !editor.active {
+        return;
+    }
+
+    for (key, surface) in [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4]
+        .into_iter()
+        .zip(Surface::ALL)
+    {
+        if keyboard.just_pressed(key) {
+            editor.surface = surface;
+            info!("Drawing a {:?} zone", surface);
+        }
+    }
+
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    if ctrl && keyboard.just_pressed(KeyCode::KeyZ) {
+        editor.points.pop();
+    }
+
+    let cursor_point = cursor_ray(&camera_query, &windows)
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes));
+    let Some(point) = cursor_point else {
+        return;
+    };
+    if mouse_button.just_pressed(MouseButton::Left) {
+        editor.points.push(point);
+    }
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        if let Some(index) = zones.index_at(point) {
+            let zone = zones.0.remove(index);
+            info!("Removed {:?} zone", zone.surface);
+        }
+    }
+}
+
+/// Gizmo color of a surface
+fn surface_color(surface: Surface) -> Color {
+    match surface {
+        Surface::Asphalt => Color::srgb(0.5, 0.5, 0.55),
+        Surface::Dirt => Color::srgb(0.7, 0.5, 0.25),
+        Surface::Grass => Color::srgb(0.2, 0.8, 0.2),
+        Surface::Ice => Color::srgb(0.6, 0.9, 1.0),
+    }
+}
+
+/// Draw the zones while the editor is active, and the zone being drawn
+fn draw_surface_zones(mut gizmos: Gizmos, editor: Res<SurfaceZoneEditor>, zones: Res<SurfaceZones>) {
+    if !editor.active {
+        return;
+    }
+    for zone in &zones.0 {
+        let outline = zone.points.iter().chain(zone.points.first()).copied();
+        gizmos.linestrip(outline, surface_color(zone.surface));
+    }
+    if !editor.points.is_empty() {
+        let color = surface_color(editor.surface);
+        gizmos.linestrip(editor.points.iter().copied(), color);
+        for &point in &editor.points {
+            gizmos.sphere(Isometry3d::from_translation(point), 0.2, color);
+        }
+    }
+}
+
+/// Give each car the surface of the zone it is in
+fn update_car_surface(zones: Res<SurfaceZones>, mut car_query: Query<(&mut Car, &Transform)>) {
+    for (mut car, transform) in car_query.iter_mut() {
+        let surface = zones.surface_at(transform.translation);
+        if car.surface != surface {
+            car.surface = surface;
+            debug!("Driving on {:?}", surface);
+        }
+    }
+}
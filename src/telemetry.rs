@@ -0,0 +1,299 @@
+//! Telemetry recording
+//!
+//! Shift+F10 starts and stops recording the first player's car to a CSV file
+//! in the working directory, one row per physics step, for analysing laps in
+//! a spreadsheet or a sim-racing telemetry tool. `telemetry start json` in
+//! the console records to a JSON array instead, and `telemetry stop` stops.
+//!
+//! Each sample holds:
+//!
+//! | Field                   | Contents                                     |
+//! |-------------------------|----------------------------------------------|
+//! | `time`                  | seconds since the recording started          |
+//! | `lap`                   | lap being driven, 0 before the start line    |
+//! | `sector`                | index of the gate being driven toward        |
+//! | `lap_time`              | seconds into the lap                         |
+//! | `x`, `y`, `z`           | position in meters                           |
+//! | `heading`               | degrees from north (-Z) toward east (+X)     |
+//! | `speed`                 | forward speed in m/s, negative in reverse    |
+//! | `throttle`, `brake`     | pedals from 0 to 1                           |
+//! | `steering`              | wheel angle in radians, positive to the left |
+//! | `gear`, `rpm`           | gear, -1 being reverse, and engine speed     |
+//! | `slip_fl` ... `slip_rr` | tire slip of each wheel                      |
+//! | `surface`               | kind of ground under the car                 |
+//!
+//! Samples are written as they are taken, so a recording survives the game
+//! being closed in the middle of it.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::car::{Car, CarSet, PrimaryCar, Surface};
+use crate::console::ConsoleCommandsExt;
+use crate::track::LapTimer;
+
+/// Columns of a CSV recording, in the order of `TelemetrySample`'s fields
+const CSV_HEADER: &str = "time,lap,sector,lap_time,x,y,z,heading,speed,throttle,brake,steering,\
+                          gear,rpm,slip_fl,slip_fr,slip_rl,slip_rr,surface";
+
+/// Plugin for recording telemetry
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TelemetryRecorder>()
+            .add_console_command(
+                "telemetry",
+                "telemetry [start [csv|json]|stop]",
+                "Record the car's telemetry to a file",
+                telemetry_command,
+            )
+            .add_systems(Update, toggle_recording)
+            .add_systems(FixedUpdate, record_telemetry.after(CarSet::Constraints));
+    }
+}
+
+/// File format of a recording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TelemetryFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl TelemetryFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TelemetryFormat::Csv => "csv",
+            TelemetryFormat::Json => "json",
+        }
+    }
+}
+
+/// One physics step of the car
+#[derive(Serialize)]
+struct TelemetrySample {
+    time: f32,
+    lap: u32,
+    sector: usize,
+    lap_time: f32,
+    x: f32,
+    y: f32,
+    z: f32,
+    heading: f32,
+    speed: f32,
+    throttle: f32,
+    brake: f32,
+    steering: f32,
+    gear: i32,
+    rpm: f32,
+    slip_fl: f32,
+    slip_fr: f32,
+    slip_rl: f32,
+    slip_rr: f32,
+    surface: Surface,
+}
+
+impl TelemetrySample {
+    /// The sample as a CSV row
+    fn csv_row(&self) -> String {
+        format!(
+            "{:.4},{},{},{:.3},{:.3},{:.3},{:.3},{:.1},{:.3},{:.3},{:.3},{:.4},{},{:.0},\
+             {:.4},{:.4},{:.4},{:.4},{:?}",
+            self.time,
+            self.lap,
+            self.sector,
+            self.lap_time,
+            self.x,
+            self.y,
+            self.z,
+            self.heading,
+            self.speed,
+            self.throttle,
+            self.brake,
+            self.steering,
+            self.gear,
+            self.rpm,
+            self.slip_fl,
+            self.slip_fr,
+            self.slip_rl,
+            self.slip_rr,
+            self.surface,
+        )
+    }
+}
+
+/// A recording being written
+struct Recording {
+    path: String,
+    format: TelemetryFormat,
+    writer: BufWriter<File>,
+    /// Seconds since the recording started
+    time: f32,
+    samples: usize,
+}
+
+impl Recording {
+    /// Create the file, named after the current time
+    fn start(format: TelemetryFormat) -> std::io::Result<Self> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = format!("gaussrace-{}.{}", seconds, format.extension());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        match format {
+            TelemetryFormat::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+            TelemetryFormat::Json => write!(writer, "[")?,
+        }
+        Ok(Self {
+            path,
+            format,
+            writer,
+            time: 0.0,
+            samples: 0,
+        })
+    }
+
+    fn write(&mut self, sample: &TelemetrySample) -> std::io::Result<()> {
+        match self.format {
+            TelemetryFormat::Csv => writeln!(self.writer, "{}", sample.csv_row())?,
+            TelemetryFormat::Json => {
+                let separator = if self.samples == 0 { "\n" } else { ",\n" };
+                self.writer.write_all(separator.as_bytes())?;
+                serde_json::to_writer(&mut self.writer, sample)?;
+            }
+        }
+        self.samples += 1;
+        Ok(())
+    }
+}
+
+impl Drop for Recording {
+    /// Close the JSON array and flush the file, also when the game quits
+    fn drop(&mut self) {
+        let result = match self.format {
+            TelemetryFormat::Csv => Ok(()),
+            TelemetryFormat::Json => self.writer.write_all(b"\n]\n"),
+        }
+        .and_then(|()| self.writer.flush());
+        match result {
+            Ok(()) => info!(
+                "Saved {} telemetry samples ({:.1} s) to {}",
+                self.samples, self.time, self.path
+            ),
+            Err(err) => error!("Failed to finish telemetry {}: {}", self.path, err),
+        }
+    }
+}
+
+/// Resource with the recording in progress, if any
+#[derive(Resource, Default)]
+pub struct TelemetryRecorder {
+    recording: Option<Recording>,
+}
+
+impl TelemetryRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Start a new recording, finishing any running one
+    pub fn start(&mut self, format: TelemetryFormat) -> Result<&str, String> {
+        self.stop();
+        let recording = Recording::start(format).map_err(|err| err.to_string())?;
+        info!("Recording telemetry to {}", recording.path);
+        Ok(self.recording.insert(recording).path.as_str())
+    }
+
+    /// Finish the running recording
+    pub fn stop(&mut self) {
+        self.recording = None;
+    }
+}
+
+/// Start and stop recording with Shift+F10
+fn toggle_recording(keyboard: Res<ButtonInput<KeyCode>>, mut recorder: ResMut<TelemetryRecorder>) {
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !shift || !keyboard.just_pressed(KeyCode::F10) {
+        return;
+    }
+    if recorder.is_recording() {
+        recorder.stop();
+    } else if let Err(err) = recorder.start(TelemetryFormat::Csv) {
+        error!("Failed to start recording telemetry: {}", err);
+    }
+}
+
+/// Write a sample of the car for this physics step
+fn record_telemetry(
+    time: Res<Time>,
+    mut recorder: ResMut<TelemetryRecorder>,
+    car_query: Query<(&Car, &Transform, Option<&LapTimer>), With<PrimaryCar>>,
+) {
+    let Some(recording) = recorder.bypass_change_detection().recording.as_mut() else {
+        return;
+    };
+    let Ok((car, transform, timer)) = car_query.single() else {
+        return;
+    };
+    recording.time += time.delta_secs();
+
+    let forward = transform.forward();
+    let sample = TelemetrySample {
+        time: recording.time,
+        lap: timer.map_or(0, |timer| timer.lap),
+        sector: timer.map_or(0, |timer| timer.next_gate),
+        lap_time: timer.map_or(0.0, |timer| timer.lap_time),
+        x: transform.translation.x,
+        y: transform.translation.y,
+        z: transform.translation.z,
+        heading: forward.x.atan2(-forward.z).to_degrees().rem_euclid(360.0),
+        speed: car.velocity,
+        throttle: car.throttle,
+        brake: car.brake,
+        steering: car.steering,
+        gear: car.gear,
+        rpm: car.rpm,
+        slip_fl: car.wheel_slip[0],
+        slip_fr: car.wheel_slip[1],
+        slip_rl: car.wheel_slip[2],
+        slip_rr: car.wheel_slip[3],
+        surface: car.surface,
+    };
+    if let Err(err) = recording.write(&sample) {
+        error!("Failed to write telemetry {}: {}", recording.path, err);
+        recorder.stop();
+    }
+}
+
+/// Start or stop recording, or tell whether a recording is running
+fn telemetry_command(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let mut recorder = world.resource_mut::<TelemetryRecorder>();
+    let format = match arguments {
+        [] => {
+            return Ok(match &recorder.recording {
+                Some(recording) => format!(
+                    "Recording to {}, {} samples so far",
+                    recording.path, recording.samples
+                ),
+                None => "Not recording".to_string(),
+            })
+        }
+        ["stop"] => {
+            if !recorder.is_recording() {
+                return Err("not recording".to_string());
+            }
+            recorder.stop();
+            return Ok("Telemetry saved".to_string());
+        }
+        ["start"] | ["start", "csv"] => TelemetryFormat::Csv,
+        ["start", "json"] => TelemetryFormat::Json,
+        _ => return Err("usage: telemetry [start [csv|json]|stop]".to_string()),
+    };
+    let path = recorder.start(format)?;
+    Ok(format!("Recording to {}", path))
+}
@@ -0,0 +1,331 @@
+//! Live telemetry graphs
+//!
+//! ' (the quote key) shows graphs of the first player's speed, pedals and
+//! steering over the last seconds of driving, scrolling to the left as the car
+//! goes, for learning where to brake on a scanned road. While there is a ghost
+//! lap its speed at the same place on the track is drawn next to the car's, so
+//! a corner where the ghost brakes later or carries more speed stands out. The
+//! length of the graphs and the ghost line can be changed in the overlay.
+
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
+
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::ghost::GhostLaps;
+use crate::hud::{KMH, MPH};
+use crate::settings::Settings;
+use crate::track::LapTimer;
+
+/// Seconds of driving shown at first, and at most
+const DEFAULT_SECONDS: f32 = 10.0;
+const MAX_SECONDS: f32 = 60.0;
+
+/// Size of each graph in points
+const GRAPH_WIDTH: f32 = 320.0;
+const GRAPH_HEIGHT: f32 = 56.0;
+
+/// Lowest top of the speed graph, in m/s
+const MIN_SPEED_SCALE: f32 = 10.0;
+
+/// Seconds of the ghost's lap searched ahead of the last match for the
+/// sample nearest to the car
+const GHOST_SEARCH_SECONDS: f32 = 5.0;
+
+/// Distance from the ghost's line beyond which the car isn't compared to it,
+/// in meters
+const GHOST_MAX_DISTANCE: f32 = 15.0;
+
+const SPEED_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 230, 230);
+const GHOST_COLOR: egui::Color32 = egui::Color32::from_rgb(153, 204, 255);
+const THROTTLE_COLOR: egui::Color32 = egui::Color32::from_rgb(77, 230, 77);
+const BRAKE_COLOR: egui::Color32 = egui::Color32::from_rgb(242, 77, 64);
+const STEERING_COLOR: egui::Color32 = egui::Color32::from_rgb(242, 204, 51);
+
+/// Plugin for the telemetry graphs
+pub struct TelemetryGraphsPlugin;
+
+impl Plugin for TelemetryGraphsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin::default());
+        }
+        app.init_resource::<TelemetryGraphs>()
+            .add_systems(Update, toggle_graphs)
+            .add_systems(FixedUpdate, sample_graphs.after(CarSet::Constraints))
+            .add_systems(
+                EguiPrimaryContextPass,
+                show_graphs.run_if(|graphs: Res<TelemetryGraphs>| graphs.open),
+            );
+    }
+}
+
+/// One physics step of the car
+struct GraphSample {
+    /// Seconds of driving since the game started
+    time: f32,
+    /// Speed in m/s
+    speed: f32,
+    throttle: f32,
+    brake: f32,
+    /// Steering as a fraction of the full lock, positive to the left
+    steering: f32,
+    /// The ghost's speed where it passed the car's position, in m/s
+    ghost_speed: Option<f32>,
+}
+
+/// Color of a line in a graph and the value it plots, if the sample has one
+type GraphLine = (egui::Color32, fn(&GraphSample) -> Option<f32>);
+
+/// Resource with the graphs' history and options
+#[derive(Resource)]
+struct TelemetryGraphs {
+    open: bool,
+    /// Seconds of driving shown
+    seconds: f32,
+    show_ghost: bool,
+    /// The last `MAX_SECONDS` of samples, oldest first
+    samples: VecDeque<GraphSample>,
+    time: f32,
+    /// Ghost sample last matched to the car, and the lap it was matched on
+    ghost_index: usize,
+    ghost_lap: u32,
+}
+
+impl Default for TelemetryGraphs {
+    fn default() -> Self {
+        Self {
+            open: false,
+            seconds: DEFAULT_SECONDS,
+            show_ghost: true,
+            samples: VecDeque::new(),
+            time: 0.0,
+            ghost_index: 0,
+            ghost_lap: 0,
+        }
+    }
+}
+
+/// Show and hide the graphs with '
+fn toggle_graphs(keyboard: Res<ButtonInput<KeyCode>>, mut graphs: ResMut<TelemetryGraphs>) {
+    if keyboard.just_pressed(KeyCode::Quote) {
+        graphs.open = !graphs.open;
+    }
+}
+
+/// Add a sample of the car for this physics step, shown or not, so the
+/// graphs have a history when they are opened
+fn sample_graphs(
+    time: Res<Time>,
+    laps: Res<GhostLaps>,
+    mut graphs: ResMut<TelemetryGraphs>,
+    car_query: Query<(&Car, &Transform, Option<&LapTimer>), With<PrimaryCar>>,
+) {
+    let Ok((car, transform, timer)) = car_query.single() else {
+        return;
+    };
+    graphs.time += time.delta_secs();
+
+    // The ghost is followed from its start on every new lap
+    let lap = timer
+        .filter(|timer| timer.started())
+        .map_or(0, |timer| timer.lap);
+    if lap != graphs.ghost_lap {
+        graphs.ghost_lap = lap;
+        graphs.ghost_index = 0;
+    }
+    let position = transform.translation;
+    let ghost_speed = laps.ghost.as_ref().filter(|_| lap > 0).and_then(|ghost| {
+        let index = ghost.nearest_sample(position, graphs.ghost_index, GHOST_SEARCH_SECONDS)?;
+        if ghost.samples[index].0.distance(position) > GHOST_MAX_DISTANCE {
+            return None;
+        }
+        graphs.ghost_index = index;
+        ghost.speed_at_sample(index)
+    });
+
+    let sample = GraphSample {
+        time: graphs.time,
+        speed: car.velocity.abs(),
+        throttle: car.throttle,
+        brake: car.brake,
+        steering: if car.max_steering > 0.0 {
+            car.steering / car.max_steering
+        } else {
+            0.0
+        },
+        ghost_speed,
+    };
+    graphs.samples.push_back(sample);
+    let oldest = graphs.time - MAX_SECONDS;
+    while graphs
+        .samples
+        .front()
+        .is_some_and(|sample| sample.time < oldest)
+    {
+        graphs.samples.pop_front();
+    }
+}
+
+/// Draw the graphs in a window at the bottom left
+fn show_graphs(
+    mut contexts: EguiContexts,
+    mut graphs: ResMut<TelemetryGraphs>,
+    settings: Res<Settings>,
+) -> Result {
+    let graphs = graphs.as_mut();
+    let (unit, unit_label) = if settings.imperial {
+        (MPH, "mph")
+    } else {
+        (KMH, "km/h")
+    };
+    let end = graphs.time;
+    let start = end - graphs.seconds;
+    let shown: Vec<&GraphSample> = graphs
+        .samples
+        .iter()
+        .filter(|sample| sample.time >= start)
+        .collect();
+    let show_ghost = graphs.show_ghost;
+    // The speed graph tops out at a round number of the shown unit
+    let fastest = shown
+        .iter()
+        .flat_map(|sample| {
+            [
+                Some(sample.speed),
+                sample.ghost_speed.filter(|_| show_ghost),
+            ]
+        })
+        .flatten()
+        .fold(MIN_SPEED_SCALE, f32::max);
+    let top_speed = (fastest / unit / 20.0).ceil() * 20.0 * unit;
+
+    let mut open = graphs.open;
+    egui::Window::new("Telemetry")
+        .open(&mut open)
+        .anchor(egui::Align2::LEFT_BOTTOM, [12.0, -12.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut graphs.seconds, 2.0..=MAX_SECONDS).text("s"));
+                ui.checkbox(&mut graphs.show_ghost, "Ghost");
+            });
+            let latest = shown.last();
+
+            ui.horizontal(|ui| {
+                let speed = latest.map_or(0.0, |sample| sample.speed);
+                ui.colored_label(
+                    SPEED_COLOR,
+                    format!("Speed {:.0} {}", speed / unit, unit_label),
+                );
+                let ghost_speed = latest
+                    .and_then(|sample| sample.ghost_speed)
+                    .filter(|_| show_ghost);
+                if let Some(ghost_speed) = ghost_speed {
+                    ui.colored_label(
+                        GHOST_COLOR,
+                        format!("Ghost {:.0} {}", ghost_speed / unit, unit_label),
+                    );
+                }
+            });
+            // The car's line is drawn over the ghost's
+            let mut speed_lines: Vec<GraphLine> = Vec::new();
+            if show_ghost {
+                speed_lines.push((GHOST_COLOR, |sample| sample.ghost_speed));
+            }
+            speed_lines.push((SPEED_COLOR, |sample| Some(sample.speed)));
+            graph(ui, &shown, start..=end, 0.0..=top_speed, &speed_lines);
+
+            ui.horizontal(|ui| {
+                let (throttle, brake) =
+                    latest.map_or((0.0, 0.0), |sample| (sample.throttle, sample.brake));
+                ui.colored_label(THROTTLE_COLOR, format!("Throttle {:.0}%", throttle * 100.0));
+                ui.colored_label(BRAKE_COLOR, format!("Brake {:.0}%", brake * 100.0));
+            });
+            graph(
+                ui,
+                &shown,
+                start..=end,
+                0.0..=1.0,
+                &[
+                    (THROTTLE_COLOR, |sample| Some(sample.throttle)),
+                    (BRAKE_COLOR, |sample| Some(sample.brake)),
+                ],
+            );
+
+            let steering = latest.map_or(0.0, |sample| sample.steering);
+            let side = if steering > 0.0 {
+                "left"
+            } else if steering < 0.0 {
+                "right"
+            } else {
+                ""
+            };
+            ui.colored_label(
+                STEERING_COLOR,
+                format!("Steering {:.0}% {}", steering.abs() * 100.0, side),
+            );
+            graph(
+                ui,
+                &shown,
+                start..=end,
+                -1.0..=1.0,
+                &[(STEERING_COLOR, |sample| Some(sample.steering))],
+            );
+        });
+    graphs.open = open;
+    Ok(())
+}
+
+/// Plot lines of values against time, with a line broken where it has no
+/// value, and a center line if the values go below zero
+fn graph(
+    ui: &mut egui::Ui,
+    samples: &[&GraphSample],
+    times: RangeInclusive<f32>,
+    values: RangeInclusive<f32>,
+    lines: &[GraphLine],
+) {
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(GRAPH_WIDTH, GRAPH_HEIGHT), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(160));
+
+    let to_screen = |time: f32, value: f32| {
+        let x = (time - times.start()) / (times.end() - times.start());
+        let y = ((value - values.start()) / (values.end() - values.start())).clamp(0.0, 1.0);
+        egui::pos2(
+            rect.left() + x * rect.width(),
+            rect.bottom() - y * rect.height(),
+        )
+    };
+    if *values.start() < 0.0 {
+        painter.hline(
+            rect.x_range(),
+            to_screen(0.0, 0.0).y,
+            egui::Stroke::new(1.0_f32, egui::Color32::from_gray(90)),
+        );
+    }
+
+    for &(color, value) in lines {
+        let stroke = egui::Stroke::new(1.5_f32, color);
+        let mut points = Vec::new();
+        for sample in samples {
+            match value(sample) {
+                Some(value) => points.push(to_screen(sample.time, value)),
+                None => {
+                    if points.len() > 1 {
+                        painter.add(egui::Shape::line(std::mem::take(&mut points), stroke));
+                    }
+                    points.clear();
+                }
+            }
+        }
+        if points.len() > 1 {
+            painter.add(egui::Shape::line(points, stroke));
+        }
+    }
+}
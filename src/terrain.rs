@@ -0,0 +1,306 @@
+//! Height-field terrain extracted from the splat
+//!
+//! A single plane is a poor fit for hilly scans. Once a ground plane has been
+//! selected, the Gaussians near the primary plane are binned into a grid in
+//! the plane's frame and each cell takes the height of its densest layer,
+//! giving a heightmap the car can follow. `GroundSurface` wraps the planes and
+//! heightfield so driving code does not need to care which one is available.
+
+use std::time::Instant;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::ground_plane::{GroundPlane, GroundPlanes};
+use crate::point_cloud::SplatPointCloud;
+
+/// Size of a heightfield cell in meters
+const CELL_SIZE: f32 = 0.5;
+
+/// Maximum distance from the plane origin covered by the heightfield
+const MAX_HALF_EXTENT: f32 = 250.0;
+
+/// Gaussians further than this from the plane are ignored (trees, roofs, floaters)
+const MAX_SURFACE_OFFSET: f32 = 6.0;
+
+/// Gaussians fainter than this do not contribute to the surface
+const MIN_OPACITY: f32 = 0.1;
+
+/// Thickness of the layer searched for the densest surface in each cell
+const SURFACE_THICKNESS: f32 = 0.3;
+
+/// Minimum accumulated opacity for a cell to count as measured
+const MIN_CELL_WEIGHT: f32 = 1.0;
+
+/// Number of passes spreading measured heights into empty cells
+const HOLE_FILL_PASSES: usize = 8;
+
+/// Seconds the ground planes must keep still before the heightfield follows them
+const SETTLE_SECONDS: f32 = 0.25;
+
+/// Plugin extracting a heightfield around the selected ground plane
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, rebuild_heightfield);
+    }
+}
+
+/// Resource with the terrain height sampled on a grid in the ground plane's frame
+///
+/// Only present while a ground plane is selected and splat data is loaded.
+#[derive(Resource)]
+pub struct Heightfield {
+    origin: Vec3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    /// Plane coordinates of the center of the first cell
+    min: Vec2,
+    columns: usize,
+    rows: usize,
+    /// Surface offset along the normal for each cell, row-major
+    heights: Vec<f32>,
+}
+
+impl Heightfield {
+    /// Sample the heightfield from the splat Gaussians near a plane
+    pub fn build(plane: &GroundPlane, point_cloud: &SplatPointCloud) -> Option<Self> {
+        let (tangent, bitangent) = plane.tangents();
+
+        let mut samples = Vec::new();
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        for (index, &position) in point_cloud.positions.iter().enumerate() {
+            let opacity = point_cloud.opacities[index];
+            let offset = position - plane.origin;
+            let height = offset.dot(plane.normal);
+            let uv = Vec2::new(offset.dot(tangent), offset.dot(bitangent));
+            if opacity < MIN_OPACITY
+                || height.abs() > MAX_SURFACE_OFFSET
+                || uv.abs().max_element() > MAX_HALF_EXTENT
+            {
+                continue;
+            }
+            min = min.min(uv);
+            max = max.max(uv);
+            samples.push((uv, height, opacity));
+        }
+        if samples.is_empty() {
+            return None;
+        }
+
+        let columns = ((max.x - min.x) / CELL_SIZE) as usize + 1;
+        let rows = ((max.y - min.y) / CELL_SIZE) as usize + 1;
+        let mut cells = vec![Vec::new(); columns * rows];
+        for (uv, height, opacity) in samples {
+            let cell = ((uv - min) / CELL_SIZE).as_uvec2();
+            cells[cell.y as usize * columns + cell.x as usize].push((height, opacity));
+        }
+
+        let mut heights: Vec<Option<f32>> = cells.iter_mut().map(|c| densest_layer(c)).collect();
+        fill_holes(&mut heights, columns, rows);
+
+        Some(Self {
+            origin: plane.origin,
+            normal: plane.normal,
+            tangent,
+            bitangent,
+            min: min + Vec2::splat(CELL_SIZE * 0.5),
+            columns,
+            rows,
+            heights: heights.into_iter().map(|h| h.unwrap_or(0.0)).collect(),
+        })
+    }
+
+    /// Bilinearly interpolated surface offset along the plane normal under a point
+    ///
+    /// Returns `None` outside the sampled area.
+    pub fn offset_at(&self, point: Vec3) -> Option<f32> {
+        let offset = point - self.origin;
+        let grid = (Vec2::new(offset.dot(self.tangent), offset.dot(self.bitangent)) - self.min)
+            / CELL_SIZE;
+        let last = Vec2::new((self.columns - 1) as f32, (self.rows - 1) as f32);
+        if grid.cmplt(Vec2::ZERO).any() || grid.cmpgt(last).any() {
+            return None;
+        }
+
+        let base = grid.floor().min(last - Vec2::ONE).max(Vec2::ZERO);
+        let fraction = grid - base;
+        let (x, y) = (base.x as usize, base.y as usize);
+        let x1 = (x + 1).min(self.columns - 1);
+        let y1 = (y + 1).min(self.rows - 1);
+        let h = |x: usize, y: usize| self.heights[y * self.columns + x];
+
+        let near = h(x, y) + (h(x1, y) - h(x, y)) * fraction.x;
+        let far = h(x, y1) + (h(x1, y1) - h(x, y1)) * fraction.x;
+        Some(near + (far - near) * fraction.y)
+    }
+
+    /// Surface normal under a point, from central differences of the heights
+    pub fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        let center = self.offset_at(point)?;
+        let sample = |direction: Vec3| self.offset_at(point + direction * CELL_SIZE).unwrap_or(center);
+        let du = (sample(self.tangent) - sample(-self.tangent)) / (2.0 * CELL_SIZE);
+        let dv = (sample(self.bitangent) - sample(-self.bitangent)) / (2.0 * CELL_SIZE);
+        Some((self.normal - self.tangent * du - self.bitangent * dv).normalize())
+    }
+}
+
+/// Ground the car drives on: the extracted heightfield where available, else the planes
+#[derive(SystemParam)]
+pub struct GroundSurface<'w> {
+    planes: Res<'w, GroundPlanes>,
+    heightfield: Option<Res<'w, Heightfield>>,
+}
+
+impl GroundSurface<'_> {
+    /// The ground plane under a point
+    pub fn plane_at(&self, point: Vec3) -> GroundPlane {
+        self.planes.plane_at(point)
+    }
+
+    /// The heightfield, if the point is over the primary plane it was extracted around
+    fn heightfield_at(&self, point: Vec3) -> Option<&Heightfield> {
+        if self.planes.index_at(point) != Some(0) {
+            return None;
+        }
+        self.heightfield.as_deref()
+    }
+
+    /// Offset of the surface from its plane along the normal under a point
+    fn surface_offset(&self, point: Vec3) -> f32 {
+        self.heightfield_at(point)
+            .and_then(|heightfield| heightfield.offset_at(point))
+            .unwrap_or(0.0)
+    }
+
+    /// Project a world position onto the ground surface along the plane normal
+    pub fn project_point(&self, point: Vec3) -> Vec3 {
+        let plane = self.plane_at(point);
+        plane.project_point(point) + plane.normal * self.surface_offset(point)
+    }
+
+    /// Height of a point above the ground surface
+    pub fn height_at(&self, point: Vec3) -> f32 {
+        self.plane_at(point).height_at(point) - self.surface_offset(point)
+    }
+
+    /// Surface normal under a point
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        self.heightfield_at(point)
+            .and_then(|heightfield| heightfield.normal_at(point))
+            .unwrap_or_else(|| self.plane_at(point).normal)
+    }
+}
+
+/// Height of the densest layer of Gaussians in a cell, weighted by opacity
+fn densest_layer(samples: &mut [(f32, f32)]) -> Option<f32> {
+    if samples.iter().map(|&(_, w)| w).sum::<f32>() < MIN_CELL_WEIGHT {
+        return None;
+    }
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Slide a window of the surface thickness over the sorted heights
+    let mut best = (0.0, 0.0);
+    let mut start = 0;
+    let mut weight = 0.0;
+    let mut weighted_height = 0.0;
+    for &(height, opacity) in samples.iter() {
+        weight += opacity;
+        weighted_height += height * opacity;
+        while height - samples[start].0 > SURFACE_THICKNESS {
+            weight -= samples[start].1;
+            weighted_height -= samples[start].0 * samples[start].1;
+            start += 1;
+        }
+        if weight > best.0 {
+            best = (weight, weighted_height / weight);
+        }
+    }
+    Some(best.1)
+}
+
+/// Spread measured heights into neighboring empty cells, then smooth once
+fn fill_holes(heights: &mut [Option<f32>], columns: usize, rows: usize) {
+    let neighbor_average = |heights: &[Option<f32>], x: usize, y: usize| {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for ny in y.saturating_sub(1)..=(y + 1).min(rows - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(columns - 1) {
+                if let Some(height) = heights[ny * columns + nx] {
+                    sum += height;
+                    count += 1;
+                }
+            }
+        }
+        (count > 0).then(|| sum / count as f32)
+    };
+
+    for _ in 0..HOLE_FILL_PASSES {
+        let previous = heights.to_vec();
+        for y in 0..rows {
+            for x in 0..columns {
+                if heights[y * columns + x].is_none() {
+                    heights[y * columns + x] = neighbor_average(&previous, x, y);
+                }
+            }
+        }
+    }
+
+    let previous = heights.to_vec();
+    for y in 0..rows {
+        for x in 0..columns {
+            if previous[y * columns + x].is_some() {
+                heights[y * columns + x] = neighbor_average(&previous, x, y);
+            }
+        }
+    }
+}
+
+/// Rebuild the heightfield when the ground planes or the splat points change
+///
+/// A plane that is dragged or nudged changes every frame, so the heightfield
+/// waits for the planes to keep still rather than being rebuilt each time.
+fn rebuild_heightfield(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut settling: Local<Option<f32>>,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+) {
+    if ground_planes.is_changed() {
+        *settling = Some(SETTLE_SECONDS);
+    }
+    let settled = match settling.as_mut() {
+        Some(seconds) => {
+            *seconds -= time.delta_secs();
+            *seconds <= 0.0
+        }
+        None => false,
+    };
+    if !settled && !point_cloud.is_changed() {
+        return;
+    }
+    *settling = None;
+
+    if !ground_planes.is_selected() {
+        commands.remove_resource::<Heightfield>();
+        return;
+    }
+
+    let start = Instant::now();
+    match Heightfield::build(&ground_planes.primary(), &point_cloud) {
+        Some(heightfield) => {
+            info!(
+                "Extracted {}x{} terrain heightfield in {:.0?}",
+                heightfield.columns,
+                heightfield.rows,
+                start.elapsed()
+            );
+            commands.insert_resource(heightfield);
+        }
+        None => commands.remove_resource::<Heightfield>(),
+    }
+}
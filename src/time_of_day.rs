@@ -0,0 +1,259 @@
+//! Time of day
+//!
+//! The sun and the ambient light follow the `TimeOfDay` resource: the hour sets
+//! how high the sun stands, how bright and how warm it is, and the sun's
+//! direction turns from east to west through the day around the sun azimuth.
+//! At night the moon takes over as a dim blue light, so the headlights matter.
+//! Overcast weather dims the sun (see `weather`). The splats keep the light
+//! they were captured in; the time of day lights the car, the gates and the
+//! other meshes.
+//!
+//! The hour, the sun direction, the ambient brightness and the day-night cycle
+//! are settings (see `settings`). Home and End move the time an hour earlier or
+//! later, with Shift they turn the sun and with Ctrl they change the ambient
+//! light. With the day-night cycle on, a whole day passes in `DAY_LENGTH`.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::prelude::*;
+
+use crate::input_map::InputMap;
+use crate::settings::Settings;
+use crate::weather::Weather;
+
+/// Seconds a whole day takes with the day-night cycle on
+const DAY_LENGTH: f32 = 600.0;
+
+/// Hours of sunrise and sunset
+const SUNRISE: f32 = 6.0;
+const SUNSET: f32 = 18.0;
+
+/// Height of the sun at noon, in radians
+const NOON_ELEVATION: f32 = 1.05;
+
+/// The sun and the moon never cast light from lower than this, in radians,
+/// so the shadows stay on the ground
+const MIN_LIGHT_ELEVATION: f32 = 0.2;
+
+/// Illuminance of the sun at full daylight and of the moon, in lux
+const SUN_ILLUMINANCE: f32 = light_consts::lux::AMBIENT_DAYLIGHT;
+const MOON_ILLUMINANCE: f32 = 150.0;
+
+/// Ambient brightness at full daylight and ambient light 1
+const AMBIENT_BRIGHTNESS: f32 = 500.0;
+
+/// Fraction of the ambient brightness left at night
+const NIGHT_AMBIENT: f32 = 0.1;
+
+/// Colors of the sun low on the horizon and of the moon
+const SUNSET_COLOR: Color = Color::srgb(1.0, 0.55, 0.3);
+const MOON_COLOR: Color = Color::srgb(0.6, 0.7, 1.0);
+
+/// Highest multiplier of the ambient brightness
+pub const MAX_AMBIENT_LIGHT: f32 = 2.0;
+
+/// Change of the sun direction and the ambient light per key press
+pub const AZIMUTH_STEP: f32 = 15.0;
+pub const AMBIENT_STEP: f32 = 0.25;
+
+/// Plugin for the sun, the ambient light and the time of day
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_systems(Startup, spawn_sun)
+            .add_systems(
+                Update,
+                (
+                    adjust_time_of_day,
+                    apply_time_of_day_settings,
+                    advance_time_of_day,
+                    update_lighting,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Resource with the time of day the scene is lit for
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct TimeOfDay {
+    /// Hour from 0 to 24
+    pub hour: f32,
+    /// Compass direction of the sun at noon, in degrees
+    pub sun_azimuth: f32,
+    /// Multiplier of the ambient brightness, up to `MAX_AMBIENT_LIGHT`
+    pub ambient_light: f32,
+    /// Whether the hour moves on by itself
+    pub cycling: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 14.0,
+            sun_azimuth: 30.0,
+            ambient_light: 1.0,
+            cycling: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Height of the sun above the horizon in radians, negative at night
+    pub fn sun_elevation(&self) -> f32 {
+        let day = (self.hour - SUNRISE) / (SUNSET - SUNRISE);
+        NOON_ELEVATION * (day * PI).sin()
+    }
+
+    /// How much of the daylight there is, from 0 at night to 1 by day
+    pub fn daylight(&self) -> f32 {
+        let t = ((self.sun_elevation() + 0.1) / 0.4).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Rotation of the directional light: the sun by day and the moon,
+    /// opposite it, by night
+    fn light_rotation(&self) -> Quat {
+        let elevation = self.sun_elevation();
+        // Rising in the east and setting in the west, around the noon azimuth
+        let mut yaw = self.sun_azimuth.to_radians() + (self.hour - 12.0) / 12.0 * PI;
+        if elevation < 0.0 {
+            yaw += PI;
+        }
+        let pitch = elevation.abs().clamp(MIN_LIGHT_ELEVATION, FRAC_PI_2);
+        Quat::from_euler(EulerRot::YXZ, yaw, -pitch, 0.0)
+    }
+
+    fn light_color(&self) -> Color {
+        // Warmer the lower the sun stands
+        let warmth = 1.0 - (self.sun_elevation() / 0.5).clamp(0.0, 1.0);
+        let sun = Color::WHITE.mix(&SUNSET_COLOR, warmth);
+        MOON_COLOR.mix(&sun, self.daylight())
+    }
+
+    fn illuminance(&self) -> f32 {
+        MOON_ILLUMINANCE.lerp(SUN_ILLUMINANCE, self.daylight())
+    }
+
+    fn ambient_brightness(&self) -> f32 {
+        AMBIENT_BRIGHTNESS * self.ambient_light * NIGHT_AMBIENT.lerp(1.0, self.daylight())
+    }
+}
+
+/// Hours and minutes of an hour of the day, like "18:30"
+pub fn format_hour(hour: f32) -> String {
+    let minutes = (hour.rem_euclid(24.0) * 60.0).round() as u32 % (24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Marker for the directional light of the sun and the moon
+#[derive(Component)]
+struct Sun;
+
+/// Spawn the sun, lit by `update_lighting`
+fn spawn_sun(mut commands: Commands) {
+    commands.spawn((
+        DirectionalLight {
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::default(),
+        Sun,
+    ));
+}
+
+/// Move the time with Home and End, the sun with Shift and the ambient light
+/// with Ctrl, saved as settings
+fn adjust_time_of_day(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut settings: ResMut<Settings>,
+) {
+    if input_map.suspended {
+        return;
+    }
+    let direction = match (
+        keyboard.just_pressed(KeyCode::Home),
+        keyboard.just_pressed(KeyCode::End),
+    ) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => return,
+    };
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    if shift {
+        settings.sun_azimuth = (settings.sun_azimuth + direction * AZIMUTH_STEP).rem_euclid(360.0);
+        info!("Sun direction {:.0}°", settings.sun_azimuth);
+    } else if ctrl {
+        settings.ambient_light =
+            (settings.ambient_light + direction * AMBIENT_STEP).clamp(0.0, MAX_AMBIENT_LIGHT);
+        info!("Ambient light {:.0}%", settings.ambient_light * 100.0);
+    } else {
+        // From the hour the cycle has got to, on to the next whole hour
+        let hour = if direction > 0.0 {
+            time_of_day.hour.floor() + 1.0
+        } else {
+            time_of_day.hour.ceil() - 1.0
+        };
+        time_of_day.hour = hour.rem_euclid(24.0);
+        settings.time_of_day = time_of_day.hour;
+        info!("Time of day {}", format_hour(settings.time_of_day));
+    }
+}
+
+/// Take the time of day from the settings when they change, keeping the hour
+/// the cycle has got to unless the hour itself was changed
+fn apply_time_of_day_settings(
+    settings: Res<Settings>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut applied_hour: Local<Option<f32>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let mut new_time = TimeOfDay {
+        hour: time_of_day.hour,
+        sun_azimuth: settings.sun_azimuth,
+        ambient_light: settings.ambient_light,
+        cycling: settings.day_night_cycle,
+    };
+    if *applied_hour != Some(settings.time_of_day) {
+        *applied_hour = Some(settings.time_of_day);
+        new_time.hour = settings.time_of_day;
+    }
+    time_of_day.set_if_neq(new_time);
+}
+
+/// Move the hour on while the day-night cycle is on
+fn advance_time_of_day(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    if !time_of_day.cycling {
+        return;
+    }
+    let hours = time.delta_secs() / DAY_LENGTH * 24.0;
+    time_of_day.hour = (time_of_day.hour + hours).rem_euclid(24.0);
+}
+
+/// Light the scene for the time of day and the weather
+fn update_lighting(
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sun_query: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+) {
+    let Ok((mut sun, mut transform)) = sun_query.single_mut() else {
+        return;
+    };
+    // The sun is spawned unlit
+    if !time_of_day.is_changed() && !weather.is_changed() && !transform.is_added() {
+        return;
+    }
+    sun.color = time_of_day.light_color();
+    sun.illuminance = time_of_day.illuminance() * weather.sunlight();
+    transform.rotation = time_of_day.light_rotation();
+    ambient.color = MOON_COLOR.mix(&Color::WHITE, time_of_day.daylight());
+    ambient.brightness = time_of_day.ambient_brightness();
+}
@@ -0,0 +1,254 @@
+//! Time trial
+//!
+//! In the Time trial game mode the first player's laps are split into three
+//! sectors, ending at the gates a third and two thirds of the way around the
+//! track and at the finish line. A panel under the lap times shows the
+//! time of each sector with its delta to the same sector of the best lap:
+//! green when faster, red when slower, and purple for the fastest time through
+//! that sector on any lap. Tracks with fewer than three gates get one sector
+//! per gate.
+//!
+//! Leaving the drivable area or going the wrong way invalidates the lap of any
+//! car (see `track_limits`): it still counts as driven, but can't set a best
+//! lap, a record or the ghost.
+
+use bevy::prelude::*;
+
+use crate::car::{CarSet, PrimaryCar};
+use crate::game_mode::GameMode;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::track::{format_lap_time, CheckpointPassed, LapCompleted, LapTimer};
+
+/// Sectors a lap is split into
+const SECTORS: usize = 3;
+
+/// Distance of the sector panel from the top of the screen, below the lap panel
+const SECTOR_PANEL_TOP: f32 = 170.0;
+
+/// Plugin for the time trial mode
+pub struct TimeTrialPlugin;
+
+impl Plugin for TimeTrialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SectorTimes>()
+            .add_systems(Startup, spawn_sector_panel)
+            .add_systems(
+                Update,
+                (
+                    track_sectors.run_if(resource_equals(GameMode::TimeTrial)),
+                    update_sector_panel,
+                )
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// Resource with the first player's sector times
+#[derive(Resource, Default)]
+pub struct SectorTimes {
+    /// Times of the sectors completed on this lap
+    pub current: Vec<f32>,
+    /// Sector times of the best lap
+    pub best_lap: Vec<f32>,
+    /// Fastest time through each sector on any valid lap
+    pub best: Vec<Option<f32>>,
+    /// Lap time at the end of the last completed sector of this lap
+    sector_start: f32,
+}
+
+/// Indices of the gates ending each sector, the finish line last
+fn sector_ends(gates: usize) -> Vec<usize> {
+    let sectors = SECTORS.min(gates);
+    (1..sectors)
+        .map(|sector| sector * gates / sectors)
+        .chain((gates > 0).then_some(0))
+        .collect()
+}
+
+/// Marker for the sector panel
+#[derive(Component)]
+struct SectorPanel;
+
+/// Component on the text of a sector, with its index
+#[derive(Component)]
+struct SectorText(usize);
+
+/// Marker for the text flagging an invalid lap
+#[derive(Component)]
+struct InvalidLapText;
+
+/// Time the first player's sectors and keep the best ones
+fn track_sectors(
+    checkpoints: Res<SceneCheckpoints>,
+    mut sectors: ResMut<SectorTimes>,
+    mut passed: MessageReader<CheckpointPassed>,
+    mut completed: MessageReader<LapCompleted>,
+    car_query: Query<Entity, With<PrimaryCar>>,
+) {
+    if checkpoints.is_changed() {
+        *sectors = SectorTimes::default();
+    }
+    let Ok(car) = car_query.single() else {
+        return;
+    };
+    let ends = sector_ends(checkpoints.0.len());
+    let lap = completed.read().find(|lap| lap.car == car).copied();
+
+    for checkpoint in passed.read().filter(|checkpoint| checkpoint.car == car) {
+        if !ends.contains(&checkpoint.index) {
+            continue;
+        }
+        // The start line only ends a sector when it completes a lap
+        let lap_time = match (checkpoint.index, lap) {
+            (0, Some(lap)) => lap.time,
+            (0, None) => {
+                sectors.current.clear();
+                sectors.sector_start = 0.0;
+                continue;
+            }
+            _ => checkpoint.lap_time,
+        };
+        let sector = sectors.current.len();
+        let time = lap_time - sectors.sector_start;
+        sectors.current.push(time);
+        sectors.sector_start = lap_time;
+        if let Some(&best) = sectors.best_lap.get(sector) {
+            info!(
+                "Sector {} {} ({:+.2})",
+                sector + 1,
+                format_lap_time(time),
+                time - best
+            );
+        }
+
+        if checkpoint.index != 0 {
+            continue;
+        }
+        let Some(lap) = lap else {
+            continue;
+        };
+        if lap.valid {
+            let current = sectors.current.clone();
+            sectors.best.resize(current.len(), None);
+            for (best, &time) in sectors.best.iter_mut().zip(&current) {
+                if best.is_none_or(|best| time < best) {
+                    *best = Some(time);
+                }
+            }
+            if lap.best {
+                sectors.best_lap = current;
+            }
+        }
+        sectors.current.clear();
+        sectors.sector_start = 0.0;
+    }
+}
+
+/// Spawn the hidden sector panel under the HUD's lap panel
+fn spawn_sector_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(SECTOR_PANEL_TOP),
+                left: Val::Px(12.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            SectorPanel,
+        ))
+        .with_children(|panel| {
+            for sector in 0..SECTORS {
+                panel.spawn((sector_text(20.0), SectorText(sector)));
+            }
+            panel.spawn((
+                sector_text(20.0),
+                TextColor(Color::srgb(1.0, 0.2, 0.1)),
+                InvalidLapText,
+            ));
+        });
+}
+
+/// Text of the given size with a shadow
+fn sector_text(font_size: f32) -> impl Bundle {
+    (
+        Text::new(""),
+        TextFont {
+            font_size,
+            ..default()
+        },
+        TextShadow::default(),
+    )
+}
+
+/// Show the sector times and deltas in the Time trial mode
+fn update_sector_panel(
+    mode: Res<GameMode>,
+    checkpoints: Res<SceneCheckpoints>,
+    sectors: Res<SectorTimes>,
+    timer_query: Query<&LapTimer, With<PrimaryCar>>,
+    mut panel_query: Query<&mut Visibility, With<SectorPanel>>,
+    mut text_query: Query<(&SectorText, &mut Text, &mut TextColor)>,
+    mut invalid_query: Query<&mut Text, (With<InvalidLapText>, Without<SectorText>)>,
+) {
+    let Ok(mut visibility) = panel_query.single_mut() else {
+        return;
+    };
+    let ends = sector_ends(checkpoints.0.len());
+    let timer = timer_query.single().ok();
+    let shown = *mode == GameMode::TimeTrial && !ends.is_empty() && timer.is_some();
+    visibility.set_if_neq(if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    let Some(timer) = timer.filter(|_| shown) else {
+        return;
+    };
+
+    for (sector, mut text, mut color) in text_query.iter_mut() {
+        let index = sector.0;
+        let (line, new_color) = if index >= ends.len() {
+            (String::new(), Color::WHITE)
+        } else if let Some(&time) = sectors.current.get(index) {
+            let best = sectors.best.get(index).copied().flatten();
+            let delta = sectors.best_lap.get(index).map(|&best| time - best);
+            let line = match delta {
+                Some(delta) => format!("S{}  {}  {:+.2}", index + 1, format_lap_time(time), delta),
+                None => format!("S{}  {}", index + 1, format_lap_time(time)),
+            };
+            let color = if !timer.invalidated && best.is_none_or(|best| time < best) {
+                Color::srgb(0.75, 0.3, 1.0)
+            } else if delta.is_some_and(|delta| delta < 0.0) {
+                Color::srgb(0.1, 0.9, 0.2)
+            } else if delta.is_some() {
+                Color::srgb(1.0, 0.15, 0.1)
+            } else {
+                Color::WHITE
+            };
+            (line, color)
+        } else if index == sectors.current.len() && timer.started() {
+            let running = timer.lap_time - sectors.sector_start;
+            (
+                format!("S{}  {}", index + 1, format_lap_time(running)),
+                Color::WHITE,
+            )
+        } else {
+            (format!("S{}  -", index + 1), Color::srgb(0.6, 0.6, 0.6))
+        };
+        if text.0 != line {
+            text.0 = line;
+        }
+        color.set_if_neq(TextColor(new_color));
+    }
+
+    if let Ok(mut text) = invalid_query.single_mut() {
+        let line = if timer.invalidated { "LAP INVALID" } else { "" };
+        if text.0 != line {
+            text.0 = line.to_string();
+        }
+    }
+}
@@ -0,0 +1,542 @@
+//! Checkpoint gates and lap timing
+//!
+//! The scene's checkpoints become gates standing on the ground. The first is
+//! the start/finish line, the rest have to be driven through in order. The
+//! clock starts when a car first crosses the start line, and each time it
+//! crosses it again after passing every other gate a lap is complete. Every car
+//! is timed on its own; the gate the first player drives through next glows,
+//! and their current, last and best lap times are shown on the HUD. A lap the
+//! game mode's rules invalidate still counts, but can't be a best lap.
+//!
+//! In the Rally mode the gates make a point-to-point stage instead: the clock
+//! starts at the first gate and stops at the last, which completes the stage
+//! like a lap, and starts again the next time the car crosses the first gate.
+//!
+//! Each checkpoint is stored as the car's transform when it drives through the
+//! middle of the gate, so passing it also makes it the respawn point. The
+//! gate's width is `GATE_WIDTH` scaled by the transform's X scale.
+//!
+//! Press 'J' to edit the track:
+//!
+//! | Input         | Action                                            |
+//! |---------------|---------------------------------------------------|
+//! | click + drag  | place a gate and drag in the driving direction    |
+//! | drag a gate   | turn it                                           |
+//! | Tab           | select the next gate                              |
+//! | , / .         | make the selected gate narrower / wider           |
+//! | Backspace     | delete the selected gate                          |
+//!
+//! The first gate placed is the start/finish line. 'J' again closes the
+//! editor, and Ctrl+S saves the track into the open scene manifest.
+
+use bevy::prelude::*;
+
+use crate::camera::CarCamera;
+use crate::car::{Car, CarSet, PrimaryCar, RIDE_HEIGHT};
+use crate::game_mode::GameMode;
+use crate::ground_plane::{cursor_ray, pick_ground_point, GroundPlanes};
+use crate::point_cloud::SplatPointCloud;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::spawn_point::LastCheckpoint;
+use crate::terrain::GroundSurface;
+
+/// Width of a gate at unit X scale, in meters
+pub const GATE_WIDTH: f32 = 12.0;
+
+/// Height of the gate posts
+const GATE_HEIGHT: f32 = 3.5;
+
+/// The car passes through a gate only if it is this close to its ground, in meters
+const GATE_REACH: f32 = 4.0;
+
+/// Moves longer than this in one frame are teleports, which don't cross gates
+const MAX_FRAME_TRAVEL: f32 = 20.0;
+
+/// Minimum drag distance for the drag direction to set a gate's heading
+const MIN_HEADING_DRAG: f32 = 0.5;
+
+/// Narrowest and widest gates, as multiples of `GATE_WIDTH`
+const MIN_GATE_SCALE: f32 = 0.25;
+const MAX_GATE_SCALE: f32 = 5.0;
+
+/// Plugin for the checkpoint gates and lap timing
+pub struct TrackPlugin;
+
+impl Plugin for TrackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrackEditor>()
+            .add_message::<CheckpointPassed>()
+            .add_message::<LapCompleted>()
+            .add_systems(
+                Update,
+                (
+                    edit_track,
+                    draw_track_editor,
+                    add_lap_timers,
+                    rebuild_gates,
+                    detect_gate_crossings.after(CarSet::Constraints).before(CarSet::Camera),
+                    highlight_next_gate,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Component with the lap timing of a car
+#[derive(Component, Default)]
+pub struct LapTimer {
+    /// Lap being driven, counting from 1; 0 before the start line is crossed
+    /// and after the finish of a stage
+    pub lap: u32,
+    /// Seconds since the current lap started
+    pub lap_time: f32,
+    /// Time of the last completed lap
+    pub last_lap: Option<f32>,
+    /// Fastest completed lap
+    pub best_lap: Option<f32>,
+    /// Index of the gate to drive through next
+    pub next_gate: usize,
+    /// Whether the current lap has been invalidated
+    pub invalidated: bool,
+    /// Car position last frame, to find gate crossings
+    previous_position: Option<Vec3>,
+}
+
+impl LapTimer {
+    /// Whether the clock is running
+    pub fn started(&self) -> bool {
+        self.lap > 0
+    }
+}
+
+/// Message sent when a car drives through its next gate
+#[derive(Message, Debug, Clone, Copy)]
+pub struct CheckpointPassed {
+    /// The car passing the gate
+    pub car: Entity,
+    /// Index of the gate, 0 being the start/finish line
+    pub index: usize,
+    /// Seconds into the lap
+    pub lap_time: f32,
+}
+
+/// Message sent when a car completes a lap, or the stage in the Rally mode
+#[derive(Message, Debug, Clone, Copy)]
+pub struct LapCompleted {
+    /// The car completing the lap
+    pub car: Entity,
+    /// Number of the completed lap, counting from 1
+    pub lap: u32,
+    /// Lap time in seconds
+    pub time: f32,
+    /// Whether it is the fastest lap so far
+    pub best: bool,
+    /// Whether the lap counts for records, i.e. wasn't invalidated
+    pub valid: bool,
+}
+
+/// Component for a checkpoint gate
+#[derive(Component)]
+pub struct CheckpointGate {
+    /// Position in the driving order, 0 being the start/finish line
+    pub index: usize,
+    /// Material of the gate's posts and banner, to highlight it
+    material: Handle<StandardMaterial>,
+}
+
+/// Resource tracking the track editor
+#[derive(Resource, Default)]
+pub struct TrackEditor {
+    /// Whether the editor is active
+    pub active: bool,
+    /// Index of the selected gate
+    selected: Option<usize>,
+    /// Gate being dragged, if any
+    drag: Option<GateDrag>,
+}
+
+/// A gate being placed or turned by dragging
+#[derive(Clone, Copy)]
+struct GateDrag {
+    /// Index of the gate being turned, or `None` for a new gate
+    index: Option<usize>,
+    /// Point on the ground the gate stands at
+    anchor: Vec3,
+}
+
+/// Current, last and best lap times of a car, one per line
+pub fn lap_summary(timer: &LapTimer) -> String {
+    let lap = if timer.started() {
+        format!("Lap {}  {}", timer.lap, format_lap_time(timer.lap_time))
+    } else {
+        "Cross the start line".to_string()
+    };
+    let or_dash = |time: Option<f32>| time.map_or("-".to_string(), format_lap_time);
+    format!("{}\nLast {}\nBest {}", lap, or_dash(timer.last_lap), or_dash(timer.best_lap))
+}
+
+/// Format seconds as minutes, seconds and hundredths
+pub fn format_lap_time(seconds: f32) -> String {
+    let hundredths = (seconds.max(0.0) * 100.0).round() as u32;
+    format!("{}:{:02}.{:02}", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+}
+
+/// Width of a gate placed at a checkpoint
+pub fn gate_width(checkpoint: &Transform) -> f32 {
+    GATE_WIDTH * checkpoint.scale.x.abs().max(0.1)
+}
+
+/// Where a move from `from` to `to` passes through a gate, as the fraction of
+/// the move, if it crosses the gate forwards between its posts
+fn gate_crossing(checkpoint: &Transform, from: Vec3, to: Vec3) -> Option<f32> {
+    let forward = checkpoint.forward();
+    let before = (from - checkpoint.translation).dot(*forward);
+    let after = (to - checkpoint.translation).dot(*forward);
+    if before >= 0.0 || after < 0.0 {
+        return None;
+    }
+    let fraction = before / (before - after);
+    let offset = from.lerp(to, fraction) - checkpoint.translation;
+    let within_posts = offset.dot(*checkpoint.right()).abs() <= gate_width(checkpoint) * 0.5;
+    let within_reach = offset.dot(*checkpoint.up()).abs() <= GATE_REACH;
+    (within_posts && within_reach).then_some(fraction)
+}
+
+/// Time every car that drives
+fn add_lap_timers(
+    mut commands: Commands,
+    car_query: Query<Entity, (With<Car>, Without<LapTimer>)>,
+) {
+    for entity in car_query.iter() {
+        commands.entity(entity).insert(LapTimer::default());
+    }
+}
+
+/// Spawn the gates for the scene's checkpoints and restart the timing whenever
+/// the checkpoints change
+fn rebuild_gates(
+    mut commands: Commands,
+    checkpoints: Res<SceneCheckpoints>,
+    mut timer_query: Query<&mut LapTimer>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    gate_query: Query<Entity, With<CheckpointGate>>,
+) {
+    if !checkpoints.is_changed() {
+        return;
+    }
+    for entity in gate_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for mut timer in timer_query.iter_mut() {
+        *timer = LapTimer::default();
+    }
+    last_checkpoint.0 = None;
+
+    let post_mesh = meshes.add(Cylinder::new(0.15, GATE_HEIGHT));
+    let banner_mesh = meshes.add(Cuboid::new(1.0, 0.6, 0.1));
+    for (index, checkpoint) in checkpoints.0.iter().enumerate() {
+        let material = materials.add(StandardMaterial::default());
+        let width = gate_width(checkpoint);
+        // Checkpoints are car transforms; the gate stands on the ground below
+        let ground = Transform {
+            translation: checkpoint.translation - checkpoint.up() * RIDE_HEIGHT,
+            rotation: checkpoint.rotation,
+            ..default()
+        };
+        commands
+            .spawn((
+                CheckpointGate {
+                    index,
+                    material: material.clone(),
+                },
+                ground,
+                Visibility::default(),
+            ))
+            .with_children(|parent| {
+                for side in [-0.5, 0.5] {
+                    parent.spawn((
+                        Mesh3d(post_mesh.clone()),
+                        MeshMaterial3d(material.clone()),
+                        Transform::from_xyz(side * width, GATE_HEIGHT * 0.5, 0.0),
+                    ));
+                }
+                parent.spawn((
+                    Mesh3d(banner_mesh.clone()),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_xyz(0.0, GATE_HEIGHT - 0.3, 0.0)
+                        .with_scale(Vec3::new(width, 1.0, 1.0)),
+                ));
+            });
+    }
+    if !checkpoints.0.is_empty() {
+        let count = checkpoints.0.len();
+        info!("Track with {} gate(s) - drive through the start line to start the clock", count);
+    }
+}
+
+/// Toggle the editor with 'J', place and turn gates by dragging, change their
+/// width and delete them
+fn edit_track(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut editor: ResMut<TrackEditor>,
+    mut checkpoints: ResMut<SceneCheckpoints>,
+    ground: GroundSurface,
+    ground_planes: Res<GroundPlanes>,
+    point_cloud: Res<SplatPointCloud>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CarCamera>>,
+    windows: Query<&Window>,
+    mut gizmos: Gizmos,
+) {
+    if keyboard.just_pressed(KeyCode::KeyJ) {
+        editor.active = !editor.active;
+        editor.drag = None;
+        if editor.active {
+            info!("Track editor ACTIVE - click and drag to place a gate in the driving direction");
+            info!("Drag a gate: turn it, Tab: next gate, ',' '.': width, Backspace: delete");
+        } else {
+            info!("Track editor INACTIVE - {} gate(s)", checkpoints.0.len());
+            info!("Press Ctrl+S to save the track into the scene");
+        }
+    }
+    if !editor.active {
+        return;
+    }
+
+    if editor.selected.is_some_and(|index| index >= checkpoints.0.len()) {
+        editor.selected = None;
+    }
+    if keyboard.just_pressed(KeyCode::Tab) && !checkpoints.0.is_empty() {
+        let count = checkpoints.0.len();
+        editor.selected = Some(editor.selected.map_or(0, |index| (index + 1) % count));
+    }
+    if let Some(index) = editor.selected {
+        let mut scale = None;
+        if keyboard.just_pressed(KeyCode::Comma) {
+            scale = Some(0.8);
+        }
+        if keyboard.just_pressed(KeyCode::Period) {
+            scale = Some(1.25);
+        }
+        if let Some(scale) = scale {
+            let gate = &mut checkpoints.0[index];
+            gate.scale.x = (gate.scale.x * scale).clamp(MIN_GATE_SCALE, MAX_GATE_SCALE);
+            info!("Gate {} is {:.1} m wide", index, gate_width(gate));
+        }
+        if keyboard.just_pressed(KeyCode::Backspace) {
+            checkpoints.0.remove(index);
+            editor.selected = None;
+            info!("Deleted gate {}", index);
+        }
+    }
+
+    let cursor_point = cursor_ray(&camera_query, &windows)
+        .and_then(|ray| pick_ground_point(ray, &point_cloud, &ground_planes));
+
+    if mouse_button.just_pressed(MouseButton::Left) {
+        if let Some(point) = cursor_point {
+            // Grab the gate clicked between the posts of, if any
+            let grabbed = checkpoints
+                .0
+                .iter()
+                .enumerate()
+                .map(|(index, gate)| (index, gate.translation.distance(point)))
+                .filter(|&(index, distance)| distance <= gate_width(&checkpoints.0[index]) * 0.5)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(index, _)| index);
+            let anchor = grabbed.map_or(point, |index| {
+                let gate = &checkpoints.0[index];
+                gate.translation - gate.up() * RIDE_HEIGHT
+            });
+            editor.selected = grabbed;
+            editor.drag = Some(GateDrag { index: grabbed, anchor });
+        }
+    }
+    let Some(drag) = editor.drag else {
+        return;
+    };
+
+    // Heading from the anchor toward the cursor, flattened onto the ground; a
+    // short drag keeps the gate's heading, or faces a new one away from the
+    // previous gate
+    let anchor = drag.anchor;
+    let up = ground.normal_at(anchor);
+    let fallback = match drag.index {
+        Some(index) => checkpoints.0[index].forward().as_vec3(),
+        None => checkpoints.0.last().map_or(Vec3::NEG_Z, |last| anchor - last.translation),
+    };
+    let heading = cursor_point
+        .map(|point| (point - anchor).reject_from_normalized(up))
+        .filter(|drag| drag.length() >= MIN_HEADING_DRAG)
+        .unwrap_or(fallback)
+        .reject_from_normalized(up)
+        .normalize_or(Vec3::NEG_Z);
+    gizmos.arrow(anchor, anchor + heading * 4.0, Color::srgb(1.0, 0.8, 0.0));
+
+    if mouse_button.just_released(MouseButton::Left) {
+        let transform =
+            Transform::from_translation(anchor + up * RIDE_HEIGHT).looking_to(heading, up);
+        match drag.index {
+            Some(index) => {
+                let scale = checkpoints.0[index].scale;
+                checkpoints.0[index] = transform.with_scale(scale);
+            }
+            None => {
+                checkpoints.0.push(transform);
+                editor.selected = Some(checkpoints.0.len() - 1);
+                if checkpoints.0.len() == 1 {
+                    info!("Placed the start/finish line");
+                } else {
+                    info!("Placed gate {}", checkpoints.0.len() - 1);
+                }
+            }
+        }
+        editor.drag = None;
+    }
+}
+
+/// Outline the gates and their driving direction while editing the track
+fn draw_track_editor(
+    mut gizmos: Gizmos,
+    editor: Res<TrackEditor>,
+    checkpoints: Res<SceneCheckpoints>,
+) {
+    if !editor.active {
+        return;
+    }
+    let mut previous = None;
+    for (index, gate) in checkpoints.0.iter().enumerate() {
+        let color = if editor.selected == Some(index) {
+            Color::srgb(1.0, 1.0, 0.0)
+        } else if index == 0 {
+            Color::WHITE
+        } else {
+            Color::srgb(0.2, 0.5, 1.0)
+        };
+        let center = gate.translation - gate.up() * RIDE_HEIGHT;
+        let half = gate.right() * gate_width(gate) * 0.5;
+        gizmos.line(center - half, center + half, color);
+        gizmos.arrow(center, center + gate.forward() * 3.0, color);
+        // The driving order, back to the start line
+        if let Some(previous) = previous {
+            gizmos.line(previous, center, Color::srgba(1.0, 1.0, 1.0, 0.3));
+        }
+        previous = Some(center);
+    }
+}
+
+/// Advance the lap clocks and count the gates the cars drive through
+fn detect_gate_crossings(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    checkpoints: Res<SceneCheckpoints>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    mut passed: MessageWriter<CheckpointPassed>,
+    mut completed: MessageWriter<LapCompleted>,
+    mut car_query: Query<(Entity, &Transform, &mut LapTimer, Has<PrimaryCar>)>,
+) {
+    let dt = time.delta_secs();
+    // A single gate is both the start and the finish of a stage
+    let finish_gate = if mode.is_stage() { checkpoints.0.len().saturating_sub(1) } else { 0 };
+    for (car, car_transform, mut timer, primary) in car_query.iter_mut() {
+        // Times from before a mode change don't count in the new one
+        if mode.is_changed() {
+            *timer = LapTimer::default();
+        }
+        let position = car_transform.translation;
+        let previous = timer.previous_position.replace(position);
+
+        let crossing = previous
+            .filter(|previous| previous.distance(position) <= MAX_FRAME_TRAVEL)
+            .zip(checkpoints.0.get(timer.next_gate))
+            .and_then(|(previous, checkpoint)| gate_crossing(checkpoint, previous, position));
+        let Some(fraction) = crossing else {
+            if timer.started() {
+                timer.lap_time += dt;
+            }
+            continue;
+        };
+
+        let index = timer.next_gate;
+        let at_crossing = timer.lap_time + dt * fraction;
+        // Respawning puts the first player back at their last gate
+        if primary {
+            last_checkpoint.0 = Some(checkpoints.0[index].with_scale(Vec3::ONE));
+        }
+        timer.next_gate = (index + 1) % checkpoints.0.len();
+
+        let finish = index == finish_gate && timer.started();
+        if index != 0 && !finish {
+            timer.lap_time += dt;
+            passed.write(CheckpointPassed { car, index, lap_time: at_crossing });
+            continue;
+        }
+
+        if finish {
+            let valid = !timer.invalidated;
+            let best = valid && timer.best_lap.is_none_or(|best| at_crossing < best);
+            timer.last_lap = Some(at_crossing);
+            if best {
+                timer.best_lap = Some(at_crossing);
+            }
+            let lap = timer.lap;
+            completed.write(LapCompleted { car, lap, time: at_crossing, best, valid });
+            info!(
+                "{} - {}{}",
+                if index == 0 { format!("Lap {}", timer.lap) } else { "Stage".to_string() },
+                format_lap_time(at_crossing),
+                if best { " (best)" } else if valid { "" } else { " (invalid)" }
+            );
+        } else {
+            info!("Clock started");
+        }
+        // The clock stops at the end of a stage until the start line is crossed again
+        if index != 0 {
+            timer.lap = 0;
+            timer.lap_time = 0.0;
+            timer.next_gate = 0;
+            timer.invalidated = false;
+            continue;
+        }
+        passed.write(CheckpointPassed { car, index, lap_time: at_crossing });
+        timer.lap += 1;
+        timer.lap_time = dt * (1.0 - fraction);
+        timer.invalidated = false;
+    }
+}
+
+/// Make the gate the first player drives through next glow
+fn highlight_next_gate(
+    timer_query: Query<&LapTimer, With<PrimaryCar>>,
+    mut highlighted: Local<Option<usize>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    gate_query: Query<Ref<CheckpointGate>>,
+) {
+    let Ok(timer) = timer_query.single() else {
+        return;
+    };
+    let any_new = gate_query.iter().any(|gate| gate.is_added());
+    if *highlighted == Some(timer.next_gate) && !any_new {
+        return;
+    }
+    *highlighted = Some(timer.next_gate);
+    for gate in gate_query.iter() {
+        let Some(material) = materials.get_mut(&gate.material) else {
+            continue;
+        };
+        let color = if gate.index == 0 {
+            Color::srgb(0.9, 0.9, 0.9)
+        } else {
+            Color::srgb(0.2, 0.5, 1.0)
+        };
+        let next = gate.index == timer.next_gate;
+        material.base_color = color;
+        material.emissive = if next {
+            LinearRgba::from(color) * 4.0
+        } else {
+            LinearRgba::BLACK
+        };
+    }
+}
@@ -0,0 +1,217 @@
+//! Track layouts saved as shareable `.track` files
+//!
+//! A track file holds a layout driven on a scan: the car spawn, the checkpoint
+//! gates, whether they make a rally stage, the collectible coins, the drivable
+//! area and the surface zones. It doesn't reference the splat, so one scan can
+//! have many tracks, and they can be passed around separately from the (much
+//! larger) scan. The ground planes and collision proxies describe the scan
+//! itself and stay in its sidecar or scene manifest.
+//!
+//! Drop a `.track` file onto the window or pass it on the command line to
+//! drive it on the open scan; it replaces the layout saved with the scan.
+//! Ctrl+Shift+S saves the current layout into the open track file, or into a
+//! new one next to the scan.
+
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boundary::DrivableArea;
+use crate::car::{Car, PrimaryCar};
+use crate::collectibles::SceneCollectibles;
+use crate::game_mode::GameMode;
+use crate::scene_manifest::SceneCheckpoints;
+use crate::sidecar::primary_splat_path;
+use crate::spawn_point::SpawnPoint;
+use crate::splat_loader::{resolve_local_path, LoadedSplat, LoadedSplats};
+use crate::surface_zones::{SurfaceZone, SurfaceZones};
+
+/// Plugin loading and saving track files
+pub struct TrackFilePlugin;
+
+impl Plugin for TrackFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<LoadTrack>()
+            .add_systems(Update, (load_track, save_track).chain());
+    }
+}
+
+/// Message requesting a track file to be loaded from a path
+#[derive(Message, Debug, Clone)]
+pub struct LoadTrack(pub String);
+
+/// Resource with the path of the track file being driven
+///
+/// While it exists, opening a splat keeps the track's layout instead of
+/// restoring the one saved in the splat's sidecar.
+#[derive(Resource)]
+pub struct ActiveTrack {
+    pub path: String,
+}
+
+/// Contents of a track file, in world space
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrackFile {
+    /// File name of the scan the track was laid out on, as a hint for players
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan: Option<String>,
+    /// Where the car starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<Transform>,
+    /// Checkpoint gates in driving order, the first being the start/finish line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checkpoints: Vec<Transform>,
+    /// Whether the gates make a point-to-point stage, driven in the Rally mode
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stage: bool,
+    /// Coins to collect, as points on the ground
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collectibles: Vec<Vec3>,
+    /// Corners of the drivable area
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boundary: Vec<Vec3>,
+    /// Regions of gravel, grass or ice
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub surfaces: Vec<SurfaceZone>,
+}
+
+impl TrackFile {
+    /// Read and parse a track file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let local = resolve_local_path(path).ok_or_else(|| format!("{} not found", path))?;
+        let contents = std::fs::read_to_string(&local).map_err(|err| err.to_string())?;
+        serde_json::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    /// Write the track to a file, on a single line to keep it small
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, contents).map_err(|err| err.to_string())
+    }
+}
+
+/// Whether a path points at a track file
+pub fn is_track_file(path: &str) -> bool {
+    path.to_lowercase().ends_with(".track")
+}
+
+/// The mode to drive a loaded track in: Rally for a stage, and out of it for
+/// a circuit
+pub fn mode_for_track(mode: GameMode, stage: bool) -> GameMode {
+    match (stage, mode.is_stage()) {
+        (true, false) => GameMode::Rally,
+        (false, true) => GameMode::Race,
+        _ => mode,
+    }
+}
+
+/// File name of a path, for comparing scans across directories
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path).file_name().map(|name| name.to_string_lossy().to_string())
+}
+
+/// First unused `<scan>-<n>.track` next to the scan, or in the working
+/// directory without one
+fn new_track_path(scan_path: Option<&str>) -> PathBuf {
+    let scan = scan_path.and_then(resolve_local_path);
+    let directory = scan.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+    let stem = scan
+        .as_deref()
+        .and_then(Path::file_stem)
+        .map_or("track".to_string(), |stem| stem.to_string_lossy().to_string());
+    (1..)
+        .map(|number| directory.join(format!("{}-{}.track", stem, number)))
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| directory.join(format!("{}.track", stem)))
+}
+
+/// Apply requested track files: spawn, gates, coins, drivable area and surface zones
+fn load_track(
+    mut commands: Commands,
+    mut requests: MessageReader<LoadTrack>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    mut spawn: ResMut<SpawnPoint>,
+    mut checkpoints: ResMut<SceneCheckpoints>,
+    mut mode: ResMut<GameMode>,
+    mut coins: ResMut<SceneCollectibles>,
+    mut area: ResMut<DrivableArea>,
+    mut zones: ResMut<SurfaceZones>,
+    mut car_query: Query<(&mut Car, &mut Transform), With<PrimaryCar>>,
+) {
+    for LoadTrack(path) in requests.read() {
+        let track = match TrackFile::load(path) {
+            Ok(track) => track,
+            Err(err) => {
+                error!("Failed to load track {}: {}", path, err);
+                continue;
+            }
+        };
+        info!("Loading track {} with {} gate(s)", path, track.checkpoints.len());
+        let scan = primary_splat_path(&loaded_splats, &splat_query).and_then(file_name);
+        if let (Some(expected), Some(scan)) = (&track.scan, scan) {
+            if *expected != scan {
+                warn!("Track {} was laid out on {}, not {}", path, expected, scan);
+            }
+        }
+
+        checkpoints.0 = track.checkpoints;
+        let track_mode = mode_for_track(*mode, track.stage);
+        mode.set_if_neq(track_mode);
+        coins.0 = track.collectibles;
+        area.points = track.boundary;
+        zones.0 = track.surfaces;
+        if let Some(car_spawn) = track.spawn {
+            spawn.0 = car_spawn;
+            if let Ok((mut car, mut transform)) = car_query.single_mut() {
+                *transform = car_spawn;
+                car.stop();
+            }
+        }
+        commands.insert_resource(ActiveTrack { path: path.clone() });
+    }
+}
+
+/// Save the layout into the open track file, or a new one, with Ctrl+Shift+S
+fn save_track(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    active_track: Option<Res<ActiveTrack>>,
+    loaded_splats: Res<LoadedSplats>,
+    splat_query: Query<&LoadedSplat>,
+    spawn: Res<SpawnPoint>,
+    checkpoints: Res<SceneCheckpoints>,
+    mode: Res<GameMode>,
+    coins: Res<SceneCollectibles>,
+    area: Res<DrivableArea>,
+    zones: Res<SurfaceZones>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::ControlLeft) || keyboard.pressed(KeyCode::ControlRight);
+    let shift = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if !ctrl || !shift || !keyboard.just_pressed(KeyCode::KeyS) {
+        return;
+    }
+
+    let scan_path = primary_splat_path(&loaded_splats, &splat_query);
+    let path = active_track
+        .and_then(|track| resolve_local_path(&track.path))
+        .unwrap_or_else(|| new_track_path(scan_path));
+    let track = TrackFile {
+        scan: scan_path.and_then(file_name),
+        spawn: Some(spawn.0),
+        checkpoints: checkpoints.0.clone(),
+        stage: mode.is_stage(),
+        collectibles: coins.0.clone(),
+        boundary: area.points.clone(),
+        surfaces: zones.0.clone(),
+    };
+    match track.save(&path) {
+        Ok(()) => {
+            let path = path.to_string_lossy().to_string();
+            info!("Saved the track to {}", path);
+            commands.insert_resource(ActiveTrack { path });
+        }
+        Err(err) => error!("Failed to save track {}: {}", path.display(), err),
+    }
+}
@@ -0,0 +1,306 @@
+//! Off-track and wrong-way detection
+//!
+//! A car is off track while it is outside the drivable area, and going the
+//! wrong way once it has driven against the order of the gates for
+//! `WRONG_WAY_DELAY`. The direction of the track at a point is that of the
+//! nearest leg between consecutive gates. Breaking either limit during a lap
+//! is dealt with by the game mode's rule: the lap is invalidated, or
+//! `PENALTY` seconds are added to it, or nothing happens beyond the warning.
+//! Each time a car leaves the track or turns the wrong way counts once.
+//!
+//! The first player is warned in the middle of the screen, with the penalty
+//! or the invalidation shown below the warning for a moment.
+
+use bevy::prelude::*;
+
+use crate::boundary::DrivableArea;
+use crate::car::{Car, CarSet, PrimaryCar};
+use crate::game_mode::{GameMode, TrackLimitRule};
+use crate::scene_manifest::SceneCheckpoints;
+use crate::track::LapTimer;
+
+/// Seconds added to the lap for each violation under the penalty rule
+pub const PENALTY: f32 = 2.0;
+
+/// Seconds of driving against the gates before the car is going the wrong way
+const WRONG_WAY_DELAY: f32 = 1.5;
+
+/// Below this speed the direction of travel doesn't count, in m/s
+const WRONG_WAY_SPEED: f32 = 3.0;
+
+/// The car drives against the gates while the cosine between its direction of
+/// travel and the track's is below this
+const WRONG_WAY_COS: f32 = -0.5;
+
+/// Seconds the penalty notice stays up
+const NOTICE_DURATION: f32 = 2.5;
+
+/// Plugin for the track limits
+pub struct TrackLimitsPlugin;
+
+impl Plugin for TrackLimitsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<TrackLimitsBroken>()
+            .add_systems(Startup, spawn_limits_warning)
+            .add_systems(
+                Update,
+                (add_track_limits, detect_track_limits, update_limits_warning)
+                    .chain()
+                    .after(CarSet::Camera),
+            );
+    }
+}
+
+/// A way of breaking the track limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackLimit {
+    OffTrack,
+    WrongWay,
+}
+
+impl TrackLimit {
+    pub fn label(self) -> &'static str {
+        match self {
+            TrackLimit::OffTrack => "left the track",
+            TrackLimit::WrongWay => "went the wrong way",
+        }
+    }
+}
+
+/// Message sent when a car breaks the track limits during a lap
+#[derive(Message, Debug, Clone, Copy)]
+pub struct TrackLimitsBroken {
+    /// The car breaking them
+    pub car: Entity,
+    pub limit: TrackLimit,
+    /// What the mode's rule did to the lap
+    pub rule: TrackLimitRule,
+}
+
+/// Component with where a car stands against the track limits
+#[derive(Component, Default)]
+pub struct TrackLimits {
+    /// Whether the car is outside the drivable area
+    pub off_track: bool,
+    /// Whether the car is going the wrong way
+    pub wrong_way: bool,
+    /// Seconds the car has driven against the gates
+    wrong_way_time: f32,
+    /// Car position last frame, for its direction of travel
+    previous_position: Option<Vec3>,
+}
+
+/// Marker for the warning panel
+#[derive(Component)]
+struct LimitsWarning;
+
+/// Marker for the warning text
+#[derive(Component)]
+struct LimitsWarningText;
+
+/// Marker for the text with what the rule did
+#[derive(Component)]
+struct LimitsNoticeText;
+
+/// Direction of the track at a point: that of the nearest leg between
+/// consecutive gates, back to the start line unless the gates make a stage
+fn track_direction(gates: &[Transform], stage: bool, point: Vec3) -> Option<Vec3> {
+    let legs = if stage {
+        gates.len().saturating_sub(1)
+    } else {
+        gates.len()
+    };
+    if gates.len() < 2 {
+        return None;
+    }
+    (0..legs)
+        .map(|index| {
+            let (a, b) = (
+                gates[index].translation,
+                gates[(index + 1) % gates.len()].translation,
+            );
+            let leg = b - a;
+            let t = ((point - a).dot(leg) / leg.length_squared().max(1e-6)).clamp(0.0, 1.0);
+            (leg, (a + leg * t).distance_squared(point))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .and_then(|(leg, _)| leg.try_normalize())
+}
+
+/// Watch the track limits of every car that drives
+fn add_track_limits(
+    mut commands: Commands,
+    car_query: Query<Entity, (With<Car>, Without<TrackLimits>)>,
+) {
+    for entity in car_query.iter() {
+        commands.entity(entity).insert(TrackLimits::default());
+    }
+}
+
+/// Find cars leaving the track or going the wrong way and apply the mode's rule
+fn detect_track_limits(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    area: Res<DrivableArea>,
+    checkpoints: Res<SceneCheckpoints>,
+    mut broken: MessageWriter<TrackLimitsBroken>,
+    mut car_query: Query<(
+        Entity,
+        &Transform,
+        &mut LapTimer,
+        &mut TrackLimits,
+        Has<PrimaryCar>,
+    )>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    let rule = mode.track_limit_rule();
+    for (car, transform, mut timer, mut limits, primary) in car_query.iter_mut() {
+        let position = transform.translation;
+        let previous = limits.previous_position.replace(position);
+
+        let off_track = area.is_closed() && !area.contains(position);
+        let left_track = off_track && !limits.off_track;
+        limits.off_track = off_track;
+
+        // Against the gates at speed for long enough, while the clock runs
+        let against = previous
+            .map(|previous| (position - previous) / dt)
+            .filter(|velocity| velocity.length() > WRONG_WAY_SPEED)
+            .zip(track_direction(&checkpoints.0, mode.is_stage(), position))
+            .is_some_and(|(velocity, direction)| {
+                velocity.normalize().dot(direction) < WRONG_WAY_COS
+            });
+        limits.wrong_way_time = if against && timer.started() {
+            limits.wrong_way_time + dt
+        } else {
+            0.0
+        };
+        let wrong_way = limits.wrong_way_time >= WRONG_WAY_DELAY;
+        let turned_around = wrong_way && !limits.wrong_way;
+        limits.wrong_way = wrong_way;
+
+        if !timer.started() {
+            continue;
+        }
+        let limit = match (left_track, turned_around) {
+            (true, _) => TrackLimit::OffTrack,
+            (false, true) => TrackLimit::WrongWay,
+            (false, false) => continue,
+        };
+        match rule {
+            TrackLimitRule::Warn => {}
+            TrackLimitRule::Penalty => {
+                timer.lap_time += PENALTY;
+                if primary {
+                    info!("{} s penalty - {}", PENALTY, limit.label());
+                }
+            }
+            TrackLimitRule::Invalidate => {
+                if !timer.invalidated && primary {
+                    info!("Lap {} invalidated - {}", timer.lap, limit.label());
+                }
+                timer.invalidated = true;
+            }
+        }
+        broken.write(TrackLimitsBroken { car, limit, rule });
+    }
+}
+
+/// Spawn the hidden warning in the middle of the screen
+fn spawn_limits_warning(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(38.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            LimitsWarning,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                limits_text(34.0, Color::srgb(1.0, 0.2, 0.1)),
+                LimitsWarningText,
+            ));
+            parent.spawn((limits_text(22.0, Color::WHITE), LimitsNoticeText));
+        });
+}
+
+/// Text of the given size and color with a shadow
+fn limits_text(font_size: f32, color: Color) -> impl Bundle {
+    (
+        Text::new(""),
+        TextFont {
+            font_size,
+            ..default()
+        },
+        TextColor(color),
+        TextShadow::default(),
+    )
+}
+
+/// Warn the first player while they are off track or going the wrong way, and
+/// show what the rule did
+fn update_limits_warning(
+    time: Res<Time>,
+    mut broken: MessageReader<TrackLimitsBroken>,
+    mut notice: Local<Option<(String, f32)>>,
+    car_query: Query<(Entity, &TrackLimits), With<PrimaryCar>>,
+    mut panel_query: Query<&mut Visibility, With<LimitsWarning>>,
+    mut warning_query: Query<&mut Text, With<LimitsWarningText>>,
+    mut notice_query: Query<&mut Text, (With<LimitsNoticeText>, Without<LimitsWarningText>)>,
+) {
+    let Ok((car, limits)) = car_query.single() else {
+        return;
+    };
+    for violation in broken.read().filter(|violation| violation.car == car) {
+        let text = match violation.rule {
+            TrackLimitRule::Warn => continue,
+            TrackLimitRule::Penalty => {
+                format!("+{:.1} s penalty - {}", PENALTY, violation.limit.label())
+            }
+            TrackLimitRule::Invalidate => format!("Lap invalidated - {}", violation.limit.label()),
+        };
+        *notice = Some((text, NOTICE_DURATION));
+    }
+    if let Some((_, seconds)) = notice.as_mut() {
+        *seconds -= time.delta_secs();
+        if *seconds <= 0.0 {
+            *notice = None;
+        }
+    }
+
+    let warning = if limits.wrong_way {
+        "WRONG WAY"
+    } else if limits.off_track {
+        "OFF TRACK"
+    } else {
+        ""
+    };
+    let notice_text = notice.as_ref().map_or("", |(text, _)| text.as_str());
+    if let Ok(mut visibility) = panel_query.single_mut() {
+        visibility.set_if_neq(if warning.is_empty() && notice_text.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+    if let Ok(mut text) = warning_query.single_mut() {
+        if text.0 != warning {
+            text.0 = warning.to_string();
+        }
+    }
+    if let Ok(mut text) = notice_query.single_mut() {
+        if text.0 != notice_text {
+            text.0 = notice_text.to_string();
+        }
+    }
+}
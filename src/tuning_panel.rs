@@ -0,0 +1,213 @@
+//! Live tuning panel
+//!
+//! Shift+F3 opens a developer panel with sliders for the first player's car,
+//! the chase camera and the ground planes, so the handling can be tuned while
+//! driving instead of editing a vehicle file or recompiling. Changes apply
+//! right away but aren't saved; the values worth keeping go into the vehicle
+//! definition (see `vehicle`).
+
+use std::ops::RangeInclusive;
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
+
+use crate::camera::CarCamera;
+use crate::camera_effects::CameraEffects;
+use crate::car::{Car, PrimaryCar, Surface};
+use crate::ground_plane::GroundPlanes;
+
+/// Plugin for the live tuning panel
+pub struct TuningPanelPlugin;
+
+impl Plugin for TuningPanelPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin::default());
+        }
+        app.init_resource::<TuningPanel>()
+            .add_systems(Update, toggle_tuning_panel)
+            .add_systems(
+                EguiPrimaryContextPass,
+                show_tuning_panel.run_if(|panel: Res<TuningPanel>| panel.open),
+            );
+    }
+}
+
+/// Resource with whether the tuning panel is open
+#[derive(Resource, Default)]
+struct TuningPanel {
+    open: bool,
+}
+
+/// Open and close the panel with Shift+F3
+fn toggle_tuning_panel(keyboard: Res<ButtonInput<KeyCode>>, mut panel: ResMut<TuningPanel>) {
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if shift && keyboard.just_pressed(KeyCode::F3) {
+        panel.open = !panel.open;
+    }
+}
+
+/// A labelled slider, returning whether it moved
+fn slider(ui: &mut egui::Ui, label: &str, value: &mut f32, range: RangeInclusive<f32>) -> bool {
+    ui.add(egui::Slider::new(value, range).text(label))
+        .changed()
+}
+
+/// Draw the panel and apply whatever changed in it
+fn show_tuning_panel(
+    mut contexts: EguiContexts,
+    mut panel: ResMut<TuningPanel>,
+    mut car_query: Query<(&mut Car, &mut Mass, &mut CenterOfMass), With<PrimaryCar>>,
+    mut camera_query: Query<&mut CarCamera>,
+    mut effects: ResMut<CameraEffects>,
+    mut ground_planes: ResMut<GroundPlanes>,
+) -> Result {
+    let mut open = panel.open;
+    egui::Window::new("Tuning")
+        .open(&mut open)
+        .default_width(320.0)
+        .vscroll(true)
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::CollapsingHeader::new("Car")
+                .default_open(true)
+                .show(ui, |ui| {
+                    let Ok((mut car, mut mass, mut center_of_mass)) = car_query.single_mut() else {
+                        ui.label("No car");
+                        return;
+                    };
+                    if tune_car(ui, car.bypass_change_detection()) {
+                        car.set_changed();
+                        // The rigid body keeps its own copy of these
+                        *mass = Mass(car.mass);
+                        *center_of_mass = CenterOfMass(Vec3::Y * car.center_of_mass_height);
+                    }
+                });
+
+            egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+                for mut camera in camera_query.iter_mut() {
+                    let tuned = camera.bypass_change_detection();
+                    let mut changed = false;
+                    changed |= slider(ui, "height", &mut tuned.offset.y, 0.5..=20.0);
+                    changed |= slider(ui, "distance", &mut tuned.offset.z, 2.0..=40.0);
+                    changed |= slider(ui, "smoothness", &mut tuned.smoothness, 0.5..=20.0);
+                    changed |= slider(ui, "look ahead", &mut tuned.look_ahead, 0.0..=20.0);
+                    if changed {
+                        camera.set_changed();
+                    }
+                }
+                ui.separator();
+                let tuned = effects.bypass_change_detection();
+                let mut changed = false;
+                changed |= slider(ui, "speed FOV", &mut tuned.speed_fov, 0.0..=1.0);
+                changed |= slider(ui, "speed shake", &mut tuned.speed_shake, 0.0..=0.02);
+                changed |= slider(ui, "impact shake", &mut tuned.impact_shake, 0.0..=3.0);
+                if changed {
+                    effects.set_changed();
+                }
+            });
+
+            egui::CollapsingHeader::new("Ground planes").show(ui, |ui| {
+                let planes = ground_planes.bypass_change_detection();
+                if planes.planes.is_empty() {
+                    ui.label("No ground plane selected");
+                }
+                let mut changed = false;
+                for (index, plane) in planes.planes.iter_mut().enumerate() {
+                    ui.label(format!("Plane {}", index + 1));
+                    ui.horizontal(|ui| {
+                        ui.label("origin");
+                        for axis in 0..3 {
+                            changed |= ui
+                                .add(egui::DragValue::new(&mut plane.origin[axis]).speed(0.01))
+                                .changed();
+                        }
+                    });
+                    let mut normal = plane.normal;
+                    let mut tilted = false;
+                    for (axis, label) in
+                        ["normal x", "normal y", "normal z"].into_iter().enumerate()
+                    {
+                        tilted |= slider(ui, label, &mut normal[axis], -1.0..=1.0);
+                    }
+                    if tilted {
+                        if let Some(normal) = normal.try_normalize() {
+                            plane.normal = normal;
+                            plane.up = normal;
+                            changed = true;
+                        }
+                    }
+                    if ui.button("Snap normal to axis").clicked() {
+                        plane.snap_normal_to_axis();
+                        changed = true;
+                    }
+                    ui.separator();
+                }
+                if changed {
+                    ground_planes.set_changed();
+                }
+            });
+        });
+    panel.open = open;
+    Ok(())
+}
+
+/// Sliders for the car's tunables, returning whether any moved
+fn tune_car(ui: &mut egui::Ui, car: &mut Car) -> bool {
+    let mut changed = false;
+    ui.label("Engine and brakes");
+    changed |= slider(ui, "max speed", &mut car.max_speed, 5.0..=150.0);
+    changed |= slider(ui, "peak torque", &mut car.peak_torque, 50.0..=1500.0);
+    changed |= slider(ui, "final drive", &mut car.final_drive, 1.0..=8.0);
+    changed |= slider(ui, "shift time", &mut car.shift_time, 0.0..=1.0);
+    changed |= slider(ui, "brake power", &mut car.brake_power, 1.0..=100.0);
+    changed |= slider(
+        ui,
+        "rolling resistance",
+        &mut car.rolling_resistance,
+        0.0..=0.05,
+    );
+
+    ui.label("Steering");
+    changed |= slider(ui, "max steering", &mut car.max_steering, 0.1..=1.2);
+    changed |= slider(ui, "steering speed", &mut car.steering_speed, 0.5..=10.0);
+
+    ui.label("Chassis");
+    changed |= slider(ui, "mass", &mut car.mass, 300.0..=4000.0);
+    changed |= slider(
+        ui,
+        "center of mass",
+        &mut car.center_of_mass_height,
+        0.0..=1.5,
+    );
+    changed |= slider(ui, "wheelbase", &mut car.wheelbase, 1.5..=4.0);
+    changed |= slider(ui, "track width", &mut car.track_width, 1.0..=2.5);
+    changed |= slider(ui, "wheel radius", &mut car.wheel_radius, 0.2..=0.6);
+
+    ui.label("Suspension");
+    changed |= slider(ui, "spring rate", &mut car.spring_rate, 5_000.0..=200_000.0);
+    changed |= slider(ui, "damping", &mut car.damping, 100.0..=20_000.0);
+    changed |= slider(ui, "travel", &mut car.suspension_travel, 0.05..=0.6);
+
+    ui.label("Tires");
+    changed |= slider(ui, "load sensitivity", &mut car.load_sensitivity, 0.0..=1.0);
+    for surface in Surface::ALL {
+        let Some(grip) = car.grip.get_mut(&surface) else {
+            continue;
+        };
+        let name = format!("{:?}", surface).to_lowercase();
+        changed |= slider(
+            ui,
+            &format!("{} grip", name),
+            &mut grip.longitudinal,
+            0.0..=3.0,
+        );
+        changed |= slider(
+            ui,
+            &format!("{} side grip", name),
+            &mut grip.lateral,
+            0.0..=3.0,
+        );
+    }
+    changed
+}
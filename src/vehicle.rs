@@ -0,0 +1,542 @@
+//! Data-driven vehicle definitions
+//!
+//! Every `.toml` file in `assets/vehicles/` describes a vehicle: its `Car`
+//! tunables (speed, engine, gearbox, steering, suspension, grip, ...) and how
+//! it looks. Any tunable left out of a file keeps the built-in default, so a
+//! definition only needs the values that make the vehicle different. The
+//! files are read at startup, so new vehicles need no recompiling.
+//!
+//! The vehicle menu is open when the game starts and holds the car in place:
+//! Up/Down pick a vehicle and Enter starts driving it. When the game starts in
+//! the main menu, the vehicle is picked there instead, and `--vehicle <name>`
+//! on the command line picks it without a menu. Press 'V' to open the menu
+//! again.
+//!
+//! Instead of the procedural boxes a vehicle can use a glTF model, given as
+//! `appearance.model` relative to the `assets` directory. The model's wheel
+//! nodes are found by name (`wheel_fl`, `wheel_fr`, `wheel_rl` and `wheel_rr`
+//! unless `appearance.wheel_nodes` says otherwise) and move with the
+//! suspension like the procedural wheels.
+//!
+//! ```toml
+//! name = "Hatchback"
+//!
+//! [car]
+//! max_speed = 32.0
+//! peak_torque = 200.0
+//!
+//! [appearance]
+//! body_color = [0.2, 0.4, 0.8]
+//! ```
+
+use std::path::Path;
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::car::{Car, CarSet, CarWheel, PrimaryCar};
+use crate::car_lights::spawn_car_lights;
+use crate::cli;
+use crate::main_menu::{open_main_menu_at_start, MainMenu};
+
+/// Directory the vehicle definitions are read from
+pub const VEHICLES_DIR: &str = "assets/vehicles";
+
+/// Plugin loading vehicle definitions and dressing the car as the selected one
+pub struct VehiclePlugin;
+
+impl Plugin for VehiclePlugin {
+    fn build(&self, app: &mut App) {
+        let vehicles = Vehicles::load(Path::new(VEHICLES_DIR), &toml::Table::new());
+        let selected = match &cli::args().vehicle {
+            Some(name) => vehicles.find(name).unwrap_or_else(|| {
+                warn!("There is no vehicle called {}", name);
+                0
+            }),
+            None => 0,
+        };
+        app.insert_resource(vehicles)
+            .insert_resource(SelectedVehicle(selected))
+            .init_resource::<VehicleMenu>()
+            .add_systems(Startup, open_vehicle_menu.after(open_main_menu_at_start))
+            .add_systems(
+                Update,
+                (
+                    (navigate_vehicle_menu, update_vehicle_menu, apply_selected_vehicle).chain(),
+                    find_model_wheels,
+                    hold_car_in_menu.after(CarSet::Input).before(CarSet::Physics),
+                ),
+            );
+    }
+}
+
+/// A vehicle as described by a definition file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VehicleDefinition {
+    pub name: String,
+    /// Tunables copied onto the `Car` component
+    #[serde(default)]
+    pub car: Car,
+    #[serde(default)]
+    pub appearance: VehicleAppearance,
+}
+
+impl Default for VehicleDefinition {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            car: Car::default(),
+            appearance: VehicleAppearance::default(),
+        }
+    }
+}
+
+/// Meshes and colors of a vehicle, built from boxes and cylinders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VehicleAppearance {
+    /// Colors in sRGB, from 0 to 1
+    pub body_color: [f32; 3],
+    pub cabin_color: [f32; 3],
+    pub wheel_color: [f32; 3],
+    /// Size of the main body box; it sits on the car origin
+    pub body_size: Vec3,
+    /// Size of the cabin box
+    pub cabin_size: Vec3,
+    /// Center of the cabin box relative to the car origin
+    pub cabin_offset: Vec3,
+    /// Width of the wheel cylinders; their radius is the car's wheel radius
+    pub wheel_width: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Extra boxes such as spoilers, bumpers or a truck bed
+    pub parts: Vec<VehiclePart>,
+    /// glTF model replacing the procedural meshes, relative to the `assets` directory
+    pub model: Option<String>,
+    /// Names of the model's front left, front right, rear left and rear right wheel nodes
+    pub wheel_nodes: [String; 4],
+    /// Position of the model's origin relative to the car origin
+    pub model_offset: Vec3,
+    /// Rotation of the model around the vertical axis in degrees, e.g. 180
+    /// for models facing +Z
+    pub model_yaw: f32,
+    pub model_scale: f32,
+}
+
+/// An extra box on a vehicle's body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VehiclePart {
+    pub size: Vec3,
+    /// Center of the box relative to the car origin
+    pub offset: Vec3,
+    /// Color in sRGB, from 0 to 1
+    pub color: [f32; 3],
+}
+
+impl Default for VehicleAppearance {
+    fn default() -> Self {
+        Self {
+            body_color: [0.8, 0.2, 0.2],
+            cabin_color: [0.6, 0.15, 0.15],
+            wheel_color: [0.1, 0.1, 0.1],
+            body_size: Vec3::new(2.0, 0.8, 4.0),
+            cabin_size: Vec3::new(1.6, 0.6, 2.0),
+            cabin_offset: Vec3::new(0.0, 1.0, 0.2),
+            wheel_width: 0.3,
+            metallic: 0.8,
+            roughness: 0.3,
+            parts: Vec::new(),
+            model: None,
+            wheel_nodes: ["wheel_fl", "wheel_fr", "wheel_rl", "wheel_rr"].map(String::from),
+            model_offset: Vec3::ZERO,
+            model_yaw: 0.0,
+            model_scale: 1.0,
+        }
+    }
+}
+
+/// Resource with every known vehicle
+#[derive(Resource, Clone)]
+pub struct Vehicles(pub Vec<VehicleDefinition>);
+
+impl Vehicles {
+    /// Read all definitions in a directory, in file name order, falling back
+    /// to the built-in vehicle if there are none
+    ///
+    /// `car_defaults` holds `Car` tunables for the ones a definition leaves
+    /// out, in the same form as a definition's `[car]` table.
+    pub fn load(dir: &Path, car_defaults: &toml::Table) -> Self {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        let mut vehicles = Vec::new();
+        for path in paths {
+            match VehicleDefinition::load(&path, car_defaults) {
+                Ok(vehicle) => {
+                    info!("Loaded vehicle '{}' from {}", vehicle.name, path.display());
+                    vehicles.push(vehicle);
+                }
+                Err(err) => warn!("Ignoring vehicle {}: {}", path.display(), err),
+            }
+        }
+        if vehicles.is_empty() {
+            info!("No vehicle definitions in {}, using the built-in car", dir.display());
+            let car = toml::Value::Table(car_defaults.clone())
+                .try_into()
+                .unwrap_or_else(|err| {
+                    warn!("Ignoring unreadable car defaults: {}", err);
+                    Car::default()
+                });
+            vehicles.push(VehicleDefinition {
+                car,
+                ..default()
+            });
+        }
+        Self(vehicles)
+    }
+
+    /// Index of the vehicle of the given name, ignoring case
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|vehicle| vehicle.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl VehicleDefinition {
+    /// Read a vehicle definition file, taking the tunables it leaves out from
+    /// `car_defaults`
+    pub fn load(path: &Path, car_defaults: &toml::Table) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut definition: toml::Table = toml::from_str(&contents).map_err(|err| err.to_string())?;
+        let mut car = car_defaults.clone();
+        match definition.remove("car") {
+            Some(toml::Value::Table(own)) => car.extend(own),
+            Some(_) => return Err("car is not a table".to_string()),
+            None => {}
+        }
+        definition.insert("car".to_string(), toml::Value::Table(car));
+        toml::Value::Table(definition)
+            .try_into()
+            .map_err(|err: toml::de::Error| err.to_string())
+    }
+}
+
+/// Resource with the index into `Vehicles` of the vehicle being driven
+#[derive(Resource, Debug, Default, PartialEq)]
+pub struct SelectedVehicle(pub usize);
+
+/// Resource tracking the vehicle menu
+#[derive(Resource, Default)]
+pub struct VehicleMenu {
+    /// The menu's root node while it is open
+    root: Option<Entity>,
+    /// Index into `Vehicles` of the highlighted vehicle
+    highlighted: usize,
+}
+
+impl VehicleMenu {
+    /// Whether the menu is open
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+}
+
+/// Marker for the text listing the vehicles
+#[derive(Component)]
+struct VehicleMenuText;
+
+/// Show the vehicle menu before the first drive, unless the main menu is up
+/// or the command line picked the vehicle
+fn open_vehicle_menu(
+    mut commands: Commands,
+    mut menu: ResMut<VehicleMenu>,
+    main_menu: Res<MainMenu>,
+    vehicles: Res<Vehicles>,
+) {
+    let picked = cli::args()
+        .vehicle
+        .as_ref()
+        .is_some_and(|name| vehicles.find(name).is_some());
+    if !main_menu.is_open() && !picked {
+        menu.root = Some(spawn_vehicle_menu(&mut commands));
+    }
+}
+
+/// Open the menu with 'V', move the highlight and pick a vehicle
+fn navigate_vehicle_menu(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    vehicles: Res<Vehicles>,
+    mut menu: ResMut<VehicleMenu>,
+    mut selected: ResMut<SelectedVehicle>,
+) {
+    let Some(root) = menu.root else {
+        if keyboard.just_pressed(KeyCode::KeyV) {
+            menu.highlighted = selected.0;
+            menu.root = Some(spawn_vehicle_menu(&mut commands));
+        }
+        return;
+    };
+
+    let count = vehicles.0.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        menu.highlighted = (menu.highlighted + 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        menu.highlighted = (menu.highlighted + count - 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::Enter) {
+        commands.entity(root).despawn();
+        menu.root = None;
+        selected.set_if_neq(SelectedVehicle(menu.highlighted));
+    }
+}
+
+/// Spawn the vehicle menu panel and return its root
+fn spawn_vehicle_menu(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                VehicleMenuText,
+            ));
+        })
+        .id()
+}
+
+/// List the vehicles with their main figures, marking the highlighted one
+fn update_vehicle_menu(
+    menu: Res<VehicleMenu>,
+    vehicles: Res<Vehicles>,
+    mut text_query: Query<(&mut Text, Ref<VehicleMenuText>)>,
+) {
+    let Ok((mut text, marker)) = text_query.single_mut() else {
+        return;
+    };
+    if !menu.is_changed() && !marker.is_added() {
+        return;
+    }
+
+    let mut lines = vec![
+        "Choose a vehicle - Up/Down: select, Enter: drive".to_string(),
+        String::new(),
+    ];
+    for (index, vehicle) in vehicles.0.iter().enumerate() {
+        let marker = if index == menu.highlighted { ">" } else { " " };
+        let car = &vehicle.car;
+        lines.push(format!(
+            "{} {:<12} {:>4.0} kg  {:>4.0} Nm  top {:>3.0} km/h",
+            marker,
+            vehicle.name,
+            car.mass,
+            car.peak_torque,
+            car.max_speed * 3.6,
+        ));
+    }
+    text.0 = lines.join("\n");
+}
+
+/// Keep the car standing still while the menu is open
+fn hold_car_in_menu(menu: Res<VehicleMenu>, mut car_query: Query<&mut Car, With<PrimaryCar>>) {
+    if !menu.is_open() {
+        return;
+    }
+    if let Ok(mut car) = car_query.single_mut() {
+        car.throttle = 0.0;
+        car.brake = 0.0;
+        car.stop();
+    }
+}
+
+/// Give the car the tunables and meshes of the selected vehicle when the selection changes
+fn apply_selected_vehicle(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    vehicles: Res<Vehicles>,
+    asset_server: Res<AssetServer>,
+    selected: Res<SelectedVehicle>,
+    mut car_query: Query<(Entity, &mut Car), With<PrimaryCar>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    let Some(vehicle) = vehicles.0.get(selected.0) else {
+        return;
+    };
+    let Ok((entity, mut car)) = car_query.single_mut() else {
+        return;
+    };
+
+    // Keep the player's choice of gearbox across vehicles
+    let shift_mode = car.shift_mode;
+    *car = vehicle.car.clone();
+    car.shift_mode = shift_mode;
+    car.stop();
+
+    let mut entity_commands = commands.entity(entity);
+    entity_commands
+        .insert((Mass(car.mass), CenterOfMass(Vec3::Y * car.center_of_mass_height)))
+        .despawn_children();
+    let appearance = &vehicle.appearance;
+    if let Some(model) = &appearance.model {
+        let scene = asset_server.load(GltfAssetLabel::Scene(0).from_asset(model.clone()));
+        entity_commands.with_child((
+            SceneRoot(scene),
+            CarModel {
+                wheel_nodes: appearance.wheel_nodes.clone(),
+                wheels_found: false,
+            },
+            Transform::from_translation(appearance.model_offset)
+                .with_rotation(Quat::from_rotation_y(appearance.model_yaw.to_radians()))
+                .with_scale(Vec3::splat(appearance.model_scale)),
+        ));
+    } else {
+        spawn_procedural_body(&mut entity_commands, &car, appearance, &mut meshes, &mut materials);
+    }
+    spawn_car_lights(&mut entity_commands, appearance.body_size, &mut meshes, &mut materials);
+
+    info!("Driving the {}", vehicle.name);
+}
+
+/// Build the car's body and wheels from boxes and cylinders
+pub fn spawn_procedural_body(
+    entity_commands: &mut EntityCommands,
+    car: &Car,
+    appearance: &VehicleAppearance,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let material = |color: [f32; 3], metallic: f32, roughness: f32| StandardMaterial {
+        base_color: Color::srgb(color[0], color[1], color[2]),
+        metallic,
+        perceptual_roughness: roughness,
+        ..default()
+    };
+    let body_mesh = meshes.add(Cuboid::from_size(appearance.body_size));
+    let cabin_mesh = meshes.add(Cuboid::from_size(appearance.cabin_size));
+    let wheel_mesh = meshes.add(Cylinder::new(car.wheel_radius, appearance.wheel_width));
+    let body_material =
+        materials.add(material(appearance.body_color, appearance.metallic, appearance.roughness));
+    let cabin_material = materials.add(material(
+        appearance.cabin_color,
+        appearance.metallic * 0.75,
+        appearance.roughness + 0.1,
+    ));
+    let wheel_material = materials.add(material(appearance.wheel_color, 0.2, 0.8));
+    let parts: Vec<_> = appearance
+        .parts
+        .iter()
+        .map(|part| {
+            (
+                meshes.add(Cuboid::from_size(part.size)),
+                materials.add(material(part.color, appearance.metallic, appearance.roughness)),
+                part.size,
+                part.offset,
+            )
+        })
+        .collect();
+
+    entity_commands.with_children(|parent| {
+        parent.spawn((
+            CarPanel {
+                size: appearance.body_size,
+                detachable: false,
+            },
+            Mesh3d(body_mesh),
+            MeshMaterial3d(body_material),
+            Transform::from_xyz(0.0, appearance.body_size.y * 0.5, 0.0),
+        ));
+        parent.spawn((
+            CarPanel {
+                size: appearance.cabin_size,
+                detachable: false,
+            },
+            Mesh3d(cabin_mesh),
+            MeshMaterial3d(cabin_material),
+            Transform::from_translation(appearance.cabin_offset),
+        ));
+        for (mesh, material, size, offset) in parts {
+            parent.spawn((
+                CarPanel {
+                    size,
+                    detachable: true,
+                },
+                Mesh3d(mesh),
+                MeshMaterial3d(material),
+                Transform::from_translation(offset),
+            ));
+        }
+        for (index, position) in car.wheel_positions().into_iter().enumerate() {
+            let rest = Transform::from_translation(position)
+                .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+            parent.spawn((
+                CarWheel::new(index, rest),
+                Mesh3d(wheel_mesh.clone()),
+                MeshMaterial3d(wheel_material.clone()),
+                rest,
+            ));
+        }
+    });
+}
+
+/// Component marking a box of a procedural car's bodywork
+#[derive(Component)]
+pub struct CarPanel {
+    pub size: Vec3,
+    /// Whether crashes can knock it off, like spoilers and bumpers
+    pub detachable: bool,
+}
+
+/// Component on a car's glTF model, until its wheel nodes have been found
+#[derive(Component)]
+struct CarModel {
+    wheel_nodes: [String; 4],
+    wheels_found: bool,
+}
+
+/// Tag the wheel nodes of a car model once its scene has been spawned
+fn find_model_wheels(
+    mut commands: Commands,
+    mut model_query: Query<(Entity, &mut CarModel)>,
+    children_query: Query<&Children>,
+    node_query: Query<(&Name, &Transform)>,
+) {
+    for (entity, mut model) in model_query.iter_mut() {
+        if model.wheels_found {
+            continue;
+        }
+        for node in children_query.iter_descendants(entity) {
+            let Ok((name, transform)) = node_query.get(node) else {
+                continue;
+            };
+            if let Some(index) = model.wheel_nodes.iter().position(|wheel| wheel == name.as_str()) {
+                commands.entity(node).insert(CarWheel::new(index, *transform));
+                model.wheels_found = true;
+            }
+        }
+    }
+}
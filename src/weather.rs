@@ -0,0 +1,367 @@
+//! Weather
+//!
+//! Every race can be driven in clear weather, rain, fog or snow, picked on the
+//! Weather line of the main menu. Rain and snow fall around the camera, and
+//! in the rain drops gather on the screen as on a windshield and run down it.
+//! Rain, fog and snow cut the visibility: meshes fade into the distance fog
+//! (see `sky`), and since the splats don't take the fog, a haze over the
+//! screen dims the whole view. Wet and snowy roads cut the tire grip of every car on top of that of
+//! the surface, and the overcast sky dims the sun (see `time_of_day`).
+
+use std::f32::consts::TAU;
+
+use bevy::light::NotShadowCaster;
+use bevy::prelude::*;
+
+use crate::camera::{CameraMode, CarCamera};
+use crate::car::{Car, CarSet};
+use crate::particles::jitter;
+use crate::split_screen::SecondCamera;
+
+/// Rain drops or snowflakes falling around the camera at once
+const PRECIPITATION_COUNT: usize = 400;
+
+/// Half the width and half the height of the box around the camera the rain
+/// and snow fall in, in meters
+const PRECIPITATION_RADIUS: f32 = 25.0;
+const PRECIPITATION_HEIGHT: f32 = 12.0;
+
+/// How fast rain and snow fall, in m/s
+const RAIN_SPEED: f32 = 14.0;
+const SNOW_SPEED: f32 = 1.5;
+
+/// How far snowflakes sway from side to side, in m/s
+const SNOW_SWAY: f32 = 0.8;
+
+/// Drops hitting the screen per second in the rain, and most on it at once
+const DROPLET_RATE: f32 = 6.0;
+const MAX_DROPLETS: usize = 30;
+
+/// Seconds a drop stays on the screen
+const DROPLET_LIFETIME: f32 = 3.0;
+
+/// Plugin for the weather
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .add_systems(Startup, spawn_weather_screen)
+            .add_systems(
+                Update,
+                (
+                    announce_weather,
+                    update_weather_grip
+                        .after(CarSet::Input)
+                        .before(CarSet::Physics),
+                    (
+                        spawn_precipitation,
+                        fall_precipitation,
+                        update_weather_screen,
+                    )
+                        .chain()
+                        .after(CarSet::Camera),
+                ),
+            );
+    }
+}
+
+/// Resource with the weather of the race
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    Fog,
+    Snow,
+}
+
+impl Weather {
+    /// Every weather, in menu order
+    pub const ALL: [Weather; 4] = [Weather::Clear, Weather::Rain, Weather::Fog, Weather::Snow];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Weather::Clear => "Clear",
+            Weather::Rain => "Rain",
+            Weather::Fog => "Fog",
+            Weather::Snow => "Snow",
+        }
+    }
+
+    /// The weather `direction` steps along `ALL`, wrapping around
+    pub fn step(self, direction: i32) -> Self {
+        let count = Self::ALL.len() as i32;
+        let index = Self::ALL
+            .iter()
+            .position(|&weather| weather == self)
+            .unwrap_or(0) as i32;
+        Self::ALL[(index + direction).rem_euclid(count) as usize]
+    }
+
+    /// Multiplier of the tire grip
+    pub fn grip(self) -> f32 {
+        match self {
+            Weather::Clear => 1.0,
+            Weather::Rain => 0.75,
+            Weather::Fog => 0.9,
+            Weather::Snow => 0.55,
+        }
+    }
+
+    /// Multiplier of the sunlight
+    pub fn sunlight(self) -> f32 {
+        match self {
+            Weather::Clear => 1.0,
+            Weather::Rain => 0.35,
+            Weather::Fog => 0.5,
+            Weather::Snow => 0.6,
+        }
+    }
+
+    /// How much the sky is covered, from 0 for clear to 1
+    pub fn overcast(self) -> f32 {
+        match self {
+            Weather::Clear => 0.0,
+            Weather::Rain => 0.7,
+            Weather::Fog => 0.9,
+            Weather::Snow => 0.6,
+        }
+    }
+
+    /// Distance at which meshes have faded into the fog, in meters
+    pub fn visibility(self) -> Option<f32> {
+        match self {
+            Weather::Clear => None,
+            Weather::Rain => Some(180.0),
+            Weather::Fog => Some(45.0),
+            Weather::Snow => Some(90.0),
+        }
+    }
+
+    /// Color of the fog, the overcast sky and the haze
+    pub fn fog_color(self) -> Color {
+        match self {
+            Weather::Clear => Color::NONE,
+            Weather::Rain => Color::srgb(0.45, 0.5, 0.55),
+            Weather::Fog => Color::srgb(0.7, 0.72, 0.75),
+            Weather::Snow => Color::srgb(0.85, 0.87, 0.9),
+        }
+    }
+
+    /// Opacity of the haze over the screen
+    fn haze(self) -> f32 {
+        match self {
+            Weather::Clear => 0.0,
+            Weather::Rain => 0.12,
+            Weather::Fog => 0.4,
+            Weather::Snow => 0.2,
+        }
+    }
+}
+
+/// Component of a rain drop or snowflake, with the phase of its sway
+#[derive(Component)]
+struct Precipitation(f32);
+
+/// Marker for the haze over the screen, which holds the drops on it
+#[derive(Component)]
+struct WeatherHaze;
+
+/// Component of a drop on the screen
+#[derive(Component)]
+struct Droplet {
+    age: f32,
+    /// How fast it runs down the screen, in percent of its height per second
+    slide: f32,
+    alpha: f32,
+}
+
+/// Log the weather whenever it changes
+fn announce_weather(weather: Res<Weather>) {
+    if weather.is_changed() && !weather.is_added() {
+        info!("Weather: {}", weather.label());
+    }
+}
+
+/// Give every car the grip of the weather
+fn update_weather_grip(weather: Res<Weather>, mut car_query: Query<&mut Car>) {
+    let grip = weather.grip();
+    for mut car in car_query.iter_mut() {
+        if car.weather_grip != grip {
+            car.weather_grip = grip;
+        }
+    }
+}
+
+/// Scatter the rain or snow around the camera when the weather changes
+fn spawn_precipitation(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera_query: Query<&Transform, (With<CarCamera>, Without<SecondCamera>)>,
+    drop_query: Query<Entity, With<Precipitation>>,
+) {
+    if !weather.is_changed() {
+        return;
+    }
+    for entity in drop_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let (mesh, color) = match *weather {
+        Weather::Rain => (
+            meshes.add(Cuboid::new(0.015, 0.6, 0.015)),
+            Color::srgba(0.75, 0.8, 0.9, 0.4),
+        ),
+        Weather::Snow => (
+            meshes.add(Sphere::new(0.05).mesh().uv(6, 4)),
+            Color::srgba(1.0, 1.0, 1.0, 0.9),
+        ),
+        Weather::Clear | Weather::Fog => return,
+    };
+    let material = materials.add(StandardMaterial {
+        base_color: color,
+        unlit: true,
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+    let center = camera_query
+        .single()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+    let extent = Vec3::new(
+        PRECIPITATION_RADIUS,
+        PRECIPITATION_HEIGHT,
+        PRECIPITATION_RADIUS,
+    );
+    for _ in 0..PRECIPITATION_COUNT {
+        let phase = (jitter().x + 1.0) * 0.5 * TAU;
+        commands.spawn((
+            Precipitation(phase),
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_translation(center + jitter() * extent),
+            NotShadowCaster,
+        ));
+    }
+}
+
+/// Let the rain and snow fall, wrapping them around the box that follows the
+/// camera so it is always falling around the driver
+fn fall_precipitation(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    camera_query: Query<&Transform, (With<CarCamera>, Without<SecondCamera>)>,
+    mut drop_query: Query<(&Precipitation, &mut Transform), Without<CarCamera>>,
+) {
+    let Ok(camera) = camera_query.single() else {
+        return;
+    };
+    let dt = time.delta_secs();
+    let t = time.elapsed_secs();
+    let wrap = |offset: f32, half: f32| (offset + half).rem_euclid(2.0 * half) - half;
+    for (precipitation, mut transform) in drop_query.iter_mut() {
+        let velocity = match *weather {
+            Weather::Snow => Vec3::new(
+                (t * 1.3 + precipitation.0).sin() * SNOW_SWAY,
+                -SNOW_SPEED,
+                (t * 0.9 + precipitation.0).cos() * SNOW_SWAY,
+            ),
+            _ => Vec3::NEG_Y * RAIN_SPEED,
+        };
+        let offset = transform.translation + velocity * dt - camera.translation;
+        transform.translation = camera.translation
+            + Vec3::new(
+                wrap(offset.x, PRECIPITATION_RADIUS),
+                wrap(offset.y, PRECIPITATION_HEIGHT),
+                wrap(offset.z, PRECIPITATION_RADIUS),
+            );
+    }
+}
+
+/// Spawn the haze over the whole screen, under the HUD
+fn spawn_weather_screen(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        BackgroundColor(Color::NONE),
+        GlobalZIndex(-1),
+        WeatherHaze,
+    ));
+}
+
+/// Tint the haze for the weather, and in the rain splash drops on the screen
+/// that run down it and dry up
+fn update_weather_screen(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mode: Res<CameraMode>,
+    mut pending: Local<f32>,
+    mut haze_query: Query<(Entity, &mut BackgroundColor), (With<WeatherHaze>, Without<Droplet>)>,
+    mut droplet_query: Query<(Entity, &mut Droplet, &mut Node, &mut BackgroundColor)>,
+) {
+    let Ok((haze, mut haze_color)) = haze_query.single_mut() else {
+        return;
+    };
+    if weather.is_changed() {
+        haze_color.0 = weather.fog_color().with_alpha(weather.haze());
+    }
+
+    let dt = time.delta_secs();
+    // Only a camera following the car looks through the windshield
+    let raining = *weather == Weather::Rain && !mode.is_free();
+    let mut count = 0;
+    for (entity, mut droplet, mut node, mut color) in droplet_query.iter_mut() {
+        droplet.age += dt;
+        if !raining || droplet.age >= DROPLET_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        count += 1;
+        if let Val::Percent(top) = node.top {
+            node.top = Val::Percent(top + droplet.slide * dt);
+        }
+        let life = droplet.age / DROPLET_LIFETIME;
+        color.0.set_alpha(droplet.alpha * (1.0 - life * life));
+    }
+    if !raining {
+        *pending = 0.0;
+        return;
+    }
+
+    *pending += DROPLET_RATE * dt;
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+        if count >= MAX_DROPLETS {
+            continue;
+        }
+        count += 1;
+        let random = jitter() * 0.5 + Vec3::splat(0.5);
+        let size = 6.0 + 14.0 * random.z;
+        let alpha = 0.25 + 0.2 * random.z;
+        commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(random.x * 100.0),
+                top: Val::Percent(random.y * 100.0),
+                width: Val::Px(size),
+                height: Val::Px(size * 1.2),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.8, 0.85, 0.9, alpha)),
+            BorderRadius::MAX,
+            Droplet {
+                age: 0.0,
+                // The big drops run, the small ones stay put
+                slide: (size - 12.0).max(0.0) * 0.4,
+                alpha,
+            },
+            ChildOf(haze),
+        ));
+    }
+}